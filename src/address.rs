@@ -0,0 +1,144 @@
+use crate::base58::{decode_base58_checksum, encode_base58_checksum};
+use crate::bech32::{decode_segwit_address, encode_segwit_address};
+use crate::error::Error;
+use crate::network::Network;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Address {
+    P2pkh([u8; 20]),
+    P2sh([u8; 20]),
+    // Version 0 is P2WPKH/P2WSH, version 1 is P2TR.
+    Witness { version: u8, program: Vec<u8> },
+}
+
+impl Address {
+    pub fn encode(&self, network: Network) -> String {
+        let params = network.params();
+        match self {
+            Address::P2pkh(hash) => {
+                encode_base58_checksum(&[&[params.p2pkh_prefix], &hash[..]].concat())
+            }
+            Address::P2sh(hash) => {
+                encode_base58_checksum(&[&[params.p2sh_prefix], &hash[..]].concat())
+            }
+            Address::Witness { version, program } => {
+                encode_segwit_address(params.bech32_hrp, *version, program)
+            }
+        }
+    }
+
+    pub fn script_pubkey(&self) -> Vec<u8> {
+        match self {
+            // OP_DUP OP_HASH160 <hash> OP_EQUALVERIFY OP_CHECKSIG
+            Address::P2pkh(hash) => [&[0x76, 0xa9, 0x14][..], hash, &[0x88, 0xac]].concat(),
+            // OP_HASH160 <hash> OP_EQUAL
+            Address::P2sh(hash) => [&[0xa9, 0x14][..], hash, &[0x87]].concat(),
+            // OP_0 or OP_1..OP_16, then the program push.
+            Address::Witness { version, program } => {
+                let version_op = if *version == 0 { 0x00 } else { 0x50 + version };
+                [&[version_op, program.len() as u8][..], program].concat()
+            }
+        }
+    }
+
+    pub fn decode(address: &str, network: Network) -> Result<Self, Error> {
+        let params = network.params();
+        let hrp_prefix = format!("{}1", params.bech32_hrp);
+        if address.to_lowercase().starts_with(&hrp_prefix) {
+            let (version, program) = decode_segwit_address(params.bech32_hrp, address)?;
+            return Ok(Address::Witness { version, program });
+        }
+        let payload = decode_base58_checksum(address)?;
+        if payload.len() != 21 {
+            return Err(Error::InvalidLength(payload.len()));
+        }
+        let hash: [u8; 20] = payload[1..].try_into().unwrap();
+        match payload[0] {
+            prefix if prefix == params.p2pkh_prefix => Ok(Address::P2pkh(hash)),
+            prefix if prefix == params.p2sh_prefix => Ok(Address::P2sh(hash)),
+            _ => Err(Error::WrongNetwork),
+        }
+    }
+}
+
+#[cfg(test)]
+mod address_tests {
+    use super::*;
+
+    #[test]
+    fn base58_addresses_round_trip() {
+        let hash: [u8; 20] = hex::decode("74d691da1574e6b3c192ecfb52cc8984ee7b6c56")
+            .unwrap()
+            .try_into()
+            .unwrap();
+        for network in Network::all() {
+            for address in [Address::P2pkh(hash), Address::P2sh(hash)] {
+                assert_eq!(
+                    Address::decode(&address.encode(network), network).unwrap(),
+                    address
+                );
+            }
+        }
+        assert_eq!(
+            Address::P2sh(hash).encode(Network::Mainnet),
+            "3CLoMMyuoDQTPRD3XYZtCvgvkadrAdvdXh"
+        );
+    }
+
+    #[test]
+    fn witness_address_uses_network_hrp() {
+        let address = Address::Witness {
+            version: 0,
+            program: hex::decode("751e76e8199196d454941c45d1b3a323f1433bd6").unwrap(),
+        };
+        assert_eq!(
+            address.encode(Network::Mainnet),
+            "bc1qw508d6qejxtdg4y5r3zarvary0c5xw7kv8f3t4"
+        );
+        assert!(address.encode(Network::Regtest).starts_with("bcrt1q"));
+        assert_eq!(
+            Address::decode(&address.encode(Network::Regtest), Network::Regtest).unwrap(),
+            address
+        );
+    }
+
+    #[test]
+    fn script_pubkeys() {
+        for (address, script_pubkey) in [
+            (
+                // The genesis block coinbase address.
+                "1A1zP1eP5QGefi2DMPTfTL5SLmv7DivfNa",
+                "76a91462e907b15cbf27d5425399ebf6f0fb50ebb88f1888ac",
+            ),
+            (
+                "3CLoMMyuoDQTPRD3XYZtCvgvkadrAdvdXh",
+                "a91474d691da1574e6b3c192ecfb52cc8984ee7b6c5687",
+            ),
+            (
+                "bc1qw508d6qejxtdg4y5r3zarvary0c5xw7kv8f3t4",
+                "0014751e76e8199196d454941c45d1b3a323f1433bd6",
+            ),
+            (
+                "bc1p5cyxnuxmeuwuvkwfem96lqzszd02n6xdcjrs20cac6yqjjwudpxqkedrcr",
+                "5120a60869f0dbcf1dc659c9cecbaf8050135ea9e8cdc487053f1dc6880949dc684c",
+            ),
+        ] {
+            assert_eq!(
+                hex::encode(
+                    Address::decode(address, Network::Mainnet)
+                        .unwrap()
+                        .script_pubkey()
+                ),
+                script_pubkey
+            );
+        }
+    }
+
+    #[test]
+    fn wrong_network_is_rejected() {
+        assert_eq!(
+            Address::decode("1F1Pn2y6pDb68E5nYJJeba4TLg2U7B6KF1", Network::Testnet),
+            Err(Error::WrongNetwork)
+        );
+    }
+}
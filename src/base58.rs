@@ -0,0 +1,112 @@
+use num::{BigUint, ToPrimitive, Zero};
+
+use crate::error::Error;
+use crate::hash::hash256;
+
+const BASE58_ALPHABET: &[u8; 58] = b"123456789ABCDEFGHJKLMNPQRSTUVWXYZabcdefghijkmnopqrstuvwxyz";
+
+pub fn encode_base58(data: &[u8]) -> String {
+    // Leading zero bytes would vanish in the number, so each one becomes a '1'.
+    let leading_zeros = data.iter().take_while(|byte| **byte == 0).count();
+    let mut num = BigUint::from_bytes_be(data);
+    let mut encoded = Vec::new();
+    while !num.is_zero() {
+        let remainder = (&num % 58u32).to_usize().unwrap();
+        num /= 58u32;
+        encoded.push(BASE58_ALPHABET[remainder]);
+    }
+    encoded.extend(std::iter::repeat_n(b'1', leading_zeros));
+    encoded.reverse();
+    String::from_utf8(encoded).unwrap()
+}
+
+pub fn encode_base58_checksum(data: &[u8]) -> String {
+    let mut payload = data.to_vec();
+    payload.extend_from_slice(&hash256(data)[..4]);
+    encode_base58(&payload)
+}
+
+pub fn decode_base58(s: &str) -> Result<Vec<u8>, Error> {
+    let leading_ones = s.chars().take_while(|c| *c == '1').count();
+    let mut num = BigUint::zero();
+    for c in s.chars() {
+        let digit = BASE58_ALPHABET
+            .iter()
+            .position(|a| *a as char == c)
+            .ok_or(Error::InvalidBase58Character(c))?;
+        num = num * 58u32 + digit;
+    }
+    let mut decoded = vec![0u8; leading_ones];
+    if !num.is_zero() {
+        decoded.extend(num.to_bytes_be());
+    }
+    Ok(decoded)
+}
+
+pub fn decode_base58_checksum(s: &str) -> Result<Vec<u8>, Error> {
+    let mut decoded = decode_base58(s)?;
+    if decoded.len() < 4 {
+        return Err(Error::InvalidLength(decoded.len()));
+    }
+    let checksum = decoded.split_off(decoded.len() - 4);
+    if hash256(&decoded)[..4] != checksum[..] {
+        return Err(Error::InvalidChecksum);
+    }
+    Ok(decoded)
+}
+
+#[cfg(test)]
+mod base58_tests {
+    use super::*;
+
+    #[test]
+    fn encode_base58_works() {
+        // Chapter 4, exercise 4
+        let cases = [
+            (
+                "7c076ff316692a3d7eb3c3bb0f8b1488cf72e1afcd929e29307032997a838a3d",
+                "9MA8fRQrT4u8Zj8ZRd6MAiiyaxb2Y1CMpvVkHQu5hVM6",
+            ),
+            (
+                "eff69ef2b1bd93a66ed5219add4fb51e11a840f404876325a1e8ffe0529a2c",
+                "4fE3H2E6XMp4SsxtwinF7w9a34ooUrwWe4WsW1458Pd",
+            ),
+            (
+                "c7207fee197d27c618aea621406f6bf5ef6fca38681d82b2f06fddbdce6feab6",
+                "EQJsjkd6JaGwxrjEhfeqPenqHwrBmPQZjJGNSCHBkcF7",
+            ),
+        ];
+        for (data, expected) in cases {
+            let data = hex::decode(data).unwrap();
+            assert_eq!(encode_base58(&data), expected);
+            assert_eq!(decode_base58(expected).unwrap(), data);
+        }
+    }
+
+    #[test]
+    fn leading_zeros_round_trip() {
+        let data = [0u8, 0, 1, 2, 3];
+        let encoded = encode_base58(&data);
+        assert!(encoded.starts_with("11"));
+        assert_eq!(decode_base58(&encoded).unwrap(), data);
+    }
+
+    #[test]
+    fn checksum_is_verified() {
+        let encoded = encode_base58_checksum(b"programming bitcoin");
+        assert_eq!(
+            decode_base58_checksum(&encoded).unwrap(),
+            b"programming bitcoin"
+        );
+        let mut tampered = encoded.into_bytes();
+        tampered[3] = if tampered[3] == b'2' { b'3' } else { b'2' };
+        assert_eq!(
+            decode_base58_checksum(&String::from_utf8(tampered).unwrap()),
+            Err(Error::InvalidChecksum)
+        );
+        assert_eq!(
+            decode_base58("0OIl"),
+            Err(Error::InvalidBase58Character('0'))
+        );
+    }
+}
@@ -0,0 +1,97 @@
+use crate::error::Error;
+
+const BASE64_ALPHABET: &[u8; 64] =
+    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+// RFC 4648 with padding, the text form PSBTs are exchanged in.
+pub fn encode_base64(data: &[u8]) -> String {
+    let mut encoded = String::with_capacity(data.len().div_ceil(3) * 4);
+    for chunk in data.chunks(3) {
+        let mut group = [0u8; 3];
+        group[..chunk.len()].copy_from_slice(chunk);
+        let bits = u32::from_be_bytes([0, group[0], group[1], group[2]]);
+        for i in 0..4 {
+            if i <= chunk.len() {
+                encoded.push(BASE64_ALPHABET[(bits >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
+
+// Strict: padding is required, and bits left over after the last byte must be zero.
+pub fn decode_base64(s: &str) -> Result<Vec<u8>, Error> {
+    let s = s.as_bytes();
+    if !s.len().is_multiple_of(4) {
+        return Err(Error::InvalidBase64(format!("length {}", s.len())));
+    }
+    let mut decoded = Vec::with_capacity(s.len() / 4 * 3);
+    for (index, chunk) in s.chunks(4).enumerate() {
+        let last = index == s.len() / 4 - 1;
+        let padding = chunk.iter().rev().take_while(|c| **c == b'=').count();
+        if padding > 2 || (padding > 0 && !last) {
+            return Err(Error::InvalidBase64("misplaced padding".to_string()));
+        }
+        let mut bits = 0u32;
+        for c in &chunk[..4 - padding] {
+            let digit = BASE64_ALPHABET
+                .iter()
+                .position(|a| a == c)
+                .ok_or_else(|| Error::InvalidBase64(format!("character {:?}", *c as char)))?;
+            bits = bits << 6 | digit as u32;
+        }
+        bits <<= 6 * padding;
+        if bits & ((1 << (8 * padding)) - 1) != 0 {
+            return Err(Error::InvalidBase64("non-zero trailing bits".to_string()));
+        }
+        decoded.extend_from_slice(&bits.to_be_bytes()[1..4 - padding]);
+    }
+    Ok(decoded)
+}
+
+#[cfg(test)]
+mod base64_tests {
+    use super::*;
+
+    // RFC 4648, section 10.
+    #[test]
+    fn rfc4648_vectors() {
+        for (data, encoded) in [
+            ("", ""),
+            ("f", "Zg=="),
+            ("fo", "Zm8="),
+            ("foo", "Zm9v"),
+            ("foob", "Zm9vYg=="),
+            ("fooba", "Zm9vYmE="),
+            ("foobar", "Zm9vYmFy"),
+        ] {
+            assert_eq!(encode_base64(data.as_bytes()), encoded);
+            assert_eq!(decode_base64(encoded).unwrap(), data.as_bytes());
+        }
+        let all: Vec<u8> = (0..=255).collect();
+        assert_eq!(decode_base64(&encode_base64(&all)).unwrap(), all);
+    }
+
+    #[test]
+    fn rejects_malformed_strings() {
+        for s in [
+            "Zg",
+            "Zg=",
+            "Zg===",
+            "Z===",
+            "Zg==Zm8=",
+            "Zm9v!A==",
+            "Zh==",
+            "Zm9=",
+            "cHNi dP8=",
+        ] {
+            assert!(
+                matches!(decode_base64(s), Err(Error::InvalidBase64(_))),
+                "{:?}",
+                s
+            );
+        }
+    }
+}
@@ -0,0 +1,214 @@
+use crate::error::Error;
+
+const CHARSET: &[u8; 32] = b"qpzry9x8gf2tvdw0s3jn54khce6mua7l";
+const BECH32_CONST: u32 = 1;
+const BECH32M_CONST: u32 = 0x2bc8_30a3;
+
+// BIP173 bech32 is used for witness version 0, BIP350 bech32m for every later version.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Variant {
+    Bech32,
+    Bech32m,
+}
+
+impl Variant {
+    fn constant(&self) -> u32 {
+        match self {
+            Variant::Bech32 => BECH32_CONST,
+            Variant::Bech32m => BECH32M_CONST,
+        }
+    }
+}
+
+fn polymod(values: &[u8]) -> u32 {
+    const GENERATOR: [u32; 5] = [0x3b6a57b2, 0x26508e6d, 0x1ea119fa, 0x3d4233dd, 0x2a1462b3];
+    let mut checksum: u32 = 1;
+    for value in values {
+        let top = checksum >> 25;
+        checksum = (checksum & 0x1ffffff) << 5 ^ *value as u32;
+        for (i, generator) in GENERATOR.iter().enumerate() {
+            if (top >> i) & 1 == 1 {
+                checksum ^= generator;
+            }
+        }
+    }
+    checksum
+}
+
+fn hrp_expand(hrp: &str) -> Vec<u8> {
+    let mut expanded: Vec<u8> = hrp.bytes().map(|b| b >> 5).collect();
+    expanded.push(0);
+    expanded.extend(hrp.bytes().map(|b| b & 31));
+    expanded
+}
+
+fn create_checksum(hrp: &str, data: &[u8], variant: Variant) -> Vec<u8> {
+    let mut values = hrp_expand(hrp);
+    values.extend_from_slice(data);
+    values.extend_from_slice(&[0; 6]);
+    let checksum = polymod(&values) ^ variant.constant();
+    (0..6)
+        .map(|i| ((checksum >> (5 * (5 - i))) & 31) as u8)
+        .collect()
+}
+
+// `data` holds 5-bit values.
+pub fn encode(hrp: &str, data: &[u8], variant: Variant) -> String {
+    let mut encoded = format!("{}1", hrp);
+    for value in data
+        .iter()
+        .chain(create_checksum(hrp, data, variant).iter())
+    {
+        encoded.push(CHARSET[*value as usize] as char);
+    }
+    encoded
+}
+
+pub fn decode(s: &str) -> Result<(String, Vec<u8>, Variant), Error> {
+    if s.to_lowercase() != s && s.to_uppercase() != s {
+        return Err(Error::InvalidBech32("mixed case".to_string()));
+    }
+    let s = s.to_lowercase();
+    if s.len() > 90 {
+        return Err(Error::InvalidLength(s.len()));
+    }
+    let separator = s
+        .rfind('1')
+        .ok_or_else(|| Error::InvalidBech32("missing separator".to_string()))?;
+    if separator == 0 || separator + 7 > s.len() {
+        return Err(Error::InvalidBech32("bad separator position".to_string()));
+    }
+    let (hrp, rest) = s.split_at(separator);
+    if hrp.bytes().any(|b| !(33..=126).contains(&b)) {
+        return Err(Error::InvalidBech32(
+            "invalid human readable part".to_string(),
+        ));
+    }
+    let mut data = Vec::with_capacity(rest.len() - 1);
+    for c in rest[1..].chars() {
+        let value = CHARSET
+            .iter()
+            .position(|a| *a as char == c)
+            .ok_or_else(|| Error::InvalidBech32(format!("invalid character {:?}", c)))?;
+        data.push(value as u8);
+    }
+    let mut values = hrp_expand(hrp);
+    values.extend_from_slice(&data);
+    let variant = match polymod(&values) {
+        BECH32_CONST => Variant::Bech32,
+        BECH32M_CONST => Variant::Bech32m,
+        _ => return Err(Error::InvalidChecksum),
+    };
+    data.truncate(data.len() - 6);
+    Ok((hrp.to_string(), data, variant))
+}
+
+pub fn convert_bits(data: &[u8], from: u32, to: u32, pad: bool) -> Result<Vec<u8>, Error> {
+    let mut accumulator: u32 = 0;
+    let mut bits: u32 = 0;
+    let max_value: u32 = (1 << to) - 1;
+    let mut converted = Vec::new();
+    for value in data {
+        let value = *value as u32;
+        if value >> from != 0 {
+            return Err(Error::InvalidBech32("value out of range".to_string()));
+        }
+        accumulator = (accumulator << from) | value;
+        bits += from;
+        while bits >= to {
+            bits -= to;
+            converted.push(((accumulator >> bits) & max_value) as u8);
+        }
+    }
+    if pad {
+        if bits > 0 {
+            converted.push(((accumulator << (to - bits)) & max_value) as u8);
+        }
+    } else if bits >= from || ((accumulator << (to - bits)) & max_value) != 0 {
+        return Err(Error::InvalidBech32("invalid padding".to_string()));
+    }
+    Ok(converted)
+}
+
+pub fn encode_segwit_address(hrp: &str, version: u8, program: &[u8]) -> String {
+    let variant = if version == 0 {
+        Variant::Bech32
+    } else {
+        Variant::Bech32m
+    };
+    let mut data = vec![version];
+    data.extend(convert_bits(program, 8, 5, true).unwrap());
+    encode(hrp, &data, variant)
+}
+
+pub fn decode_segwit_address(hrp: &str, address: &str) -> Result<(u8, Vec<u8>), Error> {
+    let (decoded_hrp, data, variant) = decode(address)?;
+    if decoded_hrp != hrp {
+        return Err(Error::WrongNetwork);
+    }
+    let (version, program) = data
+        .split_first()
+        .ok_or_else(|| Error::InvalidBech32("empty data".to_string()))?;
+    let program = convert_bits(program, 5, 8, false)?;
+    if *version > 16 || program.len() < 2 || program.len() > 40 {
+        return Err(Error::InvalidBech32("invalid witness program".to_string()));
+    }
+    if *version == 0 && program.len() != 20 && program.len() != 32 {
+        return Err(Error::InvalidLength(program.len()));
+    }
+    let expected = if *version == 0 {
+        Variant::Bech32
+    } else {
+        Variant::Bech32m
+    };
+    if variant != expected {
+        return Err(Error::InvalidBech32("wrong checksum variant".to_string()));
+    }
+    Ok((*version, program))
+}
+
+#[cfg(test)]
+mod bech32_tests {
+    use super::*;
+
+    #[test]
+    fn bip173_p2wpkh() {
+        let program = hex::decode("751e76e8199196d454941c45d1b3a323f1433bd6").unwrap();
+        let address = "bc1qw508d6qejxtdg4y5r3zarvary0c5xw7kv8f3t4";
+        assert_eq!(encode_segwit_address("bc", 0, &program), address);
+        assert_eq!(
+            decode_segwit_address("bc", &address.to_uppercase()).unwrap(),
+            (0, program)
+        );
+    }
+
+    #[test]
+    fn bip350_p2tr() {
+        let program =
+            hex::decode("79be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798")
+                .unwrap();
+        let address = "bc1p0xlxvlhemja6c4dqv22uapctqupfhlxm9h8z3k2e72q4k9hcz7vqzk5jj0";
+        assert_eq!(encode_segwit_address("bc", 1, &program), address);
+        assert_eq!(decode_segwit_address("bc", address).unwrap(), (1, program));
+    }
+
+    #[test]
+    fn invalid_addresses_are_rejected() {
+        // Version 1 program with a bech32 (not bech32m) checksum, from BIP350.
+        assert!(decode_segwit_address(
+            "bc",
+            "bc1p0xlxvlhemja6c4dqv22uapctqupfhlxm9h8z3k2e72q4k9hcz7vq5zuyut"
+        )
+        .is_err());
+        // Mixed case, from BIP173.
+        assert!(decode_segwit_address(
+            "tb",
+            "tb1qrp33g0q5c5txsp9arysrx4k6zdkfs4nce4xj0gdcccefvpysxf3q0sL5k7"
+        )
+        .is_err());
+        assert_eq!(
+            decode_segwit_address("tb", "bc1qw508d6qejxtdg4y5r3zarvary0c5xw7kv8f3t4"),
+            Err(Error::WrongNetwork)
+        );
+    }
+}
@@ -0,0 +1,298 @@
+use super::path::DerivationPath;
+use super::{ExtendedPrivKey, ExtendedPubKey, HARDENED};
+use crate::address::Address;
+use crate::error::Error;
+use crate::hash::hash160;
+use crate::network::Network;
+use crate::secp256k1::point::Secp256k1Point;
+use crate::taproot::output_key;
+
+// The BIP43 purpose field, which fixes the script type of every address in the account.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Purpose {
+    // P2PKH
+    Bip44,
+    // P2SH-P2WPKH
+    Bip49,
+    // P2WPKH
+    Bip84,
+    // P2TR key path
+    Bip86,
+}
+
+impl Purpose {
+    pub fn number(&self) -> u32 {
+        match self {
+            Purpose::Bip44 => 44,
+            Purpose::Bip49 => 49,
+            Purpose::Bip84 => 84,
+            Purpose::Bip86 => 86,
+        }
+    }
+
+    // SLIP-132 version bytes (ypub/zpub and friends). Taproot has no registered version.
+    pub fn xpub_version(&self, network: Network) -> [u8; 4] {
+        match (self, network) {
+            (Purpose::Bip49, Network::Mainnet) => [0x04, 0x9d, 0x7c, 0xb2],
+            (Purpose::Bip49, _) => [0x04, 0x4a, 0x52, 0x62],
+            (Purpose::Bip84, Network::Mainnet) => [0x04, 0xb2, 0x47, 0x46],
+            (Purpose::Bip84, _) => [0x04, 0x5f, 0x1c, 0xf6],
+            (Purpose::Bip44 | Purpose::Bip86, _) => network.params().xpub_version,
+        }
+    }
+
+    pub fn xprv_version(&self, network: Network) -> [u8; 4] {
+        match (self, network) {
+            (Purpose::Bip49, Network::Mainnet) => [0x04, 0x9d, 0x78, 0x78],
+            (Purpose::Bip49, _) => [0x04, 0x4a, 0x4e, 0x28],
+            (Purpose::Bip84, Network::Mainnet) => [0x04, 0xb2, 0x43, 0x0c],
+            (Purpose::Bip84, _) => [0x04, 0x5f, 0x18, 0xbc],
+            (Purpose::Bip44 | Purpose::Bip86, _) => network.params().xprv_version,
+        }
+    }
+
+    pub fn address(&self, public_key: &Secp256k1Point) -> Address {
+        match self {
+            Purpose::Bip44 => Address::P2pkh(public_key.hash160(true)),
+            Purpose::Bip49 => {
+                let mut redeem_script = vec![0x00, 0x14];
+                redeem_script.extend_from_slice(&public_key.hash160(true));
+                Address::P2sh(hash160(&redeem_script))
+            }
+            Purpose::Bip84 => Address::Witness {
+                version: 0,
+                program: public_key.hash160(true).to_vec(),
+            },
+            Purpose::Bip86 => Address::Witness {
+                version: 1,
+                program: output_key(public_key, None).x_only().to_vec(),
+            },
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Account {
+    purpose: Purpose,
+    xpub: ExtendedPubKey,
+}
+
+impl Account {
+    // m / purpose' / coin_type' / account', with coin type 1 for every test network.
+    // The account must fit below the hardened range.
+    pub fn path(purpose: Purpose, network: Network, account: u32) -> Result<DerivationPath, Error> {
+        if account >= HARDENED {
+            return Err(Error::InvalidDerivationPath(format!("{}'", account)));
+        }
+        let coin_type = match network {
+            Network::Mainnet => 0,
+            _ => 1,
+        };
+        Ok(DerivationPath::new(vec![
+            purpose.number() + HARDENED,
+            coin_type + HARDENED,
+            account + HARDENED,
+        ]))
+    }
+
+    pub fn from_master(
+        master: &ExtendedPrivKey,
+        purpose: Purpose,
+        account: u32,
+    ) -> Result<Self, Error> {
+        let path = Self::path(purpose, master.network(), account)?;
+        let xpub = master.derive_path(&path)?.to_xpub();
+        Ok(Account { purpose, xpub })
+    }
+
+    pub fn from_xpub(xpub: ExtendedPubKey, purpose: Purpose) -> Self {
+        Account { purpose, xpub }
+    }
+
+    // Reads an account xpub, picking the purpose from its SLIP-132 version. Plain
+    // xpub/tpub strings are treated as BIP44.
+    pub fn decode(s: &str, network: Network) -> Result<Self, Error> {
+        for purpose in [Purpose::Bip49, Purpose::Bip84] {
+            let version = purpose.xpub_version(network);
+            if let Ok(xpub) = ExtendedPubKey::decode_with_version(s, network, version) {
+                return Ok(Account { purpose, xpub });
+            }
+        }
+        Ok(Account {
+            purpose: Purpose::Bip44,
+            xpub: ExtendedPubKey::decode(s, network)?,
+        })
+    }
+
+    pub fn purpose(&self) -> Purpose {
+        self.purpose
+    }
+
+    pub fn xpub(&self) -> &ExtendedPubKey {
+        &self.xpub
+    }
+
+    pub fn encode(&self) -> String {
+        self.xpub
+            .encode_with_version(self.purpose.xpub_version(self.xpub.network()))
+    }
+
+    pub fn address(&self, change: bool, index: u32) -> Result<Address, Error> {
+        let key = self.xpub.derive_child(change as u32)?.derive_child(index)?;
+        Ok(self.purpose.address(key.public_key()))
+    }
+
+    pub fn receive_addresses(&self) -> Result<AddressIter, Error> {
+        self.addresses(false)
+    }
+
+    pub fn change_addresses(&self) -> Result<AddressIter, Error> {
+        self.addresses(true)
+    }
+
+    fn addresses(&self, change: bool) -> Result<AddressIter, Error> {
+        Ok(AddressIter {
+            purpose: self.purpose,
+            chain: self.xpub.derive_child(change as u32)?,
+            next_index: 0,
+        })
+    }
+}
+
+// Yields (index, address) along a receive or change chain, skipping the
+// astronomically rare indexes that BIP32 says to skip.
+pub struct AddressIter {
+    purpose: Purpose,
+    chain: ExtendedPubKey,
+    next_index: u32,
+}
+
+impl Iterator for AddressIter {
+    type Item = (u32, Address);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.next_index < HARDENED {
+            let index = self.next_index;
+            self.next_index += 1;
+            if let Ok(key) = self.chain.derive_child(index) {
+                return Some((index, self.purpose.address(key.public_key())));
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod account_tests {
+    use super::*;
+
+    // BIP84 and BIP86 both use the "abandon abandon ... about" mnemonic.
+    fn abandon_master() -> ExtendedPrivKey {
+        ExtendedPrivKey::decode("xprv9s21ZrQH143K3GJpoapnV8SFfukcVBSfeCficPSGfubmSFDxo1kuHnLisriDvSnRRuL2Qrg5ggqHKNVpxR86QEC8w35uxmGoggxtQTPvfUu", Network::Mainnet).unwrap()
+    }
+
+    fn encoded(addresses: Vec<(u32, Address)>, network: Network) -> Vec<String> {
+        addresses
+            .into_iter()
+            .map(|(_, address)| address.encode(network))
+            .collect()
+    }
+
+    #[test]
+    fn bip44_first_address() {
+        let account = Account::from_master(&abandon_master(), Purpose::Bip44, 0).unwrap();
+        assert_eq!(
+            account.address(false, 0).unwrap().encode(Network::Mainnet),
+            "1LqBGSKuX5yYUonjxT5qGfpUsXKYYWeabA"
+        );
+    }
+
+    #[test]
+    fn bip49_testnet_vector() {
+        let master = ExtendedPrivKey::decode("tprv8ZgxMBicQKsPe5YMU9gHen4Ez3ApihUfykaqUorj9t6FDqy3nP6eoXiAo2ssvpAjoLroQxHqr3R5nE3a5dU3DHTjTgJDd7zrbniJr6nrCzd", Network::Testnet).unwrap();
+        let account = Account::from_master(&master, Purpose::Bip49, 0).unwrap();
+        assert_eq!(account.encode(), "upub5EFU65HtV5TeiSHmZZm7FUffBGy8UKeqp7vw43jYbvZPpoVsgU93oac7Wk3u6moKegAEWtGNF8DehrnHtv21XXEMYRUocHqguyjknFHYfgY");
+        assert_eq!(
+            account.address(false, 0).unwrap().encode(Network::Testnet),
+            "2Mww8dCYPUpKHofjgcXcBCEGmniw9CoaiD2"
+        );
+    }
+
+    #[test]
+    fn bip84_vector() {
+        let master = ExtendedPrivKey::decode_with_version(
+            "zprvAWgYBBk7JR8Gjrh4UJQ2uJdG1r3WNRRfURiABBE3RvMXYSrRJL62XuezvGdPvG6GFBZduosCc1YP5wixPox7zhZLfiUm8aunE96BBa4Kei5",
+            Network::Mainnet,
+            Purpose::Bip84.xprv_version(Network::Mainnet),
+        )
+        .unwrap();
+        assert_eq!(master, abandon_master());
+        let account = Account::from_master(&master, Purpose::Bip84, 0).unwrap();
+        let zpub = "zpub6rFR7y4Q2AijBEqTUquhVz398htDFrtymD9xYYfG1m4wAcvPhXNfE3EfH1r1ADqtfSdVCToUG868RvUUkgDKf31mGDtKsAYz2oz2AGutZYs";
+        assert_eq!(account.encode(), zpub);
+        assert_eq!(Account::decode(zpub, Network::Mainnet).unwrap(), account);
+        assert_eq!(
+            encoded(
+                account.receive_addresses().unwrap().take(2).collect(),
+                Network::Mainnet
+            ),
+            [
+                "bc1qcr8te4kr609gcawutmrza0j4xv80jy8z306fyu",
+                "bc1qnjg0jd8228aq7egyzacy8cys3knf9xvrerkf9g"
+            ]
+        );
+        assert_eq!(
+            encoded(
+                account.change_addresses().unwrap().take(1).collect(),
+                Network::Mainnet
+            ),
+            ["bc1q8c6fshw2dlwun7ekn9qwf37cu2rn755upcp6el"]
+        );
+    }
+
+    #[test]
+    fn bip86_vector() {
+        let account = Account::from_master(&abandon_master(), Purpose::Bip86, 0).unwrap();
+        assert_eq!(
+            encoded(
+                account.receive_addresses().unwrap().take(2).collect(),
+                Network::Mainnet
+            ),
+            [
+                "bc1p5cyxnuxmeuwuvkwfem96lqzszd02n6xdcjrs20cac6yqjjwudpxqkedrcr",
+                "bc1p4qhjn9zdvkux4e44uhx8tc55attvtyu358kutcqkudyccelu0was9fqzwh"
+            ]
+        );
+        assert_eq!(
+            account.address(true, 0).unwrap().encode(Network::Mainnet),
+            "bc1p3qkhfews2uk44qtvauqyr2ttdsw7svhkl9nkm9s9c3x4ax5h60wqwruhk7"
+        );
+    }
+
+    #[test]
+    fn account_paths() {
+        assert_eq!(
+            Account::path(Purpose::Bip84, Network::Mainnet, 0)
+                .unwrap()
+                .to_string(),
+            "m/84'/0'/0'"
+        );
+        assert_eq!(
+            Account::path(Purpose::Bip86, Network::Regtest, 3)
+                .unwrap()
+                .to_string(),
+            "m/86'/1'/3'"
+        );
+        assert_eq!(
+            Account::path(Purpose::Bip84, Network::Mainnet, HARDENED - 1)
+                .unwrap()
+                .to_string(),
+            "m/84'/0'/2147483647'"
+        );
+        assert!(matches!(
+            Account::path(Purpose::Bip84, Network::Mainnet, HARDENED),
+            Err(Error::InvalidDerivationPath(_))
+        ));
+        assert!(Account::from_master(&abandon_master(), Purpose::Bip84, u32::MAX).is_err());
+    }
+}
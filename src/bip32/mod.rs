@@ -0,0 +1,631 @@
+pub mod account;
+pub mod path;
+
+use std::fmt;
+
+use num::{BigUint, Zero};
+
+use self::path::DerivationPath;
+use crate::base58::{decode_base58_checksum, encode_base58_checksum};
+use crate::error::Error;
+use crate::hash::hmac_sha512;
+use crate::network::Network;
+use crate::secp256k1::point::{generator_point, n, Secp256k1Point};
+use crate::secp256k1::private_key::PrivateKey;
+
+// Child numbers at or above this are hardened and can only be derived from a private key.
+pub const HARDENED: u32 = 0x8000_0000;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExtendedPrivKey {
+    network: Network,
+    depth: u8,
+    parent_fingerprint: [u8; 4],
+    child_number: u32,
+    chain_code: [u8; 32],
+    private_key: PrivateKey,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExtendedPubKey {
+    network: Network,
+    depth: u8,
+    parent_fingerprint: [u8; 4],
+    child_number: u32,
+    chain_code: [u8; 32],
+    public_key: Secp256k1Point,
+}
+
+// The 78 byte payload shared by xprv and xpub, before the key data is interpreted.
+struct RawExtendedKey {
+    version: [u8; 4],
+    depth: u8,
+    parent_fingerprint: [u8; 4],
+    child_number: u32,
+    chain_code: [u8; 32],
+    key_data: [u8; 33],
+}
+
+impl RawExtendedKey {
+    fn encode(&self) -> String {
+        let mut payload = Vec::with_capacity(78);
+        payload.extend_from_slice(&self.version);
+        payload.push(self.depth);
+        payload.extend_from_slice(&self.parent_fingerprint);
+        payload.extend_from_slice(&self.child_number.to_be_bytes());
+        payload.extend_from_slice(&self.chain_code);
+        payload.extend_from_slice(&self.key_data);
+        encode_base58_checksum(&payload)
+    }
+
+    fn decode(s: &str) -> Result<Self, Error> {
+        let payload = decode_base58_checksum(s)?;
+        if payload.len() != 78 {
+            return Err(Error::InvalidLength(payload.len()));
+        }
+        let raw = RawExtendedKey {
+            version: payload[0..4].try_into().unwrap(),
+            depth: payload[4],
+            parent_fingerprint: payload[5..9].try_into().unwrap(),
+            child_number: u32::from_be_bytes(payload[9..13].try_into().unwrap()),
+            chain_code: payload[13..45].try_into().unwrap(),
+            key_data: payload[45..78].try_into().unwrap(),
+        };
+        if raw.depth == 0 && raw.parent_fingerprint != [0; 4] {
+            return Err(Error::InvalidExtendedKey(
+                "zero depth with non-zero parent fingerprint".to_string(),
+            ));
+        }
+        if raw.depth == 0 && raw.child_number != 0 {
+            return Err(Error::InvalidExtendedKey(
+                "zero depth with non-zero index".to_string(),
+            ));
+        }
+        Ok(raw)
+    }
+
+    fn check_version(
+        &self,
+        network: Network,
+        version_of: fn(Network) -> [u8; 4],
+    ) -> Result<(), Error> {
+        if self.version == version_of(network) {
+            Ok(())
+        } else if Network::all()
+            .into_iter()
+            .any(|other| version_of(other) == self.version)
+        {
+            Err(Error::WrongNetwork)
+        } else {
+            Err(Error::UnknownVersion(self.version))
+        }
+    }
+}
+
+// Splits HMAC-SHA512 output into the key tweak (IL) and the chain code (IR).
+fn split_hmac(i: [u8; 64]) -> (BigUint, [u8; 32]) {
+    (
+        BigUint::from_bytes_be(&i[..32]),
+        i[32..].try_into().unwrap(),
+    )
+}
+
+fn fingerprint_of(point: &Secp256k1Point) -> [u8; 4] {
+    point.hash160(true)[..4].try_into().unwrap()
+}
+
+fn next_depth(depth: u8) -> Result<u8, Error> {
+    depth
+        .checked_add(1)
+        .ok_or_else(|| Error::InvalidExtendedKey("maximum depth reached".to_string()))
+}
+
+impl ExtendedPrivKey {
+    pub fn new_master(seed: &[u8], network: Network) -> Result<Self, Error> {
+        if seed.len() < 16 || seed.len() > 64 {
+            return Err(Error::InvalidLength(seed.len()));
+        }
+        let (secret, chain_code) = split_hmac(hmac_sha512(b"Bitcoin seed", seed));
+        if secret.is_zero() || secret >= n() {
+            return Err(Error::SecretOutOfRange);
+        }
+        Ok(Self {
+            network,
+            depth: 0,
+            parent_fingerprint: [0; 4],
+            child_number: 0,
+            chain_code,
+            private_key: PrivateKey::new(secret),
+        })
+    }
+
+    pub fn network(&self) -> Network {
+        self.network
+    }
+
+    pub fn depth(&self) -> u8 {
+        self.depth
+    }
+
+    pub fn parent_fingerprint(&self) -> [u8; 4] {
+        self.parent_fingerprint
+    }
+
+    pub fn child_number(&self) -> u32 {
+        self.child_number
+    }
+
+    pub fn chain_code(&self) -> &[u8; 32] {
+        &self.chain_code
+    }
+
+    pub fn private_key(&self) -> &PrivateKey {
+        &self.private_key
+    }
+
+    pub fn fingerprint(&self) -> [u8; 4] {
+        fingerprint_of(self.private_key.point())
+    }
+
+    pub fn identifier(&self) -> [u8; 20] {
+        self.private_key.point().hash160(true)
+    }
+
+    pub fn derive_child(&self, index: u32) -> Result<Self, Error> {
+        let mut data = Vec::with_capacity(37);
+        if index >= HARDENED {
+            data.push(0x00);
+            data.extend_from_slice(&self.private_key.to_bytes());
+        } else {
+            data.extend_from_slice(&self.private_key.point().sec(true));
+        }
+        data.extend_from_slice(&index.to_be_bytes());
+        let (tweak, chain_code) = split_hmac(hmac_sha512(&self.chain_code, &data));
+        if tweak >= n() {
+            return Err(Error::InvalidChildKey);
+        }
+        let secret = (tweak + self.private_key.secret()) % n();
+        if secret.is_zero() {
+            return Err(Error::InvalidChildKey);
+        }
+        Ok(Self {
+            network: self.network,
+            depth: next_depth(self.depth)?,
+            parent_fingerprint: self.fingerprint(),
+            child_number: index,
+            chain_code,
+            private_key: PrivateKey::new(secret),
+        })
+    }
+
+    pub fn derive_path(&self, path: &DerivationPath) -> Result<Self, Error> {
+        path.indexes()
+            .iter()
+            .try_fold(self.clone(), |key, index| key.derive_child(*index))
+    }
+
+    pub fn to_xpub(&self) -> ExtendedPubKey {
+        ExtendedPubKey {
+            network: self.network,
+            depth: self.depth,
+            parent_fingerprint: self.parent_fingerprint,
+            child_number: self.child_number,
+            chain_code: self.chain_code,
+            public_key: self.private_key.point().clone(),
+        }
+    }
+
+    fn to_raw(&self, version: [u8; 4]) -> RawExtendedKey {
+        let mut key_data = [0u8; 33];
+        key_data[1..].copy_from_slice(&self.private_key.to_bytes());
+        RawExtendedKey {
+            version,
+            depth: self.depth,
+            parent_fingerprint: self.parent_fingerprint,
+            child_number: self.child_number,
+            chain_code: self.chain_code,
+            key_data,
+        }
+    }
+
+    fn from_raw(raw: RawExtendedKey, network: Network) -> Result<Self, Error> {
+        if raw.key_data[0] != 0x00 {
+            return Err(Error::InvalidPrefix(raw.key_data[0]));
+        }
+        Ok(Self {
+            network,
+            depth: raw.depth,
+            parent_fingerprint: raw.parent_fingerprint,
+            child_number: raw.child_number,
+            chain_code: raw.chain_code,
+            private_key: PrivateKey::from_bytes(&raw.key_data[1..])?,
+        })
+    }
+
+    pub fn decode(s: &str, network: Network) -> Result<Self, Error> {
+        let raw = RawExtendedKey::decode(s)?;
+        raw.check_version(network, |network| network.params().xprv_version)?;
+        Self::from_raw(raw, network)
+    }
+
+    // For alternative version bytes such as SLIP-132 yprv/zprv.
+    pub fn encode_with_version(&self, version: [u8; 4]) -> String {
+        self.to_raw(version).encode()
+    }
+
+    pub fn decode_with_version(s: &str, network: Network, version: [u8; 4]) -> Result<Self, Error> {
+        let raw = RawExtendedKey::decode(s)?;
+        if raw.version != version {
+            return Err(Error::UnknownVersion(raw.version));
+        }
+        Self::from_raw(raw, network)
+    }
+}
+
+impl fmt::Display for ExtendedPrivKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let version = self.network.params().xprv_version;
+        write!(f, "{}", self.to_raw(version).encode())
+    }
+}
+
+impl ExtendedPubKey {
+    pub fn network(&self) -> Network {
+        self.network
+    }
+
+    pub fn depth(&self) -> u8 {
+        self.depth
+    }
+
+    pub fn parent_fingerprint(&self) -> [u8; 4] {
+        self.parent_fingerprint
+    }
+
+    pub fn child_number(&self) -> u32 {
+        self.child_number
+    }
+
+    pub fn chain_code(&self) -> &[u8; 32] {
+        &self.chain_code
+    }
+
+    pub fn public_key(&self) -> &Secp256k1Point {
+        &self.public_key
+    }
+
+    pub fn fingerprint(&self) -> [u8; 4] {
+        fingerprint_of(&self.public_key)
+    }
+
+    pub fn identifier(&self) -> [u8; 20] {
+        self.public_key.hash160(true)
+    }
+
+    pub fn derive_child(&self, index: u32) -> Result<Self, Error> {
+        if index >= HARDENED {
+            return Err(Error::HardenedDerivationFromPublicKey);
+        }
+        let mut data = self.public_key.sec(true);
+        data.extend_from_slice(&index.to_be_bytes());
+        let (mut tweak, chain_code) = split_hmac(hmac_sha512(&self.chain_code, &data));
+        if tweak >= n() {
+            return Err(Error::InvalidChildKey);
+        }
+        let public_key = generator_point().multiply_by(&mut tweak) + self.public_key.clone();
+        if public_key.is_infinity() {
+            return Err(Error::InvalidChildKey);
+        }
+        Ok(Self {
+            network: self.network,
+            depth: next_depth(self.depth)?,
+            parent_fingerprint: self.fingerprint(),
+            child_number: index,
+            chain_code,
+            public_key,
+        })
+    }
+
+    pub fn derive_path(&self, path: &DerivationPath) -> Result<Self, Error> {
+        path.indexes()
+            .iter()
+            .try_fold(self.clone(), |key, index| key.derive_child(*index))
+    }
+
+    fn to_raw(&self, version: [u8; 4]) -> RawExtendedKey {
+        RawExtendedKey {
+            version,
+            depth: self.depth,
+            parent_fingerprint: self.parent_fingerprint,
+            child_number: self.child_number,
+            chain_code: self.chain_code,
+            key_data: self.public_key.sec(true).try_into().unwrap(),
+        }
+    }
+
+    fn from_raw(raw: RawExtendedKey, network: Network) -> Result<Self, Error> {
+        if raw.key_data[0] != 0x02 && raw.key_data[0] != 0x03 {
+            return Err(Error::InvalidPrefix(raw.key_data[0]));
+        }
+        Ok(Self {
+            network,
+            depth: raw.depth,
+            parent_fingerprint: raw.parent_fingerprint,
+            child_number: raw.child_number,
+            chain_code: raw.chain_code,
+            public_key: Secp256k1Point::parse_sec(&raw.key_data)?,
+        })
+    }
+
+    pub fn decode(s: &str, network: Network) -> Result<Self, Error> {
+        let raw = RawExtendedKey::decode(s)?;
+        raw.check_version(network, |network| network.params().xpub_version)?;
+        Self::from_raw(raw, network)
+    }
+
+    // For alternative version bytes such as SLIP-132 ypub/zpub.
+    pub fn encode_with_version(&self, version: [u8; 4]) -> String {
+        self.to_raw(version).encode()
+    }
+
+    pub fn decode_with_version(s: &str, network: Network, version: [u8; 4]) -> Result<Self, Error> {
+        let raw = RawExtendedKey::decode(s)?;
+        if raw.version != version {
+            return Err(Error::UnknownVersion(raw.version));
+        }
+        Self::from_raw(raw, network)
+    }
+}
+
+impl fmt::Display for ExtendedPubKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let version = self.network.params().xpub_version;
+        write!(f, "{}", self.to_raw(version).encode())
+    }
+}
+
+#[cfg(test)]
+mod bip32_tests {
+    use super::*;
+
+    // Walks `path` from the master key, checking the serialized keys at every step.
+    fn check_chain(seed: &str, steps: &[(Option<u32>, &str, &str)]) {
+        let mut key =
+            ExtendedPrivKey::new_master(&hex::decode(seed).unwrap(), Network::Mainnet).unwrap();
+        for (index, xprv, xpub) in steps {
+            if let Some(index) = index {
+                let parent_xpub = key.to_xpub();
+                key = key.derive_child(*index).unwrap();
+                if *index < HARDENED {
+                    assert_eq!(parent_xpub.derive_child(*index).unwrap(), key.to_xpub());
+                }
+            }
+            assert_eq!(key.to_string(), *xprv);
+            assert_eq!(key.to_xpub().to_string(), *xpub);
+            assert_eq!(
+                ExtendedPrivKey::decode(xprv, Network::Mainnet).unwrap(),
+                key
+            );
+            assert_eq!(
+                ExtendedPubKey::decode(xpub, Network::Mainnet).unwrap(),
+                key.to_xpub()
+            );
+        }
+    }
+
+    #[test]
+    fn test_vector_1() {
+        check_chain(
+            "000102030405060708090a0b0c0d0e0f",
+            &[
+                (
+                    None,
+                    "xprv9s21ZrQH143K3QTDL4LXw2F7HEK3wJUD2nW2nRk4stbPy6cq3jPPqjiChkVvvNKmPGJxWUtg6LnF5kejMRNNU3TGtRBeJgk33yuGBxrMPHi",
+                    "xpub661MyMwAqRbcFtXgS5sYJABqqG9YLmC4Q1Rdap9gSE8NqtwybGhePY2gZ29ESFjqJoCu1Rupje8YtGqsefD265TMg7usUDFdp6W1EGMcet8",
+                ),
+                (
+                    Some(HARDENED),
+                    "xprv9uHRZZhk6KAJC1avXpDAp4MDc3sQKNxDiPvvkX8Br5ngLNv1TxvUxt4cV1rGL5hj6KCesnDYUhd7oWgT11eZG7XnxHrnYeSvkzY7d2bhkJ7",
+                    "xpub68Gmy5EdvgibQVfPdqkBBCHxA5htiqg55crXYuXoQRKfDBFA1WEjWgP6LHhwBZeNK1VTsfTFUHCdrfp1bgwQ9xv5ski8PX9rL2dZXvgGDnw",
+                ),
+                (
+                    Some(1),
+                    "xprv9wTYmMFdV23N2TdNG573QoEsfRrWKQgWeibmLntzniatZvR9BmLnvSxqu53Kw1UmYPxLgboyZQaXwTCg8MSY3H2EU4pWcQDnRnrVA1xe8fs",
+                    "xpub6ASuArnXKPbfEwhqN6e3mwBcDTgzisQN1wXN9BJcM47sSikHjJf3UFHKkNAWbWMiGj7Wf5uMash7SyYq527Hqck2AxYysAA7xmALppuCkwQ",
+                ),
+                (
+                    Some(2 + HARDENED),
+                    "xprv9z4pot5VBttmtdRTWfWQmoH1taj2axGVzFqSb8C9xaxKymcFzXBDptWmT7FwuEzG3ryjH4ktypQSAewRiNMjANTtpgP4mLTj34bhnZX7UiM",
+                    "xpub6D4BDPcP2GT577Vvch3R8wDkScZWzQzMMUm3PWbmWvVJrZwQY4VUNgqFJPMM3No2dFDFGTsxxpG5uJh7n7epu4trkrX7x7DogT5Uv6fcLW5",
+                ),
+                (
+                    Some(2),
+                    "xprvA2JDeKCSNNZky6uBCviVfJSKyQ1mDYahRjijr5idH2WwLsEd4Hsb2Tyh8RfQMuPh7f7RtyzTtdrbdqqsunu5Mm3wDvUAKRHSC34sJ7in334",
+                    "xpub6FHa3pjLCk84BayeJxFW2SP4XRrFd1JYnxeLeU8EqN3vDfZmbqBqaGJAyiLjTAwm6ZLRQUMv1ZACTj37sR62cfN7fe5JnJ7dh8zL4fiyLHV",
+                ),
+                (
+                    Some(1000000000),
+                    "xprvA41z7zogVVwxVSgdKUHDy1SKmdb533PjDz7J6N6mV6uS3ze1ai8FHa8kmHScGpWmj4WggLyQjgPie1rFSruoUihUZREPSL39UNdE3BBDu76",
+                    "xpub6H1LXWLaKsWFhvm6RVpEL9P4KfRZSW7abD2ttkWP3SSQvnyA8FSVqNTEcYFgJS2UaFcxupHiYkro49S8yGasTvXEYBVPamhGW6cFJodrTHy",
+                ),
+            ],
+        );
+    }
+
+    #[test]
+    fn test_vector_2() {
+        check_chain(
+            "fffcf9f6f3f0edeae7e4e1dedbd8d5d2cfccc9c6c3c0bdbab7b4b1aeaba8a5a29f9c999693908d8a8784817e7b7875726f6c696663605d5a5754514e4b484542",
+            &[
+                (
+                    None,
+                    "xprv9s21ZrQH143K31xYSDQpPDxsXRTUcvj2iNHm5NUtrGiGG5e2DtALGdso3pGz6ssrdK4PFmM8NSpSBHNqPqm55Qn3LqFtT2emdEXVYsCzC2U",
+                    "xpub661MyMwAqRbcFW31YEwpkMuc5THy2PSt5bDMsktWQcFF8syAmRUapSCGu8ED9W6oDMSgv6Zz8idoc4a6mr8BDzTJY47LJhkJ8UB7WEGuduB",
+                ),
+                (
+                    Some(0),
+                    "xprv9vHkqa6EV4sPZHYqZznhT2NPtPCjKuDKGY38FBWLvgaDx45zo9WQRUT3dKYnjwih2yJD9mkrocEZXo1ex8G81dwSM1fwqWpWkeS3v86pgKt",
+                    "xpub69H7F5d8KSRgmmdJg2KhpAK8SR3DjMwAdkxj3ZuxV27CprR9LgpeyGmXUbC6wb7ERfvrnKZjXoUmmDznezpbZb7ap6r1D3tgFxHmwMkQTPH",
+                ),
+                (
+                    Some(2147483647 + HARDENED),
+                    "xprv9wSp6B7kry3Vj9m1zSnLvN3xH8RdsPP1Mh7fAaR7aRLcQMKTR2vidYEeEg2mUCTAwCd6vnxVrcjfy2kRgVsFawNzmjuHc2YmYRmagcEPdU9",
+                    "xpub6ASAVgeehLbnwdqV6UKMHVzgqAG8Gr6riv3Fxxpj8ksbH9ebxaEyBLZ85ySDhKiLDBrQSARLq1uNRts8RuJiHjaDMBU4Zn9h8LZNnBC5y4a",
+                ),
+                (
+                    Some(1),
+                    "xprv9zFnWC6h2cLgpmSA46vutJzBcfJ8yaJGg8cX1e5StJh45BBciYTRXSd25UEPVuesF9yog62tGAQtHjXajPPdbRCHuWS6T8XA2ECKADdw4Ef",
+                    "xpub6DF8uhdarytz3FWdA8TvFSvvAh8dP3283MY7p2V4SeE2wyWmG5mg5EwVvmdMVCQcoNJxGoWaU9DCWh89LojfZ537wTfunKau47EL2dhHKon",
+                ),
+                (
+                    Some(2147483646 + HARDENED),
+                    "xprvA1RpRA33e1JQ7ifknakTFpgNXPmW2YvmhqLQYMmrj4xJXXWYpDPS3xz7iAxn8L39njGVyuoseXzU6rcxFLJ8HFsTjSyQbLYnMpCqE2VbFWc",
+                    "xpub6ERApfZwUNrhLCkDtcHTcxd75RbzS1ed54G1LkBUHQVHQKqhMkhgbmJbZRkrgZw4koxb5JaHWkY4ALHY2grBGRjaDMzQLcgJvLJuZZvRcEL",
+                ),
+                (
+                    Some(2),
+                    "xprvA2nrNbFZABcdryreWet9Ea4LvTJcGsqrMzxHx98MMrotbir7yrKCEXw7nadnHM8Dq38EGfSh6dqA9QWTyefMLEcBYJUuekgW4BYPJcr9E7j",
+                    "xpub6FnCn6nSzZAw5Tw7cgR9bi15UV96gLZhjDstkXXxvCLsUXBGXPdSnLFbdpq8p9HmGsApME5hQTZ3emM2rnY5agb9rXpVGyy3bdW6EEgAtqt",
+                ),
+            ],
+        );
+    }
+
+    #[test]
+    fn test_vector_3() {
+        // Retention of leading zeros in the private key.
+        check_chain(
+            "4b381541583be4423346c643850da4b320e46a87ae3d2a4e6da11eba819cd4acba45d239319ac14f863b8d5ab5a0d0c64d2e8a1e7d1457df2e5a3c51c73235be",
+            &[
+                (
+                    None,
+                    "xprv9s21ZrQH143K25QhxbucbDDuQ4naNntJRi4KUfWT7xo4EKsHt2QJDu7KXp1A3u7Bi1j8ph3EGsZ9Xvz9dGuVrtHHs7pXeTzjuxBrCmmhgC6",
+                    "xpub661MyMwAqRbcEZVB4dScxMAdx6d4nFc9nvyvH3v4gJL378CSRZiYmhRoP7mBy6gSPSCYk6SzXPTf3ND1cZAceL7SfJ1Z3GC8vBgp2epUt13",
+                ),
+                (
+                    Some(HARDENED),
+                    "xprv9uPDJpEQgRQfDcW7BkF7eTya6RPxXeJCqCJGHuCJ4GiRVLzkTXBAJMu2qaMWPrS7AANYqdq6vcBcBUdJCVVFceUvJFjaPdGZ2y9WACViL4L",
+                    "xpub68NZiKmJWnxxS6aaHmn81bvJeTESw724CRDs6HbuccFQN9Ku14VQrADWgqbhhTHBaohPX4CjNLf9fq9MYo6oDaPPLPxSb7gwQN3ih19Zm4Y",
+                ),
+            ],
+        );
+    }
+
+    #[test]
+    fn test_vector_4() {
+        // Retention of leading zeros in the hardened derivation data.
+        check_chain(
+            "3ddd5602285899a946114506157c7997e5444528f3003f6134712147db19b678",
+            &[
+                (
+                    None,
+                    "xprv9s21ZrQH143K48vGoLGRPxgo2JNkJ3J3fqkirQC2zVdk5Dgd5w14S7fRDyHH4dWNHUgkvsvNDCkvAwcSHNAQwhwgNMgZhLtQC63zxwhQmRv",
+                    "xpub661MyMwAqRbcGczjuMoRm6dXaLDEhW1u34gKenbeYqAix21mdUKJyuyu5F1rzYGVxyL6tmgBUAEPrEz92mBXjByMRiJdba9wpnN37RLLAXa",
+                ),
+                (
+                    Some(HARDENED),
+                    "xprv9vB7xEWwNp9kh1wQRfCCQMnZUEG21LpbR9NPCNN1dwhiZkjjeGRnaALmPXCX7SgjFTiCTT6bXes17boXtjq3xLpcDjzEuGLQBM5ohqkao9G",
+                    "xpub69AUMk3qDBi3uW1sXgjCmVjJ2G6WQoYSnNHyzkmdCHEhSZ4tBok37xfFEqHd2AddP56Tqp4o56AePAgCjYdvpW2PU2jbUPFKsav5ut6Ch1m",
+                ),
+                (
+                    Some(1 + HARDENED),
+                    "xprv9xJocDuwtYCMNAo3Zw76WENQeAS6WGXQ55RCy7tDJ8oALr4FWkuVoHJeHVAcAqiZLE7Je3vZJHxspZdFHfnBEjHqU5hG1Jaj32dVoS6XLT1",
+                    "xpub6BJA1jSqiukeaesWfxe6sNK9CCGaujFFSJLomWHprUL9DePQ4JDkM5d88n49sMGJxrhpjazuXYWdMf17C9T5XnxkopaeS7jGk1GyyVziaMt",
+                ),
+            ],
+        );
+    }
+
+    #[test]
+    fn test_vector_5() {
+        let invalid_xpubs = [
+            // pubkey version / prvkey mismatch
+            "xpub661MyMwAqRbcEYS8w7XLSVeEsBXy79zSzH1J8vCdxAZningWLdN3zgtU6LBpB85b3D2yc8sfvZU521AAwdZafEz7mnzBBsz4wKY5fTtTQBm",
+            // invalid pubkey prefix 04
+            "xpub661MyMwAqRbcEYS8w7XLSVeEsBXy79zSzH1J8vCdxAZningWLdN3zgtU6Txnt3siSujt9RCVYsx4qHZGc62TG4McvMGcAUjeuwZdduYEvFn",
+            // invalid pubkey prefix 01
+            "xpub661MyMwAqRbcEYS8w7XLSVeEsBXy79zSzH1J8vCdxAZningWLdN3zgtU6N8ZMMXctdiCjxTNq964yKkwrkBJJwpzZS4HS2fxvyYUA4q2Xe4",
+            // zero depth with non-zero parent fingerprint
+            "xpub661no6RGEX3uJkY4bNnPcw4URcQTrSibUZ4NqJEw5eBkv7ovTwgiT91XX27VbEXGENhYRCf7hyEbWrR3FewATdCEebj6znwMfQkhRYHRLpJ",
+            // zero depth with non-zero index
+            "xpub661MyMwAuDcm6CRQ5N4qiHKrJ39Xe1R1NyfouMKTTWcguwVcfrZJaNvhpebzGerh7gucBvzEQWRugZDuDXjNDRmXzSZe4c7mnTK97pTvGS8",
+            // unknown extended key version
+            "DMwo58pR1QLEFihHiXPVykYB6fJmsTeHvyTp7hRThAtCX8CvYzgPcn8XnmdfHPmHJiEDXkTiJTVV9rHEBUem2mwVbbNfvT2MTcAqj3nesx8uBf9",
+            // invalid pubkey 020000000000000000000000000000000000000000000000000000000000000007
+            "xpub661MyMwAqRbcEYS8w7XLSVeEsBXy79zSzH1J8vCdxAZningWLdN3zgtU6Q5JXayek4PRsn35jii4veMimro1xefsM58PgBMrvdYre8QyULY",
+        ];
+        for xpub in invalid_xpubs {
+            assert!(ExtendedPubKey::decode(xpub, Network::Mainnet).is_err());
+        }
+        let invalid_xprvs = [
+            // prvkey version / pubkey mismatch
+            "xprv9s21ZrQH143K24Mfq5zL5MhWK9hUhhGbd45hLXo2Pq2oqzMMo63oStZzFGTQQD3dC4H2D5GBj7vWvSQaaBv5cxi9gafk7NF3pnBju6dwKvH",
+            // invalid prvkey prefix 04
+            "xprv9s21ZrQH143K24Mfq5zL5MhWK9hUhhGbd45hLXo2Pq2oqzMMo63oStZzFGpWnsj83BHtEy5Zt8CcDr1UiRXuWCmTQLxEK9vbz5gPstX92JQ",
+            // invalid prvkey prefix 01
+            "xprv9s21ZrQH143K24Mfq5zL5MhWK9hUhhGbd45hLXo2Pq2oqzMMo63oStZzFAzHGBP2UuGCqWLTAPLcMtD9y5gkZ6Eq3Rjuahrv17fEQ3Qen6J",
+            // zero depth with non-zero parent fingerprint
+            "xprv9s2SPatNQ9Vc6GTbVMFPFo7jsaZySyzk7L8n2uqKXJen3KUmvQNTuLh3fhZMBoG3G4ZW1N2kZuHEPY53qmbZzCHshoQnNf4GvELZfqTUrcv",
+            // zero depth with non-zero index
+            "xprv9s21ZrQH4r4TsiLvyLXqM9P7k1K3EYhA1kkD6xuquB5i39AU8KF42acDyL3qsDbU9NmZn6MsGSUYZEsuoePmjzsB3eFKSUEh3Gu1N3cqVUN",
+            // unknown extended key version
+            "DMwo58pR1QLEFihHiXPVykYB6fJmsTeHvyTp7hRThAtCX8CvYzgPcn8XnmdfHGMQzT7ayAmfo4z3gY5KfbrZWZ6St24UVf2Qgo6oujFktLHdHY4",
+            // private key 0 not in 1..n-1
+            "xprv9s21ZrQH143K24Mfq5zL5MhWK9hUhhGbd45hLXo2Pq2oqzMMo63oStZzF93Y5wvzdUayhgkkFoicQZcP3y52uPPxFnfoLZB21Teqt1VvEHx",
+            // private key n not in 1..n-1
+            "xprv9s21ZrQH143K24Mfq5zL5MhWK9hUhhGbd45hLXo2Pq2oqzMMo63oStZzFAzHGBP2UuGCqWLTAPLcMtD9y5gkZ6Eq3Rjuahrv17fENZ3QzxW",
+            // invalid checksum
+            "xprv9s21ZrQH143K3QTDL4LXw2F7HEK3wJUD2nW2nRk4stbPy6cq3jPPqjiChkVvvNKmPGJxWUtg6LnF5kejMRNNU3TGtRBeJgk33yuGBxrMPHL",
+        ];
+        for xprv in invalid_xprvs {
+            assert!(ExtendedPrivKey::decode(xprv, Network::Mainnet).is_err());
+        }
+    }
+
+    #[test]
+    fn derive_path_matches_test_vector_1() {
+        let master = ExtendedPrivKey::new_master(
+            &hex::decode("000102030405060708090a0b0c0d0e0f").unwrap(),
+            Network::Mainnet,
+        )
+        .unwrap();
+        let key = master
+            .derive_path(&"m/0h/1/2h/2/1000000000".parse().unwrap())
+            .unwrap();
+        assert_eq!(
+            key.to_xpub().to_string(),
+            "xpub6H1LXWLaKsWFhvm6RVpEL9P4KfRZSW7abD2ttkWP3SSQvnyA8FSVqNTEcYFgJS2UaFcxupHiYkro49S8yGasTvXEYBVPamhGW6cFJodrTHy"
+        );
+        let xpub = master
+            .derive_path(&"m/0h/1/2h".parse().unwrap())
+            .unwrap()
+            .to_xpub();
+        assert_eq!(
+            xpub.derive_path(&"m/2/1000000000".parse().unwrap())
+                .unwrap(),
+            key.to_xpub()
+        );
+    }
+
+    #[test]
+    fn hardened_child_from_xpub_fails() {
+        let master = ExtendedPrivKey::new_master(&[7u8; 32], Network::Regtest).unwrap();
+        assert_eq!(
+            master.to_xpub().derive_child(HARDENED),
+            Err(Error::HardenedDerivationFromPublicKey)
+        );
+    }
+
+    #[test]
+    fn fingerprint_links_parent_and_child() {
+        let master = ExtendedPrivKey::new_master(&[7u8; 32], Network::Testnet).unwrap();
+        let child = master.derive_child(HARDENED + 44).unwrap();
+        assert_eq!(child.parent_fingerprint(), master.fingerprint());
+        assert_eq!(master.fingerprint(), master.identifier()[..4]);
+        assert!(master.to_string().starts_with("tprv"));
+        assert_eq!(
+            ExtendedPrivKey::decode(&master.to_string(), Network::Mainnet),
+            Err(Error::WrongNetwork)
+        );
+    }
+}
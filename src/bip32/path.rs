@@ -0,0 +1,139 @@
+use std::fmt;
+use std::str::FromStr;
+
+use super::HARDENED;
+use crate::error::Error;
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct DerivationPath(Vec<u32>);
+
+impl DerivationPath {
+    pub fn new(indexes: Vec<u32>) -> Self {
+        DerivationPath(indexes)
+    }
+
+    pub fn master() -> Self {
+        DerivationPath(Vec::new())
+    }
+
+    pub fn indexes(&self) -> &[u32] {
+        &self.0
+    }
+
+    pub fn child(&self, index: u32) -> Self {
+        let mut indexes = self.0.clone();
+        indexes.push(index);
+        DerivationPath(indexes)
+    }
+
+    pub fn extend(&self, other: &DerivationPath) -> Self {
+        let mut indexes = self.0.clone();
+        indexes.extend_from_slice(&other.0);
+        DerivationPath(indexes)
+    }
+}
+
+fn parse_index(component: &str) -> Result<u32, Error> {
+    let invalid = || Error::InvalidDerivationPath(component.to_string());
+    let (number, hardened) = match component.strip_suffix(['\'', 'h', 'H']) {
+        Some(number) => (number, true),
+        None => (component, false),
+    };
+    if number.is_empty() || !number.bytes().all(|b| b.is_ascii_digit()) {
+        return Err(invalid());
+    }
+    let index: u32 = number.parse().map_err(|_| invalid())?;
+    if index >= HARDENED {
+        return Err(invalid());
+    }
+    Ok(if hardened { index + HARDENED } else { index })
+}
+
+impl FromStr for DerivationPath {
+    type Err = Error;
+
+    // Accepts "m", "m/84'/0'/0'/0/5" and the equivalent "m/84h/0h/0h/0/5".
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut components = s.split('/');
+        if components.next() != Some("m") {
+            return Err(Error::InvalidDerivationPath(s.to_string()));
+        }
+        let indexes = components
+            .filter(|component| !component.is_empty() || s != "m/")
+            .map(parse_index)
+            .collect::<Result<Vec<u32>, Error>>()?;
+        Ok(DerivationPath(indexes))
+    }
+}
+
+impl fmt::Display for DerivationPath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "m")?;
+        for index in &self.0 {
+            if *index >= HARDENED {
+                write!(f, "/{}'", index - HARDENED)?;
+            } else {
+                write!(f, "/{}", index)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod path_tests {
+    use super::*;
+
+    #[test]
+    fn parses_both_hardened_notations() {
+        let expected = DerivationPath::new(vec![84 + HARDENED, HARDENED, HARDENED, 0, 5]);
+        assert_eq!(
+            "m/84'/0'/0'/0/5".parse::<DerivationPath>().unwrap(),
+            expected
+        );
+        assert_eq!(
+            "m/84h/0h/0H/0/5".parse::<DerivationPath>().unwrap(),
+            expected
+        );
+        assert_eq!(expected.to_string(), "m/84'/0'/0'/0/5");
+    }
+
+    #[test]
+    fn master_path() {
+        assert_eq!(
+            "m".parse::<DerivationPath>().unwrap(),
+            DerivationPath::master()
+        );
+        assert_eq!(
+            "m/".parse::<DerivationPath>().unwrap(),
+            DerivationPath::master()
+        );
+        assert_eq!(DerivationPath::master().to_string(), "m");
+    }
+
+    #[test]
+    fn rejects_malformed_paths() {
+        for path in [
+            "",
+            "84'/0'",
+            "m/84''",
+            "m//1",
+            "m/-1",
+            "m/2147483648",
+            "m/a",
+            "m/1/",
+        ] {
+            assert!(path.parse::<DerivationPath>().is_err(), "{}", path);
+        }
+    }
+
+    #[test]
+    fn child_and_extend() {
+        let account: DerivationPath = "m/44'/1'/0'".parse().unwrap();
+        let change: DerivationPath = "m/1".parse().unwrap();
+        assert_eq!(
+            account.extend(&change).child(7).to_string(),
+            "m/44'/1'/0'/1/7"
+        );
+    }
+}
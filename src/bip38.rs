@@ -0,0 +1,532 @@
+use aes::cipher::generic_array::GenericArray;
+use aes::cipher::{BlockDecrypt, BlockEncrypt, KeyInit};
+use aes::Aes256;
+use num::{BigUint, Zero};
+use rand::RngCore;
+use unicode_normalization::UnicodeNormalization;
+
+use crate::base58::{decode_base58_checksum, encode_base58_checksum};
+use crate::error::Error;
+use crate::hash::hash256;
+use crate::network::Network;
+use crate::secp256k1::point::{generator_point, n, Secp256k1Point};
+use crate::secp256k1::private_key::PrivateKey;
+
+const NON_EC_MULTIPLY_PREFIX: [u8; 2] = [0x01, 0x42];
+const EC_MULTIPLY_PREFIX: [u8; 2] = [0x01, 0x43];
+// Followed by 0x51 when a lot and sequence number are present, 0x53 otherwise.
+const INTERMEDIATE_CODE_MAGIC: [u8; 7] = [0x2c, 0xe9, 0xb3, 0xe1, 0xff, 0x39, 0xe2];
+const CONFIRMATION_CODE_MAGIC: [u8; 5] = [0x64, 0x3b, 0xf6, 0xa8, 0x9a];
+
+const FLAG_NON_EC_MULTIPLY: u8 = 0xc0;
+const FLAG_COMPRESSED: u8 = 0x20;
+const FLAG_LOT_SEQUENCE: u8 = 0x04;
+
+const MAX_LOT: u32 = 0xfffff;
+const MAX_SEQUENCE: u32 = 0xfff;
+
+// What the party holding an intermediate code hands back to its owner.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GeneratedKey {
+    pub encrypted_key: String,
+    pub confirmation_code: String,
+    pub address: String,
+}
+
+// Encrypts a key the owner already has. The address hash salts scrypt, so the
+// same key encrypts differently depending on whether its pubkey is compressed.
+pub fn encrypt(key: &PrivateKey, compressed: bool, passphrase: &str) -> String {
+    let address_hash = address_hash(key.point(), compressed);
+    let derived: [u8; 64] = scrypt(normalize(passphrase).as_bytes(), &address_hash, 14, 8, 8);
+    let (half1, half2) = derived.split_at(32);
+    let secret = key.to_bytes();
+
+    let mut payload = NON_EC_MULTIPLY_PREFIX.to_vec();
+    payload.push(FLAG_NON_EC_MULTIPLY | compressed_flag(compressed));
+    payload.extend_from_slice(&address_hash);
+    payload.extend_from_slice(&encrypt_block(half2, &secret[..16], &half1[..16]));
+    payload.extend_from_slice(&encrypt_block(half2, &secret[16..], &half1[16..]));
+    encode_base58_checksum(&payload)
+}
+
+// Decrypts either kind of "6P..." key. Returns the key and whether its pubkey is compressed.
+pub fn decrypt(encrypted_key: &str, passphrase: &str) -> Result<(PrivateKey, bool), Error> {
+    let payload = decode_base58_checksum(encrypted_key)?;
+    if payload.len() != 39 {
+        return Err(Error::InvalidLength(payload.len()));
+    }
+    let flag = payload[2];
+    let compressed = flag & FLAG_COMPRESSED != 0;
+    let address_hash: [u8; 4] = payload[3..7].try_into().unwrap();
+    let passphrase = normalize(passphrase);
+
+    let key = match [payload[0], payload[1]] {
+        NON_EC_MULTIPLY_PREFIX => {
+            if flag & !FLAG_COMPRESSED != FLAG_NON_EC_MULTIPLY {
+                return Err(Error::InvalidBip38(format!(
+                    "unknown flag byte {:#04x}",
+                    flag
+                )));
+            }
+            let derived: [u8; 64] = scrypt(passphrase.as_bytes(), &address_hash, 14, 8, 8);
+            let (half1, half2) = derived.split_at(32);
+            let mut secret = decrypt_block(half2, &payload[7..23], &half1[..16]).to_vec();
+            secret.extend_from_slice(&decrypt_block(half2, &payload[23..39], &half1[16..]));
+            PrivateKey::from_bytes(&secret).map_err(|_| Error::WrongPassphrase)?
+        }
+        EC_MULTIPLY_PREFIX => {
+            if flag & !(FLAG_COMPRESSED | FLAG_LOT_SEQUENCE) != 0 {
+                return Err(Error::InvalidBip38(format!(
+                    "unknown flag byte {:#04x}",
+                    flag
+                )));
+            }
+            let owner_entropy: [u8; 8] = payload[7..15].try_into().unwrap();
+            let lot_sequence = flag & FLAG_LOT_SEQUENCE != 0;
+            let pass_factor = scalar(&pass_factor(&passphrase, &owner_entropy, lot_sequence))?;
+            let pass_point = generator_point().multiply_by(&mut pass_factor.clone());
+            let derived = ec_multiply_key(&pass_point, &address_hash, &owner_entropy);
+            let (half1, half2) = derived.split_at(32);
+            // encryptedpart2 decrypts to encryptedpart1[8..16] || seedb[16..24].
+            let part2 = decrypt_block(half2, &payload[23..39], &half1[16..]);
+            let part1 = [&payload[15..23], &part2[..8]].concat();
+            let mut seed_b = decrypt_block(half2, &part1, &half1[..16]).to_vec();
+            seed_b.extend_from_slice(&part2[8..]);
+            let factor_b = scalar(&hash256(&seed_b))?;
+            PrivateKey::new(pass_factor * factor_b % n())
+        }
+        prefix => {
+            return Err(Error::InvalidBip38(format!(
+                "unknown prefix {}",
+                hex::encode(prefix)
+            )))
+        }
+    };
+
+    if self::address_hash(key.point(), compressed) != address_hash {
+        return Err(Error::WrongPassphrase);
+    }
+    Ok((key, compressed))
+}
+
+// The owner's side of EC multiply: an 8 byte salt, or a 4 byte salt when a lot
+// (up to 1048575) and sequence number (up to 4095) are given.
+pub fn intermediate_code(
+    passphrase: &str,
+    owner_salt: &[u8],
+    lot_sequence: Option<(u32, u32)>,
+) -> Result<String, Error> {
+    let mut owner_entropy = [0u8; 8];
+    match lot_sequence {
+        None if owner_salt.len() == 8 => owner_entropy.copy_from_slice(owner_salt),
+        Some((lot, sequence)) if owner_salt.len() == 4 => {
+            if lot > MAX_LOT || sequence > MAX_SEQUENCE {
+                return Err(Error::InvalidBip38(format!(
+                    "lot {} or sequence {} out of range",
+                    lot, sequence
+                )));
+            }
+            owner_entropy[..4].copy_from_slice(owner_salt);
+            owner_entropy[4..].copy_from_slice(&(lot * 4096 + sequence).to_be_bytes());
+        }
+        _ => return Err(Error::InvalidLength(owner_salt.len())),
+    }
+    let pass_factor = pass_factor(
+        &normalize(passphrase),
+        &owner_entropy,
+        lot_sequence.is_some(),
+    );
+    let pass_point = generator_point().multiply_by(&mut scalar(&pass_factor)?);
+
+    let mut payload = INTERMEDIATE_CODE_MAGIC.to_vec();
+    payload.push(if lot_sequence.is_some() { 0x51 } else { 0x53 });
+    payload.extend_from_slice(&owner_entropy);
+    payload.extend_from_slice(&pass_point.sec(true));
+    Ok(encode_base58_checksum(&payload))
+}
+
+pub fn generate_intermediate_code(
+    passphrase: &str,
+    lot_sequence: Option<(u32, u32)>,
+) -> Result<String, Error> {
+    let mut owner_salt = vec![0u8; if lot_sequence.is_some() { 4 } else { 8 }];
+    rand::thread_rng().fill_bytes(&mut owner_salt);
+    intermediate_code(passphrase, &owner_salt, lot_sequence)
+}
+
+// The generating party's side of EC multiply: it learns the address and the
+// encrypted key, but never the private key or the passphrase.
+pub fn encrypt_from_intermediate(
+    intermediate_code: &str,
+    seed_b: &[u8; 24],
+    compressed: bool,
+) -> Result<GeneratedKey, Error> {
+    let (owner_entropy, lot_sequence, pass_point) = parse_intermediate_code(intermediate_code)?;
+    let factor_b = scalar(&hash256(seed_b))?;
+    let generated = pass_point.clone().multiply_by(&mut factor_b.clone());
+    let address = generated.address(compressed, Network::Mainnet);
+    let address_hash = self::address_hash(&generated, compressed);
+    let derived = ec_multiply_key(&pass_point, &address_hash, &owner_entropy);
+    let (half1, half2) = derived.split_at(32);
+    let flag = compressed_flag(compressed) | if lot_sequence { FLAG_LOT_SEQUENCE } else { 0 };
+
+    let part1 = encrypt_block(half2, &seed_b[..16], &half1[..16]);
+    let part2 = encrypt_block(half2, &[&part1[8..], &seed_b[16..]].concat(), &half1[16..]);
+    let mut payload = EC_MULTIPLY_PREFIX.to_vec();
+    payload.push(flag);
+    payload.extend_from_slice(&address_hash);
+    payload.extend_from_slice(&owner_entropy);
+    payload.extend_from_slice(&part1[..8]);
+    payload.extend_from_slice(&part2);
+
+    // The confirmation code carries pointb = factorb * G, encrypted like seedb.
+    let point_b = generator_point()
+        .multiply_by(&mut factor_b.clone())
+        .sec(true);
+    let mut confirmation = CONFIRMATION_CODE_MAGIC.to_vec();
+    confirmation.push(flag);
+    confirmation.extend_from_slice(&address_hash);
+    confirmation.extend_from_slice(&owner_entropy);
+    confirmation.push(point_b[0] ^ (half2[31] & 1));
+    confirmation.extend_from_slice(&encrypt_block(half2, &point_b[1..17], &half1[..16]));
+    confirmation.extend_from_slice(&encrypt_block(half2, &point_b[17..], &half1[16..]));
+
+    Ok(GeneratedKey {
+        encrypted_key: encode_base58_checksum(&payload),
+        confirmation_code: encode_base58_checksum(&confirmation),
+        address,
+    })
+}
+
+pub fn generate_encrypted_key(
+    intermediate_code: &str,
+    compressed: bool,
+) -> Result<GeneratedKey, Error> {
+    let mut seed_b = [0u8; 24];
+    rand::thread_rng().fill_bytes(&mut seed_b);
+    encrypt_from_intermediate(intermediate_code, &seed_b, compressed)
+}
+
+// Lets the owner check that a generated key really belongs to their passphrase,
+// without the encrypted key itself. Returns the confirmed address.
+pub fn verify_confirmation_code(
+    confirmation_code: &str,
+    passphrase: &str,
+) -> Result<String, Error> {
+    let payload = decode_base58_checksum(confirmation_code)?;
+    if payload.len() != 51 {
+        return Err(Error::InvalidLength(payload.len()));
+    }
+    if payload[..5] != CONFIRMATION_CODE_MAGIC {
+        return Err(Error::InvalidBip38("not a confirmation code".to_string()));
+    }
+    let flag = payload[5];
+    let address_hash: [u8; 4] = payload[6..10].try_into().unwrap();
+    let owner_entropy: [u8; 8] = payload[10..18].try_into().unwrap();
+    let lot_sequence = flag & FLAG_LOT_SEQUENCE != 0;
+
+    let pass_factor = scalar(&pass_factor(
+        &normalize(passphrase),
+        &owner_entropy,
+        lot_sequence,
+    ))?;
+    let pass_point = generator_point().multiply_by(&mut pass_factor.clone());
+    let derived = ec_multiply_key(&pass_point, &address_hash, &owner_entropy);
+    let (half1, half2) = derived.split_at(32);
+    let mut point_b = vec![payload[18] ^ (half2[31] & 1)];
+    point_b.extend_from_slice(&decrypt_block(half2, &payload[19..35], &half1[..16]));
+    point_b.extend_from_slice(&decrypt_block(half2, &payload[35..51], &half1[16..]));
+    // A wrong passphrase almost always decrypts to something that is not a point.
+    let point_b = Secp256k1Point::parse_sec(&point_b).map_err(|_| Error::WrongPassphrase)?;
+
+    let generated = point_b.multiply_by(&mut pass_factor.clone());
+    let compressed = flag & FLAG_COMPRESSED != 0;
+    if self::address_hash(&generated, compressed) != address_hash {
+        return Err(Error::WrongPassphrase);
+    }
+    Ok(generated.address(compressed, Network::Mainnet))
+}
+
+fn parse_intermediate_code(code: &str) -> Result<([u8; 8], bool, Secp256k1Point), Error> {
+    let payload = decode_base58_checksum(code)?;
+    if payload.len() != 49 {
+        return Err(Error::InvalidLength(payload.len()));
+    }
+    if payload[..7] != INTERMEDIATE_CODE_MAGIC || ![0x51, 0x53].contains(&payload[7]) {
+        return Err(Error::InvalidBip38("not an intermediate code".to_string()));
+    }
+    let owner_entropy = payload[8..16].try_into().unwrap();
+    let pass_point = Secp256k1Point::parse_sec(&payload[16..])?;
+    Ok((owner_entropy, payload[7] == 0x51, pass_point))
+}
+
+// Passphrases are NFC normalized before they reach scrypt.
+fn normalize(passphrase: &str) -> String {
+    passphrase.nfc().collect()
+}
+
+fn compressed_flag(compressed: bool) -> u8 {
+    if compressed {
+        FLAG_COMPRESSED
+    } else {
+        0
+    }
+}
+
+// The BIP is defined over mainnet P2PKH addresses, whatever network the key is used on.
+fn address_hash(point: &Secp256k1Point, compressed: bool) -> [u8; 4] {
+    let address = point.address(compressed, Network::Mainnet);
+    hash256(address.as_bytes())[..4].try_into().unwrap()
+}
+
+// With a lot and sequence number only the first four bytes of the owner
+// entropy salt scrypt, and the whole of it is hashed in afterwards.
+fn pass_factor(passphrase: &str, owner_entropy: &[u8; 8], lot_sequence: bool) -> [u8; 32] {
+    if lot_sequence {
+        let prefactor: [u8; 32] = scrypt(passphrase.as_bytes(), &owner_entropy[..4], 14, 8, 8);
+        hash256(&[&prefactor[..], &owner_entropy[..]].concat())
+    } else {
+        scrypt(passphrase.as_bytes(), owner_entropy, 14, 8, 8)
+    }
+}
+
+fn ec_multiply_key(
+    pass_point: &Secp256k1Point,
+    address_hash: &[u8; 4],
+    owner_entropy: &[u8; 8],
+) -> [u8; 64] {
+    let salt = [&address_hash[..], &owner_entropy[..]].concat();
+    scrypt(&pass_point.sec(true), &salt, 10, 1, 1)
+}
+
+fn scalar(bytes: &[u8]) -> Result<BigUint, Error> {
+    let scalar = BigUint::from_bytes_be(bytes);
+    if scalar.is_zero() || scalar >= n() {
+        return Err(Error::SecretOutOfRange);
+    }
+    Ok(scalar)
+}
+
+fn scrypt<const LEN: usize>(password: &[u8], salt: &[u8], log_n: u8, r: u32, p: u32) -> [u8; LEN] {
+    let params = scrypt::Params::new(log_n, r, p, LEN).unwrap();
+    let mut output = [0u8; LEN];
+    scrypt::scrypt(password, salt, &params, &mut output).unwrap();
+    output
+}
+
+// Each 16 byte half is xored with the matching half of derivedhalf1, then
+// AES-256 encrypted under derivedhalf2.
+fn encrypt_block(key: &[u8], block: &[u8], mask: &[u8]) -> [u8; 16] {
+    let mut block: [u8; 16] = std::array::from_fn(|i| block[i] ^ mask[i]);
+    Aes256::new(GenericArray::from_slice(key))
+        .encrypt_block(GenericArray::from_mut_slice(&mut block));
+    block
+}
+
+fn decrypt_block(key: &[u8], block: &[u8], mask: &[u8]) -> [u8; 16] {
+    let mut block: [u8; 16] = block.try_into().unwrap();
+    Aes256::new(GenericArray::from_slice(key))
+        .decrypt_block(GenericArray::from_mut_slice(&mut block));
+    std::array::from_fn(|i| block[i] ^ mask[i])
+}
+
+#[cfg(test)]
+mod bip38_tests {
+    use super::*;
+
+    // (passphrase, encrypted key, WIF) from the "No compression, no EC multiply"
+    // and "Compression, no EC multiply" sections of BIP38.
+    const NON_EC_MULTIPLY_VECTORS: [(&str, &str, &str); 5] = [
+        (
+            "TestingOneTwoThree",
+            "6PRVWUbkzzsbcVac2qwfssoUJAN1Xhrg6bNk8J7Nzm5H7kxEbn2Nh2ZoGg",
+            "5KN7MzqK5wt2TP1fQCYyHBtDrXdJuXbUzm4A9rKAteGu3Qi5CVR",
+        ),
+        (
+            "Satoshi",
+            "6PRNFFkZc2NZ6dJqFfhRoFNMR9Lnyj7dYGrzdgXXVMXcxoKTePPX1dWByq",
+            "5HtasZ6ofTHP6HCwTqTkLDuLQisYPah7aUnSKfC7h4hMUVw2gi5",
+        ),
+        (
+            // GREEK UPSILON WITH HOOK, COMBINING ACUTE ACCENT, NULL, DESERET CAPITAL
+            // LETTER LONG I, PILE OF POO; NFC folds the first two together.
+            "\u{03d2}\u{0301}\u{0000}\u{10400}\u{1f4a9}",
+            "6PRW5o9FLp4gJDDVqJQKJFTpMvdsSGJxMYHtHaQBF3ooa8mwD69bapcDQn",
+            "5Jajm8eQ22H3pGWLEVCXyvND8dQZhiQhoLJNKjYXk9roUFTMSZ4",
+        ),
+        (
+            "TestingOneTwoThree",
+            "6PYNKZ1EAgYgmQfmNVamxyXVWHzK5s6DGhwP4J5o44cvXdoY7sRzhtpUeo",
+            "L44B5gGEpqEDRS9vVPz7QT35jcBG2r3CZwSwQ4fCewXAhAhqGVpP",
+        ),
+        (
+            "Satoshi",
+            "6PYLtMnXvfG3oJde97zRyLYFZCYizPU5T3LwgdYJz1fRhh16bU7u6PPmY7",
+            "KwYgW8gcxj1JWJXhPSu4Fqwzfhp5Yfi42mdYmMa4XqK7NJxXUSK7",
+        ),
+    ];
+
+    struct EcMultiplyVector {
+        passphrase: &'static str,
+        owner_salt: &'static str,
+        lot_sequence: Option<(u32, u32)>,
+        intermediate_code: &'static str,
+        encrypted_key: &'static str,
+        address: &'static str,
+        wif: &'static str,
+    }
+
+    // The EC multiply sections of BIP38. The owner salts are not listed there but are
+    // the leading bytes of the owner entropy carried in each encrypted key.
+    const EC_MULTIPLY_VECTORS: [EcMultiplyVector; 4] = [
+        EcMultiplyVector {
+            passphrase: "TestingOneTwoThree",
+            owner_salt: "a50dba6772cb9383",
+            lot_sequence: None,
+            intermediate_code:
+                "passphrasepxFy57B9v8HtUsszJYKReoNDV6VHjUSGt8EVJmux9n1J3Ltf1gRxyDGXqnf9qm",
+            encrypted_key: "6PfQu77ygVyJLZjfvMLyhLMQbYnu5uguoJJ4kMCLqWwPEdfpwANVS76gTX",
+            address: "1PE6TQi6HTVNz5DLwB1LcpMBALubfuN2z2",
+            wif: "5K4caxezwjGCGfnoPTZ8tMcJBLB7Jvyjv4xxeacadhq8nLisLR2",
+        },
+        EcMultiplyVector {
+            passphrase: "Satoshi",
+            owner_salt: "67010a9573418906",
+            lot_sequence: None,
+            intermediate_code:
+                "passphraseoRDGAXTWzbp72eVbtUDdn1rwpgPUGjNZEc6CGBo8i5EC1FPW8wcnLdq4ThKzAS",
+            encrypted_key: "6PfLGnQs6VZnrNpmVKfjotbnQuaJK4KZoPFrAjx1JMJUa1Ft8gnf5WxfKd",
+            address: "1CqzrtZC6mXSAhoxtFwVjz8LtwLJjDYU3V",
+            wif: "5KJ51SgxWaAYR13zd9ReMhJpwrcX47xTJh2D3fGPG9CM8vkv5sH",
+        },
+        EcMultiplyVector {
+            passphrase: "MOLON LABE",
+            owner_salt: "4fca5a97",
+            lot_sequence: Some((263183, 1)),
+            intermediate_code:
+                "passphraseaB8feaLQDENqCgr4gKZpmf4VoaT6qdjJNJiv7fsKvjqavcJxvuR1hy25aTu5sX",
+            encrypted_key: "6PgNBNNzDkKdhkT6uJntUXwwzQV8Rr2tZcbkDcuC9DZRsS6AtHts4Ypo1j",
+            address: "1Jscj8ALrYu2y9TD8NrpvDBugPedmbj4Yh",
+            wif: "5JLdxTtcTHcfYcmJsNVy1v2PMDx432JPoYcBTVVRHpPaxUrdtf8",
+        },
+        EcMultiplyVector {
+            passphrase: "\u{039c}\u{039f}\u{039b}\u{03a9}\u{039d} \u{039b}\u{0391}\u{0392}\u{0395}",
+            owner_salt: "c40ea76f",
+            lot_sequence: Some((806938, 1)),
+            intermediate_code:
+                "passphrased3z9rQJHSyBkNBwTRPkUGNVEVrUAcfAXDyRU1V28ie6hNFbqDwbFBvsTK7yWVK",
+            encrypted_key: "6PgGWtx25kUg8QWvwuJAgorN6k9FbE25rv5dMRwu5SKMnfpfVe5mar2ngH",
+            address: "1Lurmih3KruL4xDB5FmHof38yawNtP9oGf",
+            wif: "5KMKKuUmAkiNbA3DazMQiLfDq47qs8MAEThm4yL8R2PhV1ov33D",
+        },
+    ];
+
+    #[test]
+    fn non_ec_multiply_vectors() {
+        for (passphrase, encrypted_key, wif) in NON_EC_MULTIPLY_VECTORS {
+            let (key, compressed) = PrivateKey::from_wif(wif, Network::Mainnet).unwrap();
+            assert_eq!(encrypt(&key, compressed, passphrase), encrypted_key);
+            assert_eq!(
+                decrypt(encrypted_key, passphrase).unwrap(),
+                (key, compressed)
+            );
+        }
+    }
+
+    #[test]
+    fn ec_multiply_vectors() {
+        for vector in EC_MULTIPLY_VECTORS {
+            let owner_salt = hex::decode(vector.owner_salt).unwrap();
+            assert_eq!(
+                intermediate_code(vector.passphrase, &owner_salt, vector.lot_sequence).unwrap(),
+                vector.intermediate_code
+            );
+            let (key, compressed) = decrypt(vector.encrypted_key, vector.passphrase).unwrap();
+            assert_eq!(key.wif(compressed, Network::Mainnet), vector.wif);
+            assert_eq!(
+                key.point().address(compressed, Network::Mainnet),
+                vector.address
+            );
+        }
+    }
+
+    #[test]
+    fn ec_multiply_confirmation_codes() {
+        // seedb recovered from the MOLON LABE vectors while decrypting them.
+        let cases = [
+            (
+                &EC_MULTIPLY_VECTORS[2],
+                "87a13b07858fa753cd3ab3f1c5eafb5f12579b6c33c9a53f",
+                "cfrm38V8aXBn7JWA1ESmFMUn6erxeBGZGAxJPY4e36S9QWkzZKtaVqLNMgnifETYw7BPwWC9aPD",
+            ),
+            (
+                &EC_MULTIPLY_VECTORS[3],
+                "03b06a1ea7f9219ae364560d7b985ab1fa27025aaa7e427a",
+                "cfrm38V8G4qq2ywYEFfWLD5Cc6msj9UwsG2Mj4Z6QdGJAFQpdatZLavkgRd1i4iBMdRngDqDs51",
+            ),
+        ];
+        for (vector, seed_b, confirmation_code) in cases {
+            let seed_b: [u8; 24] = hex::decode(seed_b).unwrap().try_into().unwrap();
+            let generated =
+                encrypt_from_intermediate(vector.intermediate_code, &seed_b, false).unwrap();
+            assert_eq!(
+                generated,
+                GeneratedKey {
+                    encrypted_key: vector.encrypted_key.to_string(),
+                    confirmation_code: confirmation_code.to_string(),
+                    address: vector.address.to_string(),
+                }
+            );
+            assert_eq!(
+                verify_confirmation_code(confirmation_code, vector.passphrase).unwrap(),
+                vector.address
+            );
+        }
+    }
+
+    #[test]
+    fn wrong_passphrase_is_rejected() {
+        let (_, encrypted_key, _) = NON_EC_MULTIPLY_VECTORS[3];
+        assert_eq!(
+            decrypt(encrypted_key, "TestingOneTwoThre").unwrap_err(),
+            Error::WrongPassphrase
+        );
+        let vector = &EC_MULTIPLY_VECTORS[0];
+        assert_eq!(
+            decrypt(vector.encrypted_key, "Satoshi").unwrap_err(),
+            Error::WrongPassphrase
+        );
+        assert_eq!(
+            verify_confirmation_code(
+                "cfrm38V8aXBn7JWA1ESmFMUn6erxeBGZGAxJPY4e36S9QWkzZKtaVqLNMgnifETYw7BPwWC9aPD",
+                "molon labe"
+            )
+            .unwrap_err(),
+            Error::WrongPassphrase
+        );
+    }
+
+    #[test]
+    fn generated_keys_round_trip() {
+        let code = generate_intermediate_code("correct horse", Some((7, 42))).unwrap();
+        let generated = generate_encrypted_key(&code, true).unwrap();
+        let (key, compressed) = decrypt(&generated.encrypted_key, "correct horse").unwrap();
+        assert!(compressed);
+        assert_eq!(
+            key.point().address(true, Network::Mainnet),
+            generated.address
+        );
+        assert_eq!(
+            verify_confirmation_code(&generated.confirmation_code, "correct horse").unwrap(),
+            generated.address
+        );
+    }
+
+    #[test]
+    fn rejects_bad_owner_salts() {
+        assert!(intermediate_code("x", &[0; 4], None).is_err());
+        assert!(intermediate_code("x", &[0; 8], Some((1, 1))).is_err());
+        assert!(intermediate_code("x", &[0; 4], Some((MAX_LOT + 1, 0))).is_err());
+        assert!(intermediate_code("x", &[0; 4], Some((0, MAX_SEQUENCE + 1))).is_err());
+    }
+}
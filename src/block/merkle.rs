@@ -0,0 +1,233 @@
+use super::BlockHeader;
+use crate::hash::hash256;
+
+pub fn merkle_parent(left: &[u8; 32], right: &[u8; 32]) -> [u8; 32] {
+    hash256(&[&left[..], &right[..]].concat())
+}
+
+// Pairs up a level, the last hash with itself when there is an odd number.
+pub fn merkle_parent_level(hashes: &[[u8; 32]]) -> Vec<[u8; 32]> {
+    hashes
+        .chunks(2)
+        .map(|pair| merkle_parent(&pair[0], pair.last().unwrap()))
+        .collect()
+}
+
+// All zeros for no hashes, as in Core.
+pub fn merkle_root(hashes: &[[u8; 32]]) -> [u8; 32] {
+    compute_merkle_root(hashes).0
+}
+
+// CVE-2012-2459: because the last hash of an odd level pairs with itself,
+// repeating the transactions at the end of a level gives the same root. Core
+// marks a block whose list pairs two equal hashes as mutated, since no valid
+// block has the same transaction twice, and rejects it without marking the
+// header as bad, as the real transaction list may still come along.
+pub fn is_mutated(hashes: &[[u8; 32]]) -> bool {
+    compute_merkle_root(hashes).1
+}
+
+fn compute_merkle_root(hashes: &[[u8; 32]]) -> ([u8; 32], bool) {
+    let mut level = hashes.to_vec();
+    let mut mutated = false;
+    while level.len() > 1 {
+        mutated |= level.chunks_exact(2).any(|pair| pair[0] == pair[1]);
+        level = merkle_parent_level(&level);
+    }
+    (level.first().copied().unwrap_or([0; 32]), mutated)
+}
+
+// The siblings along the path from the leaf at `index` up to the root, the
+// lowest first.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MerkleProof {
+    pub index: usize,
+    pub siblings: Vec<[u8; 32]>,
+}
+
+impl MerkleProof {
+    pub fn new(hashes: &[[u8; 32]], index: usize) -> Self {
+        if index >= hashes.len() {
+            panic!("no leaf {} among {}", index, hashes.len());
+        }
+        let mut level = hashes.to_vec();
+        let mut position = index;
+        let mut siblings = Vec::new();
+        while level.len() > 1 {
+            let sibling = (position ^ 1).min(level.len() - 1);
+            siblings.push(level[sibling]);
+            level = merkle_parent_level(&level);
+            position /= 2;
+        }
+        MerkleProof { index, siblings }
+    }
+
+    // The root a tree with `leaf` at this proof's place would have.
+    pub fn root(&self, leaf: &[u8; 32]) -> [u8; 32] {
+        self.siblings
+            .iter()
+            .enumerate()
+            .fold(*leaf, |node, (depth, sibling)| {
+                if self.index >> depth & 1 == 0 {
+                    merkle_parent(&node, sibling)
+                } else {
+                    merkle_parent(sibling, &node)
+                }
+            })
+    }
+
+    // The index must fit in the tree the proof climbs. A leaf copied to fill
+    // out a level still proves at the copy's index too, which the leaf count,
+    // if known, rules out.
+    pub fn verify(&self, leaf: &[u8; 32], root: &[u8; 32]) -> bool {
+        self.index >> self.siblings.len() == 0 && self.root(leaf) == *root
+    }
+}
+
+impl BlockHeader {
+    // `txids` in block order, the coinbase first. A mutated list fails even
+    // though its root matches.
+    pub fn check_merkle_root(&self, txids: &[[u8; 32]]) -> bool {
+        let (root, mutated) = compute_merkle_root(txids);
+        !txids.is_empty() && !mutated && root == self.merkle_root
+    }
+}
+
+#[cfg(test)]
+mod merkle_tests {
+    use super::*;
+    use crate::helper::hex_to_hash;
+
+    // Programming Bitcoin, chapter 11.
+    const BOOK_HASHES: [&str; 12] = [
+        "c117ea8ec828342f4dfb0ad6bd140e03a50720ece40169ee38bdc15d9eb64cf5",
+        "c131474164b412e3406696da1ee20ab0fc9bf41c8f05fa8ceea7a08d672d7cc5",
+        "f391da6ecfeed1814efae39e7fcb3838ae0b02c02ae7d0a5848a66947c0727b0",
+        "3d238a92a94532b946c90e19c49351c763696cff3db400485b813aecb8a13181",
+        "10092f2633be5f3ce349bf9ddbde36caa3dd10dfa0ec8106bce23acbff637dae",
+        "7d37b3d54fa6a64869084bfd2e831309118b9e833610e6228adacdbd1b4ba161",
+        "8118a77e542892fe15ae3fc771a4abfd2f5d5d5997544c3487ac36b5c85170fc",
+        "dff6879848c2c9b62fe652720b8df5272093acfaa45a43cdb3696fe2466a3877",
+        "b825c0745f46ac58f7d3759e6dc535a1fec7820377f24d4c2c6ad2cc55c0cb59",
+        "95513952a04bd8992721e9b7e2937f1c04ba31e0469fbe615a78197f68f52b7c",
+        "2e6d722e5e4dbdf2447ddecc9f7dabb8e299bae921c99ad5b0184cd9eb8e5908",
+        "b13a750047bc0bdceb2473e5fe488c2596d7a7124b4e716fdd29b046ef99bbf0",
+    ];
+
+    // Mainnet block 100000's transactions and header.
+    const BLOCK_100000_TXIDS: [&str; 4] = [
+        "8c14f0db3df150123e6f3dbbf30f8b955a8249b62ac1d1ff16284aefa3d06d87",
+        "fff2525b8931402dd09222c50775608f75787bd2b87e56995a7bdd30f79702c4",
+        "6359f0868171b1d194cbee1af2f16ea598ae8fad666d9b012c8ed2b79a236ec4",
+        "e9a66845e05d5abc0ad04ec80f774a7e585c6e8db975962d069a522137b80c1d",
+    ];
+    const BLOCK_100000_MERKLE_ROOT: &str =
+        "f3e94742aca4b5ef85488dc37c06c3282295ffec960994b2c0d5ac2a25a95766";
+
+    fn book_hashes() -> Vec<[u8; 32]> {
+        BOOK_HASHES
+            .iter()
+            .map(|hash| hex::decode(hash).unwrap().try_into().unwrap())
+            .collect()
+    }
+
+    fn block_100000() -> (BlockHeader, Vec<[u8; 32]>) {
+        let txids = BLOCK_100000_TXIDS
+            .iter()
+            .map(|txid| hex_to_hash(txid).unwrap())
+            .collect();
+        let header = BlockHeader {
+            version: 1,
+            prev_block: [0; 32],
+            merkle_root: hex_to_hash(BLOCK_100000_MERKLE_ROOT).unwrap(),
+            timestamp: 0,
+            bits: 0,
+            nonce: 0,
+        };
+        (header, txids)
+    }
+
+    #[test]
+    fn roots() {
+        let hashes = book_hashes();
+        assert_eq!(
+            hex::encode(merkle_parent(&hashes[0], &hashes[1])),
+            "8b30c5ba100f6f2e5ad1e2a742e5020491240f8eb514fe97c713c31718ad7ecd"
+        );
+        let level = merkle_parent_level(&hashes[..11]);
+        assert_eq!(level.len(), 6);
+        assert_eq!(level[5], merkle_parent(&hashes[10], &hashes[10]));
+        assert_eq!(
+            hex::encode(merkle_root(&hashes)),
+            "acbcab8bcc1af95d8d563b77d24c3d19b18f1486383d75a5085c4e86c86beed6"
+        );
+        assert_eq!(merkle_root(&hashes[..1]), hashes[0]);
+        assert_eq!(merkle_root(&[]), [0; 32]);
+
+        let (header, txids) = block_100000();
+        assert!(header.check_merkle_root(&txids));
+        assert!(!header.check_merkle_root(&txids[..3]));
+        assert!(!header.check_merkle_root(&[]));
+    }
+
+    #[test]
+    fn proofs() {
+        let hashes = book_hashes();
+        let root = merkle_root(&hashes);
+        for count in [1, 2, 3, 5, 11, 12] {
+            let root = merkle_root(&hashes[..count]);
+            for (index, leaf) in hashes[..count].iter().enumerate() {
+                let proof = MerkleProof::new(&hashes[..count], index);
+                assert!(proof.verify(leaf, &root), "{} of {}", index, count);
+            }
+        }
+        let proof = MerkleProof::new(&hashes, 5);
+        assert_eq!(proof.siblings.len(), 4);
+        assert_eq!(proof.siblings[0], hashes[4]);
+        assert!(!proof.verify(&hashes[4], &root));
+        let mut moved = proof.clone();
+        moved.index = 4;
+        assert!(!moved.verify(&hashes[5], &root));
+        // An index past what the path can reach.
+        moved.index = 5 + 16;
+        assert!(!moved.verify(&hashes[5], &root));
+
+        let (header, txids) = block_100000();
+        let proof = MerkleProof::new(&txids, 2);
+        assert!(proof.verify(&txids[2], &header.merkle_root));
+    }
+
+    #[test]
+    #[should_panic(expected = "no leaf 12 among 12")]
+    fn proof_of_missing_leaf() {
+        MerkleProof::new(&book_hashes(), 12);
+    }
+
+    #[test]
+    fn mutated_trees() {
+        let (header, txids) = block_100000();
+        assert!(!is_mutated(&txids));
+        // Three transactions, then the third repeated to make four: the same
+        // root, and the same block to a header-only check.
+        let three = &txids[..3];
+        let four = [txids[0], txids[1], txids[2], txids[2]];
+        assert_eq!(merkle_root(three), merkle_root(&four));
+        assert!(!is_mutated(three));
+        assert!(is_mutated(&four));
+        let header = BlockHeader {
+            merkle_root: merkle_root(three),
+            ..header
+        };
+        assert!(header.check_merkle_root(three));
+        assert!(!header.check_merkle_root(&four));
+
+        // One level up: six transactions repeated as eight, with the last
+        // pair doubled.
+        let hashes = book_hashes();
+        let six = &hashes[..6];
+        let eight: Vec<[u8; 32]> = six.iter().chain(&hashes[4..6]).copied().collect();
+        assert_eq!(merkle_root(six), merkle_root(&eight));
+        assert!(!is_mutated(six));
+        assert!(is_mutated(&eight));
+    }
+}
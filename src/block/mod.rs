@@ -0,0 +1,246 @@
+use num::{BigUint, ToPrimitive, Zero};
+
+use crate::error::Error;
+use crate::hash::hash256;
+use crate::helper::{decode_hex, read_array, read_u32_le};
+use crate::network::Network;
+
+pub mod merkle;
+pub mod retarget;
+
+// The bits of the genesis block, whose target is difficulty 1.
+pub const MAX_BITS: u32 = 0x1d00ffff;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BlockHeader {
+    pub version: u32,
+    // Hashes in the byte order they are serialized in, reversed for display.
+    pub prev_block: [u8; 32],
+    pub merkle_root: [u8; 32],
+    pub timestamp: u32,
+    pub bits: u32,
+    pub nonce: u32,
+}
+
+impl BlockHeader {
+    // Exactly 80 bytes.
+    pub fn parse(bytes: &[u8]) -> Result<Self, Error> {
+        let mut input = bytes;
+        let header = BlockHeader::read(&mut input)?;
+        if !input.is_empty() {
+            return Err(Error::TrailingBytes(input.len()));
+        }
+        Ok(header)
+    }
+
+    pub fn from_hex(s: &str) -> Result<Self, Error> {
+        BlockHeader::parse(&decode_hex(s)?)
+    }
+
+    pub fn read(input: &mut &[u8]) -> Result<Self, Error> {
+        Ok(BlockHeader {
+            version: read_u32_le(input)?,
+            prev_block: read_array(input)?,
+            merkle_root: read_array(input)?,
+            timestamp: read_u32_le(input)?,
+            bits: read_u32_le(input)?,
+            nonce: read_u32_le(input)?,
+        })
+    }
+
+    pub fn serialize(&self) -> Vec<u8> {
+        let mut result = self.version.to_le_bytes().to_vec();
+        result.extend(self.prev_block);
+        result.extend(self.merkle_root);
+        result.extend(self.timestamp.to_le_bytes());
+        result.extend(self.bits.to_le_bytes());
+        result.extend(self.nonce.to_le_bytes());
+        result
+    }
+
+    pub fn to_hex(&self) -> String {
+        hex::encode(self.serialize())
+    }
+
+    pub fn hash(&self) -> [u8; 32] {
+        hash256(&self.serialize())
+    }
+
+    pub fn target(&self) -> BigUint {
+        bits_to_target(self.bits)
+    }
+
+    // How many times harder the target is to meet than difficulty 1's.
+    pub fn difficulty(&self) -> f64 {
+        bits_to_target(MAX_BITS).to_f64().unwrap() / self.target().to_f64().unwrap()
+    }
+
+    // Core's CheckProofOfWork: the hash, read as a little endian number, is
+    // at most the target. Bits that are negative, zero, too big for 256 bits
+    // or easier than the network allows are never met.
+    pub fn check_pow(&self, network: Network) -> bool {
+        let target = self.target();
+        if self.bits & 0x00800000 != 0
+            || target.is_zero()
+            || target.bits() > 256
+            || target > network.pow_limit()
+        {
+            return false;
+        }
+        BigUint::from_bytes_le(&self.hash()) <= target
+    }
+}
+
+// Bits are a compact float: a byte of exponent, the length of the target in
+// bytes, over three bytes of its most significant digits. Core keeps the top
+// bit of those for a sign, which targets do not use.
+pub fn bits_to_target(bits: u32) -> BigUint {
+    let exponent = bits >> 24;
+    let coefficient = BigUint::from(bits & 0x007fffff);
+    if exponent <= 3 {
+        coefficient >> (8 * (3 - exponent))
+    } else {
+        coefficient << (8 * (exponent - 3))
+    }
+}
+
+// The bits for the largest target of three significant bytes not above
+// `target`. A leading byte over 0x7f would read as a sign, so it moves down to
+// make room for a zero byte.
+pub fn target_to_bits(target: &BigUint) -> u32 {
+    if target.is_zero() {
+        return 0;
+    }
+    let bytes = target.to_bytes_be();
+    let mut exponent = bytes.len() as u32;
+    let mut coefficient = bytes
+        .iter()
+        .chain([0, 0].iter())
+        .take(3)
+        .fold(0u32, |coefficient, byte| coefficient << 8 | *byte as u32);
+    if coefficient & 0x00800000 != 0 {
+        coefficient >>= 8;
+        exponent += 1;
+    }
+    exponent << 24 | coefficient
+}
+
+#[cfg(test)]
+mod block_tests {
+    use super::*;
+    use crate::helper::hash_to_hex;
+
+    const GENESIS: &str = "0100000000000000000000000000000000000000000000000000000000000000000000003ba3edfd7a7b12b27ac72c3e67768f617fc81bc3888a51323a9fb8aa4b1e5e4a29ab5f49ffff001d1dac2b7c";
+
+    // Programming Bitcoin, chapter 9: block 471744, and a block whose proof of
+    // work the chapter checks.
+    const BOOK_HEADER: &str = "020000208ec39428b17323fa0ddec8e887b4a7c53b8c0a0a220cfd0000000000000000005b0750fce0a889502d40508d39576821155e9c9e3f5c3157f961db38fd8b25be1e77a759e93c0118a4ffd71d";
+    const BOOK_POW_HEADER: &str = "04000000fbedbbf0cfdaf278c094f187f2eb987c86a199da22bbb20400000000000000007b7697b29129648fa08b4bcd13c9d5e60abb973a1efac9c8d573c71c807c56c3d6213557faa80518c3737ec1";
+
+    #[test]
+    fn parse_and_serialize() {
+        let header = BlockHeader::from_hex(BOOK_HEADER).unwrap();
+        assert_eq!(header.version, 0x20000002);
+        assert_eq!(
+            hash_to_hex(&header.prev_block),
+            "000000000000000000fd0c220a0a8c3bc5a7b487e8c8de0dfa2373b12894c38e"
+        );
+        assert_eq!(
+            hash_to_hex(&header.merkle_root),
+            "be258bfd38db61f957315c3f9e9c5e15216857398d50402d5089a8e0fc50075b"
+        );
+        assert_eq!(header.timestamp, 0x59a7771e);
+        assert_eq!(header.bits, 0x18013ce9);
+        assert_eq!(header.nonce, 0x1dd7ffa4);
+        assert_eq!(header.to_hex(), BOOK_HEADER);
+        assert_eq!(
+            hash_to_hex(&header.hash()),
+            "0000000000000000007e9e4c586439b0cdbe13b1370bdd9435d76a644d047523"
+        );
+
+        assert_eq!(
+            BlockHeader::parse(&[0; 79]).unwrap_err(),
+            Error::UnexpectedEof
+        );
+        assert_eq!(
+            BlockHeader::parse(&[0; 81]).unwrap_err(),
+            Error::TrailingBytes(1)
+        );
+    }
+
+    #[test]
+    fn genesis() {
+        let header = BlockHeader::from_hex(GENESIS).unwrap();
+        assert_eq!(header.hash(), Network::Mainnet.genesis_hash());
+        assert_eq!(header.prev_block, [0; 32]);
+        assert_eq!(header.bits, MAX_BITS);
+        assert_eq!(header.difficulty(), 1.0);
+        assert!(header.target() <= Network::Mainnet.pow_limit());
+        assert!(header.check_pow(Network::Mainnet));
+    }
+
+    #[test]
+    fn targets() {
+        let header = BlockHeader::from_hex(BOOK_HEADER).unwrap();
+        assert_eq!(
+            format!("{:064x}", header.target()),
+            "0000000000000000013ce9000000000000000000000000000000000000000000"
+        );
+        assert_eq!(header.difficulty().floor(), 888171856257.0);
+        assert!(header.check_pow(Network::Mainnet));
+
+        for bits in [MAX_BITS, 0x18013ce9, 0x170ed0eb, 0x207fffff, 0x1b0404cb] {
+            assert_eq!(target_to_bits(&bits_to_target(bits)), bits);
+        }
+        // Core's tests for SetCompact and GetCompact.
+        assert_eq!(bits_to_target(0x01123456), BigUint::from(0x12u32));
+        assert_eq!(bits_to_target(0x02123456), BigUint::from(0x1234u32));
+        assert_eq!(bits_to_target(0x04123456), BigUint::from(0x12345600u32));
+        assert_eq!(target_to_bits(&BigUint::from(0x12u32)), 0x01120000);
+        assert_eq!(target_to_bits(&BigUint::from(0x80u32)), 0x02008000);
+        assert_eq!(target_to_bits(&BigUint::from(0x12345600u32)), 0x04123456);
+        assert_eq!(target_to_bits(&BigUint::zero()), 0);
+        // Digits past the third are dropped.
+        assert_eq!(target_to_bits(&BigUint::from(0x123456789u64)), 0x05012345);
+    }
+
+    #[test]
+    fn proof_of_work() {
+        let mut header = BlockHeader::from_hex(BOOK_POW_HEADER).unwrap();
+        assert!(header.check_pow(Network::Mainnet));
+        header.nonce ^= 1;
+        assert!(!header.check_pow(Network::Mainnet));
+    }
+
+    // Bits no hash meets, whatever the network's limit.
+    #[test]
+    fn unmeetable_bits() {
+        let mut header = BlockHeader::from_hex(GENESIS).unwrap();
+        // Negative.
+        header.bits = 0x1d80ffff;
+        assert!(!header.check_pow(Network::Regtest));
+        // Zero.
+        header.bits = 0x1d000000;
+        assert!(!header.check_pow(Network::Regtest));
+        // Past 256 bits, in Core's SetCompact terms: more than 34 bytes, or
+        // more than 33 or 32 when the top one or two of the three are used.
+        for bits in [0x23000001, 0x22000100, 0x21010000] {
+            header.bits = bits;
+            assert!(bits_to_target(bits).bits() > 256, "{:08x}", bits);
+            assert!(!header.check_pow(Network::Regtest), "{:08x}", bits);
+        }
+    }
+
+    #[test]
+    fn above_the_pow_limit() {
+        // A header that meets regtest's limit is no good on mainnet.
+        let mut header = BlockHeader::from_hex(GENESIS).unwrap();
+        header.bits = 0x207fffff;
+        while !header.check_pow(Network::Regtest) {
+            header.nonce += 1;
+        }
+        assert!(header.target() > Network::Mainnet.pow_limit());
+        assert!(!header.check_pow(Network::Mainnet));
+        assert!(!header.check_pow(Network::Testnet));
+    }
+}
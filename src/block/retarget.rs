@@ -0,0 +1,271 @@
+use super::{bits_to_target, target_to_bits, BlockHeader};
+use crate::error::Error;
+use crate::network::Network;
+
+// Difficulty changes every 2016 blocks, to keep them ten minutes apart.
+pub const RETARGET_INTERVAL: u32 = 2016;
+pub const TARGET_SPACING: u32 = 10 * 60;
+pub const TARGET_TIMESPAN: u32 = RETARGET_INTERVAL * TARGET_SPACING;
+
+// The bits for the period after one with `prev_bits` whose first and last
+// blocks are `time_differential` seconds apart. That spans 2015 intervals,
+// not 2016, an off by one consensus keeps. The target moves at most four
+// times either way and never past the network's limit.
+pub fn calculate_new_bits(prev_bits: u32, time_differential: i64, network: Network) -> u32 {
+    let timespan = TARGET_TIMESPAN as i64;
+    let time_differential = time_differential.clamp(timespan / 4, timespan * 4) as u64;
+    let target = bits_to_target(prev_bits) * time_differential / TARGET_TIMESPAN;
+    target_to_bits(&target.min(network.pow_limit()))
+}
+
+// The bits the block at `height`, with `timestamp`, must have. `chain` ends
+// with the block before it and reaches back at least to the last block at a
+// multiple of RETARGET_INTERVAL.
+pub fn next_bits(
+    chain: &[BlockHeader],
+    height: u32,
+    timestamp: u32,
+    network: Network,
+) -> Result<u32, Error> {
+    if height == 0 {
+        return Err(Error::InvalidHeaderChain(
+            "the genesis block has no previous block".to_string(),
+        ));
+    }
+    let needed = ((height - 1) % RETARGET_INTERVAL + 1) as usize;
+    if chain.len() < needed {
+        return Err(Error::InvalidHeaderChain(format!(
+            "{} headers before height {}, {} needed",
+            chain.len(),
+            height,
+            needed
+        )));
+    }
+    let period = &chain[chain.len() - needed..];
+    let (first, last) = (&period[0], &period[needed - 1]);
+    let params = network.params();
+
+    if !height.is_multiple_of(RETARGET_INTERVAL) {
+        if !params.pow_allow_min_difficulty_blocks {
+            return Ok(last.bits);
+        }
+        let min_bits = target_to_bits(&network.pow_limit());
+        if timestamp as u64 > last.timestamp as u64 + 2 * TARGET_SPACING as u64 {
+            return Ok(min_bits);
+        }
+        // Otherwise the difficulty before any blocks at the limit, back to
+        // the start of the period.
+        let bits = period[1..]
+            .iter()
+            .rev()
+            .find(|header| header.bits != min_bits)
+            .unwrap_or(first)
+            .bits;
+        return Ok(bits);
+    }
+
+    if params.pow_no_retargeting {
+        return Ok(last.bits);
+    }
+    let time_differential = last.timestamp as i64 - first.timestamp as i64;
+    Ok(calculate_new_bits(last.bits, time_differential, network))
+}
+
+#[cfg(test)]
+mod retarget_tests {
+    use super::*;
+    use crate::block::MAX_BITS;
+
+    // Programming Bitcoin, chapter 9: the first and last blocks of a mainnet
+    // period in June 2017, and the bits of the one after.
+    const PERIOD_FIRST: &str = "000000203471101bbda3fe307664b3283a9ef0e97d9a38a7eacd8800000000000000000010c8aba8479bbaa5e0848152fd3c2289ca50e1c3e58c9a4faaafbdf5803c5448ddb845597e8b0118e43a81d3";
+    const PERIOD_LAST: &str = "02000020f1472d9db4b563c35f97c428ac903f23b7fc055d1cfc26000000000000000000b3f449fcbe1bc4cfbcb8283a0d2c037f961a3fdf2b8bedc144973735eea707e1264258597e8b0118e5f00474";
+    const NEXT_BITS: u32 = 0x18018d30;
+
+    fn header(bits: u32, timestamp: u32) -> BlockHeader {
+        BlockHeader {
+            version: 1,
+            prev_block: [0; 32],
+            merkle_root: [0; 32],
+            timestamp,
+            bits,
+            nonce: 0,
+        }
+    }
+
+    // A period of blocks `spacing` seconds apart from `start`.
+    fn period(bits: u32, start: u32, spacing: u32) -> Vec<BlockHeader> {
+        (0..RETARGET_INTERVAL)
+            .map(|i| header(bits, start + i * spacing))
+            .collect()
+    }
+
+    #[test]
+    fn historical() {
+        let first = BlockHeader::from_hex(PERIOD_FIRST).unwrap();
+        let last = BlockHeader::from_hex(PERIOD_LAST).unwrap();
+        assert!(first.check_pow(Network::Mainnet) && last.check_pow(Network::Mainnet));
+        assert_eq!(first.bits, last.bits);
+        let time_differential = last.timestamp as i64 - first.timestamp as i64;
+        assert_eq!(
+            calculate_new_bits(last.bits, time_differential, Network::Mainnet),
+            NEXT_BITS
+        );
+
+        // Only the first and last blocks count.
+        let mut chain = period(last.bits, first.timestamp, TARGET_SPACING);
+        chain[0] = first;
+        chain[RETARGET_INTERVAL as usize - 1] = last;
+        assert_eq!(
+            next_bits(&chain, 2016 * 235, 0, Network::Mainnet),
+            Ok(NEXT_BITS)
+        );
+    }
+
+    // Core's pow_tests: the first and last times of mainnet periods, the
+    // bits of their last block and those of the block after.
+    #[test]
+    fn mainnet_retargets() {
+        let cases = [
+            // Blocks 0 to 2015, slower than planned but already at the limit.
+            (1231006505, 1233061996, 0x1d00ffff, 0x1d00ffff),
+            // Blocks 30240 to 32255, with neither clamp.
+            (1261130161, 1262152739, 0x1d00ffff, 0x1d00d86a),
+            // Blocks 66528 to 68543, under three and a half days, so the
+            // target only shrinks fourfold.
+            (1279008237, 1279297671, 0x1c05a3f4, 0x1c0168fd),
+            // Block 46367 against a start time Core made up, over eight
+            // weeks earlier, so the target only grows fourfold.
+            (1263163443, 1269211443, 0x1c387f6f, 0x1d00e1fd),
+        ];
+        for (first_time, last_time, bits, expected) in cases {
+            let time_differential = last_time as i64 - first_time as i64;
+            assert_eq!(
+                calculate_new_bits(bits, time_differential, Network::Mainnet),
+                expected
+            );
+            let mut chain = period(bits, first_time, TARGET_SPACING);
+            chain[RETARGET_INTERVAL as usize - 1].timestamp = last_time;
+            assert_eq!(
+                next_bits(&chain, 2016 * 100, 0, Network::Mainnet),
+                Ok(expected)
+            );
+        }
+        let timespan = TARGET_TIMESPAN as i64;
+        assert_eq!(
+            calculate_new_bits(0x1c05a3f4, timespan / 4, Network::Mainnet),
+            0x1c0168fd
+        );
+        assert_eq!(
+            calculate_new_bits(0x1c387f6f, timespan * 4, Network::Mainnet),
+            0x1d00e1fd
+        );
+    }
+
+    #[test]
+    fn clamps() {
+        let timespan = TARGET_TIMESPAN as i64;
+        // The book's case: three and a half days is the least that counts.
+        assert_eq!(
+            calculate_new_bits(0x1801d854, timespan / 4, Network::Mainnet),
+            0x17761500
+        );
+        for time_differential in [-1000, 0, timespan / 8] {
+            assert_eq!(
+                calculate_new_bits(0x1801d854, time_differential, Network::Mainnet),
+                0x17761500
+            );
+        }
+        assert_eq!(
+            calculate_new_bits(0x1801d854, timespan * 10, Network::Mainnet),
+            calculate_new_bits(0x1801d854, timespan * 4, Network::Mainnet)
+        );
+        assert_eq!(
+            calculate_new_bits(0x1801d854, timespan, Network::Mainnet),
+            0x1801d854
+        );
+        // Never easier than the limit, which is difficulty 1 on mainnet.
+        assert_eq!(
+            calculate_new_bits(MAX_BITS, timespan * 2, Network::Mainnet),
+            MAX_BITS
+        );
+        assert_eq!(
+            calculate_new_bits(0x1c7fffff, timespan * 4, Network::Mainnet),
+            MAX_BITS
+        );
+        assert_eq!(
+            calculate_new_bits(0x1e0377ae, timespan * 4, Network::Signet),
+            0x1e0377ae
+        );
+    }
+
+    #[test]
+    fn between_retargets() {
+        let mut chain = period(0x1b0404cb, 1_000_000, TARGET_SPACING);
+        chain.truncate(100);
+        assert_eq!(
+            next_bits(&chain, 2016 * 10 + 100, u32::MAX, Network::Mainnet),
+            Ok(0x1b0404cb)
+        );
+        assert!(next_bits(&chain, 2016 * 10 + 101, 0, Network::Mainnet).is_err());
+        assert!(next_bits(&[], 0, 0, Network::Mainnet).is_err());
+        // A chain longer than needed is fine.
+        assert_eq!(
+            next_bits(&chain, 2016 * 10 + 50, 0, Network::Mainnet),
+            Ok(0x1b0404cb)
+        );
+    }
+
+    #[test]
+    fn testnet_min_difficulty() {
+        let mut chain = period(0x1b0404cb, 1_000_000, TARGET_SPACING);
+        chain.truncate(100);
+        let last_time = chain[99].timestamp;
+        let height = 2016 * 10 + 100;
+        // Only more than twenty minutes after the last block.
+        assert_eq!(
+            next_bits(&chain, height, last_time + 1201, Network::Testnet),
+            Ok(MAX_BITS)
+        );
+        assert_eq!(
+            next_bits(&chain, height, last_time + 1200, Network::Testnet),
+            Ok(0x1b0404cb)
+        );
+        // Blocks at the limit do not lower the difficulty of the next ones.
+        chain.push(header(MAX_BITS, last_time + 3000));
+        chain.push(header(MAX_BITS, last_time + 6000));
+        assert_eq!(
+            next_bits(&chain, height + 2, last_time + 6000, Network::Testnet),
+            Ok(0x1b0404cb)
+        );
+        // Though a period that starts at the limit keeps it.
+        let chain = period(MAX_BITS, 1_000_000, TARGET_SPACING * 2);
+        assert_eq!(
+            next_bits(&chain[..10], 2016 * 10 + 10, 1_000_000, Network::Testnet),
+            Ok(MAX_BITS)
+        );
+        // Mainnet has no such rule.
+        let chain = period(0x1b0404cb, 1_000_000, TARGET_SPACING);
+        assert_eq!(
+            next_bits(&chain[..10], 2016 * 10 + 10, u32::MAX, Network::Mainnet),
+            Ok(0x1b0404cb)
+        );
+    }
+
+    #[test]
+    fn retargets() {
+        // Blocks twice as fast double the difficulty, but for the off by one.
+        let chain = period(0x1b0404cb, 1_000_000, TARGET_SPACING / 2);
+        let bits = next_bits(&chain, 2016 * 10, 0, Network::Mainnet).unwrap();
+        assert_eq!(bits, 0x1b020224);
+        // The testnet rule is not applied at retargets.
+        assert_eq!(
+            next_bits(&chain, 2016 * 10, u32::MAX, Network::Testnet),
+            Ok(bits)
+        );
+        assert_eq!(
+            next_bits(&chain, 2016 * 10, 0, Network::Regtest),
+            Ok(0x1b0404cb)
+        );
+    }
+}
@@ -0,0 +1,90 @@
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Error {
+    InvalidBase58Character(char),
+    InvalidBech32(String),
+    InvalidChecksum,
+    InvalidLength(usize),
+    InvalidPrefix(u8),
+    PointNotOnCurve,
+    SecretOutOfRange,
+    WrongNetwork,
+    UnknownNetwork(String),
+    UnknownVersion([u8; 4]),
+    InvalidExtendedKey(String),
+    HardenedDerivationFromPublicKey,
+    InvalidChildKey,
+    InvalidDerivationPath(String),
+    InvalidWordCount(usize),
+    UnknownWord(String),
+    InvalidBip38(String),
+    WrongPassphrase,
+    InvalidHex(String),
+    UnexpectedEof,
+    NonCanonicalVarint,
+    TrailingBytes(usize),
+    InvalidTransaction(String),
+    InvalidSignature(String),
+    InvalidInputIndex(usize),
+    UnsupportedScript(String),
+    InvalidSighashType(u32),
+    MissingKey(usize),
+    InsufficientFunds(u64),
+    InvalidFeeRate(String),
+    InvalidBase64(String),
+    InvalidPsbt(String),
+    InvalidAsm(String),
+    InvalidHeaderChain(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::InvalidBase58Character(c) => write!(f, "invalid base58 character {:?}", c),
+            Error::InvalidBech32(reason) => write!(f, "invalid bech32 string: {}", reason),
+            Error::InvalidChecksum => write!(f, "checksum mismatch"),
+            Error::InvalidLength(len) => write!(f, "invalid length {}", len),
+            Error::InvalidPrefix(prefix) => write!(f, "invalid prefix byte {:#04x}", prefix),
+            Error::PointNotOnCurve => write!(f, "point is not on the secp256k1 curve"),
+            Error::SecretOutOfRange => write!(f, "secret is not in the range [1, n)"),
+            Error::WrongNetwork => write!(f, "encoded for a different network"),
+            Error::UnknownNetwork(name) => write!(f, "unknown network {:?}", name),
+            Error::UnknownVersion(version) => {
+                write!(f, "unknown version bytes {}", hex::encode(version))
+            }
+            Error::InvalidExtendedKey(reason) => write!(f, "invalid extended key: {}", reason),
+            Error::HardenedDerivationFromPublicKey => {
+                write!(f, "hardened children cannot be derived from a public key")
+            }
+            Error::InvalidChildKey => write!(f, "derived key is invalid, try the next index"),
+            Error::InvalidDerivationPath(path) => write!(f, "invalid derivation path {:?}", path),
+            Error::InvalidWordCount(count) => write!(f, "invalid mnemonic word count {}", count),
+            Error::UnknownWord(word) => write!(f, "{:?} is not in the wordlist", word),
+            Error::InvalidBip38(reason) => write!(f, "invalid BIP38 string: {}", reason),
+            Error::WrongPassphrase => write!(f, "passphrase does not match the encrypted key"),
+            Error::InvalidHex(reason) => write!(f, "invalid hex: {}", reason),
+            Error::UnexpectedEof => write!(f, "unexpected end of data"),
+            Error::NonCanonicalVarint => write!(f, "varint is not minimally encoded"),
+            Error::TrailingBytes(count) => write!(f, "{} unexpected trailing bytes", count),
+            Error::InvalidTransaction(reason) => write!(f, "invalid transaction: {}", reason),
+            Error::InvalidSignature(reason) => write!(f, "invalid signature: {}", reason),
+            Error::InvalidInputIndex(index) => write!(f, "transaction has no input {}", index),
+            Error::UnsupportedScript(reason) => write!(f, "unsupported script: {}", reason),
+            Error::InvalidSighashType(sighash_type) => {
+                write!(f, "sighash type {:#04x} is not valid here", sighash_type)
+            }
+            Error::MissingKey(index) => write!(f, "no key to sign input {}", index),
+            Error::InsufficientFunds(missing) => {
+                write!(f, "insufficient funds, {} more satoshis needed", missing)
+            }
+            Error::InvalidFeeRate(rate) => write!(f, "invalid fee rate {} sat/vB", rate),
+            Error::InvalidBase64(reason) => write!(f, "invalid base64: {}", reason),
+            Error::InvalidPsbt(reason) => write!(f, "invalid PSBT: {}", reason),
+            Error::InvalidAsm(word) => write!(f, "invalid script asm at {:?}", word),
+            Error::InvalidHeaderChain(reason) => write!(f, "invalid header chain: {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
@@ -0,0 +1,10 @@
+// Test data shared by more than one module's tests.
+
+// Programming Bitcoin, chapter 7: the chapter 5 transaction and the output it spends.
+pub const BOOK_TX: &str = "0100000001813f79011acb80925dfe69b3def355fe914bd1d96a3f5f71bf8303c6a989c7d1000000006b483045022100ed81ff192e75a3fd2304004dcadb746fa5e24c5031ccfcf21320b0277457c98f02207a986d955c6e0cb35d446a89d3f56100f4d7f67801c31967743a9c8e10615bed01210349fc4e631e3624a545de3f89f5d8684c7b8138bd94bdd531d2e213bf016b278afeffffff02a135ef01000000001976a914bc3b654dca7e56b04dca18f2566cdaf02e8d9ada88ac99c39800000000001976a9141c4bc762dd5423e332166702cb75f40df79fea1288ac19430600";
+pub const BOOK_PREV_SCRIPT_PUBKEY: &str = "76a914a802fc56c704ce87c42d7c92eb75e7896bdc41ae88ac";
+
+// A signature checker for scripts that must not reach a signature check.
+pub fn no_signatures(_: &[u8], _: u32) -> [u8; 32] {
+    panic!("no signature should be checked")
+}
@@ -0,0 +1,98 @@
+use hmac::{Hmac, Mac};
+use ripemd::Ripemd160;
+use sha1::Sha1;
+use sha2::{Digest, Sha256, Sha512};
+
+pub fn sha256(data: &[u8]) -> [u8; 32] {
+    Sha256::digest(data).into()
+}
+
+pub fn sha1(data: &[u8]) -> [u8; 20] {
+    Sha1::digest(data).into()
+}
+
+pub fn ripemd160(data: &[u8]) -> [u8; 20] {
+    Ripemd160::digest(data).into()
+}
+
+// sha256 applied twice, used for txids, block hashes and checksums.
+pub fn hash256(data: &[u8]) -> [u8; 32] {
+    sha256(&sha256(data))
+}
+
+// ripemd160 of the sha256, used for public key and script hashes.
+pub fn hash160(data: &[u8]) -> [u8; 20] {
+    ripemd160(&sha256(data))
+}
+
+// BIP340 tagged hash: sha256(sha256(tag) || sha256(tag) || data).
+pub fn tagged_hash(tag: &str, data: &[u8]) -> [u8; 32] {
+    let tag_hash = sha256(tag.as_bytes());
+    let mut hasher = Sha256::new();
+    hasher.update(tag_hash);
+    hasher.update(tag_hash);
+    hasher.update(data);
+    hasher.finalize().into()
+}
+
+pub fn hmac_sha256(key: &[u8], data: &[u8]) -> [u8; 32] {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).unwrap();
+    mac.update(data);
+    mac.finalize().into_bytes().into()
+}
+
+pub fn hmac_sha512(key: &[u8], data: &[u8]) -> [u8; 64] {
+    let mut mac = Hmac::<Sha512>::new_from_slice(key).unwrap();
+    mac.update(data);
+    mac.finalize().into_bytes().into()
+}
+
+#[cfg(test)]
+mod hash_tests {
+    use super::*;
+
+    #[test]
+    fn hash256_works() {
+        assert_eq!(
+            hex::encode(hash256(b"hello")),
+            "9595c9df90075148eb06860365df33584b75bff782a510c6cd4883a419833d50"
+        );
+    }
+
+    #[test]
+    fn hash160_works() {
+        assert_eq!(
+            hex::encode(hash160(b"hello")),
+            "b6a9c8c230722b7c748331a8b450f05566dc7d0f"
+        );
+    }
+
+    #[test]
+    fn sha1_and_ripemd160_work() {
+        assert_eq!(
+            hex::encode(sha1(b"abc")),
+            "a9993e364706816aba3e25717850c26c9cd0d89d"
+        );
+        assert_eq!(
+            hex::encode(ripemd160(b"abc")),
+            "8eb208f7e05d987a9b044a8e98c6b087f15a0bfc"
+        );
+    }
+
+    #[test]
+    fn tagged_hash_works() {
+        let mut preimage = sha256(b"TapLeaf").to_vec();
+        preimage.extend_from_slice(&sha256(b"TapLeaf"));
+        preimage.extend_from_slice(b"script");
+        assert_eq!(tagged_hash("TapLeaf", b"script"), sha256(&preimage));
+    }
+
+    #[test]
+    fn hmac_sha512_works() {
+        // RFC 4231, test case 2
+        assert_eq!(
+            hex::encode(hmac_sha512(b"Jefe", b"what do ya want for nothing?")),
+            "164b7a7bfcf819e2e395fbe73b56e0a387bd64222e831fd610270cd7ea2505549758bf75c05a994a6d034f65f8f0e6fdcaeab1a34d4a6b4b636e070a38bce737"
+        );
+    }
+}
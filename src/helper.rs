@@ -0,0 +1,164 @@
+use num::BigUint;
+
+use crate::error::Error;
+
+// Big endian, left padded with zeros to 32 bytes.
+pub fn int_to_32_bytes(num: &BigUint) -> [u8; 32] {
+    let bytes = num.to_bytes_be();
+    if bytes.len() > 32 {
+        panic!("{} does not fit in 32 bytes", num);
+    }
+    let mut padded = [0u8; 32];
+    padded[32 - bytes.len()..].copy_from_slice(&bytes);
+    padded
+}
+
+// Hashes are stored in the order they come out of sha256 but shown byte reversed.
+pub fn hash_to_hex(hash: &[u8; 32]) -> String {
+    let mut reversed = *hash;
+    reversed.reverse();
+    hex::encode(reversed)
+}
+
+pub fn hex_to_hash(s: &str) -> Result<[u8; 32], Error> {
+    let bytes = decode_hex(s)?;
+    let mut hash: [u8; 32] = bytes
+        .try_into()
+        .map_err(|bytes: Vec<u8>| Error::InvalidLength(bytes.len()))?;
+    hash.reverse();
+    Ok(hash)
+}
+
+pub fn decode_hex(s: &str) -> Result<Vec<u8>, Error> {
+    hex::decode(s).map_err(|e| Error::InvalidHex(e.to_string()))
+}
+
+// The parsers below take `&mut &[u8]` and advance it past whatever they consume.
+pub fn read_bytes<'a>(input: &mut &'a [u8], len: usize) -> Result<&'a [u8], Error> {
+    if input.len() < len {
+        return Err(Error::UnexpectedEof);
+    }
+    let (bytes, rest) = input.split_at(len);
+    *input = rest;
+    Ok(bytes)
+}
+
+pub fn read_array<const N: usize>(input: &mut &[u8]) -> Result<[u8; N], Error> {
+    Ok(read_bytes(input, N)?.try_into().unwrap())
+}
+
+pub fn read_u8(input: &mut &[u8]) -> Result<u8, Error> {
+    Ok(read_array::<1>(input)?[0])
+}
+
+pub fn read_u16_le(input: &mut &[u8]) -> Result<u16, Error> {
+    Ok(u16::from_le_bytes(read_array(input)?))
+}
+
+pub fn read_u32_le(input: &mut &[u8]) -> Result<u32, Error> {
+    Ok(u32::from_le_bytes(read_array(input)?))
+}
+
+pub fn read_u64_le(input: &mut &[u8]) -> Result<u64, Error> {
+    Ok(u64::from_le_bytes(read_array(input)?))
+}
+
+// CompactSize: one byte below 0xfd, otherwise a marker and a 2, 4 or 8 byte
+// little endian integer. Like Core, values that fit a shorter form are rejected.
+pub fn read_varint(input: &mut &[u8]) -> Result<u64, Error> {
+    let (value, min) = match read_u8(input)? {
+        0xfd => (read_u16_le(input)? as u64, 0xfd),
+        0xfe => (read_u32_le(input)? as u64, 0x1_0000),
+        0xff => (read_u64_le(input)?, 0x1_0000_0000),
+        byte => return Ok(byte as u64),
+    };
+    if value < min {
+        return Err(Error::NonCanonicalVarint);
+    }
+    Ok(value)
+}
+
+pub fn encode_varint(value: u64) -> Vec<u8> {
+    match value {
+        0..=0xfc => vec![value as u8],
+        0xfd..=0xffff => [&[0xfd], &(value as u16).to_le_bytes()[..]].concat(),
+        0x1_0000..=0xffff_ffff => [&[0xfe], &(value as u32).to_le_bytes()[..]].concat(),
+        _ => [&[0xff], &value.to_le_bytes()[..]].concat(),
+    }
+}
+
+// A varint length followed by that many bytes.
+pub fn read_var_bytes(input: &mut &[u8]) -> Result<Vec<u8>, Error> {
+    let len = read_varint(input)?;
+    if len > input.len() as u64 {
+        return Err(Error::UnexpectedEof);
+    }
+    Ok(read_bytes(input, len as usize)?.to_vec())
+}
+
+pub fn encode_var_bytes(bytes: &[u8]) -> Vec<u8> {
+    [encode_varint(bytes.len() as u64), bytes.to_vec()].concat()
+}
+
+#[cfg(test)]
+mod helper_tests {
+    use super::*;
+
+    #[test]
+    fn int_to_32_bytes_pads() {
+        let bytes = int_to_32_bytes(&BigUint::from(0x0102u64));
+        assert_eq!(bytes[..30], [0u8; 30]);
+        assert_eq!(bytes[30..], [1, 2]);
+    }
+
+    #[test]
+    fn varint_round_trips_at_boundaries() {
+        for (value, len) in [
+            (0u64, 1),
+            (0xfc, 1),
+            (0xfd, 3),
+            (0xffff, 3),
+            (0x1_0000, 5),
+            (0xffff_ffff, 5),
+            (0x1_0000_0000, 9),
+            (u64::MAX, 9),
+        ] {
+            let encoded = encode_varint(value);
+            assert_eq!(encoded.len(), len);
+            let mut input = &encoded[..];
+            assert_eq!(read_varint(&mut input).unwrap(), value);
+            assert!(input.is_empty());
+        }
+    }
+
+    #[test]
+    fn varint_rejects_non_canonical_and_short_input() {
+        for bytes in ["fdfc00", "fe ffff0000", "ff ffffffff00000000"] {
+            let bytes = hex::decode(bytes.replace(' ', "")).unwrap();
+            assert_eq!(
+                read_varint(&mut &bytes[..]).unwrap_err(),
+                Error::NonCanonicalVarint
+            );
+        }
+        assert_eq!(
+            read_varint(&mut &[0xfe, 0x00][..]).unwrap_err(),
+            Error::UnexpectedEof
+        );
+        assert_eq!(
+            read_var_bytes(&mut &[0x03, 0x01, 0x02][..]).unwrap_err(),
+            Error::UnexpectedEof
+        );
+    }
+
+    #[test]
+    fn hashes_display_reversed() {
+        let hash = hex_to_hash("000000000019d6689c085ae165831e934ff763ae46a2a6c172b3f1b60a8ce26f")
+            .unwrap();
+        assert_eq!(hash[31], 0);
+        assert_eq!(hash[0], 0x6f);
+        assert_eq!(
+            hash_to_hex(&hash),
+            "000000000019d6689c085ae165831e934ff763ae46a2a6c172b3f1b60a8ce26f"
+        );
+    }
+}
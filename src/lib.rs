@@ -1 +1,19 @@
+pub mod address;
+pub mod base58;
+pub mod base64;
+pub mod bech32;
+pub mod bip32;
+pub mod bip38;
+pub mod bip39;
+pub mod block;
+pub mod error;
+#[cfg(test)]
+mod fixtures;
+pub mod hash;
+pub mod helper;
+pub mod network;
+pub mod psbt;
+pub mod script;
 pub mod secp256k1;
+pub mod taproot;
+pub mod tx;
@@ -0,0 +1,197 @@
+use std::fmt;
+use std::str::FromStr;
+
+use num::{BigUint, Num};
+
+use crate::error::Error;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Network {
+    Mainnet,
+    Testnet,
+    Signet,
+    Regtest,
+}
+
+// Everything that changes between chains lives here so encoders only need a `Network`.
+#[derive(Debug, PartialEq, Eq)]
+pub struct NetworkParams {
+    pub p2pkh_prefix: u8,
+    pub p2sh_prefix: u8,
+    pub wif_prefix: u8,
+    pub xpub_version: [u8; 4],
+    pub xprv_version: [u8; 4],
+    pub bech32_hrp: &'static str,
+    pub magic: [u8; 4],
+    pub default_port: u16,
+    // Block hashes and targets are written the way block explorers display them (big endian).
+    pub genesis_hash: &'static str,
+    pub pow_limit: &'static str,
+    // Testnet and regtest take a block at the limit after 20 minutes without one.
+    pub pow_allow_min_difficulty_blocks: bool,
+    // Regtest never changes its difficulty.
+    pub pow_no_retargeting: bool,
+}
+
+const MAINNET: NetworkParams = NetworkParams {
+    p2pkh_prefix: 0x00,
+    p2sh_prefix: 0x05,
+    wif_prefix: 0x80,
+    xpub_version: [0x04, 0x88, 0xb2, 0x1e],
+    xprv_version: [0x04, 0x88, 0xad, 0xe4],
+    bech32_hrp: "bc",
+    magic: [0xf9, 0xbe, 0xb4, 0xd9],
+    default_port: 8333,
+    genesis_hash: "000000000019d6689c085ae165831e934ff763ae46a2a6c172b3f1b60a8ce26f",
+    pow_limit: "00000000ffffffffffffffffffffffffffffffffffffffffffffffffffffffff",
+    pow_allow_min_difficulty_blocks: false,
+    pow_no_retargeting: false,
+};
+
+const TESTNET: NetworkParams = NetworkParams {
+    p2pkh_prefix: 0x6f,
+    p2sh_prefix: 0xc4,
+    wif_prefix: 0xef,
+    xpub_version: [0x04, 0x35, 0x87, 0xcf],
+    xprv_version: [0x04, 0x35, 0x83, 0x94],
+    bech32_hrp: "tb",
+    magic: [0x0b, 0x11, 0x09, 0x07],
+    default_port: 18333,
+    genesis_hash: "000000000933ea01ad0ee984209779baaec3ced90fa3f408719526f8d77f4943",
+    pow_limit: "00000000ffffffffffffffffffffffffffffffffffffffffffffffffffffffff",
+    pow_allow_min_difficulty_blocks: true,
+    pow_no_retargeting: false,
+};
+
+const SIGNET: NetworkParams = NetworkParams {
+    p2pkh_prefix: 0x6f,
+    p2sh_prefix: 0xc4,
+    wif_prefix: 0xef,
+    xpub_version: [0x04, 0x35, 0x87, 0xcf],
+    xprv_version: [0x04, 0x35, 0x83, 0x94],
+    bech32_hrp: "tb",
+    magic: [0x0a, 0x03, 0xcf, 0x40],
+    default_port: 38333,
+    genesis_hash: "00000008819873e925422c1ff0f99f7cc9bbb232af63a077a480a3633bee1ef6",
+    pow_limit: "00000377ae000000000000000000000000000000000000000000000000000000",
+    pow_allow_min_difficulty_blocks: false,
+    pow_no_retargeting: false,
+};
+
+const REGTEST: NetworkParams = NetworkParams {
+    p2pkh_prefix: 0x6f,
+    p2sh_prefix: 0xc4,
+    wif_prefix: 0xef,
+    xpub_version: [0x04, 0x35, 0x87, 0xcf],
+    xprv_version: [0x04, 0x35, 0x83, 0x94],
+    bech32_hrp: "bcrt",
+    magic: [0xfa, 0xbf, 0xb5, 0xda],
+    default_port: 18444,
+    genesis_hash: "0f9188f13cb7b2c71f2a335e3a4fc328bf5beb436012afca590b1a11466e2206",
+    pow_limit: "7fffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffff",
+    pow_allow_min_difficulty_blocks: true,
+    pow_no_retargeting: true,
+};
+
+impl Network {
+    pub fn params(&self) -> &'static NetworkParams {
+        match self {
+            Network::Mainnet => &MAINNET,
+            Network::Testnet => &TESTNET,
+            Network::Signet => &SIGNET,
+            Network::Regtest => &REGTEST,
+        }
+    }
+
+    pub fn all() -> [Network; 4] {
+        [
+            Network::Mainnet,
+            Network::Testnet,
+            Network::Signet,
+            Network::Regtest,
+        ]
+    }
+
+    pub fn from_magic(magic: [u8; 4]) -> Option<Network> {
+        Network::all()
+            .into_iter()
+            .find(|network| network.params().magic == magic)
+    }
+
+    // Genesis hash in the byte order used inside block headers (little endian).
+    pub fn genesis_hash(&self) -> [u8; 32] {
+        let mut hash: [u8; 32] = hex::decode(self.params().genesis_hash)
+            .unwrap()
+            .try_into()
+            .unwrap();
+        hash.reverse();
+        hash
+    }
+
+    pub fn pow_limit(&self) -> BigUint {
+        BigUint::from_str_radix(self.params().pow_limit, 16).unwrap()
+    }
+}
+
+impl fmt::Display for Network {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Network::Mainnet => "main",
+            Network::Testnet => "test",
+            Network::Signet => "signet",
+            Network::Regtest => "regtest",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl FromStr for Network {
+    type Err = Error;
+
+    // Accepts the names bitcoind uses for `-chain=`, plus the long forms.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "main" | "mainnet" | "bitcoin" => Ok(Network::Mainnet),
+            "test" | "testnet" | "testnet3" => Ok(Network::Testnet),
+            "signet" => Ok(Network::Signet),
+            "regtest" => Ok(Network::Regtest),
+            _ => Err(Error::UnknownNetwork(s.to_string())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod network_tests {
+    use super::*;
+
+    #[test]
+    fn parse_and_display_round_trip() {
+        for network in Network::all() {
+            assert_eq!(network.to_string().parse::<Network>().unwrap(), network);
+        }
+        assert_eq!("mainnet".parse::<Network>().unwrap(), Network::Mainnet);
+        assert!("litecoin".parse::<Network>().is_err());
+    }
+
+    #[test]
+    fn magic_identifies_network() {
+        for network in Network::all() {
+            assert_eq!(Network::from_magic(network.params().magic), Some(network));
+        }
+        assert_eq!(Network::from_magic([0, 0, 0, 0]), None);
+    }
+
+    #[test]
+    fn genesis_hash_is_internal_byte_order() {
+        let hash = Network::Mainnet.genesis_hash();
+        assert_eq!(hash[31], 0x00);
+        assert_eq!(hash[0], 0x6f);
+    }
+
+    #[test]
+    fn regtest_has_easiest_pow_limit() {
+        for network in [Network::Mainnet, Network::Testnet, Network::Signet] {
+            assert!(network.pow_limit() < Network::Regtest.pow_limit());
+        }
+    }
+}
@@ -0,0 +1,142 @@
+use std::collections::BTreeMap;
+
+use super::{p2sh_hash, script_pushes, taproot_program, Input, Psbt};
+use crate::error::Error;
+use crate::hash::hash160;
+use crate::taproot::tap_leaf_hash;
+use crate::tx::encode_push;
+
+const OP_CHECKSIG: u8 = 0xac;
+const OP_CHECKMULTISIG: u8 = 0xae;
+
+impl Psbt {
+    // The Finalizer role for every input that is not finalized yet.
+    pub fn finalize(&mut self) -> Result<(), Error> {
+        (0..self.inputs.len()).try_for_each(|index| self.finalize_input(index))
+    }
+
+    // Builds the final script_sig and witness from the partial signatures, then
+    // drops everything but the UTXOs and the fields of the transaction itself.
+    // Handles P2PK, P2PKH, bare multisig and P2WPKH, also nested in P2SH and P2WSH
+    // as they allow, and taproot key path spends and single key leaf scripts.
+    pub fn finalize_input(&mut self, index: usize) -> Result<(), Error> {
+        let input = self
+            .inputs
+            .get_mut(index)
+            .ok_or(Error::InvalidInputIndex(index))?;
+        if input.is_finalized() {
+            return Ok(());
+        }
+        let invalid = |reason: &str| Error::InvalidPsbt(format!("input {}: {}", index, reason));
+        let spent_output = input.spent_output()?.ok_or_else(|| invalid("no UTXO"))?;
+
+        let mut script_sig = Vec::new();
+        let mut script = spent_output.script_pubkey.as_slice();
+        if p2sh_hash(script).is_some() {
+            script = input
+                .redeem_script
+                .as_deref()
+                .ok_or_else(|| invalid("P2SH input without a redeem script"))?;
+            script_sig = encode_push(script);
+        }
+        let witness = match script {
+            [0x00, 0x14, hash @ ..] if hash.len() == 20 => {
+                let p2pkh = [&[0x76, 0xa9, 0x14][..], hash, &[0x88, 0xac]].concat();
+                satisfy(&p2pkh, &input.partial_sigs).ok_or_else(|| invalid("missing signatures"))?
+            }
+            [0x00, 0x20, program @ ..] if program.len() == 32 => {
+                let witness_script = input
+                    .witness_script
+                    .as_deref()
+                    .ok_or_else(|| invalid("P2WSH input without a witness script"))?;
+                let mut witness = satisfy(witness_script, &input.partial_sigs)
+                    .ok_or_else(|| invalid("missing signatures"))?;
+                witness.push(witness_script.to_vec());
+                witness
+            }
+            _ if taproot_program(script).is_some() => {
+                taproot_witness(input).ok_or_else(|| invalid("missing signatures"))?
+            }
+            _ => {
+                let pushes = satisfy(script, &input.partial_sigs)
+                    .ok_or_else(|| invalid("missing signatures"))?;
+                let mut legacy_script_sig: Vec<u8> =
+                    pushes.iter().flat_map(|push| encode_push(push)).collect();
+                legacy_script_sig.extend(script_sig);
+                script_sig = legacy_script_sig;
+                Vec::new()
+            }
+        };
+
+        *input = Input {
+            non_witness_utxo: input.non_witness_utxo.take(),
+            witness_utxo: input.witness_utxo.take(),
+            final_script_sig: Some(script_sig).filter(|script_sig| !script_sig.is_empty()),
+            final_script_witness: Some(witness).filter(|witness| !witness.is_empty()),
+            previous_output: input.previous_output,
+            sequence: input.sequence,
+            required_time_locktime: input.required_time_locktime,
+            required_height_locktime: input.required_height_locktime,
+            unknown: std::mem::take(&mut input.unknown),
+            ..Default::default()
+        };
+        Ok(())
+    }
+}
+
+// The items that satisfy a P2PK, P2PKH or multisig script with the signatures
+// at hand, or None if there are not enough of them.
+fn satisfy(script: &[u8], partial_sigs: &BTreeMap<Vec<u8>, Vec<u8>>) -> Option<Vec<Vec<u8>>> {
+    match script {
+        [0x76, 0xa9, 0x14, hash @ .., 0x88, OP_CHECKSIG] if hash.len() == 20 => partial_sigs
+            .iter()
+            .find(|(sec, _)| hash160(sec) == hash)
+            .map(|(sec, signature)| vec![signature.clone(), sec.clone()]),
+        [len @ (33 | 65), sec @ .., OP_CHECKSIG] if sec.len() == *len as usize => partial_sigs
+            .get(sec)
+            .map(|signature| vec![signature.clone()]),
+        [m @ 0x51..=0x60, .., n @ 0x51..=0x60, OP_CHECKMULTISIG] => {
+            let keys = script_pushes(script);
+            if keys.len() != (n - 0x50) as usize {
+                return None;
+            }
+            // Signatures go in the order of their keys, after the dummy element
+            // CHECKMULTISIG pops one too many.
+            let mut items = vec![Vec::new()];
+            items.extend(
+                keys.iter()
+                    .filter_map(|sec| partial_sigs.get(*sec).cloned())
+                    .take((m - 0x50) as usize),
+            );
+            (items.len() == (m - 0x50) as usize + 1).then_some(items)
+        }
+        _ => None,
+    }
+}
+
+// A key path signature if there is one, else the first leaf of the form
+// <key> OP_CHECKSIG that has a signature.
+fn taproot_witness(input: &Input) -> Option<Vec<Vec<u8>>> {
+    if let Some(signature) = &input.tap_key_sig {
+        return Some(vec![signature.clone()]);
+    }
+    input
+        .tap_leaf_scripts
+        .iter()
+        .find_map(
+            |(control_block, (script, leaf_version))| match script.as_slice() {
+                [0x20, x_only @ .., OP_CHECKSIG] if x_only.len() == 32 => {
+                    let leaf_hash = tap_leaf_hash(script, *leaf_version);
+                    let signature = input
+                        .tap_script_sigs
+                        .get(&(x_only.try_into().unwrap(), leaf_hash))?;
+                    Some(vec![
+                        signature.clone(),
+                        script.clone(),
+                        control_block.clone(),
+                    ])
+                }
+                _ => None,
+            },
+        )
+}
@@ -0,0 +1,762 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::bip32::path::DerivationPath;
+use crate::error::Error;
+use crate::helper::{
+    encode_var_bytes, encode_varint, read_array, read_u32_le, read_u8, read_var_bytes, read_varint,
+};
+use crate::secp256k1::point::Secp256k1Point;
+use crate::tx::locktime::LOCKTIME_THRESHOLD;
+use crate::tx::{OutPoint, Tx, TxOut};
+
+const PSBT_IN_NON_WITNESS_UTXO: u64 = 0x00;
+const PSBT_IN_WITNESS_UTXO: u64 = 0x01;
+const PSBT_IN_PARTIAL_SIG: u64 = 0x02;
+const PSBT_IN_SIGHASH_TYPE: u64 = 0x03;
+const PSBT_IN_REDEEM_SCRIPT: u64 = 0x04;
+const PSBT_IN_WITNESS_SCRIPT: u64 = 0x05;
+const PSBT_IN_BIP32_DERIVATION: u64 = 0x06;
+const PSBT_IN_FINAL_SCRIPTSIG: u64 = 0x07;
+const PSBT_IN_FINAL_SCRIPTWITNESS: u64 = 0x08;
+const PSBT_IN_PREVIOUS_TXID: u64 = 0x0e;
+const PSBT_IN_OUTPUT_INDEX: u64 = 0x0f;
+const PSBT_IN_SEQUENCE: u64 = 0x10;
+const PSBT_IN_REQUIRED_TIME_LOCKTIME: u64 = 0x11;
+const PSBT_IN_REQUIRED_HEIGHT_LOCKTIME: u64 = 0x12;
+const PSBT_IN_TAP_KEY_SIG: u64 = 0x13;
+const PSBT_IN_TAP_SCRIPT_SIG: u64 = 0x14;
+const PSBT_IN_TAP_LEAF_SCRIPT: u64 = 0x15;
+const PSBT_IN_TAP_BIP32_DERIVATION: u64 = 0x16;
+const PSBT_IN_TAP_INTERNAL_KEY: u64 = 0x17;
+const PSBT_IN_TAP_MERKLE_ROOT: u64 = 0x18;
+
+const PSBT_OUT_REDEEM_SCRIPT: u64 = 0x00;
+const PSBT_OUT_WITNESS_SCRIPT: u64 = 0x01;
+const PSBT_OUT_BIP32_DERIVATION: u64 = 0x02;
+const PSBT_OUT_AMOUNT: u64 = 0x03;
+const PSBT_OUT_SCRIPT: u64 = 0x04;
+const PSBT_OUT_TAP_INTERNAL_KEY: u64 = 0x05;
+const PSBT_OUT_TAP_TREE: u64 = 0x06;
+const PSBT_OUT_TAP_BIP32_DERIVATION: u64 = 0x07;
+
+pub(crate) type Pairs = Vec<(Vec<u8>, Vec<u8>)>;
+
+// Where a key came from: the master key's fingerprint and the path from it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeyOrigin {
+    pub fingerprint: [u8; 4],
+    pub path: DerivationPath,
+}
+
+impl KeyOrigin {
+    pub fn new(fingerprint: [u8; 4], path: DerivationPath) -> Self {
+        KeyOrigin { fingerprint, path }
+    }
+
+    pub fn parse(bytes: &[u8]) -> Result<Self, Error> {
+        if bytes.len() < 4 || !bytes.len().is_multiple_of(4) {
+            return Err(Error::InvalidPsbt(format!(
+                "key origin of {} bytes",
+                bytes.len()
+            )));
+        }
+        let mut input = bytes;
+        let fingerprint = read_array(&mut input)?;
+        let mut indexes = Vec::with_capacity(input.len() / 4);
+        while !input.is_empty() {
+            indexes.push(read_u32_le(&mut input)?);
+        }
+        Ok(KeyOrigin::new(fingerprint, DerivationPath::new(indexes)))
+    }
+
+    pub fn serialize(&self) -> Vec<u8> {
+        let mut result = self.fingerprint.to_vec();
+        for index in self.path.indexes() {
+            result.extend(index.to_le_bytes());
+        }
+        result
+    }
+}
+
+// A leaf of an output's taproot tree, in the depth first order BIP371 lists them.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TapTreeLeaf {
+    pub depth: u8,
+    pub leaf_version: u8,
+    pub script: Vec<u8>,
+}
+
+// Per input data. Public keys are kept in their serialized form, SEC for ECDSA
+// and 32 bytes for x-only ones, and checked to be on the curve when parsed.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Input {
+    // The whole transaction holding the spent output, needed for legacy inputs.
+    pub non_witness_utxo: Option<Tx>,
+    pub witness_utxo: Option<TxOut>,
+    pub partial_sigs: BTreeMap<Vec<u8>, Vec<u8>>,
+    pub sighash_type: Option<u32>,
+    pub redeem_script: Option<Vec<u8>>,
+    pub witness_script: Option<Vec<u8>>,
+    pub bip32_derivation: BTreeMap<Vec<u8>, KeyOrigin>,
+    pub final_script_sig: Option<Vec<u8>>,
+    pub final_script_witness: Option<Vec<Vec<u8>>>,
+    // Part of the unsigned transaction in version 0, of the map in version 2.
+    pub previous_output: OutPoint,
+    pub sequence: Option<u32>,
+    // Version 2 only.
+    pub required_time_locktime: Option<u32>,
+    pub required_height_locktime: Option<u32>,
+    pub tap_key_sig: Option<Vec<u8>>,
+    // Keyed by x-only key and leaf hash.
+    pub tap_script_sigs: BTreeMap<([u8; 32], [u8; 32]), Vec<u8>>,
+    // Control block to script and leaf version.
+    pub tap_leaf_scripts: BTreeMap<Vec<u8>, (Vec<u8>, u8)>,
+    // The leaf hashes a key appears in, and where the key came from.
+    pub tap_bip32_derivation: BTreeMap<[u8; 32], (Vec<[u8; 32]>, KeyOrigin)>,
+    pub tap_internal_key: Option<[u8; 32]>,
+    pub tap_merkle_root: Option<[u8; 32]>,
+    // Everything else, proprietary fields included, by full key.
+    pub unknown: BTreeMap<Vec<u8>, Vec<u8>>,
+}
+
+impl Input {
+    pub fn new(previous_output: OutPoint) -> Self {
+        Input {
+            previous_output,
+            ..Default::default()
+        }
+    }
+
+    pub fn is_finalized(&self) -> bool {
+        self.final_script_sig.is_some() || self.final_script_witness.is_some()
+    }
+
+    // The output this input spends, from either UTXO field. A non-witness UTXO
+    // must be the transaction the previous output points into.
+    pub fn spent_output(&self) -> Result<Option<TxOut>, Error> {
+        if let Some(tx) = &self.non_witness_utxo {
+            if tx.txid() != self.previous_output.txid {
+                return Err(Error::InvalidPsbt(format!(
+                    "non-witness UTXO is not the transaction of {}",
+                    self.previous_output
+                )));
+            }
+            return tx
+                .outputs
+                .get(self.previous_output.vout as usize)
+                .cloned()
+                .map(Some)
+                .ok_or_else(|| Error::InvalidPsbt(format!("no output {}", self.previous_output)));
+        }
+        Ok(self.witness_utxo.clone())
+    }
+
+    pub(crate) fn from_pairs(pairs: Pairs, version: u32) -> Result<Self, Error> {
+        let mut input = Input::default();
+        let mut previous_txid = None;
+        let mut output_index = None;
+        for (key, value) in pairs {
+            let (key_type, key_data) = split_key(&key)?;
+            match key_type {
+                PSBT_IN_NON_WITNESS_UTXO => {
+                    no_key_data(key_data)?;
+                    input.non_witness_utxo = Some(Tx::parse(&value)?);
+                }
+                PSBT_IN_WITNESS_UTXO => {
+                    no_key_data(key_data)?;
+                    let mut value = value.as_slice();
+                    input.witness_utxo = Some(TxOut::read(&mut value)?);
+                    no_trailing_bytes(value)?;
+                }
+                PSBT_IN_PARTIAL_SIG => {
+                    input
+                        .partial_sigs
+                        .insert(sec_key(key_data)?.to_vec(), value);
+                }
+                PSBT_IN_SIGHASH_TYPE => {
+                    no_key_data(key_data)?;
+                    input.sighash_type = Some(u32_value(&value)?);
+                }
+                PSBT_IN_REDEEM_SCRIPT => {
+                    no_key_data(key_data)?;
+                    input.redeem_script = Some(value);
+                }
+                PSBT_IN_WITNESS_SCRIPT => {
+                    no_key_data(key_data)?;
+                    input.witness_script = Some(value);
+                }
+                PSBT_IN_BIP32_DERIVATION => {
+                    input
+                        .bip32_derivation
+                        .insert(sec_key(key_data)?.to_vec(), KeyOrigin::parse(&value)?);
+                }
+                PSBT_IN_FINAL_SCRIPTSIG => {
+                    no_key_data(key_data)?;
+                    input.final_script_sig = Some(value);
+                }
+                PSBT_IN_FINAL_SCRIPTWITNESS => {
+                    no_key_data(key_data)?;
+                    input.final_script_witness = Some(parse_witness(&value)?);
+                }
+                // BIP370 forbids its fields in version 0. With key data these
+                // are some other, unknown field, as BIP174's vectors have.
+                PSBT_IN_PREVIOUS_TXID
+                | PSBT_IN_OUTPUT_INDEX
+                | PSBT_IN_SEQUENCE
+                | PSBT_IN_REQUIRED_TIME_LOCKTIME
+                | PSBT_IN_REQUIRED_HEIGHT_LOCKTIME
+                    if version < 2 =>
+                {
+                    if key_data.is_empty() {
+                        return Err(Error::InvalidPsbt(format!(
+                            "version 0 with input type {:#04x}",
+                            key_type
+                        )));
+                    }
+                    input.unknown.insert(key, value);
+                }
+                PSBT_IN_PREVIOUS_TXID => {
+                    no_key_data(key_data)?;
+                    previous_txid = Some(
+                        value
+                            .as_slice()
+                            .try_into()
+                            .map_err(|_| Error::InvalidLength(value.len()))?,
+                    );
+                }
+                PSBT_IN_OUTPUT_INDEX => {
+                    no_key_data(key_data)?;
+                    output_index = Some(u32_value(&value)?);
+                }
+                PSBT_IN_SEQUENCE => {
+                    no_key_data(key_data)?;
+                    input.sequence = Some(u32_value(&value)?);
+                }
+                PSBT_IN_REQUIRED_TIME_LOCKTIME => {
+                    no_key_data(key_data)?;
+                    let locktime = u32_value(&value)?;
+                    if locktime < LOCKTIME_THRESHOLD {
+                        return Err(Error::InvalidPsbt(format!(
+                            "required time locktime {} is a height",
+                            locktime
+                        )));
+                    }
+                    input.required_time_locktime = Some(locktime);
+                }
+                PSBT_IN_REQUIRED_HEIGHT_LOCKTIME => {
+                    no_key_data(key_data)?;
+                    let locktime = u32_value(&value)?;
+                    if locktime == 0 || locktime >= LOCKTIME_THRESHOLD {
+                        return Err(Error::InvalidPsbt(format!(
+                            "required height locktime {} is not a height",
+                            locktime
+                        )));
+                    }
+                    input.required_height_locktime = Some(locktime);
+                }
+                PSBT_IN_TAP_KEY_SIG => {
+                    no_key_data(key_data)?;
+                    input.tap_key_sig = Some(schnorr_signature(value)?);
+                }
+                PSBT_IN_TAP_SCRIPT_SIG => {
+                    if key_data.len() != 64 {
+                        return Err(Error::InvalidPsbt(
+                            "tap script signature key is not an x-only key and leaf hash"
+                                .to_string(),
+                        ));
+                    }
+                    let x_only = x_only_key(&key_data[..32])?;
+                    let leaf_hash = key_data[32..].try_into().unwrap();
+                    input
+                        .tap_script_sigs
+                        .insert((x_only, leaf_hash), schnorr_signature(value)?);
+                }
+                PSBT_IN_TAP_LEAF_SCRIPT => {
+                    // Leaf version and internal key, then up to 128 hashes of the path.
+                    if key_data.len() < 33
+                        || !(key_data.len() - 33).is_multiple_of(32)
+                        || (key_data.len() - 33) / 32 > 128
+                    {
+                        return Err(Error::InvalidPsbt(format!(
+                            "control block of {} bytes",
+                            key_data.len()
+                        )));
+                    }
+                    let mut script = value;
+                    let leaf_version = script.pop().ok_or(Error::UnexpectedEof)?;
+                    input
+                        .tap_leaf_scripts
+                        .insert(key_data.to_vec(), (script, leaf_version));
+                }
+                PSBT_IN_TAP_BIP32_DERIVATION => {
+                    input
+                        .tap_bip32_derivation
+                        .insert(x_only_key(key_data)?, parse_tap_key_origin(&value)?);
+                }
+                PSBT_IN_TAP_INTERNAL_KEY => {
+                    no_key_data(key_data)?;
+                    input.tap_internal_key = Some(x_only_key(&value)?);
+                }
+                PSBT_IN_TAP_MERKLE_ROOT => {
+                    no_key_data(key_data)?;
+                    input.tap_merkle_root = Some(
+                        value
+                            .as_slice()
+                            .try_into()
+                            .map_err(|_| Error::InvalidLength(value.len()))?,
+                    );
+                }
+                _ => {
+                    input.unknown.insert(key, value);
+                }
+            }
+        }
+        if version >= 2 {
+            match (previous_txid, output_index) {
+                (Some(txid), Some(vout)) => input.previous_output = OutPoint::new(txid, vout),
+                _ => {
+                    return Err(Error::InvalidPsbt(
+                        "input without previous txid and output index".to_string(),
+                    ))
+                }
+            }
+        }
+        Ok(input)
+    }
+
+    pub(crate) fn to_pairs(&self, version: u32) -> Pairs {
+        let mut pairs = Pairs::new();
+        if let Some(tx) = &self.non_witness_utxo {
+            pairs.push((key(PSBT_IN_NON_WITNESS_UTXO, &[]), tx.serialize()));
+        }
+        if let Some(tx_out) = &self.witness_utxo {
+            pairs.push((key(PSBT_IN_WITNESS_UTXO, &[]), tx_out.serialize()));
+        }
+        for (sec, signature) in &self.partial_sigs {
+            pairs.push((key(PSBT_IN_PARTIAL_SIG, sec), signature.clone()));
+        }
+        if let Some(sighash_type) = self.sighash_type {
+            pairs.push((
+                key(PSBT_IN_SIGHASH_TYPE, &[]),
+                sighash_type.to_le_bytes().to_vec(),
+            ));
+        }
+        if let Some(script) = &self.redeem_script {
+            pairs.push((key(PSBT_IN_REDEEM_SCRIPT, &[]), script.clone()));
+        }
+        if let Some(script) = &self.witness_script {
+            pairs.push((key(PSBT_IN_WITNESS_SCRIPT, &[]), script.clone()));
+        }
+        for (sec, origin) in &self.bip32_derivation {
+            pairs.push((key(PSBT_IN_BIP32_DERIVATION, sec), origin.serialize()));
+        }
+        if let Some(script_sig) = &self.final_script_sig {
+            pairs.push((key(PSBT_IN_FINAL_SCRIPTSIG, &[]), script_sig.clone()));
+        }
+        if let Some(witness) = &self.final_script_witness {
+            pairs.push((
+                key(PSBT_IN_FINAL_SCRIPTWITNESS, &[]),
+                serialize_witness(witness),
+            ));
+        }
+        if version >= 2 {
+            pairs.push((
+                key(PSBT_IN_PREVIOUS_TXID, &[]),
+                self.previous_output.txid.to_vec(),
+            ));
+            pairs.push((
+                key(PSBT_IN_OUTPUT_INDEX, &[]),
+                self.previous_output.vout.to_le_bytes().to_vec(),
+            ));
+            for (key_type, value) in [
+                (PSBT_IN_SEQUENCE, self.sequence),
+                (PSBT_IN_REQUIRED_TIME_LOCKTIME, self.required_time_locktime),
+                (
+                    PSBT_IN_REQUIRED_HEIGHT_LOCKTIME,
+                    self.required_height_locktime,
+                ),
+            ] {
+                if let Some(value) = value {
+                    pairs.push((key(key_type, &[]), value.to_le_bytes().to_vec()));
+                }
+            }
+        }
+        if let Some(signature) = &self.tap_key_sig {
+            pairs.push((key(PSBT_IN_TAP_KEY_SIG, &[]), signature.clone()));
+        }
+        for ((x_only, leaf_hash), signature) in &self.tap_script_sigs {
+            pairs.push((
+                key(PSBT_IN_TAP_SCRIPT_SIG, &[&x_only[..], leaf_hash].concat()),
+                signature.clone(),
+            ));
+        }
+        for (control_block, (script, leaf_version)) in &self.tap_leaf_scripts {
+            pairs.push((
+                key(PSBT_IN_TAP_LEAF_SCRIPT, control_block),
+                [&script[..], &[*leaf_version]].concat(),
+            ));
+        }
+        for (x_only, origin) in &self.tap_bip32_derivation {
+            pairs.push((
+                key(PSBT_IN_TAP_BIP32_DERIVATION, x_only),
+                serialize_tap_key_origin(origin),
+            ));
+        }
+        if let Some(x_only) = &self.tap_internal_key {
+            pairs.push((key(PSBT_IN_TAP_INTERNAL_KEY, &[]), x_only.to_vec()));
+        }
+        if let Some(root) = &self.tap_merkle_root {
+            pairs.push((key(PSBT_IN_TAP_MERKLE_ROOT, &[]), root.to_vec()));
+        }
+        pairs.extend(self.unknown.clone());
+        pairs
+    }
+
+    // Fills in whatever only `other` has; where both have a value, ours is kept.
+    pub(crate) fn combine(&mut self, other: Input) {
+        combine_option(&mut self.non_witness_utxo, other.non_witness_utxo);
+        combine_option(&mut self.witness_utxo, other.witness_utxo);
+        combine_map(&mut self.partial_sigs, other.partial_sigs);
+        combine_option(&mut self.sighash_type, other.sighash_type);
+        combine_option(&mut self.redeem_script, other.redeem_script);
+        combine_option(&mut self.witness_script, other.witness_script);
+        combine_map(&mut self.bip32_derivation, other.bip32_derivation);
+        combine_option(&mut self.final_script_sig, other.final_script_sig);
+        combine_option(&mut self.final_script_witness, other.final_script_witness);
+        combine_option(&mut self.sequence, other.sequence);
+        combine_option(
+            &mut self.required_time_locktime,
+            other.required_time_locktime,
+        );
+        combine_option(
+            &mut self.required_height_locktime,
+            other.required_height_locktime,
+        );
+        combine_option(&mut self.tap_key_sig, other.tap_key_sig);
+        combine_map(&mut self.tap_script_sigs, other.tap_script_sigs);
+        combine_map(&mut self.tap_leaf_scripts, other.tap_leaf_scripts);
+        combine_map(&mut self.tap_bip32_derivation, other.tap_bip32_derivation);
+        combine_option(&mut self.tap_internal_key, other.tap_internal_key);
+        combine_option(&mut self.tap_merkle_root, other.tap_merkle_root);
+        combine_map(&mut self.unknown, other.unknown);
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Output {
+    // Part of the unsigned transaction in version 0, of the map in version 2.
+    pub amount: u64,
+    pub script_pubkey: Vec<u8>,
+    pub redeem_script: Option<Vec<u8>>,
+    pub witness_script: Option<Vec<u8>>,
+    pub bip32_derivation: BTreeMap<Vec<u8>, KeyOrigin>,
+    pub tap_internal_key: Option<[u8; 32]>,
+    pub tap_tree: Option<Vec<TapTreeLeaf>>,
+    pub tap_bip32_derivation: BTreeMap<[u8; 32], (Vec<[u8; 32]>, KeyOrigin)>,
+    pub unknown: BTreeMap<Vec<u8>, Vec<u8>>,
+}
+
+impl Output {
+    pub fn new(amount: u64, script_pubkey: Vec<u8>) -> Self {
+        Output {
+            amount,
+            script_pubkey,
+            ..Default::default()
+        }
+    }
+
+    pub fn tx_out(&self) -> TxOut {
+        TxOut::new(self.amount, self.script_pubkey.clone())
+    }
+
+    pub(crate) fn from_pairs(pairs: Pairs, version: u32) -> Result<Self, Error> {
+        let mut output = Output::default();
+        let mut amount = None;
+        let mut script_pubkey = None;
+        for (key, value) in pairs {
+            let (key_type, key_data) = split_key(&key)?;
+            match key_type {
+                PSBT_OUT_REDEEM_SCRIPT => {
+                    no_key_data(key_data)?;
+                    output.redeem_script = Some(value);
+                }
+                PSBT_OUT_WITNESS_SCRIPT => {
+                    no_key_data(key_data)?;
+                    output.witness_script = Some(value);
+                }
+                PSBT_OUT_BIP32_DERIVATION => {
+                    output
+                        .bip32_derivation
+                        .insert(sec_key(key_data)?.to_vec(), KeyOrigin::parse(&value)?);
+                }
+                PSBT_OUT_AMOUNT | PSBT_OUT_SCRIPT if version < 2 => {
+                    if key_data.is_empty() {
+                        return Err(Error::InvalidPsbt(format!(
+                            "version 0 with output type {:#04x}",
+                            key_type
+                        )));
+                    }
+                    output.unknown.insert(key, value);
+                }
+                PSBT_OUT_AMOUNT => {
+                    no_key_data(key_data)?;
+                    let mut value = value.as_slice();
+                    amount = Some(u64::from_le_bytes(read_array(&mut value)?));
+                    no_trailing_bytes(value)?;
+                }
+                PSBT_OUT_SCRIPT => {
+                    no_key_data(key_data)?;
+                    script_pubkey = Some(value);
+                }
+                PSBT_OUT_TAP_INTERNAL_KEY => {
+                    no_key_data(key_data)?;
+                    output.tap_internal_key = Some(x_only_key(&value)?);
+                }
+                PSBT_OUT_TAP_TREE => {
+                    no_key_data(key_data)?;
+                    output.tap_tree = Some(parse_tap_tree(&value)?);
+                }
+                PSBT_OUT_TAP_BIP32_DERIVATION => {
+                    output
+                        .tap_bip32_derivation
+                        .insert(x_only_key(key_data)?, parse_tap_key_origin(&value)?);
+                }
+                _ => {
+                    output.unknown.insert(key, value);
+                }
+            }
+        }
+        if version >= 2 {
+            match (amount, script_pubkey) {
+                (Some(amount), Some(script_pubkey)) => {
+                    output.amount = amount;
+                    output.script_pubkey = script_pubkey;
+                }
+                _ => {
+                    return Err(Error::InvalidPsbt(
+                        "output without amount and script".to_string(),
+                    ))
+                }
+            }
+        }
+        Ok(output)
+    }
+
+    pub(crate) fn to_pairs(&self, version: u32) -> Pairs {
+        let mut pairs = Pairs::new();
+        if let Some(script) = &self.redeem_script {
+            pairs.push((key(PSBT_OUT_REDEEM_SCRIPT, &[]), script.clone()));
+        }
+        if let Some(script) = &self.witness_script {
+            pairs.push((key(PSBT_OUT_WITNESS_SCRIPT, &[]), script.clone()));
+        }
+        for (sec, origin) in &self.bip32_derivation {
+            pairs.push((key(PSBT_OUT_BIP32_DERIVATION, sec), origin.serialize()));
+        }
+        if version >= 2 {
+            pairs.push((
+                key(PSBT_OUT_AMOUNT, &[]),
+                self.amount.to_le_bytes().to_vec(),
+            ));
+            pairs.push((key(PSBT_OUT_SCRIPT, &[]), self.script_pubkey.clone()));
+        }
+        if let Some(x_only) = &self.tap_internal_key {
+            pairs.push((key(PSBT_OUT_TAP_INTERNAL_KEY, &[]), x_only.to_vec()));
+        }
+        if let Some(leaves) = &self.tap_tree {
+            let mut value = Vec::new();
+            for leaf in leaves {
+                value.extend([leaf.depth, leaf.leaf_version]);
+                value.extend(encode_var_bytes(&leaf.script));
+            }
+            pairs.push((key(PSBT_OUT_TAP_TREE, &[]), value));
+        }
+        for (x_only, origin) in &self.tap_bip32_derivation {
+            pairs.push((
+                key(PSBT_OUT_TAP_BIP32_DERIVATION, x_only),
+                serialize_tap_key_origin(origin),
+            ));
+        }
+        pairs.extend(self.unknown.clone());
+        pairs
+    }
+
+    pub(crate) fn combine(&mut self, other: Output) {
+        combine_option(&mut self.redeem_script, other.redeem_script);
+        combine_option(&mut self.witness_script, other.witness_script);
+        combine_map(&mut self.bip32_derivation, other.bip32_derivation);
+        combine_option(&mut self.tap_internal_key, other.tap_internal_key);
+        combine_option(&mut self.tap_tree, other.tap_tree);
+        combine_map(&mut self.tap_bip32_derivation, other.tap_bip32_derivation);
+        combine_map(&mut self.unknown, other.unknown);
+    }
+}
+
+// Reads one map up to its 0x00 separator. Keys must be unique within a map.
+pub(crate) fn read_map(input: &mut &[u8]) -> Result<Pairs, Error> {
+    let mut pairs = Pairs::new();
+    let mut seen = BTreeSet::new();
+    loop {
+        let key = read_var_bytes(input)?;
+        if key.is_empty() {
+            return Ok(pairs);
+        }
+        let value = read_var_bytes(input)?;
+        if !seen.insert(key.clone()) {
+            return Err(Error::InvalidPsbt(format!(
+                "duplicate key {}",
+                hex::encode(&key)
+            )));
+        }
+        pairs.push((key, value));
+    }
+}
+
+// Pairs are written in key order, which is also the order of the types.
+pub(crate) fn serialize_map(mut pairs: Pairs) -> Vec<u8> {
+    pairs.sort();
+    let mut result = Vec::new();
+    for (key, value) in pairs {
+        result.extend(encode_var_bytes(&key));
+        result.extend(encode_var_bytes(&value));
+    }
+    result.push(0x00);
+    result
+}
+
+pub(crate) fn key(key_type: u64, key_data: &[u8]) -> Vec<u8> {
+    [encode_varint(key_type), key_data.to_vec()].concat()
+}
+
+pub(crate) fn split_key(key: &[u8]) -> Result<(u64, &[u8]), Error> {
+    let mut key_data = key;
+    let key_type = read_varint(&mut key_data)?;
+    Ok((key_type, key_data))
+}
+
+pub(crate) fn no_key_data(key_data: &[u8]) -> Result<(), Error> {
+    if !key_data.is_empty() {
+        return Err(Error::InvalidPsbt(format!(
+            "unexpected key data {}",
+            hex::encode(key_data)
+        )));
+    }
+    Ok(())
+}
+
+fn no_trailing_bytes(rest: &[u8]) -> Result<(), Error> {
+    if !rest.is_empty() {
+        return Err(Error::TrailingBytes(rest.len()));
+    }
+    Ok(())
+}
+
+pub(crate) fn u32_value(value: &[u8]) -> Result<u32, Error> {
+    Ok(u32::from_le_bytes(
+        value
+            .try_into()
+            .map_err(|_| Error::InvalidLength(value.len()))?,
+    ))
+}
+
+fn sec_key(bytes: &[u8]) -> Result<&[u8], Error> {
+    if bytes.len() != 33 && bytes.len() != 65 {
+        return Err(Error::InvalidPsbt(format!(
+            "public key of {} bytes",
+            bytes.len()
+        )));
+    }
+    Secp256k1Point::parse_sec(bytes)?;
+    Ok(bytes)
+}
+
+fn x_only_key(bytes: &[u8]) -> Result<[u8; 32], Error> {
+    if bytes.len() != 32 {
+        return Err(Error::InvalidPsbt(format!(
+            "x-only public key of {} bytes",
+            bytes.len()
+        )));
+    }
+    Secp256k1Point::lift_x(bytes)?;
+    Ok(bytes.try_into().unwrap())
+}
+
+// 64 bytes, or 65 with an explicit sighash type that is not SIGHASH_DEFAULT.
+fn schnorr_signature(value: Vec<u8>) -> Result<Vec<u8>, Error> {
+    match value.len() {
+        64 => Ok(value),
+        65 if value[64] != 0x00 => Ok(value),
+        len => Err(Error::InvalidPsbt(format!(
+            "taproot signature of {} bytes",
+            len
+        ))),
+    }
+}
+
+fn parse_witness(value: &[u8]) -> Result<Vec<Vec<u8>>, Error> {
+    let mut input = value;
+    let count = read_varint(&mut input)?;
+    let witness = (0..count)
+        .map(|_| read_var_bytes(&mut input))
+        .collect::<Result<_, _>>()?;
+    no_trailing_bytes(input)?;
+    Ok(witness)
+}
+
+fn serialize_witness(witness: &[Vec<u8>]) -> Vec<u8> {
+    let mut result = encode_varint(witness.len() as u64);
+    for item in witness {
+        result.extend(encode_var_bytes(item));
+    }
+    result
+}
+
+fn parse_tap_key_origin(value: &[u8]) -> Result<(Vec<[u8; 32]>, KeyOrigin), Error> {
+    let mut input = value;
+    let count = read_varint(&mut input)?;
+    let leaf_hashes = (0..count)
+        .map(|_| read_array(&mut input))
+        .collect::<Result<_, _>>()?;
+    Ok((leaf_hashes, KeyOrigin::parse(input)?))
+}
+
+fn serialize_tap_key_origin((leaf_hashes, origin): &(Vec<[u8; 32]>, KeyOrigin)) -> Vec<u8> {
+    let mut result = encode_varint(leaf_hashes.len() as u64);
+    for leaf_hash in leaf_hashes {
+        result.extend(leaf_hash);
+    }
+    result.extend(origin.serialize());
+    result
+}
+
+fn parse_tap_tree(value: &[u8]) -> Result<Vec<TapTreeLeaf>, Error> {
+    let mut input = value;
+    let mut leaves = Vec::new();
+    while !input.is_empty() {
+        let depth = read_u8(&mut input)?;
+        if depth > 128 {
+            return Err(Error::InvalidPsbt(format!("tap tree depth {}", depth)));
+        }
+        leaves.push(TapTreeLeaf {
+            depth,
+            leaf_version: read_u8(&mut input)?,
+            script: read_var_bytes(&mut input)?,
+        });
+    }
+    if leaves.is_empty() {
+        return Err(Error::InvalidPsbt("empty tap tree".to_string()));
+    }
+    Ok(leaves)
+}
+
+pub(crate) fn combine_option<T>(ours: &mut Option<T>, theirs: Option<T>) {
+    if ours.is_none() {
+        *ours = theirs;
+    }
+}
+
+pub(crate) fn combine_map<K: Ord, V>(ours: &mut BTreeMap<K, V>, theirs: BTreeMap<K, V>) {
+    for (key, value) in theirs {
+        ours.entry(key).or_insert(value);
+    }
+}
@@ -0,0 +1,910 @@
+use std::collections::BTreeMap;
+
+use crate::base64::{decode_base64, encode_base64};
+use crate::error::Error;
+use crate::helper::{decode_hex, encode_varint, read_bytes, read_varint};
+use crate::tx::{read_op, Tx, TxIn, SEQUENCE_FINAL};
+
+pub mod finalize;
+pub mod map;
+pub mod sign;
+pub mod update;
+
+use self::map::{
+    combine_map, key, no_key_data, read_map, serialize_map, split_key, u32_value, Pairs,
+};
+pub use self::map::{Input, KeyOrigin, Output, TapTreeLeaf};
+
+const PSBT_MAGIC: &[u8; 5] = b"psbt\xff";
+
+const PSBT_GLOBAL_UNSIGNED_TX: u64 = 0x00;
+const PSBT_GLOBAL_XPUB: u64 = 0x01;
+const PSBT_GLOBAL_TX_VERSION: u64 = 0x02;
+const PSBT_GLOBAL_FALLBACK_LOCKTIME: u64 = 0x03;
+const PSBT_GLOBAL_INPUT_COUNT: u64 = 0x04;
+const PSBT_GLOBAL_OUTPUT_COUNT: u64 = 0x05;
+const PSBT_GLOBAL_TX_MODIFIABLE: u64 = 0x06;
+const PSBT_GLOBAL_VERSION: u64 = 0xfb;
+
+// PSBT_GLOBAL_TX_MODIFIABLE flags.
+pub const INPUTS_MODIFIABLE: u8 = 0x01;
+pub const OUTPUTS_MODIFIABLE: u8 = 0x02;
+
+// A partially signed transaction, BIP174 version 0 or BIP370 version 2.
+//
+// Both are held the same way: version 0's unsigned transaction is split into the
+// fields version 2 keeps in its maps (the outpoint and sequence of each input,
+// the amount and script of each output) and put back together when serialized.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Psbt {
+    pub version: u32,
+    pub tx_version: u32,
+    // Version 0 always has one, the unsigned transaction's.
+    pub fallback_locktime: Option<u32>,
+    // Version 2 only.
+    pub tx_modifiable: Option<u8>,
+    // Serialized extended public keys.
+    pub xpubs: BTreeMap<[u8; 78], KeyOrigin>,
+    pub unknown: BTreeMap<Vec<u8>, Vec<u8>>,
+    pub inputs: Vec<Input>,
+    pub outputs: Vec<Output>,
+}
+
+impl Psbt {
+    // The Creator role for version 0. The transaction must not be signed yet.
+    pub fn from_unsigned_tx(tx: Tx) -> Result<Self, Error> {
+        if tx
+            .inputs
+            .iter()
+            .any(|tx_in| !tx_in.script_sig.is_empty() || !tx_in.witness.is_empty())
+        {
+            return Err(Error::InvalidPsbt(
+                "unsigned transaction has signatures".to_string(),
+            ));
+        }
+        Ok(Psbt {
+            version: 0,
+            tx_version: tx.version,
+            fallback_locktime: Some(tx.locktime),
+            tx_modifiable: None,
+            xpubs: BTreeMap::new(),
+            unknown: BTreeMap::new(),
+            inputs: tx
+                .inputs
+                .iter()
+                .map(|tx_in| Input {
+                    sequence: Some(tx_in.sequence),
+                    ..Input::new(tx_in.previous_output)
+                })
+                .collect(),
+            outputs: tx
+                .outputs
+                .into_iter()
+                .map(|tx_out| Output::new(tx_out.value, tx_out.script_pubkey))
+                .collect(),
+        })
+    }
+
+    // The Creator role for version 2: no inputs or outputs yet, and both may be added.
+    pub fn new_v2(tx_version: u32, fallback_locktime: Option<u32>) -> Self {
+        Psbt {
+            version: 2,
+            tx_version,
+            fallback_locktime,
+            tx_modifiable: Some(INPUTS_MODIFIABLE | OUTPUTS_MODIFIABLE),
+            xpubs: BTreeMap::new(),
+            unknown: BTreeMap::new(),
+            inputs: Vec::new(),
+            outputs: Vec::new(),
+        }
+    }
+
+    // The Constructor role, version 2 only.
+    pub fn add_input(&mut self, input: Input) -> Result<(), Error> {
+        self.check_modifiable(INPUTS_MODIFIABLE)?;
+        self.inputs.push(input);
+        Ok(())
+    }
+
+    pub fn add_output(&mut self, output: Output) -> Result<(), Error> {
+        self.check_modifiable(OUTPUTS_MODIFIABLE)?;
+        self.outputs.push(output);
+        Ok(())
+    }
+
+    fn check_modifiable(&self, flag: u8) -> Result<(), Error> {
+        if self.version < 2 || self.tx_modifiable.unwrap_or(0) & flag == 0 {
+            return Err(Error::InvalidPsbt(
+                "transaction cannot be modified".to_string(),
+            ));
+        }
+        Ok(())
+    }
+
+    pub fn parse(bytes: &[u8]) -> Result<Self, Error> {
+        let mut input = bytes;
+        if read_bytes(&mut input, PSBT_MAGIC.len())? != PSBT_MAGIC {
+            return Err(Error::InvalidPsbt("missing magic bytes".to_string()));
+        }
+        let global = read_map(&mut input)?;
+        let mut version = 0;
+        for (key, value) in &global {
+            if split_key(key)?.0 == PSBT_GLOBAL_VERSION {
+                version = u32_value(value)?;
+            }
+        }
+        if version != 0 && version != 2 {
+            return Err(Error::InvalidPsbt(format!(
+                "unsupported version {}",
+                version
+            )));
+        }
+
+        let mut psbt = Psbt {
+            version,
+            tx_modifiable: None,
+            ..Psbt::new_v2(0, None)
+        };
+        let mut unsigned_tx = None;
+        let mut input_count = None;
+        let mut output_count = None;
+        let mut tx_version = None;
+        for (key, value) in global {
+            let (key_type, key_data) = split_key(&key)?;
+            match key_type {
+                PSBT_GLOBAL_UNSIGNED_TX => {
+                    no_key_data(key_data)?;
+                    if version >= 2 {
+                        return Err(Error::InvalidPsbt(
+                            "version 2 with an unsigned transaction".to_string(),
+                        ));
+                    }
+                    unsigned_tx = Some(Tx::parse_legacy(&value)?);
+                }
+                // BIP370 forbids its fields in version 0. With key data these
+                // are some other, unknown field, as BIP174's vectors have.
+                PSBT_GLOBAL_TX_VERSION
+                | PSBT_GLOBAL_FALLBACK_LOCKTIME
+                | PSBT_GLOBAL_INPUT_COUNT
+                | PSBT_GLOBAL_OUTPUT_COUNT
+                | PSBT_GLOBAL_TX_MODIFIABLE
+                    if version < 2 =>
+                {
+                    if key_data.is_empty() {
+                        return Err(Error::InvalidPsbt(format!(
+                            "version 0 with global type {:#04x}",
+                            key_type
+                        )));
+                    }
+                    psbt.unknown.insert(key, value);
+                }
+                PSBT_GLOBAL_XPUB => {
+                    let xpub = key_data
+                        .try_into()
+                        .map_err(|_| Error::InvalidLength(key_data.len()))?;
+                    psbt.xpubs.insert(xpub, KeyOrigin::parse(&value)?);
+                }
+                PSBT_GLOBAL_TX_VERSION => {
+                    no_key_data(key_data)?;
+                    tx_version = Some(u32_value(&value)?);
+                }
+                PSBT_GLOBAL_FALLBACK_LOCKTIME => {
+                    no_key_data(key_data)?;
+                    psbt.fallback_locktime = Some(u32_value(&value)?);
+                }
+                PSBT_GLOBAL_INPUT_COUNT => {
+                    no_key_data(key_data)?;
+                    input_count = Some(read_count(&value)?);
+                }
+                PSBT_GLOBAL_OUTPUT_COUNT => {
+                    no_key_data(key_data)?;
+                    output_count = Some(read_count(&value)?);
+                }
+                PSBT_GLOBAL_TX_MODIFIABLE => {
+                    no_key_data(key_data)?;
+                    match value.as_slice() {
+                        [flags] => psbt.tx_modifiable = Some(*flags),
+                        _ => return Err(Error::InvalidLength(value.len())),
+                    }
+                }
+                PSBT_GLOBAL_VERSION => no_key_data(key_data)?,
+                _ => {
+                    psbt.unknown.insert(key, value);
+                }
+            }
+        }
+
+        if version == 0 {
+            let tx = unsigned_tx
+                .ok_or_else(|| Error::InvalidPsbt("no unsigned transaction".to_string()))?;
+            let skeleton = Psbt::from_unsigned_tx(tx)?;
+            psbt.tx_version = skeleton.tx_version;
+            psbt.fallback_locktime = skeleton.fallback_locktime;
+            for skeleton in skeleton.inputs {
+                psbt.inputs.push(Input {
+                    previous_output: skeleton.previous_output,
+                    sequence: skeleton.sequence,
+                    ..Input::from_pairs(read_map(&mut input)?, version)?
+                });
+            }
+            for skeleton in skeleton.outputs {
+                psbt.outputs.push(Output {
+                    amount: skeleton.amount,
+                    script_pubkey: skeleton.script_pubkey,
+                    ..Output::from_pairs(read_map(&mut input)?, version)?
+                });
+            }
+        } else {
+            let missing = |field: &str| Error::InvalidPsbt(format!("no {}", field));
+            psbt.tx_version = tx_version.ok_or_else(|| missing("transaction version"))?;
+            let input_count = input_count.ok_or_else(|| missing("input count"))?;
+            let output_count = output_count.ok_or_else(|| missing("output count"))?;
+            // Counts are untrusted, so no capacity is reserved for them.
+            for _ in 0..input_count {
+                psbt.inputs
+                    .push(Input::from_pairs(read_map(&mut input)?, version)?);
+            }
+            for _ in 0..output_count {
+                psbt.outputs
+                    .push(Output::from_pairs(read_map(&mut input)?, version)?);
+            }
+        }
+        if !input.is_empty() {
+            return Err(Error::TrailingBytes(input.len()));
+        }
+        Ok(psbt)
+    }
+
+    pub fn from_hex(s: &str) -> Result<Self, Error> {
+        Psbt::parse(&decode_hex(s)?)
+    }
+
+    pub fn from_base64(s: &str) -> Result<Self, Error> {
+        Psbt::parse(&decode_base64(s)?)
+    }
+
+    pub fn serialize(&self) -> Vec<u8> {
+        let mut global = Pairs::new();
+        if self.version >= 2 {
+            global.push((
+                key(PSBT_GLOBAL_TX_VERSION, &[]),
+                self.tx_version.to_le_bytes().to_vec(),
+            ));
+            if let Some(locktime) = self.fallback_locktime {
+                global.push((
+                    key(PSBT_GLOBAL_FALLBACK_LOCKTIME, &[]),
+                    locktime.to_le_bytes().to_vec(),
+                ));
+            }
+            global.push((
+                key(PSBT_GLOBAL_INPUT_COUNT, &[]),
+                encode_varint(self.inputs.len() as u64),
+            ));
+            global.push((
+                key(PSBT_GLOBAL_OUTPUT_COUNT, &[]),
+                encode_varint(self.outputs.len() as u64),
+            ));
+            if let Some(flags) = self.tx_modifiable {
+                global.push((key(PSBT_GLOBAL_TX_MODIFIABLE, &[]), vec![flags]));
+            }
+        } else {
+            let tx = self.unsigned_tx_with_locktime(self.fallback_locktime.unwrap_or(0));
+            global.push((key(PSBT_GLOBAL_UNSIGNED_TX, &[]), tx.serialize_legacy()));
+        }
+        for (xpub, origin) in &self.xpubs {
+            global.push((key(PSBT_GLOBAL_XPUB, xpub), origin.serialize()));
+        }
+        if self.version > 0 {
+            global.push((
+                key(PSBT_GLOBAL_VERSION, &[]),
+                self.version.to_le_bytes().to_vec(),
+            ));
+        }
+        global.extend(self.unknown.clone());
+
+        let mut result = PSBT_MAGIC.to_vec();
+        result.extend(serialize_map(global));
+        for input in &self.inputs {
+            result.extend(serialize_map(input.to_pairs(self.version)));
+        }
+        for output in &self.outputs {
+            result.extend(serialize_map(output.to_pairs(self.version)));
+        }
+        result
+    }
+
+    pub fn to_hex(&self) -> String {
+        hex::encode(self.serialize())
+    }
+
+    pub fn to_base64(&self) -> String {
+        encode_base64(&self.serialize())
+    }
+
+    // BIP370's locktime: the largest required locktime of the kind every input
+    // with a requirement can accept, heights winning a tie, or the fallback if
+    // no input has one.
+    pub fn locktime(&self) -> Result<u32, Error> {
+        let constrained: Vec<&Input> = self
+            .inputs
+            .iter()
+            .filter(|input| {
+                input.required_time_locktime.is_some() || input.required_height_locktime.is_some()
+            })
+            .collect();
+        if constrained.is_empty() {
+            return Ok(self.fallback_locktime.unwrap_or(0));
+        }
+        if constrained
+            .iter()
+            .all(|input| input.required_height_locktime.is_some())
+        {
+            return Ok(constrained
+                .iter()
+                .filter_map(|input| input.required_height_locktime)
+                .max()
+                .unwrap());
+        }
+        if constrained
+            .iter()
+            .all(|input| input.required_time_locktime.is_some())
+        {
+            return Ok(constrained
+                .iter()
+                .filter_map(|input| input.required_time_locktime)
+                .max()
+                .unwrap());
+        }
+        Err(Error::InvalidPsbt(
+            "inputs require both a height and a time locktime".to_string(),
+        ))
+    }
+
+    // The transaction being signed, without any signatures.
+    pub fn unsigned_tx(&self) -> Result<Tx, Error> {
+        Ok(self.unsigned_tx_with_locktime(self.locktime()?))
+    }
+
+    fn unsigned_tx_with_locktime(&self, locktime: u32) -> Tx {
+        let inputs = self
+            .inputs
+            .iter()
+            .map(|input| TxIn {
+                sequence: input.sequence.unwrap_or(SEQUENCE_FINAL),
+                ..TxIn::new(input.previous_output)
+            })
+            .collect();
+        let outputs = self.outputs.iter().map(Output::tx_out).collect();
+        Tx::new(self.tx_version, inputs, outputs, locktime)
+    }
+
+    // The Combiner role: merges what another participant added to the same
+    // transaction into this one. Where both have a value for a key ours is kept.
+    pub fn combine(&mut self, other: Psbt) -> Result<(), Error> {
+        if self.version != other.version || self.unsigned_tx()? != other.unsigned_tx()? {
+            return Err(Error::InvalidPsbt(
+                "cannot combine PSBTs of different transactions".to_string(),
+            ));
+        }
+        combine_map(&mut self.xpubs, other.xpubs);
+        combine_map(&mut self.unknown, other.unknown);
+        for (input, other) in self.inputs.iter_mut().zip(other.inputs) {
+            input.combine(other);
+        }
+        for (output, other) in self.outputs.iter_mut().zip(other.outputs) {
+            output.combine(other);
+        }
+        Ok(())
+    }
+
+    // The Extractor role: the signed transaction, once every input is finalized.
+    pub fn extract_tx(&self) -> Result<Tx, Error> {
+        let mut tx = self.unsigned_tx()?;
+        for (index, (tx_in, input)) in tx.inputs.iter_mut().zip(&self.inputs).enumerate() {
+            if !input.is_finalized() {
+                return Err(Error::InvalidPsbt(format!(
+                    "input {} is not finalized",
+                    index
+                )));
+            }
+            tx_in.script_sig = input.final_script_sig.clone().unwrap_or_default();
+            tx_in.witness = input.final_script_witness.clone().unwrap_or_default();
+        }
+        Ok(tx)
+    }
+}
+
+// The data of every push in a script, up to anything that does not parse.
+pub(crate) fn script_pushes(script: &[u8]) -> Vec<&[u8]> {
+    let mut rest = script;
+    let mut pushes = Vec::new();
+    while let Ok((opcode, data)) = read_op(&mut rest) {
+        if (0x01..=0x4e).contains(&opcode) {
+            pushes.push(data);
+        }
+    }
+    pushes
+}
+
+// The script hash of a P2SH script_pubkey.
+pub(crate) fn p2sh_hash(script_pubkey: &[u8]) -> Option<&[u8]> {
+    match script_pubkey {
+        [0xa9, 0x14, hash @ .., 0x87] if hash.len() == 20 => Some(hash),
+        _ => None,
+    }
+}
+
+// The output key of a segwit v1 script_pubkey.
+pub(crate) fn taproot_program(script_pubkey: &[u8]) -> Option<&[u8]> {
+    match script_pubkey {
+        [0x51, 0x20, program @ ..] if program.len() == 32 => Some(program),
+        _ => None,
+    }
+}
+
+fn read_count(value: &[u8]) -> Result<usize, Error> {
+    let mut input = value;
+    let count = read_varint(&mut input)?;
+    if !input.is_empty() {
+        return Err(Error::TrailingBytes(input.len()));
+    }
+    Ok(count as usize)
+}
+
+#[cfg(test)]
+mod psbt_tests {
+    use super::*;
+    use crate::bip32::path::DerivationPath;
+    use crate::bip32::ExtendedPrivKey;
+    use crate::helper::hash_to_hex;
+    use crate::network::Network;
+    use crate::secp256k1::private_key::PrivateKey;
+    use crate::taproot;
+    use crate::tx::{OutPoint, TxOut};
+
+    // BIP174's, BIP370's and BIP371's test vectors, with the signer, combiner
+    // and extractor ones as rust-bitcoin vendors them.
+    fn vectors() -> serde_json::Value {
+        serde_json::from_str(include_str!("../../test_data/bip174_tests.json")).unwrap()
+    }
+
+    fn signer_vector(name: &str) -> String {
+        vectors()["signer"][name].as_str().unwrap().to_string()
+    }
+
+    fn bip174_master() -> ExtendedPrivKey {
+        ExtendedPrivKey::decode(&signer_vector("master"), Network::Testnet).unwrap()
+    }
+
+    fn bip174_key(index: u32) -> PrivateKey {
+        let path = format!("m/0'/0'/{}'", index).parse().unwrap();
+        bip174_master()
+            .derive_path(&path)
+            .unwrap()
+            .private_key()
+            .clone()
+    }
+
+    // The combined PSBT as the updater leaves it, before anyone signs.
+    fn bip174_updated() -> Psbt {
+        let mut psbt = Psbt::from_hex(&signer_vector("combined")).unwrap();
+        for input in &mut psbt.inputs {
+            input.partial_sigs.clear();
+        }
+        psbt
+    }
+
+    #[test]
+    fn bip174_invalid_vectors() {
+        for vector in vectors()["invalid"].as_array().unwrap() {
+            let hex = vector["hex"].as_str().unwrap();
+            assert!(Psbt::from_hex(hex).is_err(), "{}", vector["comment"]);
+            if let Some(base64) = vector["base64"].as_str() {
+                assert!(Psbt::from_base64(base64).is_err(), "{}", vector["comment"]);
+            }
+        }
+    }
+
+    #[test]
+    fn bip174_valid_vectors() {
+        for vector in vectors()["valid"].as_array().unwrap() {
+            let hex = vector["hex"].as_str().unwrap();
+            let psbt = Psbt::from_hex(hex).unwrap();
+            assert_eq!(psbt.to_hex(), hex, "{}", vector["comment"]);
+            let comment = vector["comment"].as_str().unwrap();
+            let version = if comment.starts_with("version 2") {
+                2
+            } else {
+                0
+            };
+            assert_eq!(psbt.version, version, "{}", comment);
+            if let Some(base64) = vector["base64"].as_str() {
+                assert_eq!(Psbt::from_base64(base64).unwrap(), psbt);
+                assert_eq!(psbt.to_base64(), base64);
+            }
+        }
+    }
+
+    #[test]
+    fn parses_bip174_fields() {
+        let valid = &vectors()["valid"];
+        let psbt = Psbt::from_hex(valid[0]["hex"].as_str().unwrap()).unwrap();
+        assert_eq!(psbt.version, 0);
+        assert_eq!(psbt.tx_version, 2);
+        assert_eq!(psbt.locktime().unwrap(), 1257139);
+        assert_eq!(
+            hash_to_hex(&psbt.inputs[0].previous_output.txid),
+            "f61b1742ca13176464adb3cb66050c00787bb3a4eead37e985f2df1e37718126"
+        );
+        assert_eq!(psbt.inputs[0].sequence, Some(0xffff_fffe));
+        assert!(psbt.inputs[0].non_witness_utxo.is_some());
+        assert_eq!(psbt.outputs[1].amount, 100_000_000);
+
+        let psbt = Psbt::from_hex(valid[1]["hex"].as_str().unwrap()).unwrap();
+        assert!(psbt.inputs[0].is_finalized());
+        assert_eq!(
+            psbt.inputs[1].witness_utxo.as_ref().unwrap().value,
+            100_000_000
+        );
+
+        let psbt = Psbt::from_hex(valid[2]["hex"].as_str().unwrap()).unwrap();
+        assert_eq!(psbt.inputs[0].sighash_type, Some(1));
+
+        let psbt = Psbt::from_hex(valid[5]["hex"].as_str().unwrap()).unwrap();
+        assert_eq!(psbt.inputs[0].unknown.len(), 1);
+
+        // Taproot key path input, then script path input with signatures.
+        let psbt = Psbt::from_hex(valid[6]["hex"].as_str().unwrap()).unwrap();
+        let internal_key = psbt.inputs[0].tap_internal_key.unwrap();
+        assert!(psbt.inputs[0]
+            .tap_bip32_derivation
+            .contains_key(&internal_key));
+        let psbt = Psbt::from_hex(valid[11]["hex"].as_str().unwrap()).unwrap();
+        let input = &psbt.inputs[0];
+        assert!(input.tap_merkle_root.is_some());
+        for (script, leaf_version) in input.tap_leaf_scripts.values() {
+            let leaf_hash = taproot::tap_leaf_hash(script, *leaf_version);
+            assert!(input
+                .tap_script_sigs
+                .keys()
+                .any(|(_, signed_leaf)| *signed_leaf == leaf_hash));
+        }
+    }
+
+    #[test]
+    fn bip174_updater() {
+        let expected = bip174_updated();
+        let mut psbt = Psbt::from_unsigned_tx(expected.unsigned_tx().unwrap()).unwrap();
+        for (input, expected) in psbt.inputs.iter_mut().zip(&expected.inputs) {
+            input.non_witness_utxo = expected.non_witness_utxo.clone();
+            input.witness_utxo = expected.witness_utxo.clone();
+            input.redeem_script = expected.redeem_script.clone();
+            input.witness_script = expected.witness_script.clone();
+            input.sighash_type = expected.sighash_type;
+        }
+        let paths: Vec<DerivationPath> = (0..6)
+            .map(|index| format!("m/0'/0'/{}'", index).parse().unwrap())
+            .collect();
+        psbt.add_key_origins(&bip174_master(), &paths).unwrap();
+        assert_eq!(psbt, expected);
+    }
+
+    #[test]
+    fn bip174_signers_and_combiner() {
+        let mut first = bip174_updated();
+        assert_eq!(first.sign(&[bip174_key(0), bip174_key(2)]).unwrap(), 2);
+        assert_eq!(first.to_hex(), signer_vector("psbt1"));
+
+        let mut second = bip174_updated();
+        assert_eq!(second.sign(&[bip174_key(1), bip174_key(3)]).unwrap(), 2);
+        first.combine(second).unwrap();
+        // The vector does not list the second input's signatures in key order, so
+        // only the parsed form can match.
+        let combined = Psbt::from_hex(&signer_vector("combined")).unwrap();
+        assert_eq!(first, combined);
+
+        let mut psbt = bip174_updated();
+        assert_eq!(psbt.sign_with_master(&bip174_master()).unwrap(), 4);
+        assert_eq!(psbt, combined);
+    }
+
+    #[test]
+    fn bip174_finalizer_and_extractor() {
+        let mut psbt = Psbt::from_hex(&signer_vector("combined")).unwrap();
+        assert!(psbt.extract_tx().is_err());
+        psbt.finalize().unwrap();
+        for input in &psbt.inputs {
+            assert!(input.partial_sigs.is_empty());
+            assert!(input.bip32_derivation.is_empty());
+            assert!(input.redeem_script.is_none());
+        }
+        assert!(psbt.inputs[0].non_witness_utxo.is_some());
+        assert!(psbt.inputs[0].final_script_witness.is_none());
+        assert!(psbt.inputs[1].witness_utxo.is_some());
+        assert_eq!(
+            psbt.extract_tx().unwrap().to_hex(),
+            signer_vector("extracted")
+        );
+
+        // Finalized inputs are no longer signed.
+        assert_eq!(psbt.sign(&[bip174_key(0)]).unwrap(), 0);
+    }
+
+    #[test]
+    fn finalizing_needs_enough_signatures() {
+        let mut psbt = Psbt::from_hex(&signer_vector("psbt1")).unwrap();
+        assert!(matches!(psbt.finalize(), Err(Error::InvalidPsbt(_))));
+        assert!(!psbt.inputs[0].is_finalized());
+    }
+
+    #[test]
+    fn combine_needs_the_same_transaction() {
+        let mut psbt = bip174_updated();
+        let mut other = bip174_updated();
+        other.outputs[0].amount += 1;
+        assert!(psbt.combine(other).is_err());
+        let mut other = bip174_updated();
+        other.version = 2;
+        assert!(psbt.combine(other).is_err());
+    }
+
+    #[test]
+    fn signer_checks_scripts_and_utxos() {
+        let mut psbt = bip174_updated();
+        psbt.inputs[0].redeem_script = psbt.inputs[1].redeem_script.clone();
+        assert!(matches!(
+            psbt.sign(&[bip174_key(0)]),
+            Err(Error::InvalidPsbt(_))
+        ));
+
+        let mut psbt = bip174_updated();
+        let non_witness_utxo = psbt.inputs[0].non_witness_utxo.take().unwrap();
+        psbt.inputs[0].witness_utxo =
+            Some(non_witness_utxo.outputs[psbt.inputs[0].previous_output.vout as usize].clone());
+        assert!(matches!(
+            psbt.sign(&[bip174_key(0)]),
+            Err(Error::InvalidPsbt(_))
+        ));
+
+        let mut psbt = bip174_updated();
+        psbt.inputs[0].previous_output.vout += 1;
+        assert!(psbt.sign(&[bip174_key(0)]).is_err());
+
+        let mut psbt = bip174_updated();
+        psbt.inputs[1].sighash_type = Some(0x04);
+        assert!(matches!(
+            psbt.sign(&[bip174_key(2)]),
+            Err(Error::InvalidSighashType(0x04))
+        ));
+
+        let mut psbt = bip174_updated();
+        psbt.inputs[0].sighash_type = Some(0x03);
+        psbt.outputs.truncate(0);
+        assert!(matches!(
+            psbt.sign(&[bip174_key(0)]),
+            Err(Error::InvalidPsbt(_))
+        ));
+    }
+
+    #[test]
+    fn converts_between_versions() {
+        let valid = &vectors()["valid"];
+        let v0 = Psbt::from_hex(valid[4]["hex"].as_str().unwrap()).unwrap();
+        let v2 = Psbt {
+            version: 2,
+            ..v0.clone()
+        };
+        let serialized = v2.serialize();
+        let parsed = Psbt::parse(&serialized).unwrap();
+        assert_eq!(parsed, v2);
+        assert_eq!(parsed.unsigned_tx().unwrap(), v0.unsigned_tx().unwrap());
+        assert_eq!(
+            Psbt {
+                version: 0,
+                ..parsed
+            },
+            v0
+        );
+
+        // Version 2 must not have an unsigned transaction.
+        let mut global = read_map(&mut &serialized[5..]).unwrap();
+        let maps = &serialized[5 + serialize_map(global.clone()).len()..];
+        global.push((
+            key(PSBT_GLOBAL_UNSIGNED_TX, &[]),
+            v0.unsigned_tx().unwrap().serialize(),
+        ));
+        let bytes = [&PSBT_MAGIC[..], &serialize_map(global), maps].concat();
+        assert!(matches!(Psbt::parse(&bytes), Err(Error::InvalidPsbt(_))));
+    }
+
+    #[test]
+    fn version_2_requires_its_fields() {
+        let mut psbt = Psbt::new_v2(2, None);
+        psbt.add_input(Input::new(OutPoint::new([1; 32], 0)))
+            .unwrap();
+        psbt.add_output(Output::new(1000, vec![0x51])).unwrap();
+        let serialized = psbt.serialize();
+        assert_eq!(Psbt::parse(&serialized).unwrap(), psbt);
+
+        let mut global = read_map(&mut &serialized[5..]).unwrap();
+        let maps = &serialized[5 + serialize_map(global.clone()).len()..];
+        for key_type in [
+            PSBT_GLOBAL_TX_VERSION,
+            PSBT_GLOBAL_INPUT_COUNT,
+            PSBT_GLOBAL_OUTPUT_COUNT,
+        ] {
+            let without: Pairs = global
+                .iter()
+                .filter(|(key, _)| key[0] != key_type as u8)
+                .cloned()
+                .collect();
+            let bytes = [&PSBT_MAGIC[..], &serialize_map(without), maps].concat();
+            assert!(matches!(Psbt::parse(&bytes), Err(Error::InvalidPsbt(_))));
+        }
+
+        // An input map without its output index.
+        let mut input_pairs = psbt.inputs[0].to_pairs(2);
+        input_pairs.retain(|(key, _)| key[0] != 0x0f);
+        let bytes = [
+            &PSBT_MAGIC[..],
+            &serialize_map(global.clone()),
+            &serialize_map(input_pairs),
+            &serialize_map(psbt.outputs[0].to_pairs(2)),
+        ]
+        .concat();
+        assert!(matches!(Psbt::parse(&bytes), Err(Error::InvalidPsbt(_))));
+
+        global.retain(|(key, _)| key[0] != PSBT_GLOBAL_VERSION as u8);
+        global.push((key(PSBT_GLOBAL_VERSION, &[]), 1u32.to_le_bytes().to_vec()));
+        let bytes = [&PSBT_MAGIC[..], &serialize_map(global), maps].concat();
+        assert!(matches!(Psbt::parse(&bytes), Err(Error::InvalidPsbt(_))));
+    }
+
+    #[test]
+    fn version_0_rejects_version_2_fields() {
+        let bytes = decode_hex(vectors()["valid"][0]["hex"].as_str().unwrap()).unwrap();
+        let mut rest = &bytes[5..];
+        let mut maps = vec![read_map(&mut rest).unwrap()];
+        while !rest.is_empty() {
+            maps.push(read_map(&mut rest).unwrap());
+        }
+        let with = |map: usize, pair: (Vec<u8>, Vec<u8>)| {
+            let mut maps = maps.clone();
+            maps[map].push(pair);
+            let serialized: Vec<u8> = maps.into_iter().flat_map(serialize_map).collect();
+            Psbt::parse(&[&PSBT_MAGIC[..], &serialized].concat())
+        };
+        assert!(with(0, (vec![0xf0], vec![])).is_ok());
+        for pair in [
+            (vec![0x02], vec![0x02, 0x00, 0x00, 0x00]),
+            (vec![0x03], vec![0x00; 4]),
+            (vec![0x04], vec![0x01]),
+            (vec![0x05], vec![0x02]),
+            (vec![0x06], vec![0x03]),
+        ] {
+            assert!(matches!(with(0, pair), Err(Error::InvalidPsbt(_))));
+        }
+        for pair in [
+            (vec![0x0e], vec![0x01; 32]),
+            (vec![0x0f], vec![0x00; 4]),
+            (vec![0x10], vec![0xff; 4]),
+            (vec![0x11], 600_000_000u32.to_le_bytes().to_vec()),
+            (vec![0x12], 100u32.to_le_bytes().to_vec()),
+        ] {
+            assert!(matches!(with(1, pair), Err(Error::InvalidPsbt(_))));
+        }
+        for pair in [(vec![0x03], vec![0x00; 8]), (vec![0x04], vec![0x51])] {
+            assert!(matches!(with(2, pair), Err(Error::InvalidPsbt(_))));
+        }
+        // With key data they are unknown fields, as in BIP174's own vectors.
+        let psbt = with(1, (vec![0x0f, 0x01], vec![0x02])).unwrap();
+        assert_eq!(psbt.inputs[0].unknown.len(), 1);
+    }
+
+    #[test]
+    fn version_2_locktime() {
+        let mut psbt = Psbt::new_v2(2, Some(100));
+        let input = |time: Option<u32>, height: Option<u32>| Input {
+            required_time_locktime: time,
+            required_height_locktime: height,
+            ..Input::new(OutPoint::new([1; 32], 0))
+        };
+        psbt.add_input(input(None, None)).unwrap();
+        assert_eq!(psbt.locktime().unwrap(), 100);
+        psbt.add_input(input(Some(1_657_000_000), Some(10_000)))
+            .unwrap();
+        psbt.add_input(input(None, Some(10_001))).unwrap();
+        assert_eq!(psbt.locktime().unwrap(), 10_001);
+        psbt.inputs[2] = input(Some(1_657_000_001), None);
+        assert_eq!(psbt.locktime().unwrap(), 1_657_000_001);
+        psbt.add_input(input(None, Some(10_002))).unwrap();
+        assert!(psbt.locktime().is_err());
+
+        // Required locktimes must be of their kind.
+        psbt.inputs = vec![input(Some(10_000), None)];
+        assert!(Psbt::parse(&psbt.serialize()).is_err());
+        psbt.inputs = vec![input(None, Some(1_657_000_000))];
+        assert!(Psbt::parse(&psbt.serialize()).is_err());
+    }
+
+    #[test]
+    fn constructor_respects_modifiable_flags() {
+        let mut v0 = bip174_updated();
+        assert!(v0.add_input(Input::default()).is_err());
+        assert!(v0.add_output(Output::default()).is_err());
+
+        let mut psbt = Psbt::new_v2(2, None);
+        psbt.tx_modifiable = Some(OUTPUTS_MODIFIABLE);
+        assert!(psbt.add_input(Input::default()).is_err());
+        psbt.add_output(Output::new(1000, vec![0x51])).unwrap();
+        psbt.tx_modifiable = Some(0);
+        assert!(psbt.add_output(Output::default()).is_err());
+    }
+
+    #[test]
+    fn from_unsigned_tx_rejects_signed_transactions() {
+        let valid = &vectors()["valid"];
+        let psbt = Psbt::from_hex(valid[1]["hex"].as_str().unwrap()).unwrap();
+        let signed = psbt.extract_tx();
+        assert!(signed.is_err());
+        let mut tx = psbt.unsigned_tx().unwrap();
+        tx.inputs[0].script_sig = psbt.inputs[0].final_script_sig.clone().unwrap();
+        assert!(matches!(
+            Psbt::from_unsigned_tx(tx),
+            Err(Error::InvalidPsbt(_))
+        ));
+    }
+
+    #[test]
+    fn signs_and_finalizes_p2wpkh_and_taproot_inputs() {
+        let master = bip174_master();
+        let wpkh_key = bip174_key(0);
+        let tr_key = bip174_key(1);
+        let wpkh_output = TxOut::new(
+            50_000,
+            [&[0x00, 0x14][..], &wpkh_key.point().hash160(true)].concat(),
+        );
+        let output_key = taproot::output_key(tr_key.point(), None);
+        let tr_output = TxOut::new(70_000, [&[0x51, 0x20][..], &output_key.x_only()].concat());
+
+        let mut psbt = Psbt::new_v2(2, None);
+        for (index, spent) in [&wpkh_output, &tr_output].into_iter().enumerate() {
+            psbt.add_input(Input {
+                witness_utxo: Some(spent.clone()),
+                sequence: Some(0xffff_fffd),
+                ..Input::new(OutPoint::new([7; 32], index as u32))
+            })
+            .unwrap();
+        }
+        psbt.add_output(Output::new(110_000, vec![0x00, 0x14, 0x55]))
+            .unwrap();
+        let paths: Vec<DerivationPath> = (0..2)
+            .map(|index| format!("m/0'/0'/{}'", index).parse().unwrap())
+            .collect();
+        psbt.add_key_origins(&master, &paths).unwrap();
+        assert_eq!(psbt.inputs[0].bip32_derivation.len(), 1);
+        assert_eq!(
+            psbt.inputs[1].tap_internal_key,
+            Some(tr_key.point().x_only())
+        );
+        assert!(psbt.inputs[1]
+            .tap_bip32_derivation
+            .contains_key(&tr_key.point().x_only()));
+
+        let psbt = Psbt::from_base64(&psbt.to_base64()).unwrap();
+        let mut signed = psbt.clone();
+        assert_eq!(signed.sign_with_master(&master).unwrap(), 2);
+        assert_eq!(signed.inputs[1].tap_key_sig.as_ref().unwrap().len(), 64);
+        signed.finalize().unwrap();
+        let tx = signed.extract_tx().unwrap();
+        assert!(tx.verify_segwit_v0_input(0, &wpkh_output).unwrap());
+        assert!(tx
+            .verify_taproot_key_path(1, &[wpkh_output.clone(), tr_output.clone()])
+            .unwrap());
+
+        // Taproot signatures commit to every spent output.
+        let mut missing = psbt;
+        missing.inputs[0].witness_utxo = None;
+        assert!(missing.sign(&[tr_key]).is_err());
+    }
+}
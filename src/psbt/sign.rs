@@ -0,0 +1,238 @@
+use rand::RngCore;
+
+use super::{p2sh_hash, script_pushes, taproot_program, Input, Psbt};
+use crate::bip32::ExtendedPrivKey;
+use crate::error::Error;
+use crate::hash::{hash160, sha256};
+use crate::secp256k1::private_key::PrivateKey;
+use crate::taproot::{tap_leaf_hash, tweak_private_key};
+use crate::tx::sighash::{
+    p2wpkh_script_code, SighashCache, SIGHASH_ALL, SIGHASH_DEFAULT, SIGHASH_SINGLE,
+};
+use crate::tx::sign::ecdsa_signature;
+use crate::tx::{Tx, TxOut};
+
+impl Psbt {
+    // The Signer role: signs every input one of `keys` can, with the input's
+    // sighash type (ALL for ECDSA, DEFAULT for taproot when it has none), and
+    // returns how many signatures were added. Inputs without a UTXO and finalized
+    // ones are left alone; P2SH and P2WSH inputs need their scripts.
+    pub fn sign(&mut self, keys: &[PrivateKey]) -> Result<usize, Error> {
+        let tx = self.unsigned_tx()?;
+        let cache = SighashCache::new(&tx);
+        let spent_outputs = self
+            .inputs
+            .iter()
+            .map(Input::spent_output)
+            .collect::<Result<Vec<_>, _>>()?;
+        // Taproot digests commit to the outputs spent by every input.
+        let prevouts: Option<Vec<TxOut>> = spent_outputs.iter().cloned().collect();
+
+        let mut signed = 0;
+        for (index, (input, spent_output)) in self.inputs.iter_mut().zip(&spent_outputs).enumerate()
+        {
+            let Some(spent_output) = spent_output else {
+                continue;
+            };
+            if input.is_finalized() {
+                continue;
+            }
+            let signer = InputSigner {
+                tx: &tx,
+                cache: &cache,
+                index,
+                spent_output,
+                prevouts: prevouts.as_deref(),
+            };
+            signed += signer.sign(input, keys)?;
+        }
+        Ok(signed)
+    }
+
+    // Signs with the keys `master` derives, found through the key origins with its
+    // fingerprint.
+    pub fn sign_with_master(&mut self, master: &ExtendedPrivKey) -> Result<usize, Error> {
+        let mut keys = Vec::new();
+        for input in &self.inputs {
+            let origins = input.bip32_derivation.values().chain(
+                input
+                    .tap_bip32_derivation
+                    .values()
+                    .map(|(_, origin)| origin),
+            );
+            for origin in origins.filter(|origin| origin.fingerprint == master.fingerprint()) {
+                let key = master.derive_path(&origin.path)?.private_key().clone();
+                if !keys.contains(&key) {
+                    keys.push(key);
+                }
+            }
+        }
+        self.sign(&keys)
+    }
+}
+
+struct InputSigner<'a> {
+    tx: &'a Tx,
+    cache: &'a SighashCache<'a>,
+    index: usize,
+    spent_output: &'a TxOut,
+    prevouts: Option<&'a [TxOut]>,
+}
+
+impl InputSigner<'_> {
+    fn sign(&self, input: &mut Input, keys: &[PrivateKey]) -> Result<usize, Error> {
+        let mut script = self.spent_output.script_pubkey.as_slice();
+        if let Some(program) = taproot_program(script) {
+            return self.sign_taproot(input, program, keys);
+        }
+        if let Some(hash) = p2sh_hash(script) {
+            let redeem_script = input
+                .redeem_script
+                .as_deref()
+                .ok_or_else(|| self.invalid("P2SH input without a redeem script"))?;
+            if hash160(redeem_script) != hash {
+                return Err(self.invalid("redeem script does not match the P2SH hash"));
+            }
+            script = redeem_script;
+        }
+
+        let sighash_type = input.sighash_type.unwrap_or(SIGHASH_ALL);
+        if !matches!(sighash_type, 0x01..=0x03 | 0x81..=0x83) {
+            return Err(Error::InvalidSighashType(sighash_type));
+        }
+        let mut signatures = Vec::new();
+        match script {
+            [0x00, 0x14, hash @ ..] if hash.len() == 20 => {
+                let script_code = p2wpkh_script_code(hash.try_into().unwrap());
+                for key in keys.iter().filter(|key| key.point().hash160(true) == hash) {
+                    let sighash = self.cache.segwit_v0(
+                        self.index,
+                        &script_code,
+                        self.spent_output.value,
+                        sighash_type,
+                    )?;
+                    signatures.push((
+                        key.point().sec(true),
+                        ecdsa_signature(key, &sighash, sighash_type),
+                    ));
+                }
+            }
+            [0x00, 0x20, hash @ ..] if hash.len() == 32 => {
+                let witness_script = input
+                    .witness_script
+                    .as_deref()
+                    .ok_or_else(|| self.invalid("P2WSH input without a witness script"))?;
+                if sha256(witness_script) != hash {
+                    return Err(self.invalid("witness script does not match the P2WSH hash"));
+                }
+                // Segwit v0 only allows compressed keys.
+                for (key, sec) in used_keys(witness_script, keys, &[true]) {
+                    let sighash = self.cache.segwit_v0(
+                        self.index,
+                        witness_script,
+                        self.spent_output.value,
+                        sighash_type,
+                    )?;
+                    signatures.push((sec, ecdsa_signature(key, &sighash, sighash_type)));
+                }
+            }
+            _ => {
+                // The amount is not signed, so it could be lied about in a witness UTXO.
+                if input.non_witness_utxo.is_none() {
+                    return Err(self.invalid("legacy input without its previous transaction"));
+                }
+                // Legacy SIGHASH_SINGLE without a matching output signs the hash
+                // one, which lets the signature be reused for any transaction.
+                if sighash_type & 0x1f == SIGHASH_SINGLE && self.index >= self.tx.outputs.len() {
+                    return Err(self.invalid("SIGHASH_SINGLE input without a matching output"));
+                }
+                for (key, sec) in used_keys(script, keys, &[true, false]) {
+                    let sighash = self.tx.legacy_sighash(self.index, script, sighash_type);
+                    signatures.push((sec, ecdsa_signature(key, &sighash, sighash_type)));
+                }
+            }
+        }
+        let count = signatures.len();
+        input.partial_sigs.extend(signatures);
+        Ok(count)
+    }
+
+    // A key path signature if a key tweaks to the output key, and a script path
+    // one for every leaf script that uses a key.
+    fn sign_taproot(
+        &self,
+        input: &mut Input,
+        program: &[u8],
+        keys: &[PrivateKey],
+    ) -> Result<usize, Error> {
+        let prevouts = self
+            .prevouts
+            .ok_or_else(|| self.invalid("taproot input without every spent output"))?;
+        let sighash_type = input.sighash_type.unwrap_or(SIGHASH_DEFAULT);
+        let mut signed = 0;
+        for key in keys {
+            let tweaked = tweak_private_key(key, input.tap_merkle_root.as_ref());
+            if tweaked.point().x_only() == program {
+                let sighash = self
+                    .cache
+                    .taproot(self.index, prevouts, None, None, sighash_type)?;
+                input.tap_key_sig = Some(schnorr_signature(&tweaked, &sighash, sighash_type));
+                signed += 1;
+            }
+            let x_only = key.point().x_only();
+            for (script, leaf_version) in input.tap_leaf_scripts.values() {
+                if !script_pushes(script).contains(&&x_only[..]) {
+                    continue;
+                }
+                let leaf_hash = tap_leaf_hash(script, *leaf_version);
+                let sighash = self.cache.taproot(
+                    self.index,
+                    prevouts,
+                    None,
+                    Some((&leaf_hash, 0xffff_ffff)),
+                    sighash_type,
+                )?;
+                input.tap_script_sigs.insert(
+                    (x_only, leaf_hash),
+                    schnorr_signature(key, &sighash, sighash_type),
+                );
+                signed += 1;
+            }
+        }
+        Ok(signed)
+    }
+
+    fn invalid(&self, reason: &str) -> Error {
+        Error::InvalidPsbt(format!("input {}: {}", self.index, reason))
+    }
+}
+
+// The keys a script pushes, or pushes the hash of, in any of the given forms.
+fn used_keys<'a>(
+    script: &[u8],
+    keys: &'a [PrivateKey],
+    compressed: &[bool],
+) -> Vec<(&'a PrivateKey, Vec<u8>)> {
+    let pushes = script_pushes(script);
+    let mut used = Vec::new();
+    for key in keys {
+        for compressed in compressed {
+            let sec = key.point().sec(*compressed);
+            if pushes.contains(&sec.as_slice()) || pushes.contains(&&hash160(&sec)[..]) {
+                used.push((key, sec));
+            }
+        }
+    }
+    used
+}
+
+// The type byte is left off for SIGHASH_DEFAULT.
+fn schnorr_signature(key: &PrivateKey, sighash: &[u8; 32], sighash_type: u32) -> Vec<u8> {
+    let mut aux_rand = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut aux_rand);
+    let mut signature = key.sign_schnorr(sighash, &aux_rand).serialize().to_vec();
+    if sighash_type != SIGHASH_DEFAULT {
+        signature.push(sighash_type as u8);
+    }
+    signature
+}
@@ -0,0 +1,135 @@
+use std::collections::BTreeMap;
+
+use super::{script_pushes, taproot_program, KeyOrigin, Psbt};
+use crate::bip32::path::DerivationPath;
+use crate::bip32::ExtendedPrivKey;
+use crate::error::Error;
+use crate::hash::hash160;
+use crate::secp256k1::point::Secp256k1Point;
+use crate::taproot::{output_key, tap_leaf_hash};
+
+impl Psbt {
+    // The Updater role for one wallet: records where every key derived from
+    // `master` along `paths` came from, for each input and output whose scripts
+    // use it. UTXOs, redeem and witness scripts and taproot data must already be
+    // set. A BIP86 key also becomes the internal key of the output it tweaks to.
+    pub fn add_key_origins(
+        &mut self,
+        master: &ExtendedPrivKey,
+        paths: &[DerivationPath],
+    ) -> Result<(), Error> {
+        let keys = paths
+            .iter()
+            .map(|path| {
+                let key = master.derive_path(path)?.private_key().point().clone();
+                Ok((key, KeyOrigin::new(master.fingerprint(), path.clone())))
+            })
+            .collect::<Result<Vec<_>, Error>>()?;
+
+        for input in &mut self.inputs {
+            let script_pubkey = input
+                .spent_output()?
+                .map(|tx_out| tx_out.script_pubkey)
+                .unwrap_or_default();
+            let scripts = [
+                Some(&script_pubkey),
+                input.redeem_script.as_ref(),
+                input.witness_script.as_ref(),
+            ];
+            add_ecdsa_origins(&keys, &scripts, &mut input.bip32_derivation);
+
+            if let Some(program) = taproot_program(&script_pubkey) {
+                if input.tap_internal_key.is_none() && input.tap_merkle_root.is_none() {
+                    input.tap_internal_key = bip86_internal_key(&keys, program);
+                }
+                let leaves: Vec<(&[u8], u8)> = input
+                    .tap_leaf_scripts
+                    .values()
+                    .map(|(script, leaf_version)| (script.as_slice(), *leaf_version))
+                    .collect();
+                add_tap_origins(
+                    &keys,
+                    input.tap_internal_key,
+                    &leaves,
+                    &mut input.tap_bip32_derivation,
+                );
+            }
+        }
+
+        for output in &mut self.outputs {
+            let scripts = [
+                Some(&output.script_pubkey),
+                output.redeem_script.as_ref(),
+                output.witness_script.as_ref(),
+            ];
+            add_ecdsa_origins(&keys, &scripts, &mut output.bip32_derivation);
+
+            if let Some(program) = taproot_program(&output.script_pubkey) {
+                if output.tap_internal_key.is_none() && output.tap_tree.is_none() {
+                    output.tap_internal_key = bip86_internal_key(&keys, program);
+                }
+                let leaves: Vec<(&[u8], u8)> = output
+                    .tap_tree
+                    .iter()
+                    .flatten()
+                    .map(|leaf| (leaf.script.as_slice(), leaf.leaf_version))
+                    .collect();
+                add_tap_origins(
+                    &keys,
+                    output.tap_internal_key,
+                    &leaves,
+                    &mut output.tap_bip32_derivation,
+                );
+            }
+        }
+        Ok(())
+    }
+}
+
+// A key is used by a script that pushes it or its hash, compressed or not.
+fn add_ecdsa_origins(
+    keys: &[(Secp256k1Point, KeyOrigin)],
+    scripts: &[Option<&Vec<u8>>],
+    derivations: &mut BTreeMap<Vec<u8>, KeyOrigin>,
+) {
+    let pushes: Vec<&[u8]> = scripts
+        .iter()
+        .flatten()
+        .flat_map(|script| script_pushes(script))
+        .collect();
+    for (key, origin) in keys {
+        for compressed in [true, false] {
+            let sec = key.sec(compressed);
+            if pushes.contains(&sec.as_slice()) || pushes.contains(&&hash160(&sec)[..]) {
+                derivations.insert(sec, origin.clone());
+            }
+        }
+    }
+}
+
+fn add_tap_origins(
+    keys: &[(Secp256k1Point, KeyOrigin)],
+    internal_key: Option<[u8; 32]>,
+    leaves: &[(&[u8], u8)],
+    derivations: &mut BTreeMap<[u8; 32], (Vec<[u8; 32]>, KeyOrigin)>,
+) {
+    for (key, origin) in keys {
+        let x_only = key.x_only();
+        let leaf_hashes: Vec<[u8; 32]> = leaves
+            .iter()
+            .filter(|(script, _)| script_pushes(script).contains(&&x_only[..]))
+            .map(|(script, leaf_version)| tap_leaf_hash(script, *leaf_version))
+            .collect();
+        if internal_key == Some(x_only) || !leaf_hashes.is_empty() {
+            derivations.insert(x_only, (leaf_hashes, origin.clone()));
+        }
+    }
+}
+
+// The key whose BIP86 tweak, with no script tree, is the output key `program`.
+fn bip86_internal_key(keys: &[(Secp256k1Point, KeyOrigin)], program: &[u8]) -> Option<[u8; 32]> {
+    keys.iter().map(|(key, _)| key.x_only()).find(|x_only| {
+        let key = Secp256k1Point::lift_x(x_only).unwrap();
+        output_key(&key, None).x_only() == program
+    })
+}
@@ -0,0 +1,317 @@
+use super::interpreter::{decode_num, encode_num};
+use super::opcode::{Opcode, OP_0, OP_PUSHDATA1, OP_PUSHDATA2, OP_PUSHDATA4, OP_RETURN};
+use super::{Command, Script};
+use crate::error::Error;
+use crate::secp256k1::point::Secp256k1Point;
+use crate::secp256k1::signature::Signature;
+
+const SIGHASH_NAMES: [(u8, &str); 6] = [
+    (0x01, "ALL"),
+    (0x81, "ALL|ANYONECANPAY"),
+    (0x02, "NONE"),
+    (0x82, "NONE|ANYONECANPAY"),
+    (0x03, "SINGLE"),
+    (0x83, "SINGLE|ANYONECANPAY"),
+];
+
+impl Command {
+    // A strict DER signature with a defined sighash type, as pushed by a
+    // script_sig or witness.
+    pub fn ecdsa_signature(&self) -> Option<(Signature, u8)> {
+        let Command::Push(_, data) = self else {
+            return None;
+        };
+        let (sighash_type, der) = data.split_last()?;
+        sighash_name(*sighash_type)?;
+        Some((Signature::parse_der(der).ok()?, *sighash_type))
+    }
+
+    // A compressed or uncompressed SEC public key.
+    pub fn public_key(&self) -> Option<Secp256k1Point> {
+        match self {
+            Command::Push(_, data) if data.len() == 33 || data.len() == 65 => {
+                Secp256k1Point::parse_sec(data).ok()
+            }
+            _ => None,
+        }
+    }
+}
+
+impl Script {
+    // Bitcoin Core's ASM: opcode names, OP_0 to OP_16 and pushes of up to 4
+    // bytes as numbers, longer pushes as hex. Pushes Core's form would lose,
+    // like a non-minimal PUSHDATA or a number pushed as data, are written with
+    // their opcode and 0x-prefixed data instead, so from_asm gives back the
+    // same script.
+    pub fn to_asm(&self) -> String {
+        self.asm(false)
+    }
+
+    // As Core's ASM for script_sigs: signatures end in their sighash type,
+    // as in 3045...01[ALL], rather than its byte.
+    pub fn to_asm_decoded(&self) -> String {
+        // Core leaves OP_RETURN data alone.
+        let unspendable = self.commands.first() == Some(&Command::Op(OP_RETURN));
+        self.asm(!unspendable)
+    }
+
+    fn asm(&self, decode_signatures: bool) -> String {
+        let words: Vec<String> = self
+            .commands
+            .iter()
+            .map(|command| match command {
+                Command::Op(opcode) => match opcode.small_int() {
+                    Some(n) => n.to_string(),
+                    None => opcode.to_string(),
+                },
+                Command::Push(opcode, data) => push_asm(command, *opcode, data, decode_signatures),
+            })
+            .collect();
+        words.join(" ")
+    }
+
+    // Reads to_asm and to_asm_decoded output, and Core's ASM.
+    pub fn from_asm(asm: &str) -> Result<Self, Error> {
+        let invalid = |word: &str| Error::InvalidAsm(word.to_string());
+        let mut commands = Vec::new();
+        let mut words = asm.split_whitespace();
+        while let Some(word) = words.next() {
+            if let Some(opcode) = explicit_push_opcode(word) {
+                let data = words
+                    .next()
+                    .and_then(|data| data.strip_prefix("0x"))
+                    .ok_or_else(|| invalid(word))?;
+                let data = hex::decode(data).map_err(|_| invalid(data))?;
+                let fits = match opcode {
+                    OP_PUSHDATA1 => data.len() <= 0xff,
+                    OP_PUSHDATA2 => data.len() <= 0xffff,
+                    OP_PUSHDATA4 => true,
+                    _ => data.len() == opcode.0 as usize,
+                };
+                if !fits {
+                    return Err(invalid(word));
+                }
+                commands.push(Command::Push(opcode, data));
+            } else if let Some(n) = asm_number(word) {
+                commands.push(match Opcode::from_small_int(n) {
+                    Some(OP_0) => Command::Push(OP_0, Vec::new()),
+                    Some(opcode) => Command::Op(opcode),
+                    None => Command::push(&encode_num(n)),
+                });
+            } else if let Some(opcode) = opcode_from_asm(word) {
+                commands.push(match opcode {
+                    OP_0 => Command::Push(OP_0, Vec::new()),
+                    _ => Command::Op(opcode),
+                });
+            } else {
+                let (hex, sighash_type) =
+                    match word.strip_suffix(']').and_then(|w| w.split_once('[')) {
+                        Some((hex, name)) => {
+                            let (sighash_type, _) = SIGHASH_NAMES
+                                .iter()
+                                .find(|(_, known)| *known == name)
+                                .ok_or_else(|| invalid(word))?;
+                            (hex, Some(*sighash_type))
+                        }
+                        None => (word, None),
+                    };
+                let mut data = hex::decode(hex).map_err(|_| invalid(word))?;
+                data.extend(sighash_type);
+                commands.push(Command::push(&data));
+            }
+        }
+        Ok(Script { commands })
+    }
+}
+
+fn push_asm(command: &Command, opcode: Opcode, data: &[u8], decode: bool) -> String {
+    if opcode == OP_0 {
+        return "0".to_string();
+    }
+    let explicit = || format!("{} 0x{}", opcode, hex::encode(data));
+    if Command::push(data) != *command {
+        return explicit();
+    }
+    if data.len() <= 4 {
+        // A number only if reading it back gives this push and not OP_1NEGATE
+        // or OP_1 to OP_16.
+        return match decode_num(data, 4, true) {
+            Ok(n) if Opcode::from_small_int(n).is_none() => n.to_string(),
+            _ => explicit(),
+        };
+    }
+    if decode {
+        if let Some((_, sighash_type)) = command.ecdsa_signature() {
+            let name = sighash_name(sighash_type).unwrap();
+            return format!("{}[{}]", hex::encode(&data[..data.len() - 1]), name);
+        }
+    }
+    let hex = hex::encode(data);
+    if asm_number(&hex).is_some() {
+        return explicit();
+    }
+    hex
+}
+
+fn sighash_name(sighash_type: u8) -> Option<&'static str> {
+    SIGHASH_NAMES
+        .iter()
+        .find(|(known, _)| *known == sighash_type)
+        .map(|(_, name)| *name)
+}
+
+// Decimal numbers in the 4 byte script number range.
+fn asm_number(word: &str) -> Option<i64> {
+    let digits = word.strip_prefix('-').unwrap_or(word);
+    if digits.is_empty() || digits.len() > 10 || !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let n: i64 = word.parse().ok()?;
+    (n.abs() <= i32::MAX as i64).then_some(n)
+}
+
+fn explicit_push_opcode(word: &str) -> Option<Opcode> {
+    match word {
+        "OP_PUSHDATA1" => Some(OP_PUSHDATA1),
+        "OP_PUSHDATA2" => Some(OP_PUSHDATA2),
+        "OP_PUSHDATA4" => Some(OP_PUSHDATA4),
+        _ => match word.strip_prefix("OP_PUSHBYTES_")?.parse() {
+            Ok(len @ 1..=0x4b) => Some(Opcode(len)),
+            _ => None,
+        },
+    }
+}
+
+// Names of OP_0 and the opcodes that are not pushes, including Display's
+// OP_UNKNOWN_0xbb.
+fn opcode_from_asm(word: &str) -> Option<Opcode> {
+    let opcode = match word.strip_prefix("OP_UNKNOWN_0x") {
+        Some(byte) => Opcode(u8::from_str_radix(byte, 16).ok()?),
+        None => Opcode::from_name(word)?,
+    };
+    (opcode == OP_0 || !opcode.is_push()).then_some(opcode)
+}
+
+#[cfg(test)]
+mod asm_tests {
+    use super::*;
+
+    #[test]
+    fn core_asm() {
+        for (hex, asm) in [
+            (
+                "76a914bc3b654dca7e56b04dca18f2566cdaf02e8d9ada88ac",
+                "OP_DUP OP_HASH160 bc3b654dca7e56b04dca18f2566cdaf02e8d9ada OP_EQUALVERIFY OP_CHECKSIG",
+            ),
+            (
+                "0014751e76e8199196d454941c45d1b3a323f1433bd6",
+                "0 751e76e8199196d454941c45d1b3a323f1433bd6",
+            ),
+            (
+                "5120a60869f0dbcf1dc659c9cecbaf8050135ea9e8cdc487053f1dc6880949dc684c",
+                "1 a60869f0dbcf1dc659c9cecbaf8050135ea9e8cdc487053f1dc6880949dc684c",
+            ),
+            (
+                "522102fe6f0a5a297eb38c391581c4413e084773ea23954d93f7753db7dc0adc188b2f2102ff12471208c14bd580709cb2358d98975247d8765f92bc25eab3b2763ed605f852ae",
+                "2 02fe6f0a5a297eb38c391581c4413e084773ea23954d93f7753db7dc0adc188b2f 02ff12471208c14bd580709cb2358d98975247d8765f92bc25eab3b2763ed605f8 2 OP_CHECKMULTISIG",
+            ),
+            ("6a0b68656c6c6f20776f726c64", "OP_RETURN 68656c6c6f20776f726c64"),
+            // A BIP34 coinbase height and small numbers pushed as data.
+            ("03a08601", "100000"),
+            ("02e80301ff0181", "1000 -127 OP_PUSHBYTES_1 0x81"),
+            ("4f00", "-1 0"),
+            ("0105", "OP_PUSHBYTES_1 0x05"),
+            ("0100", "OP_PUSHBYTES_1 0x00"),
+            ("020001", "256"),
+            ("4c00", "OP_PUSHDATA1 0x"),
+            ("4c0107", "OP_PUSHDATA1 0x07"),
+            ("4d0300aabbcc", "OP_PUSHDATA2 0xaabbcc"),
+            ("051234567890", "OP_PUSHBYTES_5 0x1234567890"),
+            ("059234567890", "9234567890"),
+            ("bbff", "OP_UNKNOWN_0xbb OP_INVALIDOPCODE"),
+        ] {
+            let script = Script::from_hex(hex).unwrap();
+            assert_eq!(script.to_asm(), asm, "{}", hex);
+            assert_eq!(Script::from_asm(asm).unwrap(), script, "{}", asm);
+        }
+    }
+
+    #[test]
+    fn round_trips() {
+        let mut scripts: Vec<Script> = [
+            "",
+            "00",
+            "4f515a60",
+            "0101020102010404ffffff7f05ffffffff7f",
+            "4e02000000aabb",
+            "ac9bbab0b1b2",
+        ]
+        .iter()
+        .map(|hex| Script::from_hex(hex).unwrap())
+        .collect();
+        // Every one byte push and every non-push opcode.
+        scripts.push(Script::new(
+            (0..=255)
+                .map(|byte| Command::push(&[byte]))
+                .chain((0x4f..=0xff).map(|byte| Command::Op(Opcode(byte))))
+                .collect(),
+        ));
+        for script in scripts {
+            let asm = script.to_asm();
+            assert_eq!(Script::from_asm(&asm).unwrap(), script, "{}", asm);
+            let decoded = script.to_asm_decoded();
+            assert_eq!(Script::from_asm(&decoded).unwrap(), script, "{}", decoded);
+        }
+    }
+
+    #[test]
+    fn signatures_and_keys() {
+        // Programming Bitcoin, chapter 6.
+        let script_sig = Script::from_hex("483045022100ed81ff192e75a3fd2304004dcadb746fa5e24c5031ccfcf21320b0277457c98f02207a986d955c6e0cb35d446a89d3f56100f4d7f67801c31967743a9c8e10615bed01210349fc4e631e3624a545de3f89f5d8684c7b8138bd94bdd531d2e213bf016b278a").unwrap();
+        let decoded = "3045022100ed81ff192e75a3fd2304004dcadb746fa5e24c5031ccfcf21320b0277457c98f02207a986d955c6e0cb35d446a89d3f56100f4d7f67801c31967743a9c8e10615bed[ALL] 0349fc4e631e3624a545de3f89f5d8684c7b8138bd94bdd531d2e213bf016b278a";
+        assert_eq!(script_sig.to_asm_decoded(), decoded);
+        assert_eq!(Script::from_asm(decoded).unwrap(), script_sig);
+        assert!(script_sig.to_asm().starts_with("3045022100ed81"));
+        assert!(script_sig.to_asm().contains("615bed01 0349fc"));
+
+        let (signature, sighash_type) = script_sig.commands[0].ecdsa_signature().unwrap();
+        assert_eq!(sighash_type, 0x01);
+        assert_eq!(
+            signature.r().to_str_radix(16),
+            "ed81ff192e75a3fd2304004dcadb746fa5e24c5031ccfcf21320b0277457c98f"
+        );
+        assert!(script_sig.commands[0].public_key().is_none());
+        let key = script_sig.commands[1].public_key().unwrap();
+        assert_eq!(
+            hex::encode(key.sec(true)),
+            "0349fc4e631e3624a545de3f89f5d8684c7b8138bd94bdd531d2e213bf016b278a"
+        );
+        assert!(script_sig.commands[1].ecdsa_signature().is_none());
+
+        // Undefined sighash types and OP_RETURN data are left as hex.
+        let undefined = "3045022100ed81ff192e75a3fd2304004dcadb746fa5e24c5031ccfcf21320b0277457c98f02207a986d955c6e0cb35d446a89d3f56100f4d7f67801c31967743a9c8e10615bed04";
+        let script = Script::from_asm(undefined).unwrap();
+        assert_eq!(script.to_asm_decoded(), undefined);
+        let op_return = Script::new(vec![Command::Op(OP_RETURN), script_sig.commands[0].clone()]);
+        assert_eq!(op_return.to_asm_decoded(), op_return.to_asm());
+    }
+
+    #[test]
+    fn rejects_bad_asm() {
+        for asm in [
+            "OP_NOTANOPCODE",
+            "abc",
+            "OP_PUSHBYTES_2 0x01",
+            "OP_PUSHDATA1",
+            "OP_PUSHDATA1 01",
+            "3044[NOTHING]",
+            "OP_PUSHBYTES_0 0x",
+        ] {
+            assert!(
+                matches!(Script::from_asm(asm), Err(Error::InvalidAsm(_))),
+                "{}",
+                asm
+            );
+        }
+    }
+}
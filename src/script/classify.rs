@@ -0,0 +1,254 @@
+use super::interpreter::{encode_num, witness_program};
+use super::opcode::{OP_CHECKMULTISIG, OP_RETURN};
+use super::{Command, Script};
+use crate::address::Address;
+use crate::secp256k1::point::Secp256k1Point;
+
+// The standard output templates, as Bitcoin Core's Solver sorts them.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ScriptType {
+    P2pk(Secp256k1Point),
+    P2pkh([u8; 20]),
+    P2sh([u8; 20]),
+    P2wpkh([u8; 20]),
+    P2wsh([u8; 32]),
+    // The x-only output key, which need not be on the curve.
+    P2tr([u8; 32]),
+    // A witness version above 1, or version 1 with a program that is not 32
+    // bytes, left for future soft forks.
+    WitnessUnknown {
+        version: u8,
+        program: Vec<u8>,
+    },
+    Multisig {
+        required: usize,
+        keys: Vec<Secp256k1Point>,
+    },
+    // OP_RETURN followed only by pushes, and what they push.
+    NullData(Vec<Vec<u8>>),
+    Nonstandard,
+}
+
+impl ScriptType {
+    // None for the templates that have no address: bare keys, bare multisig,
+    // OP_RETURN and nonstandard scripts.
+    pub fn address(&self) -> Option<Address> {
+        let witness = |version: u8, program: &[u8]| Address::Witness {
+            version,
+            program: program.to_vec(),
+        };
+        match self {
+            ScriptType::P2pkh(hash) => Some(Address::P2pkh(*hash)),
+            ScriptType::P2sh(hash) => Some(Address::P2sh(*hash)),
+            ScriptType::P2wpkh(hash) => Some(witness(0, hash)),
+            ScriptType::P2wsh(hash) => Some(witness(0, hash)),
+            ScriptType::P2tr(key) => Some(witness(1, key)),
+            ScriptType::WitnessUnknown { version, program } => Some(witness(*version, program)),
+            _ => None,
+        }
+    }
+}
+
+impl Script {
+    pub fn classify(&self) -> ScriptType {
+        let bytes = self.serialize();
+        match bytes.as_slice() {
+            [0xa9, 0x14, hash @ .., 0x87] if hash.len() == 20 => {
+                return ScriptType::P2sh(hash.try_into().unwrap())
+            }
+            [0x76, 0xa9, 0x14, hash @ .., 0x88, 0xac] if hash.len() == 20 => {
+                return ScriptType::P2pkh(hash.try_into().unwrap())
+            }
+            [len @ (33 | 65), sec @ .., 0xac] if sec.len() == *len as usize => {
+                return match Secp256k1Point::parse_sec(sec) {
+                    Ok(key) => ScriptType::P2pk(key),
+                    Err(_) => ScriptType::Nonstandard,
+                };
+            }
+            _ => {}
+        }
+        if let Some((version, program)) = witness_program(&bytes) {
+            return match (version, program.len()) {
+                (0, 20) => ScriptType::P2wpkh(program.try_into().unwrap()),
+                (0, 32) => ScriptType::P2wsh(program.try_into().unwrap()),
+                (0, _) => ScriptType::Nonstandard,
+                (1, 32) => ScriptType::P2tr(program.try_into().unwrap()),
+                _ => ScriptType::WitnessUnknown {
+                    version,
+                    program: program.to_vec(),
+                },
+            };
+        }
+        if let [Command::Op(OP_RETURN), rest @ ..] = self.commands.as_slice() {
+            let data = Script::new(rest.to_vec());
+            if !data.is_push_only() {
+                return ScriptType::Nonstandard;
+            }
+            return ScriptType::NullData(
+                rest.iter()
+                    .map(|command| match command {
+                        Command::Push(_, data) => data.clone(),
+                        Command::Op(opcode) => encode_num(opcode.small_int().unwrap()),
+                    })
+                    .collect(),
+            );
+        }
+        self.multisig_keys()
+            .map_or(ScriptType::Nonstandard, |(required, keys)| {
+                ScriptType::Multisig { required, keys }
+            })
+    }
+
+    // <m> <key>... <n> OP_CHECKMULTISIG with 1 <= m <= n <= 16 and every key
+    // a valid point.
+    fn multisig_keys(&self) -> Option<(usize, Vec<Secp256k1Point>)> {
+        let [Command::Op(m), keys @ .., Command::Op(n), Command::Op(OP_CHECKMULTISIG)] =
+            self.commands.as_slice()
+        else {
+            return None;
+        };
+        let (m, n) = (m.small_int()?, n.small_int()?);
+        if m < 1 || m > n || n as usize != keys.len() {
+            return None;
+        }
+        let keys = keys
+            .iter()
+            .map(|key| match key {
+                Command::Push(_, sec) if sec.len() == 33 || sec.len() == 65 => {
+                    Secp256k1Point::parse_sec(sec).ok()
+                }
+                _ => None,
+            })
+            .collect::<Option<Vec<_>>>()?;
+        Some((m as usize, keys))
+    }
+}
+
+#[cfg(test)]
+mod classify_tests {
+    use super::*;
+    use crate::network::Network;
+
+    fn classify(hex: &str) -> ScriptType {
+        Script::from_hex(hex).unwrap().classify()
+    }
+
+    fn address(hex: &str) -> Option<String> {
+        classify(hex)
+            .address()
+            .map(|address| address.encode(Network::Mainnet))
+    }
+
+    #[test]
+    fn output_templates() {
+        for (script_pubkey, expected) in [
+            (
+                "76a91462e907b15cbf27d5425399ebf6f0fb50ebb88f1888ac",
+                "1A1zP1eP5QGefi2DMPTfTL5SLmv7DivfNa",
+            ),
+            (
+                "a91474d691da1574e6b3c192ecfb52cc8984ee7b6c5687",
+                "3CLoMMyuoDQTPRD3XYZtCvgvkadrAdvdXh",
+            ),
+            (
+                "0014751e76e8199196d454941c45d1b3a323f1433bd6",
+                "bc1qw508d6qejxtdg4y5r3zarvary0c5xw7kv8f3t4",
+            ),
+            (
+                "00201863143c14c5166804bd19203356da136c985678cd4d27a1b8c6329604903262",
+                "bc1qrp33g0q5c5txsp9arysrx4k6zdkfs4nce4xj0gdcccefvpysxf3qccfmv3",
+            ),
+            (
+                "5120a60869f0dbcf1dc659c9cecbaf8050135ea9e8cdc487053f1dc6880949dc684c",
+                "bc1p5cyxnuxmeuwuvkwfem96lqzszd02n6xdcjrs20cac6yqjjwudpxqkedrcr",
+            ),
+            // BIP350's version 16 example.
+            ("6002751e", "bc1sw50qgdz25j"),
+        ] {
+            assert_eq!(
+                address(script_pubkey).as_deref(),
+                Some(expected),
+                "{}",
+                script_pubkey
+            );
+        }
+        assert!(matches!(
+            classify("0014751e76e8199196d454941c45d1b3a323f1433bd6"),
+            ScriptType::P2wpkh(_)
+        ));
+        assert!(matches!(
+            classify("6002751e"),
+            ScriptType::WitnessUnknown { version: 16, .. }
+        ));
+    }
+
+    #[test]
+    fn bare_keys() {
+        // The genesis block's coinbase output pays to a bare uncompressed key.
+        let genesis = "4104678afdb0fe5548271967f1a67130b7105cd6a828e03909a67962e0ea1f61deb649f6bc3f4cef38c4f35504e51ec112de5c384df7ba0b8d578a4c702b6bf11d5fac";
+        let ScriptType::P2pk(key) = classify(genesis) else {
+            panic!("not P2PK");
+        };
+        assert_eq!(
+            key.address(false, Network::Mainnet),
+            "1A1zP1eP5QGefi2DMPTfTL5SLmv7DivfNa"
+        );
+        assert_eq!(address(genesis), None);
+        // Not a point on the curve.
+        assert_eq!(
+            classify(&format!("2102{}ac", "ff".repeat(32))),
+            ScriptType::Nonstandard
+        );
+
+        let keys = [
+            "02ff12471208c14bd580709cb2358d98975247d8765f92bc25eab3b2763ed605f8",
+            "02fe6f0a5a297eb38c391581c4413e084773ea23954d93f7753db7dc0adc188b2f",
+        ]
+        .map(|sec| Secp256k1Point::parse_sec(&hex::decode(sec).unwrap()).unwrap());
+        let multisig = Script::multisig(2, &keys);
+        let ScriptType::Multisig {
+            required,
+            keys: found,
+        } = multisig.classify()
+        else {
+            panic!("not multisig");
+        };
+        assert_eq!(required, 2);
+        assert_eq!(found, [keys[1].clone(), keys[0].clone()]);
+        assert_eq!(multisig.classify().address(), None);
+        // More signatures than keys, or the key count off.
+        let hex = multisig.to_hex();
+        assert_eq!(
+            classify(&format!("53{}", &hex[2..])),
+            ScriptType::Nonstandard
+        );
+        assert_eq!(
+            classify(&format!("{}53ae", &hex[..hex.len() - 4])),
+            ScriptType::Nonstandard
+        );
+    }
+
+    #[test]
+    fn null_data_and_nonstandard() {
+        assert_eq!(
+            classify("6a0b68656c6c6f20776f726c64"),
+            ScriptType::NullData(vec![b"hello world".to_vec()])
+        );
+        assert_eq!(
+            classify("6a0051"),
+            ScriptType::NullData(vec![vec![], vec![1]])
+        );
+        assert_eq!(classify("6a"), ScriptType::NullData(vec![]));
+        for hex in [
+            "",
+            "51",
+            "6a76",
+            // A version 0 program of neither 20 nor 32 bytes.
+            "0015751e76e8199196d454941c45d1b3a323f1433bd6aa",
+            // P2PKH with a PUSHDATA1 for the hash.
+            "76a94c1462e907b15cbf27d5425399ebf6f0fb50ebb88f1888ac",
+        ] {
+            assert_eq!(classify(hex), ScriptType::Nonstandard, "{}", hex);
+        }
+    }
+}
@@ -0,0 +1,2465 @@
+use std::fmt;
+
+use num::BigUint;
+
+use super::opcode::*;
+use super::{Command, Script};
+use crate::hash::{hash160, hash256, ripemd160, sha1, sha256, tagged_hash};
+use crate::helper::encode_varint;
+use crate::secp256k1::point::{n, Secp256k1Point};
+use crate::secp256k1::schnorr::SchnorrSignature;
+use crate::secp256k1::signature::Signature;
+use crate::taproot::{output_key, tap_leaf_hash, TAPSCRIPT_LEAF_VERSION};
+use crate::tx::locktime::{
+    LOCKTIME_THRESHOLD, SEQUENCE_LOCKTIME_DISABLE_FLAG, SEQUENCE_LOCKTIME_MASK,
+    SEQUENCE_LOCKTIME_TYPE_FLAG,
+};
+use crate::tx::sighash::{p2wpkh_script_code, SighashCache};
+use crate::tx::{read_op, Tx, TxOut};
+
+// Consensus limits.
+pub const MAX_SCRIPT_SIZE: usize = 10_000;
+pub const MAX_SCRIPT_ELEMENT_SIZE: usize = 520;
+pub const MAX_OPS_PER_SCRIPT: usize = 201;
+pub const MAX_STACK_SIZE: usize = 1000;
+pub const MAX_PUBKEYS_PER_MULTISIG: usize = 20;
+// BIP342: every signature checked costs this much of the witness size budget.
+pub const VALIDATION_WEIGHT_PER_SIGOP: i64 = 50;
+pub const TAPROOT_CONTROL_MAX_NODE_COUNT: usize = 128;
+
+// Verification flags, Bitcoin Core's SCRIPT_VERIFY_*. Each one turns on a rule
+// on top of the original consensus rules.
+pub const VERIFY_NONE: u32 = 0;
+// BIP16: a script_pubkey of the P2SH form runs the redeem script the
+// script_sig pushes last.
+pub const VERIFY_P2SH: u32 = 1 << 0;
+// BIP62 rules: signatures and public keys in their strict encodings.
+pub const VERIFY_STRICTENC: u32 = 1 << 1;
+// BIP66: strict DER signatures.
+pub const VERIFY_DERSIG: u32 = 1 << 2;
+pub const VERIFY_LOW_S: u32 = 1 << 3;
+// BIP147: the extra item OP_CHECKMULTISIG pops must be empty.
+pub const VERIFY_NULLDUMMY: u32 = 1 << 4;
+// Only pushes in the script_sig, P2SH or not.
+pub const VERIFY_SIGPUSHONLY: u32 = 1 << 5;
+// Pushes and numbers in their smallest encoding.
+pub const VERIFY_MINIMALDATA: u32 = 1 << 6;
+pub const VERIFY_DISCOURAGE_UPGRADABLE_NOPS: u32 = 1 << 7;
+// Exactly one item left on the stack. Needs VERIFY_P2SH and VERIFY_WITNESS.
+pub const VERIFY_CLEANSTACK: u32 = 1 << 8;
+// BIP65 and BIP112: OP_NOP2 and OP_NOP3 check the spending transaction's
+// locktime and sequence.
+pub const VERIFY_CHECKLOCKTIMEVERIFY: u32 = 1 << 9;
+pub const VERIFY_CHECKSEQUENCEVERIFY: u32 = 1 << 10;
+// BIP141 and BIP143: witness programs and their signature digests.
+pub const VERIFY_WITNESS: u32 = 1 << 11;
+pub const VERIFY_DISCOURAGE_UPGRADABLE_WITNESS_PROGRAM: u32 = 1 << 12;
+// OP_IF and OP_NOTIF in witness v0 scripts take only an empty item or 0x01.
+pub const VERIFY_MINIMALIF: u32 = 1 << 13;
+// A failed signature check must have been given an empty signature.
+pub const VERIFY_NULLFAIL: u32 = 1 << 14;
+// Only compressed keys in witness v0 scripts.
+pub const VERIFY_WITNESS_PUBKEYTYPE: u32 = 1 << 15;
+// Legacy scripts may not change their own script code: no OP_CODESEPARATOR,
+// and no signature that would be deleted from the script it signs.
+pub const VERIFY_CONST_SCRIPTCODE: u32 = 1 << 16;
+// BIP341 and BIP342: taproot key and script path spends.
+pub const VERIFY_TAPROOT: u32 = 1 << 17;
+pub const VERIFY_DISCOURAGE_UPGRADABLE_TAPROOT_VERSION: u32 = 1 << 18;
+pub const VERIFY_DISCOURAGE_OP_SUCCESS: u32 = 1 << 19;
+pub const VERIFY_DISCOURAGE_UPGRADABLE_PUBKEYTYPE: u32 = 1 << 20;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScriptError {
+    EvalFalse,
+    OpReturn,
+    ScriptSize,
+    PushSize,
+    OpCount,
+    StackSize,
+    Verify,
+    EqualVerify,
+    CheckSigVerify,
+    CheckMultisigVerify,
+    NumEqualVerify,
+    BadOpcode,
+    DisabledOpcode,
+    PubkeyCount,
+    SigCount,
+    InvalidStackOperation,
+    InvalidAltstackOperation,
+    UnbalancedConditional,
+    NegativeLocktime,
+    UnsatisfiedLocktime,
+    NumberOverflow,
+    NonMinimalNumber,
+    MinimalData,
+    SigHashType,
+    SigDer,
+    SigHighS,
+    PubkeyType,
+    SigNullFail,
+    SigNullDummy,
+    SigPushOnly,
+    SigFindAndDelete,
+    OpCodeSeparator,
+    CleanStack,
+    MinimalIf,
+    WitnessPubkeyType,
+    WitnessProgramWrongLength,
+    WitnessProgramWitnessEmpty,
+    WitnessProgramMismatch,
+    WitnessMalleated,
+    WitnessMalleatedP2sh,
+    WitnessUnexpected,
+    SchnorrSigSize,
+    SchnorrSigHashType,
+    SchnorrSig,
+    TaprootWrongControlSize,
+    TapscriptValidationWeight,
+    TapscriptCheckMultisig,
+    TapscriptMinimalIf,
+    TapscriptEmptyPubkey,
+    DiscourageUpgradableNops,
+    DiscourageUpgradableWitnessProgram,
+    DiscourageUpgradableTaprootVersion,
+    DiscourageOpSuccess,
+    DiscourageUpgradablePubkeyType,
+}
+
+impl fmt::Display for ScriptError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let reason = match self {
+            ScriptError::EvalFalse => {
+                "script evaluated without error but finished with a false/empty top stack element"
+            }
+            ScriptError::OpReturn => "OP_RETURN was encountered",
+            ScriptError::ScriptSize => "script is too big",
+            ScriptError::PushSize => "push value size limit exceeded",
+            ScriptError::OpCount => "operation limit exceeded",
+            ScriptError::StackSize => "stack size limit exceeded",
+            ScriptError::Verify => "script failed an OP_VERIFY operation",
+            ScriptError::EqualVerify => "script failed an OP_EQUALVERIFY operation",
+            ScriptError::CheckSigVerify => "script failed an OP_CHECKSIGVERIFY operation",
+            ScriptError::CheckMultisigVerify => "script failed an OP_CHECKMULTISIGVERIFY operation",
+            ScriptError::NumEqualVerify => "script failed an OP_NUMEQUALVERIFY operation",
+            ScriptError::BadOpcode => "opcode missing or not understood",
+            ScriptError::DisabledOpcode => "attempted to use a disabled opcode",
+            ScriptError::PubkeyCount => "pubkey count negative or limit exceeded",
+            ScriptError::SigCount => "signature count negative or greater than pubkey count",
+            ScriptError::InvalidStackOperation => "operation not valid with the current stack size",
+            ScriptError::InvalidAltstackOperation => {
+                "operation not valid with the current altstack size"
+            }
+            ScriptError::UnbalancedConditional => "invalid OP_IF construction",
+            ScriptError::NegativeLocktime => "negative locktime",
+            ScriptError::UnsatisfiedLocktime => "locktime requirement not satisfied",
+            ScriptError::NumberOverflow => "script number overflow",
+            ScriptError::NonMinimalNumber => "non-minimally encoded script number",
+            ScriptError::MinimalData => "data push larger than necessary",
+            ScriptError::SigHashType => "signature hash type missing or not understood",
+            ScriptError::SigDer => "non-canonical DER signature",
+            ScriptError::SigHighS => "non-canonical signature: S value is unnecessarily high",
+            ScriptError::PubkeyType => "public key is neither compressed or uncompressed",
+            ScriptError::SigNullFail => {
+                "signature must be zero for failed CHECK(MULTI)SIG operation"
+            }
+            ScriptError::SigNullDummy => "dummy CHECKMULTISIG argument must be zero",
+            ScriptError::SigPushOnly => "only push operators allowed in signatures",
+            ScriptError::SigFindAndDelete => "signature is found in scriptCode",
+            ScriptError::OpCodeSeparator => "using OP_CODESEPARATOR in non-witness script",
+            ScriptError::CleanStack => "stack size must be exactly one after execution",
+            ScriptError::MinimalIf => "OP_IF/NOTIF argument must be minimal",
+            ScriptError::WitnessPubkeyType => "using non-compressed keys in segwit",
+            ScriptError::WitnessProgramWrongLength => "witness program has incorrect length",
+            ScriptError::WitnessProgramWitnessEmpty => {
+                "witness program was passed an empty witness"
+            }
+            ScriptError::WitnessProgramMismatch => "witness program hash mismatch",
+            ScriptError::WitnessMalleated => "witness requires empty scriptSig",
+            ScriptError::WitnessMalleatedP2sh => "witness requires only-redeemscript scriptSig",
+            ScriptError::WitnessUnexpected => "witness provided for non-witness script",
+            ScriptError::SchnorrSigSize => "invalid Schnorr signature size",
+            ScriptError::SchnorrSigHashType => "invalid Schnorr signature hash type",
+            ScriptError::SchnorrSig => "invalid Schnorr signature",
+            ScriptError::TaprootWrongControlSize => "invalid Taproot control block size",
+            ScriptError::TapscriptValidationWeight => {
+                "too much signature validation relative to witness weight"
+            }
+            ScriptError::TapscriptCheckMultisig => {
+                "OP_CHECKMULTISIG(VERIFY) is not available in tapscript"
+            }
+            ScriptError::TapscriptMinimalIf => "OP_IF/NOTIF argument must be minimal in tapscript",
+            ScriptError::TapscriptEmptyPubkey => "empty public key in tapscript",
+            ScriptError::DiscourageUpgradableNops => "NOPx reserved for soft-fork upgrades",
+            ScriptError::DiscourageUpgradableWitnessProgram => {
+                "witness version reserved for soft-fork upgrades"
+            }
+            ScriptError::DiscourageUpgradableTaprootVersion => {
+                "taproot version reserved for soft-fork upgrades"
+            }
+            ScriptError::DiscourageOpSuccess => "OP_SUCCESSx reserved for soft-fork upgrades",
+            ScriptError::DiscourageUpgradablePubkeyType => {
+                "public key version reserved for soft-fork upgrades"
+            }
+        };
+        write!(f, "{}", reason)
+    }
+}
+
+// Which rules a script runs under, and so which digest its signatures sign.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SigVersion {
+    Base,
+    WitnessV0,
+    Tapscript,
+}
+
+// What signatures are checked against. The interpreter finds the signature and
+// key; the digest they commit to comes from the spending transaction, which
+// only the caller knows. None makes the signature invalid.
+pub trait SignatureChecker {
+    // The digest an ECDSA signature with `sighash_type` signs, where
+    // `script_code` is the executing script from its last OP_CODESEPARATOR on.
+    fn ecdsa_sighash(
+        &self,
+        script_code: &[u8],
+        sighash_type: u32,
+        sig_version: SigVersion,
+    ) -> Option<[u8; 32]>;
+
+    // The BIP341 digest, with `leaf` the tapleaf hash and OP_CODESEPARATOR
+    // position for script path spends.
+    fn taproot_sighash(
+        &self,
+        _sighash_type: u32,
+        _annex: Option<&[u8]>,
+        _leaf: Option<(&[u8; 32], u32)>,
+    ) -> Option<[u8; 32]> {
+        None
+    }
+
+    // BIP65: whether the transaction's locktime is at least `locktime`, of the
+    // same kind, and not turned off by a final input.
+    fn check_locktime(&self, _locktime: i64) -> bool {
+        false
+    }
+
+    // BIP112: whether the input's relative locktime is at least `sequence`'s,
+    // of the same kind.
+    fn check_sequence(&self, _sequence: i64) -> bool {
+        false
+    }
+}
+
+// Watches each step `Interpreter::run` takes, for tracers. The interpreter is
+// about to execute the command at its position, or has just executed the one
+// before it.
+pub trait StepObserver {
+    fn before_step(&self, interpreter: &Interpreter);
+
+    fn after_step(&self, interpreter: &Interpreter, result: &Result<(), ScriptError>);
+}
+
+// Closures make a checker for ECDSA signatures over a digest computed some
+// other way.
+impl<F: Fn(&[u8], u32) -> [u8; 32]> SignatureChecker for F {
+    fn ecdsa_sighash(
+        &self,
+        script_code: &[u8],
+        sighash_type: u32,
+        _: SigVersion,
+    ) -> Option<[u8; 32]> {
+        Some(self(script_code, sighash_type))
+    }
+}
+
+// Checks signatures of one input of a transaction.
+pub struct TxSignatureChecker<'a> {
+    tx: &'a Tx,
+    input_index: usize,
+    spent_outputs: Option<&'a [TxOut]>,
+    cache: SighashCache<'a>,
+}
+
+impl<'a> TxSignatureChecker<'a> {
+    // Enough for legacy scripts, whose signatures commit to no amounts.
+    pub fn new(tx: &'a Tx, input_index: usize) -> Self {
+        TxSignatureChecker {
+            tx,
+            input_index,
+            spent_outputs: None,
+            cache: SighashCache::new(tx),
+        }
+    }
+
+    // Segwit signatures also commit to the amount spent and taproot ones to
+    // every output spent, so these need `spent_outputs` for all inputs, in order.
+    pub fn with_spent_outputs(tx: &'a Tx, input_index: usize, spent_outputs: &'a [TxOut]) -> Self {
+        TxSignatureChecker {
+            spent_outputs: Some(spent_outputs),
+            ..TxSignatureChecker::new(tx, input_index)
+        }
+    }
+}
+
+impl SignatureChecker for TxSignatureChecker<'_> {
+    fn ecdsa_sighash(
+        &self,
+        script_code: &[u8],
+        sighash_type: u32,
+        sig_version: SigVersion,
+    ) -> Option<[u8; 32]> {
+        if sig_version == SigVersion::Base {
+            return Some(
+                self.tx
+                    .legacy_sighash(self.input_index, script_code, sighash_type),
+            );
+        }
+        let value = self.spent_outputs?.get(self.input_index)?.value;
+        self.cache
+            .segwit_v0(self.input_index, script_code, value, sighash_type)
+            .ok()
+    }
+
+    fn taproot_sighash(
+        &self,
+        sighash_type: u32,
+        annex: Option<&[u8]>,
+        leaf: Option<(&[u8; 32], u32)>,
+    ) -> Option<[u8; 32]> {
+        self.cache
+            .taproot(
+                self.input_index,
+                self.spent_outputs?,
+                annex,
+                leaf,
+                sighash_type,
+            )
+            .ok()
+    }
+
+    fn check_locktime(&self, locktime: i64) -> bool {
+        let tx_locktime = self.tx.locktime as i64;
+        let threshold = LOCKTIME_THRESHOLD as i64;
+        (locktime < threshold) == (tx_locktime < threshold)
+            && locktime <= tx_locktime
+            && self
+                .tx
+                .inputs
+                .get(self.input_index)
+                .is_some_and(|tx_in| !tx_in.is_final())
+    }
+
+    fn check_sequence(&self, sequence: i64) -> bool {
+        let Some(tx_in) = self.tx.inputs.get(self.input_index) else {
+            return false;
+        };
+        let tx_sequence = tx_in.sequence as i64;
+        if self.tx.version < 2 || tx_sequence & SEQUENCE_LOCKTIME_DISABLE_FLAG as i64 != 0 {
+            return false;
+        }
+        let mask = (SEQUENCE_LOCKTIME_TYPE_FLAG | SEQUENCE_LOCKTIME_MASK) as i64;
+        let (sequence, tx_sequence) = (sequence & mask, tx_sequence & mask);
+        let type_flag = SEQUENCE_LOCKTIME_TYPE_FLAG as i64;
+        (sequence < type_flag) == (tx_sequence < type_flag) && sequence <= tx_sequence
+    }
+}
+
+// What tapscript signatures commit to besides the transaction, and how much
+// more signature checking the size of the witness pays for.
+struct TapscriptContext<'a> {
+    leaf_hash: [u8; 32],
+    annex: Option<&'a [u8]>,
+    validation_weight_left: i64,
+}
+
+// Runs one script over a stack. Each step executes one command, so callers can
+// look at the stacks in between.
+pub struct Interpreter<'a> {
+    script: &'a Script,
+    flags: u32,
+    checker: &'a dyn SignatureChecker,
+    observer: Option<&'a dyn StepObserver>,
+    position: usize,
+    stack: Vec<Vec<u8>>,
+    alt_stack: Vec<Vec<u8>>,
+    // One entry per enclosing OP_IF, whether its branch is being taken.
+    exec_stack: Vec<bool>,
+    op_count: usize,
+    // Where the script code for signatures starts, after the last OP_CODESEPARATOR.
+    codeseparator: usize,
+    sig_version: SigVersion,
+    tapscript: Option<TapscriptContext<'a>>,
+}
+
+impl<'a> Interpreter<'a> {
+    pub fn new(
+        script: &'a Script,
+        stack: Vec<Vec<u8>>,
+        flags: u32,
+        checker: &'a dyn SignatureChecker,
+    ) -> Self {
+        Interpreter {
+            script,
+            flags,
+            checker,
+            observer: None,
+            position: 0,
+            stack,
+            alt_stack: Vec::new(),
+            exec_stack: Vec::new(),
+            op_count: 0,
+            codeseparator: 0,
+            sig_version: SigVersion::Base,
+            tapscript: None,
+        }
+    }
+
+    // Runs a P2WSH witness script, or the script a P2WPKH program stands for.
+    pub fn witness_v0(mut self) -> Self {
+        self.sig_version = SigVersion::WitnessV0;
+        self
+    }
+
+    // Runs a tapscript leaf. `witness_size` is the serialized size of the whole
+    // witness, which sets the signature budget.
+    pub fn tapscript(
+        mut self,
+        leaf_hash: [u8; 32],
+        annex: Option<&'a [u8]>,
+        witness_size: usize,
+    ) -> Self {
+        self.sig_version = SigVersion::Tapscript;
+        self.tapscript = Some(TapscriptContext {
+            leaf_hash,
+            annex,
+            validation_weight_left: witness_size as i64 + VALIDATION_WEIGHT_PER_SIGOP,
+        });
+        self
+    }
+
+    // Reports every step to `observer`, if there is one.
+    pub fn observed(mut self, observer: Option<&'a dyn StepObserver>) -> Self {
+        self.observer = observer;
+        self
+    }
+
+    pub fn script(&self) -> &'a Script {
+        self.script
+    }
+
+    pub fn sig_version(&self) -> SigVersion {
+        self.sig_version
+    }
+
+    // The index of the next command in the script.
+    pub fn position(&self) -> usize {
+        self.position
+    }
+
+    pub fn stack(&self) -> &[Vec<u8>] {
+        &self.stack
+    }
+
+    pub fn alt_stack(&self) -> &[Vec<u8>] {
+        &self.alt_stack
+    }
+
+    // The next command to execute, None once the script is done.
+    pub fn next_command(&self) -> Option<&'a Command> {
+        self.script.commands.get(self.position)
+    }
+
+    // False inside a branch that is not taken.
+    pub fn is_executing(&self) -> bool {
+        self.exec_stack.iter().all(|executing| *executing)
+    }
+
+    // Runs the script to the end and returns the stack it leaves.
+    pub fn run(mut self) -> Result<Vec<Vec<u8>>, ScriptError> {
+        if self.sig_version != SigVersion::Tapscript
+            && self.script.serialize().len() > MAX_SCRIPT_SIZE
+        {
+            return Err(ScriptError::ScriptSize);
+        }
+        while self.next_command().is_some() {
+            if let Some(observer) = self.observer {
+                observer.before_step(&self);
+            }
+            let result = self.step();
+            if let Some(observer) = self.observer {
+                observer.after_step(&self, &result);
+            }
+            result?;
+        }
+        self.finish()
+    }
+
+    // Checks that every OP_IF was closed, and hands back the stack.
+    pub fn finish(self) -> Result<Vec<Vec<u8>>, ScriptError> {
+        if !self.exec_stack.is_empty() {
+            return Err(ScriptError::UnbalancedConditional);
+        }
+        Ok(self.stack)
+    }
+
+    // Executes the next command.
+    pub fn step(&mut self) -> Result<(), ScriptError> {
+        let command = match self.next_command() {
+            Some(command) => command,
+            None => return Ok(()),
+        };
+        self.position += 1;
+        let executing = self.is_executing();
+        match command {
+            Command::Push(opcode, data) => {
+                if data.len() > MAX_SCRIPT_ELEMENT_SIZE {
+                    return Err(ScriptError::PushSize);
+                }
+                if executing {
+                    if self.flags & VERIFY_MINIMALDATA != 0 && !is_minimal_push(*opcode, data) {
+                        return Err(ScriptError::MinimalData);
+                    }
+                    self.stack.push(data.clone());
+                }
+            }
+            Command::Op(opcode) => {
+                // Tapscript has no operation limit, only the signature budget.
+                if *opcode > OP_16 && self.sig_version != SigVersion::Tapscript {
+                    self.op_count += 1;
+                    if self.op_count > MAX_OPS_PER_SCRIPT {
+                        return Err(ScriptError::OpCount);
+                    }
+                }
+                if is_disabled(*opcode) {
+                    return Err(ScriptError::DisabledOpcode);
+                }
+                if *opcode == OP_CODESEPARATOR
+                    && self.sig_version == SigVersion::Base
+                    && self.flags & VERIFY_CONST_SCRIPTCODE != 0
+                {
+                    return Err(ScriptError::OpCodeSeparator);
+                }
+                // Conditionals are followed even in branches not taken.
+                if executing || (OP_IF..=OP_ENDIF).contains(opcode) {
+                    self.execute(*opcode, executing)?;
+                }
+            }
+        }
+        if self.stack.len() + self.alt_stack.len() > MAX_STACK_SIZE {
+            return Err(ScriptError::StackSize);
+        }
+        Ok(())
+    }
+
+    fn execute(&mut self, opcode: Opcode, executing: bool) -> Result<(), ScriptError> {
+        match opcode {
+            _ if opcode == OP_1NEGATE || (OP_1..=OP_16).contains(&opcode) => {
+                let n = opcode.small_int().unwrap();
+                self.stack.push(encode_num(n));
+            }
+
+            // Flow control.
+            OP_NOP => {}
+            // Without their flags these are still NOP2 and NOP3, which are not
+            // discouraged.
+            OP_CHECKLOCKTIMEVERIFY if self.flags & VERIFY_CHECKLOCKTIMEVERIFY == 0 => {}
+            OP_CHECKSEQUENCEVERIFY if self.flags & VERIFY_CHECKSEQUENCEVERIFY == 0 => {}
+            OP_CHECKLOCKTIMEVERIFY => {
+                let locktime = self.top_locktime()?;
+                if locktime < 0 {
+                    return Err(ScriptError::NegativeLocktime);
+                }
+                if !self.checker.check_locktime(locktime) {
+                    return Err(ScriptError::UnsatisfiedLocktime);
+                }
+            }
+            OP_CHECKSEQUENCEVERIFY => {
+                let sequence = self.top_locktime()?;
+                if sequence < 0 {
+                    return Err(ScriptError::NegativeLocktime);
+                }
+                // With the disable flag set it is a NOP, for future use.
+                if sequence & SEQUENCE_LOCKTIME_DISABLE_FLAG as i64 == 0
+                    && !self.checker.check_sequence(sequence)
+                {
+                    return Err(ScriptError::UnsatisfiedLocktime);
+                }
+            }
+            OP_NOP1 | OP_NOP4 | OP_NOP5 | OP_NOP6 | OP_NOP7 | OP_NOP8 | OP_NOP9 | OP_NOP10 => {
+                if self.flags & VERIFY_DISCOURAGE_UPGRADABLE_NOPS != 0 {
+                    return Err(ScriptError::DiscourageUpgradableNops);
+                }
+            }
+            OP_IF | OP_NOTIF => {
+                let mut condition = false;
+                if executing {
+                    let item = self.stack.pop().ok_or(ScriptError::InvalidStackOperation)?;
+                    if item.len() > 1 || item.first().is_some_and(|byte| *byte != 1) {
+                        match self.sig_version {
+                            SigVersion::Tapscript => return Err(ScriptError::TapscriptMinimalIf),
+                            SigVersion::WitnessV0 if self.flags & VERIFY_MINIMALIF != 0 => {
+                                return Err(ScriptError::MinimalIf)
+                            }
+                            _ => {}
+                        }
+                    }
+                    condition = cast_to_bool(&item);
+                    if opcode == OP_NOTIF {
+                        condition = !condition;
+                    }
+                }
+                self.exec_stack.push(condition);
+            }
+            OP_ELSE => {
+                let last = self
+                    .exec_stack
+                    .last_mut()
+                    .ok_or(ScriptError::UnbalancedConditional)?;
+                *last = !*last;
+            }
+            OP_ENDIF => {
+                self.exec_stack
+                    .pop()
+                    .ok_or(ScriptError::UnbalancedConditional)?;
+            }
+            OP_VERIFY => {
+                if !cast_to_bool(&self.pop()?) {
+                    return Err(ScriptError::Verify);
+                }
+            }
+            OP_RETURN => return Err(ScriptError::OpReturn),
+
+            // Stack.
+            OP_TOALTSTACK => {
+                let item = self.pop()?;
+                self.alt_stack.push(item);
+            }
+            OP_FROMALTSTACK => {
+                let item = self
+                    .alt_stack
+                    .pop()
+                    .ok_or(ScriptError::InvalidAltstackOperation)?;
+                self.stack.push(item);
+            }
+            OP_2DROP => {
+                self.pop()?;
+                self.pop()?;
+            }
+            OP_2DUP => {
+                let items = self.top_items(2)?;
+                self.stack.extend(items);
+            }
+            OP_3DUP => {
+                let items = self.top_items(3)?;
+                self.stack.extend(items);
+            }
+            OP_2OVER => {
+                let items = self.top_items(4)?;
+                self.stack.extend_from_slice(&items[..2]);
+            }
+            OP_2ROT => {
+                self.top_items(6)?;
+                let index = self.stack.len() - 6;
+                let items: Vec<Vec<u8>> = self.stack.drain(index..index + 2).collect();
+                self.stack.extend(items);
+            }
+            OP_2SWAP => {
+                self.top_items(4)?;
+                let len = self.stack.len();
+                self.stack[len - 4..].rotate_left(2);
+            }
+            OP_IFDUP => {
+                let top = self.top(0)?.clone();
+                if cast_to_bool(&top) {
+                    self.stack.push(top);
+                }
+            }
+            OP_DEPTH => self.stack.push(encode_num(self.stack.len() as i64)),
+            OP_DROP => {
+                self.pop()?;
+            }
+            OP_DUP => {
+                let top = self.top(0)?.clone();
+                self.stack.push(top);
+            }
+            OP_NIP => {
+                self.top_items(2)?;
+                let index = self.stack.len() - 2;
+                self.stack.remove(index);
+            }
+            OP_OVER => {
+                let second = self.top(1)?.clone();
+                self.stack.push(second);
+            }
+            OP_PICK | OP_ROLL => {
+                let n = self.pop_num()?;
+                if n < 0 || n as usize >= self.stack.len() {
+                    return Err(ScriptError::InvalidStackOperation);
+                }
+                let index = self.stack.len() - 1 - n as usize;
+                let item = if opcode == OP_ROLL {
+                    self.stack.remove(index)
+                } else {
+                    self.stack[index].clone()
+                };
+                self.stack.push(item);
+            }
+            OP_ROT => {
+                self.top_items(3)?;
+                let len = self.stack.len();
+                self.stack[len - 3..].rotate_left(1);
+            }
+            OP_SWAP => {
+                self.top_items(2)?;
+                let len = self.stack.len();
+                self.stack.swap(len - 2, len - 1);
+            }
+            OP_TUCK => {
+                self.top_items(2)?;
+                let top = self.top(0)?.clone();
+                let index = self.stack.len() - 2;
+                self.stack.insert(index, top);
+            }
+            OP_SIZE => {
+                let size = self.top(0)?.len();
+                self.stack.push(encode_num(size as i64));
+            }
+
+            // Bitwise logic.
+            OP_EQUAL | OP_EQUALVERIFY => {
+                let b = self.pop()?;
+                let a = self.pop()?;
+                self.push_bool(a == b);
+                if opcode == OP_EQUALVERIFY {
+                    self.verify(ScriptError::EqualVerify)?;
+                }
+            }
+
+            // Arithmetic.
+            OP_1ADD | OP_1SUB | OP_NEGATE | OP_ABS | OP_NOT | OP_0NOTEQUAL => {
+                let a = self.pop_num()?;
+                let result = match opcode {
+                    OP_1ADD => a + 1,
+                    OP_1SUB => a - 1,
+                    OP_NEGATE => -a,
+                    OP_ABS => a.abs(),
+                    OP_NOT => (a == 0) as i64,
+                    _ => (a != 0) as i64,
+                };
+                self.stack.push(encode_num(result));
+            }
+            OP_ADD
+            | OP_SUB
+            | OP_BOOLAND
+            | OP_BOOLOR
+            | OP_NUMEQUAL
+            | OP_NUMEQUALVERIFY
+            | OP_NUMNOTEQUAL
+            | OP_LESSTHAN
+            | OP_GREATERTHAN
+            | OP_LESSTHANOREQUAL
+            | OP_GREATERTHANOREQUAL
+            | OP_MIN
+            | OP_MAX => {
+                let b = self.pop_num()?;
+                let a = self.pop_num()?;
+                let result = match opcode {
+                    OP_ADD => a + b,
+                    OP_SUB => a - b,
+                    OP_BOOLAND => (a != 0 && b != 0) as i64,
+                    OP_BOOLOR => (a != 0 || b != 0) as i64,
+                    OP_NUMEQUAL | OP_NUMEQUALVERIFY => (a == b) as i64,
+                    OP_NUMNOTEQUAL => (a != b) as i64,
+                    OP_LESSTHAN => (a < b) as i64,
+                    OP_GREATERTHAN => (a > b) as i64,
+                    OP_LESSTHANOREQUAL => (a <= b) as i64,
+                    OP_GREATERTHANOREQUAL => (a >= b) as i64,
+                    OP_MIN => a.min(b),
+                    _ => a.max(b),
+                };
+                self.stack.push(encode_num(result));
+                if opcode == OP_NUMEQUALVERIFY {
+                    self.verify(ScriptError::NumEqualVerify)?;
+                }
+            }
+            OP_WITHIN => {
+                let max = self.pop_num()?;
+                let min = self.pop_num()?;
+                let x = self.pop_num()?;
+                self.push_bool(min <= x && x < max);
+            }
+
+            // Crypto.
+            OP_RIPEMD160 => {
+                let item = self.pop()?;
+                self.stack.push(ripemd160(&item).to_vec());
+            }
+            OP_SHA1 => {
+                let item = self.pop()?;
+                self.stack.push(sha1(&item).to_vec());
+            }
+            OP_SHA256 => {
+                let item = self.pop()?;
+                self.stack.push(sha256(&item).to_vec());
+            }
+            OP_HASH160 => {
+                let item = self.pop()?;
+                self.stack.push(hash160(&item).to_vec());
+            }
+            OP_HASH256 => {
+                let item = self.pop()?;
+                self.stack.push(hash256(&item).to_vec());
+            }
+            OP_CODESEPARATOR => self.codeseparator = self.position,
+            OP_CHECKSIG | OP_CHECKSIGVERIFY => {
+                let pubkey = self.pop()?;
+                let signature = self.pop()?;
+                let valid = if self.sig_version == SigVersion::Tapscript {
+                    self.check_tapscript_signature(&signature, &pubkey)?
+                } else {
+                    let script_code = self.script_code(&[&signature])?;
+                    let valid = self.check_signature(&signature, &pubkey, &script_code)?;
+                    if !valid && self.flags & VERIFY_NULLFAIL != 0 && !signature.is_empty() {
+                        return Err(ScriptError::SigNullFail);
+                    }
+                    valid
+                };
+                self.push_bool(valid);
+                if opcode == OP_CHECKSIGVERIFY {
+                    self.verify(ScriptError::CheckSigVerify)?;
+                }
+            }
+            OP_CHECKSIGADD if self.sig_version == SigVersion::Tapscript => {
+                self.top_items(3)?;
+                let pubkey = self.pop()?;
+                let n = self.pop_num()?;
+                let signature = self.pop()?;
+                let valid = self.check_tapscript_signature(&signature, &pubkey)?;
+                self.stack.push(encode_num(n + valid as i64));
+            }
+            OP_CHECKMULTISIG | OP_CHECKMULTISIGVERIFY
+                if self.sig_version == SigVersion::Tapscript =>
+            {
+                return Err(ScriptError::TapscriptCheckMultisig)
+            }
+            OP_CHECKMULTISIG | OP_CHECKMULTISIGVERIFY => {
+                let valid = self.check_multisig()?;
+                self.push_bool(valid);
+                if opcode == OP_CHECKMULTISIGVERIFY {
+                    self.verify(ScriptError::CheckMultisigVerify)?;
+                }
+            }
+
+            // OP_VERIF, OP_VERNOTIF, OP_RESERVED and everything undefined.
+            _ => return Err(ScriptError::BadOpcode),
+        }
+        Ok(())
+    }
+
+    fn pop(&mut self) -> Result<Vec<u8>, ScriptError> {
+        self.stack.pop().ok_or(ScriptError::InvalidStackOperation)
+    }
+
+    fn pop_num(&mut self) -> Result<i64, ScriptError> {
+        let minimal = self.flags & VERIFY_MINIMALDATA != 0;
+        decode_num(&self.pop()?, 4, minimal)
+    }
+
+    // Locktimes reach 2^32 - 1, so take five bytes. They stay on the stack for
+    // the opcodes to remain NOPs to nodes that do not check them.
+    fn top_locktime(&self) -> Result<i64, ScriptError> {
+        let minimal = self.flags & VERIFY_MINIMALDATA != 0;
+        decode_num(self.top(0)?, 5, minimal)
+    }
+
+    // The item `depth` places below the top.
+    fn top(&self, depth: usize) -> Result<&Vec<u8>, ScriptError> {
+        self.stack
+            .len()
+            .checked_sub(depth + 1)
+            .map(|index| &self.stack[index])
+            .ok_or(ScriptError::InvalidStackOperation)
+    }
+
+    fn top_items(&self, count: usize) -> Result<Vec<Vec<u8>>, ScriptError> {
+        if self.stack.len() < count {
+            return Err(ScriptError::InvalidStackOperation);
+        }
+        Ok(self.stack[self.stack.len() - count..].to_vec())
+    }
+
+    fn push_bool(&mut self, value: bool) {
+        self.stack.push(if value { vec![0x01] } else { Vec::new() });
+    }
+
+    // The *VERIFY half of an opcode: pops the result it just pushed.
+    fn verify(&mut self, error: ScriptError) -> Result<(), ScriptError> {
+        if !cast_to_bool(&self.pop()?) {
+            return Err(error);
+        }
+        Ok(())
+    }
+
+    // The script from after the last OP_CODESEPARATOR. Legacy scripts also lose
+    // any push of the given signatures, since a signature cannot sign itself.
+    fn script_code(&self, signatures: &[&[u8]]) -> Result<Vec<u8>, ScriptError> {
+        let deleted: Vec<Vec<u8>> = match self.sig_version {
+            SigVersion::Base => signatures
+                .iter()
+                .map(|signature| Command::push(signature).serialize())
+                .collect(),
+            _ => Vec::new(),
+        };
+        let commands: Vec<Vec<u8>> = self.script.commands[self.codeseparator..]
+            .iter()
+            .map(Command::serialize)
+            .collect();
+        let kept: Vec<Vec<u8>> = commands
+            .iter()
+            .filter(|command| !deleted.contains(command))
+            .cloned()
+            .collect();
+        if kept.len() != commands.len() && self.flags & VERIFY_CONST_SCRIPTCODE != 0 {
+            return Err(ScriptError::SigFindAndDelete);
+        }
+        Ok(kept.concat())
+    }
+
+    // Encoding problems the flags make fatal are errors; anything else that
+    // keeps the signature from verifying just makes it invalid.
+    fn check_signature(
+        &self,
+        signature: &[u8],
+        pubkey: &[u8],
+        script_code: &[u8],
+    ) -> Result<bool, ScriptError> {
+        check_signature_encoding(signature, self.flags)?;
+        check_pubkey_encoding(pubkey, self.flags, self.sig_version)?;
+        let Some((sighash_type, der)) = signature.split_last() else {
+            return Ok(false);
+        };
+        let sighash =
+            self.checker
+                .ecdsa_sighash(script_code, *sighash_type as u32, self.sig_version);
+        Ok(sighash.is_some_and(|sighash| verify_ecdsa(der, pubkey, &sighash)))
+    }
+
+    // BIP342 signature checks: an empty signature fails quietly, any other
+    // that does not verify is an error. Keys that are not 32 bytes are for
+    // future soft forks and accept anything.
+    fn check_tapscript_signature(
+        &mut self,
+        signature: &[u8],
+        pubkey: &[u8],
+    ) -> Result<bool, ScriptError> {
+        let tapscript = self.tapscript.as_mut().unwrap();
+        if !signature.is_empty() {
+            tapscript.validation_weight_left -= VALIDATION_WEIGHT_PER_SIGOP;
+            if tapscript.validation_weight_left < 0 {
+                return Err(ScriptError::TapscriptValidationWeight);
+            }
+        }
+        match pubkey.len() {
+            0 => return Err(ScriptError::TapscriptEmptyPubkey),
+            32 if !signature.is_empty() => {
+                // The position counts every command, pushes included.
+                let codeseparator = match self.codeseparator {
+                    0 => u32::MAX,
+                    position => position as u32 - 1,
+                };
+                let leaf = (&tapscript.leaf_hash, codeseparator);
+                check_schnorr_signature(
+                    self.checker,
+                    signature,
+                    pubkey,
+                    tapscript.annex,
+                    Some(leaf),
+                )?;
+            }
+            32 => {}
+            _ if self.flags & VERIFY_DISCOURAGE_UPGRADABLE_PUBKEYTYPE != 0 => {
+                return Err(ScriptError::DiscourageUpgradablePubkeyType)
+            }
+            _ => {}
+        }
+        Ok(!signature.is_empty())
+    }
+
+    // Pops <dummy> <sig>... <m> <key>... <n> and checks that the signatures
+    // match m of the keys in order. Keys are tried from the last one back, and
+    // one that no signature matches is skipped for good.
+    fn check_multisig(&mut self) -> Result<bool, ScriptError> {
+        let key_count = self.pop_num()?;
+        if !(0..=MAX_PUBKEYS_PER_MULTISIG as i64).contains(&key_count) {
+            return Err(ScriptError::PubkeyCount);
+        }
+        // Every key counts as an operation, whether or not it is used.
+        self.op_count += key_count as usize;
+        if self.op_count > MAX_OPS_PER_SCRIPT {
+            return Err(ScriptError::OpCount);
+        }
+        let keys = (0..key_count)
+            .map(|_| self.pop())
+            .collect::<Result<Vec<_>, _>>()?;
+        let signature_count = self.pop_num()?;
+        if !(0..=key_count).contains(&signature_count) {
+            return Err(ScriptError::SigCount);
+        }
+        let signatures = (0..signature_count)
+            .map(|_| self.pop())
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let deleted: Vec<&[u8]> = signatures.iter().map(Vec::as_slice).collect();
+        let script_code = self.script_code(&deleted)?;
+        let mut valid = true;
+        let (mut signature_index, mut key_index) = (0, 0);
+        while valid && signature_index < signatures.len() {
+            let signature = &signatures[signature_index];
+            if self.check_signature(signature, &keys[key_index], &script_code)? {
+                signature_index += 1;
+            }
+            key_index += 1;
+            // Fails early once too few keys are left for the signatures.
+            valid = signatures.len() - signature_index <= keys.len() - key_index;
+        }
+        if !valid
+            && self.flags & VERIFY_NULLFAIL != 0
+            && signatures.iter().any(|signature| !signature.is_empty())
+        {
+            return Err(ScriptError::SigNullFail);
+        }
+
+        // The off-by-one in the original implementation: one more item is
+        // popped, which BIP147 requires to be empty.
+        let dummy = self.pop()?;
+        if self.flags & VERIFY_NULLDUMMY != 0 && !dummy.is_empty() {
+            return Err(ScriptError::SigNullDummy);
+        }
+        Ok(valid)
+    }
+}
+
+// Runs script_sig, then script_pubkey on the stack it leaves, and requires a
+// true value on top at the end. With VERIFY_P2SH, a P2SH script_pubkey then
+// has the redeem script run the same way on the rest of the script_sig stack,
+// and with VERIFY_WITNESS a witness program, bare or as the redeem script, is
+// checked against the witness.
+pub fn verify_script(
+    script_sig: &[u8],
+    script_pubkey: &[u8],
+    witness: &[Vec<u8>],
+    flags: u32,
+    checker: &dyn SignatureChecker,
+) -> Result<(), ScriptError> {
+    verify_script_observed(script_sig, script_pubkey, witness, flags, checker, None)
+}
+
+// verify_script, with `observer` watching every script it runs.
+pub fn verify_script_observed(
+    script_sig: &[u8],
+    script_pubkey: &[u8],
+    witness: &[Vec<u8>],
+    flags: u32,
+    checker: &dyn SignatureChecker,
+    observer: Option<&dyn StepObserver>,
+) -> Result<(), ScriptError> {
+    let script_sig_bytes = script_sig;
+    let script_sig = Script::parse(script_sig).map_err(|_| ScriptError::BadOpcode)?;
+    if flags & VERIFY_SIGPUSHONLY != 0 && !script_sig.is_push_only() {
+        return Err(ScriptError::SigPushOnly);
+    }
+    let script_pubkey_bytes = script_pubkey;
+    let script_pubkey = Script::parse(script_pubkey).map_err(|_| ScriptError::BadOpcode)?;
+    let stack = Interpreter::new(&script_sig, Vec::new(), flags, checker)
+        .observed(observer)
+        .run()?;
+    let p2sh_stack = stack.clone();
+    let mut stack = Interpreter::new(&script_pubkey, stack, flags, checker)
+        .observed(observer)
+        .run()?;
+    check_top(&stack)?;
+
+    let mut had_witness = false;
+    if flags & VERIFY_WITNESS != 0 {
+        if let Some((version, program)) = witness_program(script_pubkey_bytes) {
+            had_witness = true;
+            if !script_sig_bytes.is_empty() {
+                return Err(ScriptError::WitnessMalleated);
+            }
+            verify_witness_program(witness, version, program, false, flags, checker, observer)?;
+            // The witness has its own clean stack rule.
+            stack.truncate(1);
+        }
+    }
+
+    if flags & VERIFY_P2SH != 0 && is_p2sh(script_pubkey_bytes) {
+        if !script_sig.is_push_only() {
+            return Err(ScriptError::SigPushOnly);
+        }
+        stack = p2sh_stack;
+        // The script_pubkey succeeding means the script_sig pushed something.
+        let redeem_script_bytes = stack.pop().unwrap();
+        let redeem_script =
+            Script::parse(&redeem_script_bytes).map_err(|_| ScriptError::BadOpcode)?;
+        stack = Interpreter::new(&redeem_script, stack, flags, checker)
+            .observed(observer)
+            .run()?;
+        check_top(&stack)?;
+
+        if flags & VERIFY_WITNESS != 0 {
+            if let Some((version, program)) = witness_program(&redeem_script_bytes) {
+                had_witness = true;
+                if script_sig_bytes != Command::push(&redeem_script_bytes).serialize() {
+                    return Err(ScriptError::WitnessMalleatedP2sh);
+                }
+                verify_witness_program(witness, version, program, true, flags, checker, observer)?;
+                stack.truncate(1);
+            }
+        }
+    }
+
+    if flags & VERIFY_CLEANSTACK != 0 && stack.len() != 1 {
+        return Err(ScriptError::CleanStack);
+    }
+    if flags & VERIFY_WITNESS != 0 && !had_witness && !witness.is_empty() {
+        return Err(ScriptError::WitnessUnexpected);
+    }
+    Ok(())
+}
+
+// BIP141: a version push of OP_0 to OP_16, then a single direct push of 2 to 40
+// bytes.
+pub fn witness_program(script_pubkey: &[u8]) -> Option<(u8, &[u8])> {
+    match script_pubkey {
+        [version @ (0x00 | 0x51..=0x60), len @ 0x02..=0x28, program @ ..]
+            if program.len() == *len as usize =>
+        {
+            Some((version.saturating_sub(0x50), program))
+        }
+        _ => None,
+    }
+}
+
+fn verify_witness_program(
+    witness: &[Vec<u8>],
+    version: u8,
+    program: &[u8],
+    is_p2sh: bool,
+    flags: u32,
+    checker: &dyn SignatureChecker,
+    observer: Option<&dyn StepObserver>,
+) -> Result<(), ScriptError> {
+    let mut stack = witness.to_vec();
+    match (version, program.len()) {
+        (0, 32) => {
+            let witness_script = stack.pop().ok_or(ScriptError::WitnessProgramWitnessEmpty)?;
+            if sha256(&witness_script) != program {
+                return Err(ScriptError::WitnessProgramMismatch);
+            }
+            let witness_script =
+                Script::parse(&witness_script).map_err(|_| ScriptError::BadOpcode)?;
+            execute_witness_script(
+                Interpreter::new(&witness_script, stack, flags, checker)
+                    .observed(observer)
+                    .witness_v0(),
+            )
+        }
+        (0, 20) => {
+            if stack.len() != 2 {
+                return Err(ScriptError::WitnessProgramMismatch);
+            }
+            let script_code = p2wpkh_script_code(program.try_into().unwrap());
+            let script = Script::parse(&script_code).unwrap();
+            execute_witness_script(
+                Interpreter::new(&script, stack, flags, checker)
+                    .observed(observer)
+                    .witness_v0(),
+            )
+        }
+        (0, _) => Err(ScriptError::WitnessProgramWrongLength),
+        (1, 32) if !is_p2sh && flags & VERIFY_TAPROOT != 0 => {
+            verify_taproot(stack, program, witness, flags, checker, observer)
+        }
+        // Taproot before activation, and later versions for future soft forks,
+        // are anyone can spend.
+        (1, 32) if !is_p2sh => Ok(()),
+        _ if flags & VERIFY_DISCOURAGE_UPGRADABLE_WITNESS_PROGRAM != 0 => {
+            Err(ScriptError::DiscourageUpgradableWitnessProgram)
+        }
+        _ => Ok(()),
+    }
+}
+
+// BIP341: one item left after the annex is a key path signature, more are the
+// script path's inputs, leaf script and control block.
+fn verify_taproot(
+    mut stack: Vec<Vec<u8>>,
+    program: &[u8],
+    witness: &[Vec<u8>],
+    flags: u32,
+    checker: &dyn SignatureChecker,
+    observer: Option<&dyn StepObserver>,
+) -> Result<(), ScriptError> {
+    if stack.is_empty() {
+        return Err(ScriptError::WitnessProgramWitnessEmpty);
+    }
+    let annex = match witness {
+        [_, .., last] if last.first() == Some(&0x50) => {
+            stack.pop();
+            Some(last.as_slice())
+        }
+        _ => None,
+    };
+    if stack.len() == 1 {
+        return check_schnorr_signature(checker, &stack[0], program, annex, None);
+    }
+
+    let control_block = stack.pop().unwrap();
+    let leaf_script = stack.pop().unwrap();
+    let path_len = control_block.len().wrapping_sub(33);
+    if control_block.len() < 33
+        || !path_len.is_multiple_of(32)
+        || path_len / 32 > TAPROOT_CONTROL_MAX_NODE_COUNT
+    {
+        return Err(ScriptError::TaprootWrongControlSize);
+    }
+    let leaf_version = control_block[0] & 0xfe;
+    let leaf_hash = tap_leaf_hash(&leaf_script, leaf_version);
+    if !verify_taproot_commitment(&control_block, program, &leaf_hash) {
+        return Err(ScriptError::WitnessProgramMismatch);
+    }
+    if leaf_version != TAPSCRIPT_LEAF_VERSION {
+        if flags & VERIFY_DISCOURAGE_UPGRADABLE_TAPROOT_VERSION != 0 {
+            return Err(ScriptError::DiscourageUpgradableTaprootVersion);
+        }
+        return Ok(());
+    }
+
+    // Any OP_SUCCESSx makes the leaf succeed, as long as the script parses up
+    // to it.
+    let mut input = leaf_script.as_slice();
+    while !input.is_empty() {
+        let (opcode, _) = read_op(&mut input).map_err(|_| ScriptError::BadOpcode)?;
+        if Opcode(opcode).is_success() {
+            if flags & VERIFY_DISCOURAGE_OP_SUCCESS != 0 {
+                return Err(ScriptError::DiscourageOpSuccess);
+            }
+            return Ok(());
+        }
+    }
+    if stack.len() > MAX_STACK_SIZE {
+        return Err(ScriptError::StackSize);
+    }
+    let leaf_script = Script::parse(&leaf_script).unwrap();
+    let witness_size = encode_varint(witness.len() as u64).len()
+        + witness
+            .iter()
+            .map(|item| encode_varint(item.len() as u64).len() + item.len())
+            .sum::<usize>();
+    execute_witness_script(
+        Interpreter::new(&leaf_script, stack, flags, checker)
+            .observed(observer)
+            .tapscript(leaf_hash, annex, witness_size),
+    )
+}
+
+// The control block's internal key, tweaked with the merkle root its path and
+// the leaf hash lead to, must be the output key, with the parity it claims.
+fn verify_taproot_commitment(control_block: &[u8], program: &[u8], leaf_hash: &[u8; 32]) -> bool {
+    let Ok(internal_key) = Secp256k1Point::lift_x(&control_block[1..33]) else {
+        return false;
+    };
+    let mut node = *leaf_hash;
+    for sibling in control_block[33..].chunks(32) {
+        node = if node[..] < *sibling {
+            tagged_hash("TapBranch", &[&node[..], sibling].concat())
+        } else {
+            tagged_hash("TapBranch", &[sibling, &node[..]].concat())
+        };
+    }
+    let output_key = output_key(&internal_key, Some(&node));
+    output_key.x_only() == program && output_key.has_even_y() == (control_block[0] & 1 == 0)
+}
+
+// Witness items obey the push limit, and the script must leave exactly one
+// true item.
+fn execute_witness_script(interpreter: Interpreter) -> Result<(), ScriptError> {
+    if interpreter
+        .stack()
+        .iter()
+        .any(|item| item.len() > MAX_SCRIPT_ELEMENT_SIZE)
+    {
+        return Err(ScriptError::PushSize);
+    }
+    let stack = interpreter.run()?;
+    if stack.len() != 1 {
+        return Err(ScriptError::CleanStack);
+    }
+    check_top(&stack)
+}
+
+// A BIP340 signature, with a 65th byte for any sighash type but the default.
+// Unlike ECDSA, a signature that does not verify is always an error.
+fn check_schnorr_signature(
+    checker: &dyn SignatureChecker,
+    signature: &[u8],
+    x_only: &[u8],
+    annex: Option<&[u8]>,
+    leaf: Option<(&[u8; 32], u32)>,
+) -> Result<(), ScriptError> {
+    let (signature, sighash_type) = match signature {
+        [signature @ .., sighash_type] if signature.len() == 64 && *sighash_type != 0 => {
+            (signature, *sighash_type as u32)
+        }
+        [signature @ .., _] if signature.len() == 64 => {
+            return Err(ScriptError::SchnorrSigHashType)
+        }
+        _ if signature.len() == 64 => (signature, 0),
+        _ => return Err(ScriptError::SchnorrSigSize),
+    };
+    let sighash = checker
+        .taproot_sighash(sighash_type, annex, leaf)
+        .ok_or(ScriptError::SchnorrSigHashType)?;
+    let signature = SchnorrSignature::parse(signature).unwrap();
+    match Secp256k1Point::lift_x(x_only) {
+        Ok(key) if key.verify_schnorr(&sighash, &signature) => Ok(()),
+        _ => Err(ScriptError::SchnorrSig),
+    }
+}
+
+fn check_top(stack: &[Vec<u8>]) -> Result<(), ScriptError> {
+    match stack.last() {
+        Some(top) if cast_to_bool(top) => Ok(()),
+        _ => Err(ScriptError::EvalFalse),
+    }
+}
+
+// OP_HASH160 <20 bytes> OP_EQUAL, exactly.
+fn is_p2sh(script_pubkey: &[u8]) -> bool {
+    matches!(script_pubkey, [0xa9, 0x14, hash @ .., 0x87] if hash.len() == 20)
+}
+
+// Numbers on the stack are little endian with a sign bit, zero being empty.
+pub fn encode_num(n: i64) -> Vec<u8> {
+    let mut result = Vec::new();
+    let mut abs = n.unsigned_abs();
+    while abs > 0 {
+        result.push(abs as u8);
+        abs >>= 8;
+    }
+    match result.last_mut() {
+        Some(last) if *last & 0x80 != 0 => result.push(if n < 0 { 0x80 } else { 0x00 }),
+        Some(last) if n < 0 => *last |= 0x80,
+        _ => {}
+    }
+    result
+}
+
+// Arithmetic takes at most `max_len` byte numbers, 4 for almost every opcode,
+// though results may be longer.
+pub fn decode_num(bytes: &[u8], max_len: usize, minimal: bool) -> Result<i64, ScriptError> {
+    if bytes.len() > max_len {
+        return Err(ScriptError::NumberOverflow);
+    }
+    let last = match bytes.last() {
+        Some(last) => *last,
+        None => return Ok(0),
+    };
+    // A top byte of just the sign is only needed if the byte below is using its
+    // high bit.
+    if minimal && last & 0x7f == 0 && (bytes.len() == 1 || bytes[bytes.len() - 2] & 0x80 == 0) {
+        return Err(ScriptError::NonMinimalNumber);
+    }
+    let mut result = 0i64;
+    for (index, byte) in bytes.iter().enumerate() {
+        result |= (*byte as i64) << (8 * index);
+    }
+    if last & 0x80 != 0 {
+        result &= !(0x80i64 << (8 * (bytes.len() - 1)));
+        result = -result;
+    }
+    Ok(result)
+}
+
+// Any non-zero byte makes an item true, except a lone sign bit at the end:
+// negative zero is false.
+pub fn cast_to_bool(item: &[u8]) -> bool {
+    match item.split_last() {
+        Some((last, rest)) => rest.iter().any(|byte| *byte != 0) || (*last != 0 && *last != 0x80),
+        None => false,
+    }
+}
+
+// The opcode pushing `data` must be the smallest one that can.
+fn is_minimal_push(opcode: Opcode, data: &[u8]) -> bool {
+    match data {
+        [] => opcode == OP_0,
+        [n @ 1..=16] => opcode.small_int() == Some(*n as i64),
+        [0x81] => opcode == OP_1NEGATE,
+        _ if data.len() <= 0x4b => opcode.0 as usize == data.len(),
+        _ if data.len() <= 0xff => opcode == OP_PUSHDATA1,
+        _ if data.len() <= 0xffff => opcode == OP_PUSHDATA2,
+        _ => true,
+    }
+}
+
+// Disabled after CVE-2010-5137 and friends; they fail even where not executed.
+fn is_disabled(opcode: Opcode) -> bool {
+    matches!(
+        opcode,
+        OP_CAT
+            | OP_SUBSTR
+            | OP_LEFT
+            | OP_RIGHT
+            | OP_INVERT
+            | OP_AND
+            | OP_OR
+            | OP_XOR
+            | OP_2MUL
+            | OP_2DIV
+            | OP_MUL
+            | OP_DIV
+            | OP_MOD
+            | OP_LSHIFT
+            | OP_RSHIFT
+    )
+}
+
+fn check_signature_encoding(signature: &[u8], flags: u32) -> Result<(), ScriptError> {
+    // An empty signature is a way to fail a check on purpose.
+    let Some((sighash_type, der)) = signature.split_last() else {
+        return Ok(());
+    };
+    if flags & (VERIFY_DERSIG | VERIFY_LOW_S | VERIFY_STRICTENC) != 0 {
+        let signature = Signature::parse_der(der).map_err(|_| ScriptError::SigDer)?;
+        if flags & VERIFY_LOW_S != 0 && *signature.s() > n() / 2u32 {
+            return Err(ScriptError::SigHighS);
+        }
+    }
+    if flags & VERIFY_STRICTENC != 0 && !matches!(sighash_type & !0x80, 0x01..=0x03) {
+        return Err(ScriptError::SigHashType);
+    }
+    Ok(())
+}
+
+fn check_pubkey_encoding(
+    pubkey: &[u8],
+    flags: u32,
+    sig_version: SigVersion,
+) -> Result<(), ScriptError> {
+    let well_formed = matches!(
+        pubkey,
+        [0x02 | 0x03, rest @ ..] if rest.len() == 32
+    ) || matches!(pubkey, [0x04, rest @ ..] if rest.len() == 64);
+    if flags & VERIFY_STRICTENC != 0 && !well_formed {
+        return Err(ScriptError::PubkeyType);
+    }
+    let compressed = matches!(pubkey, [0x02 | 0x03, rest @ ..] if rest.len() == 32);
+    if flags & VERIFY_WITNESS_PUBKEYTYPE != 0 && sig_version == SigVersion::WitnessV0 && !compressed
+    {
+        return Err(ScriptError::WitnessPubkeyType);
+    }
+    Ok(())
+}
+
+// Signatures are parsed as laxly as Core does, and keys may be hybrid, with
+// the y's parity in the prefix as well; DERSIG and STRICTENC are what rule
+// those out.
+fn verify_ecdsa(der: &[u8], pubkey: &[u8], sighash: &[u8; 32]) -> bool {
+    match (Signature::parse_der_lax(der), parse_pubkey(pubkey)) {
+        (Ok(signature), Some(point)) => point.verify(BigUint::from_bytes_be(sighash), signature),
+        _ => false,
+    }
+}
+
+fn parse_pubkey(pubkey: &[u8]) -> Option<Secp256k1Point> {
+    match pubkey {
+        [prefix @ (0x06 | 0x07), rest @ ..] if rest.len() == 64 => {
+            let point = Secp256k1Point::parse_sec(&[&[0x04], rest].concat()).ok()?;
+            (point.has_even_y() == (*prefix == 0x06)).then_some(point)
+        }
+        _ => Secp256k1Point::parse_sec(pubkey).ok(),
+    }
+}
+
+#[cfg(test)]
+mod interpreter_tests {
+    use super::*;
+    use crate::fixtures::{no_signatures, BOOK_PREV_SCRIPT_PUBKEY, BOOK_TX};
+    use crate::secp256k1::private_key::PrivateKey;
+    use crate::taproot::tweak_private_key;
+    use crate::tx::sighash::SIGHASH_ALL;
+    use crate::tx::sign::ecdsa_signature;
+    use crate::tx::{OutPoint, TxIn, TxOut};
+
+    // BIP143's native P2WPKH and P2SH-P2WPKH examples.
+    const BIP143_P2WPKH_TX: &str = "01000000000102fff7f7881a8099afa6940d42d1e7f6362bec38171ea3edf433541db4e4ad969f00000000494830450221008b9d1dc26ba6a9cb62127b02742fa9d754cd3bebf337f7a55d114c8e5cdd30be022040529b194ba3f9281a99f2b1c0a19c0489bc22ede944ccf4ecbab4cc618ef3ed01eeffffffef51e1b804cc89d182d279655c3aa89e815b1b309fe287d9b2b55d57b90ec68a0100000000ffffffff02202cb206000000001976a9148280b37df378db99f66f85c95a783a76ac7a6d5988ac9093510d000000001976a9143bde42dbee7e4dbe6a21b2d50ce2f0167faa815988ac000247304402203609e17b84f6a7d30c80bfa610b5b4542f32a8a0d5447a12fb1366d7f01cc44a0220573a954c4518331561406f90300e8f3358f51928d43c212a8caed02de67eebee0121025476c2e83188368da1ff3e292e7acafcdb3566bb0ad253f62fc70f07aeee635711000000";
+    const BIP143_P2SH_P2WPKH_TX: &str = "01000000000101db6b1b20aa0fd7b23880be2ecbd4a98130974cf4748fb66092ac4d3ceb1a5477010000001716001479091972186c449eb1ded22b78e40d009bdf0089feffffff02b8b4eb0b000000001976a914a457b684d7f0d539a46a45bbc043f35b59d0d96388ac0008af2f000000001976a914fd270b1ee6abcaea97fea7ad0402e8bd8ad6d77c88ac02473044022047ac8e878352d3ebbde1c94ce3a10d057c24175747116f8288e5d794d12d482f0220217f36a485cae903c713331d877c1f64677e3622ad4010726870540656fe9dcb012103ad1d8e89212f0b92c74d23bb710c00662ad1470198ac48c43f7d6f93a2a2687392040000";
+
+    const STANDARD: u32 = VERIFY_STRICTENC
+        | VERIFY_DERSIG
+        | VERIFY_LOW_S
+        | VERIFY_MINIMALDATA
+        | VERIFY_NULLFAIL
+        | VERIFY_DISCOURAGE_UPGRADABLE_NOPS;
+
+    const WITNESS_FLAGS: u32 = STANDARD
+        | VERIFY_P2SH
+        | VERIFY_NULLDUMMY
+        | VERIFY_CLEANSTACK
+        | VERIFY_WITNESS
+        | VERIFY_DISCOURAGE_UPGRADABLE_WITNESS_PROGRAM
+        | VERIFY_MINIMALIF
+        | VERIFY_WITNESS_PUBKEYTYPE
+        | VERIFY_TAPROOT
+        | VERIFY_DISCOURAGE_UPGRADABLE_TAPROOT_VERSION
+        | VERIFY_DISCOURAGE_OP_SUCCESS
+        | VERIFY_DISCOURAGE_UPGRADABLE_PUBKEYTYPE;
+
+    fn run(script: &str, flags: u32) -> Result<(), ScriptError> {
+        verify_script(
+            &[],
+            &hex::decode(script).unwrap(),
+            &[],
+            flags,
+            &no_signatures,
+        )
+    }
+
+    #[test]
+    fn book_p2pkh_spend() {
+        let tx = Tx::from_hex(BOOK_TX).unwrap();
+        let script_pubkey = hex::decode(BOOK_PREV_SCRIPT_PUBKEY).unwrap();
+        let checker = TxSignatureChecker::new(&tx, 0);
+        let script_sig = tx.inputs[0].script_sig.clone();
+        assert_eq!(
+            verify_script(&script_sig, &script_pubkey, &[], STANDARD, &checker),
+            Ok(())
+        );
+
+        // A signature for another digest fails, and under NULLFAIL is an error.
+        let wrong_digest = |_: &[u8], _: u32| [1u8; 32];
+        assert_eq!(
+            verify_script(&script_sig, &script_pubkey, &[], VERIFY_NONE, &wrong_digest),
+            Err(ScriptError::EvalFalse)
+        );
+        assert_eq!(
+            verify_script(&script_sig, &script_pubkey, &[], STANDARD, &wrong_digest),
+            Err(ScriptError::SigNullFail)
+        );
+
+        let mut other_hash = script_pubkey.clone();
+        other_hash[3] ^= 1;
+        assert_eq!(
+            verify_script(&script_sig, &other_hash, &[], STANDARD, &checker),
+            Err(ScriptError::EqualVerify)
+        );
+    }
+
+    #[test]
+    fn p2pk_with_codeseparator() {
+        let key = PrivateKey::new(BigUint::from(8675309u32));
+        let sec = key.point().sec(true);
+        let script_pubkey = Script::new(vec![
+            Command::Op(OP_1),
+            Command::Op(OP_DROP),
+            Command::Op(OP_CODESEPARATOR),
+            Command::push(&sec),
+            Command::Op(OP_CHECKSIG),
+        ]);
+        // Only what follows the separator is signed.
+        let signed_code = Script::new(script_pubkey.commands[3..].to_vec()).serialize();
+        let checker = |script_code: &[u8], sighash_type: u32| {
+            assert_eq!(script_code, signed_code.as_slice());
+            assert_eq!(sighash_type, 1);
+            sha256(b"spending transaction")
+        };
+        let z = BigUint::from_bytes_be(&sha256(b"spending transaction"));
+        let mut signature = key.sign(&z).der();
+        signature.push(0x01);
+        let script_sig = Script::new(vec![Command::push(&signature)]).serialize();
+        assert_eq!(
+            verify_script(
+                &script_sig,
+                &script_pubkey.serialize(),
+                &[],
+                STANDARD,
+                &checker
+            ),
+            Ok(())
+        );
+
+        // An empty signature is a clean failure, which OP_NOT can turn around.
+        let mut negated = script_pubkey.clone();
+        negated.push_opcode(OP_NOT);
+        assert_eq!(
+            verify_script(&[0x00], &negated.serialize(), &[], STANDARD, &checker),
+            Ok(())
+        );
+
+        // A hash type outside ALL/NONE/SINGLE only fails under STRICTENC.
+        *signature.last_mut().unwrap() = 0x04;
+        let script_sig = Script::new(vec![Command::push(&signature)]).serialize();
+        assert_eq!(
+            verify_script(
+                &script_sig,
+                &script_pubkey.serialize(),
+                &[],
+                STANDARD,
+                &checker
+            ),
+            Err(ScriptError::SigHashType)
+        );
+    }
+
+    #[test]
+    fn lax_signatures_and_hybrid_keys() {
+        let key = PrivateKey::new(BigUint::from(8675309u32));
+        let checker = |_: &[u8], _: u32| sha256(b"spending transaction");
+        let z = BigUint::from_bytes_be(&sha256(b"spending transaction"));
+        let signature = key.sign(&z);
+        let spend = |signature: &[u8], pubkey: &[u8], flags: u32| {
+            let script_sig = Script::new(vec![Command::push(signature)]).serialize();
+            let script_pubkey = Script::new(vec![Command::push(pubkey), Command::Op(OP_CHECKSIG)]);
+            verify_script(
+                &script_sig,
+                &script_pubkey.serialize(),
+                &[],
+                flags,
+                &checker,
+            )
+        };
+
+        // r padded with a needless zero: fine until DERSIG.
+        let der = signature.der();
+        let mut padded = vec![0x30, der[1] + 1, 0x02, der[3] + 1, 0x00];
+        padded.extend(&der[4..]);
+        padded.push(0x01);
+        let sec = key.point().sec(true);
+        assert_eq!(spend(&padded, &sec, VERIFY_NONE), Ok(()));
+        assert_eq!(
+            spend(&padded, &sec, VERIFY_DERSIG),
+            Err(ScriptError::SigDer)
+        );
+
+        // A hybrid key, 0x06 or 0x07 by the parity of y: fine until STRICTENC.
+        let mut hybrid = key.point().sec(false);
+        hybrid[0] = if key.point().has_even_y() { 0x06 } else { 0x07 };
+        let mut der = der;
+        der.push(0x01);
+        assert_eq!(spend(&der, &hybrid, VERIFY_NONE), Ok(()));
+        assert_eq!(
+            spend(&der, &hybrid, VERIFY_STRICTENC),
+            Err(ScriptError::PubkeyType)
+        );
+        hybrid[0] ^= 1;
+        assert_eq!(
+            spend(&der, &hybrid, VERIFY_NONE),
+            Err(ScriptError::EvalFalse)
+        );
+    }
+
+    #[test]
+    fn arithmetic_and_stack() {
+        assert_eq!(run("5555935a87", VERIFY_NONE), Ok(()));
+        assert_eq!(run("5a5b944f87", VERIFY_NONE), Ok(()));
+        assert_eq!(run("5a5b9c", VERIFY_NONE), Err(ScriptError::EvalFalse));
+        // 5 2 8 WITHIN, and 1 2 3 ROT leaves 2 3 1.
+        assert_eq!(run("555258a5", VERIFY_NONE), Ok(()));
+        assert_eq!(run("5152537b5187", VERIFY_NONE), Ok(()));
+        assert_eq!(
+            run("51525374538887", VERIFY_NONE),
+            Err(ScriptError::EvalFalse)
+        );
+        assert_eq!(
+            run("5152745388", VERIFY_NONE),
+            Err(ScriptError::EqualVerify)
+        );
+        assert_eq!(run("5152537b7553885287", VERIFY_NONE), Ok(()));
+        assert_eq!(run("51526b6c52", VERIFY_NONE), Ok(()));
+        assert_eq!(
+            run("6c", VERIFY_NONE),
+            Err(ScriptError::InvalidAltstackOperation)
+        );
+        assert_eq!(
+            run("76", VERIFY_NONE),
+            Err(ScriptError::InvalidStackOperation)
+        );
+        // 0x7fffffff + 1 is fine, but the 5 byte result is not a number input.
+        assert_eq!(run("04ffffff7f8b05000000800087", VERIFY_NONE), Ok(()));
+        assert_eq!(
+            run("04ffffff7f8b8b", VERIFY_NONE),
+            Err(ScriptError::NumberOverflow)
+        );
+    }
+
+    #[test]
+    fn conditionals() {
+        assert_eq!(run("516351676a68", VERIFY_NONE), Ok(()));
+        assert_eq!(
+            run("0063506751686a", VERIFY_NONE),
+            Err(ScriptError::OpReturn)
+        );
+        assert_eq!(run("00635067516876", VERIFY_NONE), Ok(()));
+        assert_eq!(run("5164006751675168", VERIFY_NONE), Ok(()));
+        assert_eq!(
+            run("516351", VERIFY_NONE),
+            Err(ScriptError::UnbalancedConditional)
+        );
+        assert_eq!(
+            run("5168", VERIFY_NONE),
+            Err(ScriptError::UnbalancedConditional)
+        );
+        assert_eq!(
+            run("5167", VERIFY_NONE),
+            Err(ScriptError::UnbalancedConditional)
+        );
+        // Nested IFs in a branch not taken are still matched up.
+        assert_eq!(run("006363685167516851", VERIFY_NONE), Ok(()));
+        assert_eq!(
+            run("00636351675168", VERIFY_NONE),
+            Err(ScriptError::UnbalancedConditional)
+        );
+        assert_eq!(run("5100630063506868", VERIFY_NONE), Ok(()));
+        assert_eq!(
+            run("635168", VERIFY_NONE),
+            Err(ScriptError::InvalidStackOperation)
+        );
+    }
+
+    #[test]
+    fn opcodes_that_fail_unexecuted() {
+        // Disabled opcodes fail even in a branch not taken, OP_VER only when run.
+        assert_eq!(
+            run("00637e6851", VERIFY_NONE),
+            Err(ScriptError::DisabledOpcode)
+        );
+        assert_eq!(run("0063626851", VERIFY_NONE), Ok(()));
+        assert_eq!(run("62", VERIFY_NONE), Err(ScriptError::BadOpcode));
+        assert_eq!(run("0063656851", VERIFY_NONE), Err(ScriptError::BadOpcode));
+        assert_eq!(run("50", VERIFY_NONE), Err(ScriptError::BadOpcode));
+        assert_eq!(run("ba", VERIFY_NONE), Err(ScriptError::BadOpcode));
+        assert_eq!(run("b051", VERIFY_NONE), Ok(()));
+        assert_eq!(
+            run("b051", STANDARD),
+            Err(ScriptError::DiscourageUpgradableNops)
+        );
+        // NOP2 and NOP3 without the flags that made them CLTV and CSV.
+        assert_eq!(run("b1b251", VERIFY_DISCOURAGE_UPGRADABLE_NOPS), Ok(()));
+    }
+
+    #[test]
+    fn limits() {
+        let script = [vec![0x51], vec![0x61; 201]].concat();
+        assert_eq!(run(&hex::encode(&script), VERIFY_NONE), Ok(()));
+        let script = [vec![0x51], vec![0x61; 202]].concat();
+        assert_eq!(
+            run(&hex::encode(&script), VERIFY_NONE),
+            Err(ScriptError::OpCount)
+        );
+
+        let push = Command::push(&[0xab; 521]).serialize();
+        assert_eq!(
+            run(&hex::encode(push), VERIFY_NONE),
+            Err(ScriptError::PushSize)
+        );
+
+        let script = [vec![0x51; 1000], vec![0x76]].concat();
+        assert_eq!(
+            run(&hex::encode(&script), VERIFY_NONE),
+            Err(ScriptError::StackSize)
+        );
+        assert_eq!(run(&hex::encode(&script[1..]), VERIFY_NONE), Ok(()));
+
+        let script = [
+            vec![0x51],
+            vec![0x61; 1],
+            vec![0x4d, 0x08, 0x02],
+            vec![0; 520],
+            vec![0x75],
+        ]
+        .concat()
+        .repeat(20);
+        assert_eq!(
+            run(&hex::encode(&script), VERIFY_NONE),
+            Err(ScriptError::ScriptSize)
+        );
+    }
+
+    #[test]
+    fn minimal_data() {
+        assert_eq!(run("0105", VERIFY_NONE), Ok(()));
+        assert_eq!(
+            run("0105", VERIFY_MINIMALDATA),
+            Err(ScriptError::MinimalData)
+        );
+        assert_eq!(
+            run("4c0101", VERIFY_MINIMALDATA),
+            Err(ScriptError::MinimalData)
+        );
+        assert_eq!(
+            run("4c0000", VERIFY_MINIMALDATA),
+            Err(ScriptError::MinimalData)
+        );
+        assert_eq!(run("020500559c", VERIFY_NONE), Ok(()));
+        assert_eq!(
+            run("020500559c", VERIFY_MINIMALDATA),
+            Err(ScriptError::NonMinimalNumber)
+        );
+    }
+
+    #[test]
+    fn script_numbers() {
+        for (n, hex) in [
+            (0, ""),
+            (1, "01"),
+            (-1, "81"),
+            (127, "7f"),
+            (128, "8000"),
+            (-128, "8080"),
+            (255, "ff00"),
+            (256, "0001"),
+            (-32768, "008080"),
+            (0x7fffffff, "ffffff7f"),
+            (-0x7fffffff, "ffffffff"),
+        ] {
+            assert_eq!(hex::encode(encode_num(n)), hex);
+            assert_eq!(decode_num(&hex::decode(hex).unwrap(), 4, true), Ok(n));
+        }
+        assert_eq!(
+            decode_num(&[0x00], 4, true),
+            Err(ScriptError::NonMinimalNumber)
+        );
+        assert_eq!(
+            decode_num(&[0x80], 4, true),
+            Err(ScriptError::NonMinimalNumber)
+        );
+        assert_eq!(
+            decode_num(&[0x01, 0x00], 4, true),
+            Err(ScriptError::NonMinimalNumber)
+        );
+        assert_eq!(decode_num(&[0x01, 0x00], 4, false), Ok(1));
+        assert_eq!(decode_num(&[0x80], 4, false), Ok(0));
+        assert_eq!(
+            decode_num(&[1, 2, 3, 4, 5], 4, false),
+            Err(ScriptError::NumberOverflow)
+        );
+
+        assert!(!cast_to_bool(&[]));
+        assert!(!cast_to_bool(&[0x00, 0x00]));
+        assert!(!cast_to_bool(&[0x00, 0x80]));
+        assert!(cast_to_bool(&[0x80, 0x00]));
+        assert!(cast_to_bool(&[0x00, 0x01]));
+    }
+
+    // Spends a 2-of-3 P2SH output, signed by the given keys in that order,
+    // numbered as they are sorted in the redeem script.
+    fn spend_multisig(signers: &[usize]) -> (Tx, Script, Vec<Vec<u8>>) {
+        let mut keys: Vec<PrivateKey> = (1..=3u32)
+            .map(|secret| PrivateKey::new(BigUint::from(secret * 1000)))
+            .collect();
+        keys.sort_by_key(|key| key.point().sec(true));
+        let points: Vec<_> = keys.iter().map(|key| key.point().clone()).collect();
+        let redeem_script = Script::multisig(2, &points);
+        let tx = Tx::new(
+            1,
+            vec![TxIn::new(OutPoint::new([7; 32], 0))],
+            vec![TxOut::new(90_000, vec![0x51])],
+            0,
+        );
+        let sighash = tx.legacy_sighash(0, &redeem_script.serialize(), SIGHASH_ALL);
+        let signatures = signers
+            .iter()
+            .map(|signer| ecdsa_signature(&keys[*signer], &sighash, SIGHASH_ALL))
+            .collect();
+        (tx, redeem_script, signatures)
+    }
+
+    fn multisig_script_sig(
+        dummy: &[u8],
+        signatures: &[Vec<u8>],
+        redeem_script: &Script,
+    ) -> Vec<u8> {
+        let mut script_sig = Script::default();
+        script_sig.push_data(dummy);
+        for signature in signatures {
+            script_sig.push_data(signature);
+        }
+        script_sig.push_data(&redeem_script.serialize());
+        script_sig.serialize()
+    }
+
+    #[test]
+    fn p2sh_multisig() {
+        let flags = STANDARD | VERIFY_P2SH | VERIFY_NULLDUMMY;
+        // Signatures must come in the order of their keys in the script.
+        let (tx, redeem_script, signatures) = spend_multisig(&[0, 2]);
+        let checker = TxSignatureChecker::new(&tx, 0);
+        let script_pubkey = redeem_script.p2sh_address().script_pubkey();
+        let script_sig = multisig_script_sig(&[], &signatures, &redeem_script);
+        assert_eq!(
+            verify_script(&script_sig, &script_pubkey, &[], flags, &checker),
+            Ok(())
+        );
+
+        let reversed: Vec<Vec<u8>> = signatures.iter().rev().cloned().collect();
+        let script_sig = multisig_script_sig(&[], &reversed, &redeem_script);
+        assert_eq!(
+            verify_script(&script_sig, &script_pubkey, &[], flags, &checker),
+            Err(ScriptError::SigNullFail)
+        );
+        assert_eq!(
+            verify_script(&script_sig, &script_pubkey, &[], VERIFY_P2SH, &checker),
+            Err(ScriptError::EvalFalse)
+        );
+
+        let script_sig = multisig_script_sig(&[0xaa], &signatures, &redeem_script);
+        assert_eq!(
+            verify_script(&script_sig, &script_pubkey, &[], flags, &checker),
+            Err(ScriptError::SigNullDummy)
+        );
+        assert_eq!(
+            verify_script(&script_sig, &script_pubkey, &[], VERIFY_P2SH, &checker),
+            Ok(())
+        );
+
+        // One signature is not enough: the dummy gets taken for the second one.
+        let (_, _, signatures) = spend_multisig(&[1]);
+        let script_sig = multisig_script_sig(&[], &signatures, &redeem_script);
+        assert_eq!(
+            verify_script(&script_sig, &script_pubkey, &[], flags, &checker),
+            Err(ScriptError::SigNullFail)
+        );
+        assert_eq!(
+            verify_script(&script_sig, &script_pubkey, &[], VERIFY_P2SH, &checker),
+            Err(ScriptError::InvalidStackOperation)
+        );
+    }
+
+    #[test]
+    fn p2sh_runs_the_redeem_script() {
+        let (tx, redeem_script, signatures) = spend_multisig(&[1, 2]);
+        let checker = TxSignatureChecker::new(&tx, 0);
+        let script_pubkey = redeem_script.p2sh_address().script_pubkey();
+        let script_sig = multisig_script_sig(&[], &signatures, &redeem_script);
+        assert_eq!(
+            verify_script(&script_sig, &script_pubkey, &[], VERIFY_P2SH, &checker),
+            Ok(())
+        );
+
+        // Without BIP16 only the hash of the redeem script is checked.
+        let script_sig = multisig_script_sig(&[], &[vec![0x30], vec![0x30]], &redeem_script);
+        assert_eq!(
+            verify_script(&script_sig, &script_pubkey, &[], VERIFY_NONE, &checker),
+            Ok(())
+        );
+        assert_eq!(
+            verify_script(&script_sig, &script_pubkey, &[], VERIFY_P2SH, &checker),
+            Err(ScriptError::EvalFalse)
+        );
+
+        // The script_sig may only push.
+        let mut script_sig = Script::parse(&script_sig).unwrap();
+        script_sig.commands.insert(0, Command::Op(OP_NOP));
+        assert_eq!(
+            verify_script(
+                &script_sig.serialize(),
+                &script_pubkey,
+                &[],
+                VERIFY_P2SH,
+                &checker
+            ),
+            Err(ScriptError::SigPushOnly)
+        );
+        let redeem = Script::new(vec![Command::Op(OP_0)]);
+        let script_sig = Script::new(vec![Command::push(&redeem.serialize())]).serialize();
+        assert_eq!(
+            verify_script(
+                &script_sig,
+                &redeem.p2sh_address().script_pubkey(),
+                &[],
+                VERIFY_P2SH,
+                &checker
+            ),
+            Err(ScriptError::EvalFalse)
+        );
+    }
+
+    #[test]
+    fn bare_multisig_counts() {
+        // 0 0 0 CHECKMULTISIG: no keys, no signatures.
+        assert_eq!(run("000000ae", VERIFY_NONE), Ok(()));
+        assert_eq!(run("000000af51", VERIFY_NONE), Ok(()));
+        assert_eq!(
+            run("0000ae", VERIFY_NONE),
+            Err(ScriptError::InvalidStackOperation)
+        );
+        assert_eq!(run("00520051ae", VERIFY_NONE), Err(ScriptError::SigCount));
+        assert_eq!(run("000115ae", VERIFY_NONE), Err(ScriptError::PubkeyCount));
+        // Each key counts against the operation limit.
+        let script = [vec![0x61; 181], hex::decode("00000114ae").unwrap()].concat();
+        assert_eq!(
+            run(&hex::encode(&script), VERIFY_NONE),
+            Err(ScriptError::OpCount)
+        );
+        assert_eq!(
+            run(&hex::encode(&script[1..]), VERIFY_NONE),
+            Err(ScriptError::InvalidStackOperation)
+        );
+    }
+
+    fn verify_input(
+        tx: &Tx,
+        index: usize,
+        spent_outputs: &[TxOut],
+        flags: u32,
+    ) -> Result<(), ScriptError> {
+        let checker = TxSignatureChecker::with_spent_outputs(tx, index, spent_outputs);
+        verify_script(
+            &tx.inputs[index].script_sig,
+            &spent_outputs[index].script_pubkey,
+            &tx.inputs[index].witness,
+            flags,
+            &checker,
+        )
+    }
+
+    fn output(value: u64, script_pubkey: &str) -> TxOut {
+        TxOut::new(value, hex::decode(script_pubkey).unwrap())
+    }
+
+    #[test]
+    fn witness_v0_spends() {
+        let tx = Tx::from_hex(BIP143_P2WPKH_TX).unwrap();
+        let mut spent_outputs = vec![
+            output(
+                625_000_000,
+                "2103c9f4836b9a4f77fc0d81f7bcb01b7f1b35916864b9476c241ce9fc198bd25432ac",
+            ),
+            output(600_000_000, "00141d0f172a0ecb48aee1be1f2687d2963ae33f71a1"),
+        ];
+        assert_eq!(verify_input(&tx, 0, &spent_outputs, WITNESS_FLAGS), Ok(()));
+        assert_eq!(verify_input(&tx, 1, &spent_outputs, WITNESS_FLAGS), Ok(()));
+        // The amount is signed.
+        spent_outputs[1].value += 1;
+        assert_eq!(
+            verify_input(&tx, 1, &spent_outputs, WITNESS_FLAGS),
+            Err(ScriptError::SigNullFail)
+        );
+        // Before segwit, the program is anyone can spend.
+        assert_eq!(verify_input(&tx, 1, &spent_outputs, VERIFY_P2SH), Ok(()));
+
+        let mut changed = tx.clone();
+        changed.inputs[1].script_sig = vec![0x51];
+        assert_eq!(
+            verify_input(&changed, 1, &spent_outputs, WITNESS_FLAGS),
+            Err(ScriptError::WitnessMalleated)
+        );
+        changed.inputs[1].script_sig.clear();
+        changed.inputs[1].witness.pop();
+        assert_eq!(
+            verify_input(&changed, 1, &spent_outputs, WITNESS_FLAGS),
+            Err(ScriptError::WitnessProgramMismatch)
+        );
+        // A witness for an input that is not segwit.
+        changed.inputs[0].witness = vec![vec![0x01]];
+        assert_eq!(
+            verify_input(&changed, 0, &spent_outputs, WITNESS_FLAGS),
+            Err(ScriptError::WitnessUnexpected)
+        );
+
+        let tx = Tx::from_hex(BIP143_P2SH_P2WPKH_TX).unwrap();
+        let spent_outputs = [output(
+            1_000_000_000,
+            "a9144733f37cf4db86fbc2efed2500b4f4e49f31202387",
+        )];
+        assert_eq!(verify_input(&tx, 0, &spent_outputs, WITNESS_FLAGS), Ok(()));
+        let mut changed = tx.clone();
+        changed.inputs[0].script_sig.insert(0, 0x00);
+        assert_eq!(
+            verify_input(&changed, 0, &spent_outputs, VERIFY_P2SH | VERIFY_WITNESS),
+            Err(ScriptError::WitnessMalleatedP2sh)
+        );
+    }
+
+    #[test]
+    fn p2wsh_rules() {
+        let flags = VERIFY_P2SH | VERIFY_WITNESS;
+        let witness_script = Script::from_hex("63516751519368").unwrap().serialize();
+        let program = sha256(&witness_script);
+        let script_pubkey = [&[0x00, 0x20][..], &program].concat();
+        let run_witness = |witness: &[Vec<u8>], flags: u32| {
+            verify_script(&[], &script_pubkey, witness, flags, &no_signatures)
+        };
+        assert_eq!(
+            run_witness(&[vec![0x01], witness_script.clone()], flags),
+            Ok(())
+        );
+        assert_eq!(
+            run_witness(&[vec![], witness_script.clone()], flags),
+            Ok(())
+        );
+        // MINIMALIF wants exactly 0x01 for true.
+        assert_eq!(
+            run_witness(&[vec![0x02], witness_script.clone()], flags),
+            Ok(())
+        );
+        assert_eq!(
+            run_witness(
+                &[vec![0x02], witness_script.clone()],
+                flags | VERIFY_MINIMALIF
+            ),
+            Err(ScriptError::MinimalIf)
+        );
+        // Witness scripts must leave exactly one item.
+        assert_eq!(
+            run_witness(&[vec![0x51], vec![0x01], witness_script.clone()], flags),
+            Err(ScriptError::CleanStack)
+        );
+        assert_eq!(
+            run_witness(&[vec![0x01], vec![0x51]], flags),
+            Err(ScriptError::WitnessProgramMismatch)
+        );
+        assert_eq!(
+            run_witness(&[], flags),
+            Err(ScriptError::WitnessProgramWitnessEmpty)
+        );
+        assert_eq!(
+            run_witness(&[vec![0; 521], witness_script.clone()], flags),
+            Err(ScriptError::PushSize)
+        );
+
+        let wrong_length = hex::decode("0015010101010101010101010101010101010101010101").unwrap();
+        assert_eq!(
+            verify_script(&[], &wrong_length, &[], flags, &no_signatures),
+            Err(ScriptError::WitnessProgramWrongLength)
+        );
+        let version_2 = hex::decode("52020101").unwrap();
+        assert_eq!(
+            verify_script(&[], &version_2, &[], flags, &no_signatures),
+            Ok(())
+        );
+        assert_eq!(
+            verify_script(
+                &[],
+                &version_2,
+                &[],
+                flags | VERIFY_DISCOURAGE_UPGRADABLE_WITNESS_PROGRAM,
+                &no_signatures
+            ),
+            Err(ScriptError::DiscourageUpgradableWitnessProgram)
+        );
+    }
+
+    #[test]
+    fn bip341_key_path() {
+        let vectors: serde_json::Value =
+            serde_json::from_str(include_str!("../../test_data/bip341_tests.json")).unwrap();
+        let vectors = &vectors["keyPathSpending"][0];
+        let tx = Tx::from_hex(vectors["auxiliary"]["fullySignedTx"].as_str().unwrap()).unwrap();
+        let mut spent_outputs: Vec<TxOut> = vectors["given"]["utxosSpent"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|utxo| {
+                output(
+                    utxo["amountSats"].as_u64().unwrap(),
+                    utxo["scriptPubKey"].as_str().unwrap(),
+                )
+            })
+            .collect();
+        for input in vectors["inputSpending"].as_array().unwrap() {
+            let index = input["given"]["txinIndex"].as_u64().unwrap() as usize;
+            assert_eq!(
+                verify_input(&tx, index, &spent_outputs, WITNESS_FLAGS),
+                Ok(())
+            );
+        }
+        spent_outputs[0].value += 1;
+        assert_eq!(
+            verify_input(&tx, 3, &spent_outputs, WITNESS_FLAGS),
+            Err(ScriptError::SchnorrSig)
+        );
+        // Without the taproot flag, anything goes.
+        assert_eq!(
+            verify_input(&tx, 3, &spent_outputs, VERIFY_P2SH | VERIFY_WITNESS),
+            Ok(())
+        );
+    }
+
+    // Flattens a BIP341 test vector script tree to (script, leaf version) by id.
+    fn leaves(tree: &serde_json::Value, leaves: &mut Vec<(u64, Vec<u8>, u8)>) {
+        match tree {
+            serde_json::Value::Array(branches) => branches
+                .iter()
+                .for_each(|branch| self::leaves(branch, leaves)),
+            serde_json::Value::Null => {}
+            leaf => leaves.push((
+                leaf["id"].as_u64().unwrap(),
+                hex::decode(leaf["script"].as_str().unwrap()).unwrap(),
+                leaf["leafVersion"].as_u64().unwrap() as u8,
+            )),
+        }
+    }
+
+    #[test]
+    fn bip341_control_blocks() {
+        let vectors: serde_json::Value =
+            serde_json::from_str(include_str!("../../test_data/bip341_tests.json")).unwrap();
+        let mut checked = 0;
+        for vector in vectors["scriptPubKey"].as_array().unwrap() {
+            let mut tree = Vec::new();
+            leaves(&vector["given"]["scriptTree"], &mut tree);
+            let program =
+                hex::decode(vector["expected"]["scriptPubKey"].as_str().unwrap()).unwrap();
+            for (id, script, leaf_version) in tree {
+                let control_block = hex::decode(
+                    vector["expected"]["scriptPathControlBlocks"][id as usize]
+                        .as_str()
+                        .unwrap(),
+                )
+                .unwrap();
+                let leaf_hash = tap_leaf_hash(&script, leaf_version);
+                assert!(verify_taproot_commitment(
+                    &control_block,
+                    &program[2..],
+                    &leaf_hash
+                ));
+                let mut wrong_parity = control_block.clone();
+                wrong_parity[0] ^= 1;
+                assert!(!verify_taproot_commitment(
+                    &wrong_parity,
+                    &program[2..],
+                    &leaf_hash
+                ));
+                checked += 1;
+            }
+        }
+        assert_eq!(checked, 12);
+    }
+
+    // An output committing to two leaves, the transaction spending it, and the
+    // control blocks of the leaves.
+    struct TapTree {
+        internal_key: PrivateKey,
+        merkle_root: [u8; 32],
+        leaf_hashes: [[u8; 32]; 2],
+        control_blocks: [Vec<u8>; 2],
+        tx: Tx,
+        spent_outputs: Vec<TxOut>,
+    }
+
+    impl TapTree {
+        fn new(leaves: [(&[u8], u8); 2]) -> Self {
+            let internal_key = PrivateKey::new(BigUint::from(31337u32));
+            let leaf_hashes = leaves.map(|(script, version)| tap_leaf_hash(script, version));
+            let mut sorted = leaf_hashes;
+            sorted.sort();
+            let merkle_root = tagged_hash("TapBranch", &sorted.concat());
+            let output_key = output_key(internal_key.point(), Some(&merkle_root));
+            let parity = !output_key.has_even_y() as u8;
+            let control_blocks = [0, 1].map(|index| {
+                [
+                    &[leaves[index].1 | parity][..],
+                    &internal_key.point().x_only(),
+                    &leaf_hashes[1 - index],
+                ]
+                .concat()
+            });
+            let script_pubkey = [&[0x51, 0x20][..], &output_key.x_only()].concat();
+            TapTree {
+                internal_key,
+                merkle_root,
+                leaf_hashes,
+                control_blocks,
+                tx: Tx::new(
+                    2,
+                    vec![TxIn::new(OutPoint::new([9; 32], 1))],
+                    vec![TxOut::new(40_000, vec![0x51])],
+                    0,
+                ),
+                spent_outputs: vec![TxOut::new(50_000, script_pubkey)],
+            }
+        }
+
+        fn sign(&self, key: &PrivateKey, leaf: Option<usize>) -> Vec<u8> {
+            let checker = TxSignatureChecker::with_spent_outputs(&self.tx, 0, &self.spent_outputs);
+            let leaf = leaf.map(|index| (&self.leaf_hashes[index], u32::MAX));
+            let sighash = checker.taproot_sighash(0, None, leaf).unwrap();
+            key.sign_schnorr(&sighash, &[0; 32]).serialize().to_vec()
+        }
+
+        fn spend(&self, witness: Vec<Vec<u8>>) -> Result<(), ScriptError> {
+            let mut tx = self.tx.clone();
+            tx.inputs[0].witness = witness;
+            verify_input(&tx, 0, &self.spent_outputs, WITNESS_FLAGS)
+        }
+    }
+
+    #[test]
+    fn tapscript_spends() {
+        let alice = PrivateKey::new(BigUint::from(1111u32));
+        let bob = PrivateKey::new(BigUint::from(2222u32));
+        let mut single = Script::default();
+        single
+            .push_data(&alice.point().x_only())
+            .push_opcode(OP_CHECKSIG);
+        // Both of them, counted with OP_CHECKSIGADD.
+        let mut both = Script::default();
+        both.push_data(&alice.point().x_only())
+            .push_opcode(OP_CHECKSIG)
+            .push_data(&bob.point().x_only())
+            .push_opcode(OP_CHECKSIGADD)
+            .push_opcode(OP_2)
+            .push_opcode(OP_NUMEQUAL);
+        let scripts = [single.serialize(), both.serialize()];
+        let tree = TapTree::new([(&scripts[0], 0xc0), (&scripts[1], 0xc0)]);
+        let leaf = |index: usize| vec![scripts[index].clone(), tree.control_blocks[index].clone()];
+
+        let signature = tree.sign(&alice, Some(0));
+        assert_eq!(
+            tree.spend([vec![signature.clone()], leaf(0)].concat()),
+            Ok(())
+        );
+        // A signature for the other leaf, or the key path, does not do.
+        let other_leaf = tree.sign(&alice, Some(1));
+        assert_eq!(
+            tree.spend([vec![other_leaf.clone()], leaf(0)].concat()),
+            Err(ScriptError::SchnorrSig)
+        );
+        assert_eq!(
+            tree.spend([vec![vec![]], leaf(0)].concat()),
+            Err(ScriptError::EvalFalse)
+        );
+        let mut with_type = signature.clone();
+        with_type.push(0x00);
+        assert_eq!(
+            tree.spend([vec![with_type], leaf(0)].concat()),
+            Err(ScriptError::SchnorrSigHashType)
+        );
+        assert_eq!(
+            tree.spend([vec![signature[1..].to_vec()], leaf(0)].concat()),
+            Err(ScriptError::SchnorrSigSize)
+        );
+
+        let bob_signature = tree.sign(&bob, Some(1));
+        assert_eq!(
+            tree.spend([vec![bob_signature.clone(), other_leaf.clone()], leaf(1)].concat()),
+            Ok(())
+        );
+        assert_eq!(
+            tree.spend([vec![vec![], other_leaf.clone()], leaf(1)].concat()),
+            Err(ScriptError::EvalFalse)
+        );
+        assert_eq!(
+            tree.spend([vec![other_leaf.clone(), bob_signature.clone()], leaf(1)].concat()),
+            Err(ScriptError::SchnorrSig)
+        );
+
+        // The control block must lead to the output key.
+        let mut wrong_path = leaf(0);
+        wrong_path[1][40] ^= 1;
+        assert_eq!(
+            tree.spend([vec![signature.clone()], wrong_path].concat()),
+            Err(ScriptError::WitnessProgramMismatch)
+        );
+        let mut truncated = leaf(0);
+        truncated[1].pop();
+        assert_eq!(
+            tree.spend([vec![signature.clone()], truncated].concat()),
+            Err(ScriptError::TaprootWrongControlSize)
+        );
+
+        // The key path is the internal key tweaked with the merkle root.
+        let tweaked = tweak_private_key(&tree.internal_key, Some(&tree.merkle_root));
+        assert_eq!(tree.spend(vec![tree.sign(&tweaked, None)]), Ok(()));
+        assert_eq!(
+            tree.spend(vec![tree.sign(&tree.internal_key, None)]),
+            Err(ScriptError::SchnorrSig)
+        );
+        // An annex is committed to.
+        assert_eq!(
+            tree.spend(vec![tree.sign(&tweaked, None), vec![0x50]]),
+            Err(ScriptError::SchnorrSig)
+        );
+    }
+
+    #[test]
+    fn tapscript_rules() {
+        let run_leaf = |script: &str, leaf_version: u8, inputs: Vec<Vec<u8>>| {
+            let script = hex::decode(script).unwrap();
+            let tree = TapTree::new([(&script, leaf_version), (&[], 0xc0)]);
+            let witness = [inputs, vec![script, tree.control_blocks[0].clone()]];
+            tree.spend(witness.concat())
+        };
+        // OP_SUCCESSx make any script succeed, unless discouraged, as long as
+        // it parses up to there.
+        assert_eq!(
+            run_leaf("6a50", 0xc0, vec![]),
+            Err(ScriptError::DiscourageOpSuccess)
+        );
+        assert_eq!(run_leaf("4c50", 0xc0, vec![]), Err(ScriptError::BadOpcode));
+        // Unknown leaf versions are left for future soft forks.
+        assert_eq!(
+            run_leaf("6a", 0xc2, vec![]),
+            Err(ScriptError::DiscourageUpgradableTaprootVersion)
+        );
+
+        assert_eq!(run_leaf("6351676a68", 0xc0, vec![vec![0x01]]), Ok(()));
+        assert_eq!(
+            run_leaf("6351676a68", 0xc0, vec![vec![0x02]]),
+            Err(ScriptError::TapscriptMinimalIf)
+        );
+        assert_eq!(
+            run_leaf("00000000ae", 0xc0, vec![]),
+            Err(ScriptError::TapscriptCheckMultisig)
+        );
+        // No operation limit, and no script size limit.
+        assert_eq!(
+            run_leaf(&format!("51{}", "61".repeat(10_001)), 0xc0, vec![]),
+            Ok(())
+        );
+        // Keys of other sizes are for future soft forks.
+        assert_eq!(
+            run_leaf("020101ac", 0xc0, vec![vec![0x01]]),
+            Err(ScriptError::DiscourageUpgradablePubkeyType)
+        );
+        assert_eq!(
+            run_leaf("00ac", 0xc0, vec![vec![0x01]]),
+            Err(ScriptError::TapscriptEmptyPubkey)
+        );
+        // Every signature checked costs 50 from a budget of the witness size
+        // plus 50. Here the witness is 7k + 69 bytes for k checks.
+        let flags = WITNESS_FLAGS & !VERIFY_DISCOURAGE_UPGRADABLE_PUBKEYTYPE;
+        for (checks, expected) in [
+            (2, Ok(())),
+            (3, Err(ScriptError::TapscriptValidationWeight)),
+        ] {
+            let script = hex::decode(format!("{}51", "020101ac75".repeat(checks))).unwrap();
+            let tree = TapTree::new([(&script, 0xc0), (&[], 0xc0)]);
+            let mut tx = tree.tx.clone();
+            tx.inputs[0].witness = vec![vec![0x01]; checks];
+            tx.inputs[0].witness.push(script);
+            tx.inputs[0].witness.push(tree.control_blocks[0].clone());
+            assert_eq!(verify_input(&tx, 0, &tree.spent_outputs, flags), expected);
+        }
+    }
+
+    #[test]
+    fn timelocks_fail_for_a_missing_input() {
+        let tx_in = TxIn {
+            sequence: 10,
+            ..TxIn::new(OutPoint::new([0x11; 32], 0))
+        };
+        let tx = Tx::new(2, vec![tx_in], vec![], 100);
+        let checker = TxSignatureChecker::new(&tx, 0);
+        assert!(checker.check_locktime(50));
+        assert!(checker.check_sequence(5));
+
+        let checker = TxSignatureChecker::new(&tx, 1);
+        assert!(!checker.check_locktime(50));
+        assert!(!checker.check_sequence(5));
+    }
+}
@@ -0,0 +1,276 @@
+use std::fmt;
+
+use crate::address::Address;
+use crate::error::Error;
+use crate::hash::hash160;
+use crate::helper::decode_hex;
+use crate::secp256k1::point::Secp256k1Point;
+use crate::tx::read_op;
+
+pub mod asm;
+pub mod classify;
+pub mod interpreter;
+pub mod opcode;
+pub mod trace;
+
+use self::opcode::{Opcode, OP_0, OP_CHECKMULTISIG, OP_PUSHDATA1, OP_PUSHDATA2, OP_PUSHDATA4};
+
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub enum Command {
+    Op(Opcode),
+    // Data and the opcode that pushed it, which is kept so that pushes that
+    // are not the smallest possible serialize back the way they came.
+    Push(Opcode, Vec<u8>),
+}
+
+impl Command {
+    // A push of `data` with the smallest push opcode, OP_0 for nothing.
+    pub fn push(data: &[u8]) -> Self {
+        let opcode = match data.len() {
+            len @ 0..=0x4b => Opcode(len as u8),
+            0x4c..=0xff => OP_PUSHDATA1,
+            0x100..=0xffff => OP_PUSHDATA2,
+            _ => OP_PUSHDATA4,
+        };
+        Command::Push(opcode, data.to_vec())
+    }
+
+    pub fn serialize(&self) -> Vec<u8> {
+        match self {
+            Command::Op(opcode) => vec![opcode.0],
+            Command::Push(opcode, data) => {
+                let mut result = vec![opcode.0];
+                match *opcode {
+                    OP_PUSHDATA1 => result.push(data.len() as u8),
+                    OP_PUSHDATA2 => result.extend((data.len() as u16).to_le_bytes()),
+                    OP_PUSHDATA4 => result.extend((data.len() as u32).to_le_bytes()),
+                    _ => {}
+                }
+                result.extend(data);
+                result
+            }
+        }
+    }
+}
+
+impl fmt::Debug for Command {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Command::Op(opcode) => write!(f, "{}", opcode),
+            Command::Push(opcode, data) if opcode.0 as usize == data.len() && *opcode != OP_0 => {
+                write!(f, "<{}>", hex::encode(data))
+            }
+            Command::Push(opcode, data) => write!(f, "{} <{}>", opcode, hex::encode(data)),
+        }
+    }
+}
+
+// A script as its opcodes and pushes. The bytes transactions hold parse into
+// one, and it serializes back to exactly those bytes.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct Script {
+    pub commands: Vec<Command>,
+}
+
+impl Script {
+    pub fn new(commands: Vec<Command>) -> Self {
+        Script { commands }
+    }
+
+    // <m> <key>... <n> OP_CHECKMULTISIG with the compressed keys sorted as BIP67
+    // has it, so the same keys always give the same script whatever their order.
+    pub fn multisig(m: usize, keys: &[Secp256k1Point]) -> Self {
+        if m == 0 || m > keys.len() || keys.len() > 16 {
+            panic!("{}-of-{} is not a valid multisig.", m, keys.len());
+        }
+        let mut secs: Vec<Vec<u8>> = keys.iter().map(|key| key.sec(true)).collect();
+        secs.sort();
+        let mut commands = vec![Command::Op(Opcode::from_small_int(m as i64).unwrap())];
+        commands.extend(secs.iter().map(|sec| Command::push(sec)));
+        commands.push(Command::Op(
+            Opcode::from_small_int(keys.len() as i64).unwrap(),
+        ));
+        commands.push(Command::Op(OP_CHECKMULTISIG));
+        Script { commands }
+    }
+
+    // The address paying to this script as a P2SH redeem script.
+    pub fn p2sh_address(&self) -> Address {
+        Address::P2sh(hash160(&self.serialize()))
+    }
+
+    // Fails on a push running past the end of the script.
+    pub fn parse(bytes: &[u8]) -> Result<Self, Error> {
+        let mut input = bytes;
+        let mut commands = Vec::new();
+        while !input.is_empty() {
+            let (opcode, data) = read_op(&mut input)?;
+            let opcode = Opcode(opcode);
+            commands.push(if opcode.is_push() {
+                Command::Push(opcode, data.to_vec())
+            } else {
+                Command::Op(opcode)
+            });
+        }
+        Ok(Script { commands })
+    }
+
+    pub fn from_hex(s: &str) -> Result<Self, Error> {
+        Script::parse(&decode_hex(s)?)
+    }
+
+    pub fn serialize(&self) -> Vec<u8> {
+        self.commands.iter().flat_map(Command::serialize).collect()
+    }
+
+    pub fn to_hex(&self) -> String {
+        hex::encode(self.serialize())
+    }
+
+    pub fn push_opcode(&mut self, opcode: Opcode) -> &mut Self {
+        self.commands.push(Command::Op(opcode));
+        self
+    }
+
+    pub fn push_data(&mut self, data: &[u8]) -> &mut Self {
+        self.commands.push(Command::push(data));
+        self
+    }
+
+    // True if every command is a push, as BIP16 requires of a P2SH script_sig.
+    // OP_1NEGATE and OP_1 to OP_16 count as pushes, OP_RESERVED does not.
+    pub fn is_push_only(&self) -> bool {
+        self.commands.iter().all(|command| match command {
+            Command::Push(..) => true,
+            Command::Op(opcode) => opcode.small_int().is_some(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod script_tests {
+    use super::opcode::*;
+    use super::*;
+    use crate::network::Network;
+
+    #[test]
+    fn parses_p2pkh() {
+        // Programming Bitcoin, chapter 6.
+        let script_pubkey =
+            Script::from_hex("76a914bc3b654dca7e56b04dca18f2566cdaf02e8d9ada88ac").unwrap();
+        let hash = hex::decode("bc3b654dca7e56b04dca18f2566cdaf02e8d9ada").unwrap();
+        let mut expected = Script::default();
+        expected
+            .push_opcode(OP_DUP)
+            .push_opcode(OP_HASH160)
+            .push_data(&hash)
+            .push_opcode(OP_EQUALVERIFY)
+            .push_opcode(OP_CHECKSIG);
+        assert_eq!(script_pubkey, expected);
+        assert_eq!(
+            format!("{:?}", script_pubkey.commands),
+            "[OP_DUP, OP_HASH160, <bc3b654dca7e56b04dca18f2566cdaf02e8d9ada>, OP_EQUALVERIFY, OP_CHECKSIG]"
+        );
+
+        let script_sig = Script::from_hex("483045022100ed81ff192e75a3fd2304004dcadb746fa5e24c5031ccfcf21320b0277457c98f02207a986d955c6e0cb35d446a89d3f56100f4d7f67801c31967743a9c8e10615bed01210349fc4e631e3624a545de3f89f5d8684c7b8138bd94bdd531d2e213bf016b278a").unwrap();
+        assert_eq!(script_sig.commands.len(), 2);
+        assert!(script_sig.is_push_only());
+        assert!(!script_pubkey.is_push_only());
+    }
+
+    #[test]
+    fn round_trips_every_push_form() {
+        for hex in [
+            "",
+            "00",
+            "0100",
+            "4c00",
+            "4c0107",
+            "4d0300aabbcc",
+            "4e02000000aabb",
+            "4f515a60",
+            "6a0b68656c6c6f20776f726c64",
+            "bbff",
+        ] {
+            assert_eq!(Script::from_hex(hex).unwrap().to_hex(), hex);
+        }
+        let data = vec![0xab; 0x10000];
+        let script = Script::new(vec![Command::push(&data)]);
+        let serialized = script.serialize();
+        assert_eq!(&serialized[..5], &[0x4e, 0x00, 0x00, 0x01, 0x00]);
+        assert_eq!(Script::parse(&serialized).unwrap(), script);
+
+        assert_eq!(Command::push(&[]).serialize(), [0x00]);
+        assert_eq!(Command::push(&[0x07]).serialize(), [0x01, 0x07]);
+        assert_eq!(Command::push(&[0; 76]).serialize()[..2], [0x4c, 76]);
+        assert_eq!(
+            Command::push(&[0; 256]).serialize()[..3],
+            [0x4d, 0x00, 0x01]
+        );
+    }
+
+    #[test]
+    fn rejects_truncated_pushes() {
+        for hex in [
+            "01",
+            "4b00",
+            "4c",
+            "4c02aa",
+            "4d01",
+            "4d0200aa",
+            "4e010000",
+            "4e01000000",
+        ] {
+            assert_eq!(Script::from_hex(hex), Err(Error::UnexpectedEof), "{}", hex);
+        }
+    }
+
+    #[test]
+    fn push_only() {
+        assert!(Script::from_hex("004f5160").unwrap().is_push_only());
+        assert!(!Script::from_hex("50").unwrap().is_push_only());
+        assert!(!Script::from_hex("0061").unwrap().is_push_only());
+    }
+
+    #[test]
+    fn bip67_multisig() {
+        for (m, keys, script, address) in [
+            (
+                2,
+                &[
+                    "02ff12471208c14bd580709cb2358d98975247d8765f92bc25eab3b2763ed605f8",
+                    "02fe6f0a5a297eb38c391581c4413e084773ea23954d93f7753db7dc0adc188b2f",
+                ][..],
+                "522102fe6f0a5a297eb38c391581c4413e084773ea23954d93f7753db7dc0adc188b2f2102ff12471208c14bd580709cb2358d98975247d8765f92bc25eab3b2763ed605f852ae",
+                "39bgKC7RFbpoCRbtD5KEdkYKtNyhpsNa3Z",
+            ),
+            (
+                2,
+                &[
+                    "02632b12f4ac5b1d1b72b2a3b508c19172de44f6f46bcee50ba33f3f9291e47ed0",
+                    "027735a29bae7780a9755fae7a1c4374c656ac6a69ea9f3697fda61bb99a4f3e77",
+                    "02e2cc6bd5f45edd43bebe7cb9b675f0ce9ed3efe613b177588290ad188d11b404",
+                ][..],
+                "522102632b12f4ac5b1d1b72b2a3b508c19172de44f6f46bcee50ba33f3f9291e47ed021027735a29bae7780a9755fae7a1c4374c656ac6a69ea9f3697fda61bb99a4f3e772102e2cc6bd5f45edd43bebe7cb9b675f0ce9ed3efe613b177588290ad188d11b40453ae",
+                "3CKHTjBKxCARLzwABMu9yD85kvtm7WnMfH",
+            ),
+        ] {
+            let keys: Vec<Secp256k1Point> = keys
+                .iter()
+                .map(|sec| Secp256k1Point::parse_sec(&hex::decode(sec).unwrap()).unwrap())
+                .collect();
+            let multisig = Script::multisig(m, &keys);
+            assert_eq!(multisig.to_hex(), script);
+            assert_eq!(multisig.p2sh_address().encode(Network::Mainnet), address);
+            let reversed: Vec<Secp256k1Point> = keys.into_iter().rev().collect();
+            assert_eq!(Script::multisig(m, &reversed), multisig);
+        }
+    }
+
+    #[test]
+    #[should_panic]
+    fn multisig_needs_enough_keys() {
+        let key = crate::secp256k1::private_key::PrivateKey::new(num::BigUint::from(7u32));
+        Script::multisig(2, &[key.point().clone()]);
+    }
+}
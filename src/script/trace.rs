@@ -0,0 +1,446 @@
+use std::cell::RefCell;
+use std::fmt;
+
+use super::interpreter::{
+    verify_script_observed, Interpreter, ScriptError, SigVersion, SignatureChecker, StepObserver,
+};
+use super::opcode::*;
+use super::{Command, Script};
+
+// A digest a signature was checked against, None where the checker had none
+// to give.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SighashCheck {
+    pub sighash_type: u32,
+    pub sighash: Option<[u8; 32]>,
+}
+
+// One command and what it did. A command in a branch that is not taken still
+// makes a step, with `executing` false.
+#[derive(Debug, Clone)]
+pub struct Step {
+    pub position: usize,
+    pub command: Command,
+    pub executing: bool,
+    pub stack_before: Vec<Vec<u8>>,
+    pub stack_after: Vec<Vec<u8>>,
+    pub alt_stack_before: Vec<Vec<u8>>,
+    pub alt_stack_after: Vec<Vec<u8>>,
+    pub sighashes: Vec<SighashCheck>,
+    pub error: Option<ScriptError>,
+}
+
+// The signatures and keys a signature opcode took off the stack, in the order
+// they were on it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SignatureOperands<'a> {
+    pub signatures: Vec<&'a [u8]>,
+    pub keys: Vec<&'a [u8]>,
+}
+
+impl Step {
+    pub fn signature_operands(&self) -> Option<SignatureOperands<'_>> {
+        let stack = &self.stack_before;
+        let top = |depth: usize| {
+            stack
+                .len()
+                .checked_sub(depth + 1)
+                .map(|i| stack[i].as_slice())
+        };
+        let Command::Op(opcode) = &self.command else {
+            return None;
+        };
+        match *opcode {
+            OP_CHECKSIG | OP_CHECKSIGVERIFY => Some(SignatureOperands {
+                signatures: vec![top(1)?],
+                keys: vec![top(0)?],
+            }),
+            OP_CHECKSIGADD => Some(SignatureOperands {
+                signatures: vec![top(2)?],
+                keys: vec![top(0)?],
+            }),
+            OP_CHECKMULTISIG | OP_CHECKMULTISIGVERIFY => {
+                let count = |item: &[u8]| match item {
+                    [] => Some(0),
+                    [n @ 1..=20] => Some(*n as usize),
+                    _ => None,
+                };
+                let key_count = count(top(0)?)?;
+                let keys = (1..=key_count).rev().map(top).collect::<Option<_>>()?;
+                let signature_count = count(top(key_count + 1)?)?;
+                let signatures = (key_count + 2..key_count + 2 + signature_count)
+                    .rev()
+                    .map(top)
+                    .collect::<Option<_>>()?;
+                Some(SignatureOperands { signatures, keys })
+            }
+            _ => None,
+        }
+    }
+}
+
+// The steps of one script, in the order verify_script ran it.
+#[derive(Debug, Clone)]
+pub struct ScriptTrace {
+    pub script: Script,
+    pub sig_version: SigVersion,
+    pub steps: Vec<Step>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Trace {
+    pub scripts: Vec<ScriptTrace>,
+    // Signatures checked outside any script, as by a taproot key path spend.
+    pub key_path: Vec<SighashCheck>,
+    pub result: Result<(), ScriptError>,
+}
+
+impl Trace {
+    pub fn steps(&self) -> impl Iterator<Item = &Step> {
+        self.scripts.iter().flat_map(|script| &script.steps)
+    }
+
+    // The step that failed, if the spend failed in one.
+    pub fn failed_step(&self) -> Option<&Step> {
+        self.steps().find(|step| step.error.is_some())
+    }
+}
+
+// Watches each step and records it, and as a checker records each digest,
+// handing the digests on from the checker it wraps.
+pub struct Tracer<'a> {
+    checker: &'a dyn SignatureChecker,
+    scripts: RefCell<Vec<ScriptTrace>>,
+    current: RefCell<Option<Step>>,
+    key_path: RefCell<Vec<SighashCheck>>,
+}
+
+impl<'a> Tracer<'a> {
+    pub fn new(checker: &'a dyn SignatureChecker) -> Self {
+        Tracer {
+            checker,
+            scripts: RefCell::new(Vec::new()),
+            current: RefCell::new(None),
+            key_path: RefCell::new(Vec::new()),
+        }
+    }
+
+    pub fn finish(self, result: Result<(), ScriptError>) -> Trace {
+        Trace {
+            scripts: self.scripts.into_inner(),
+            key_path: self.key_path.into_inner(),
+            result,
+        }
+    }
+
+    fn record(&self, sighash_type: u32, sighash: Option<[u8; 32]>) -> Option<[u8; 32]> {
+        let check = SighashCheck {
+            sighash_type,
+            sighash,
+        };
+        match self.current.borrow_mut().as_mut() {
+            Some(step) => step.sighashes.push(check),
+            None => self.key_path.borrow_mut().push(check),
+        }
+        sighash
+    }
+}
+
+impl SignatureChecker for Tracer<'_> {
+    fn ecdsa_sighash(
+        &self,
+        script_code: &[u8],
+        sighash_type: u32,
+        sig_version: SigVersion,
+    ) -> Option<[u8; 32]> {
+        let sighash = self
+            .checker
+            .ecdsa_sighash(script_code, sighash_type, sig_version);
+        self.record(sighash_type, sighash)
+    }
+
+    fn taproot_sighash(
+        &self,
+        sighash_type: u32,
+        annex: Option<&[u8]>,
+        leaf: Option<(&[u8; 32], u32)>,
+    ) -> Option<[u8; 32]> {
+        let sighash = self.checker.taproot_sighash(sighash_type, annex, leaf);
+        self.record(sighash_type, sighash)
+    }
+}
+
+impl StepObserver for Tracer<'_> {
+    fn before_step(&self, interpreter: &Interpreter) {
+        let position = interpreter.position();
+        if position == 0 {
+            self.scripts.borrow_mut().push(ScriptTrace {
+                script: interpreter.script().clone(),
+                sig_version: interpreter.sig_version(),
+                steps: Vec::new(),
+            });
+        }
+        *self.current.borrow_mut() = Some(Step {
+            position,
+            command: interpreter.next_command().unwrap().clone(),
+            executing: interpreter.is_executing(),
+            stack_before: interpreter.stack().to_vec(),
+            stack_after: Vec::new(),
+            alt_stack_before: interpreter.alt_stack().to_vec(),
+            alt_stack_after: Vec::new(),
+            sighashes: Vec::new(),
+            error: None,
+        });
+    }
+
+    fn after_step(&self, interpreter: &Interpreter, result: &Result<(), ScriptError>) {
+        let mut step = self.current.borrow_mut().take().unwrap();
+        step.stack_after = interpreter.stack().to_vec();
+        step.alt_stack_after = interpreter.alt_stack().to_vec();
+        step.error = result.err();
+        self.scripts
+            .borrow_mut()
+            .last_mut()
+            .unwrap()
+            .steps
+            .push(step);
+    }
+}
+
+// verify_script, recording every step of every script it runs.
+pub fn trace_script(
+    script_sig: &[u8],
+    script_pubkey: &[u8],
+    witness: &[Vec<u8>],
+    flags: u32,
+    checker: &dyn SignatureChecker,
+) -> Trace {
+    let tracer = Tracer::new(checker);
+    let result = verify_script_observed(
+        script_sig,
+        script_pubkey,
+        witness,
+        flags,
+        &tracer,
+        Some(&tracer),
+    );
+    tracer.finish(result)
+}
+
+fn stack_text(stack: &[Vec<u8>]) -> String {
+    let items: Vec<String> = stack
+        .iter()
+        .map(|item| match item.is_empty() {
+            true => "\"\"".to_string(),
+            false => hex::encode(item),
+        })
+        .collect();
+    format!("[{}]", items.join(" "))
+}
+
+fn sighash_text(check: &SighashCheck) -> String {
+    match check.sighash {
+        Some(sighash) => format!(
+            "sighash type {:#04x}: {}",
+            check.sighash_type,
+            hex::encode(sighash)
+        ),
+        None => format!("sighash type {:#04x}: none", check.sighash_type),
+    }
+}
+
+impl fmt::Display for Step {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let command = Script::new(vec![self.command.clone()]).to_asm();
+        // Conditionals run in branches not taken too, to keep count of them.
+        let conditional = matches!(self.command, Command::Op(Opcode(0x63..=0x68)));
+        if !self.executing && !conditional {
+            return write!(f, "{:>4} {} (not executed)", self.position, command);
+        }
+        write!(
+            f,
+            "{:>4} {} {} -> {}",
+            self.position,
+            command,
+            stack_text(&self.stack_before),
+            stack_text(&self.stack_after)
+        )?;
+        if !self.alt_stack_before.is_empty() || !self.alt_stack_after.is_empty() {
+            write!(
+                f,
+                " alt {} -> {}",
+                stack_text(&self.alt_stack_before),
+                stack_text(&self.alt_stack_after)
+            )?;
+        }
+        if let Some(operands) = self.signature_operands() {
+            for signature in operands.signatures {
+                let signature = Script::new(vec![Command::push(signature)]).to_asm_decoded();
+                write!(f, "\n       signature {}", signature)?;
+            }
+            for key in operands.keys {
+                write!(f, "\n       key {}", hex::encode(key))?;
+            }
+        }
+        for check in &self.sighashes {
+            write!(f, "\n       {}", sighash_text(check))?;
+        }
+        if let Some(error) = self.error {
+            write!(f, "\n       error: {}", error)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Trace {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for script in &self.scripts {
+            writeln!(f, "{:?}: {}", script.sig_version, script.script.to_asm())?;
+            for step in &script.steps {
+                writeln!(f, "{}", step)?;
+            }
+        }
+        for check in &self.key_path {
+            writeln!(f, "key path {}", sighash_text(check))?;
+        }
+        match self.result {
+            Ok(()) => write!(f, "ok"),
+            Err(error) => write!(f, "failed: {}", error),
+        }
+    }
+}
+
+#[cfg(test)]
+mod trace_tests {
+    use super::*;
+    use crate::fixtures::{no_signatures, BOOK_PREV_SCRIPT_PUBKEY, BOOK_TX};
+    use crate::script::interpreter::{TxSignatureChecker, VERIFY_P2SH, VERIFY_STRICTENC};
+    use crate::tx::Tx;
+
+    const BOOK_SIGHASH: &str = "27e0c5994dec7824e56dec6b2fcb342eb7cdb0d0957c2fce9882f715e85d81a6";
+
+    fn trace_asm(script_pubkey: &str) -> Trace {
+        let script_pubkey = Script::from_asm(script_pubkey).unwrap().serialize();
+        trace_script(&[], &script_pubkey, &[], VERIFY_P2SH, &no_signatures)
+    }
+
+    #[test]
+    fn book_spend() {
+        let tx = Tx::from_hex(BOOK_TX).unwrap();
+        let script_pubkey = hex::decode(BOOK_PREV_SCRIPT_PUBKEY).unwrap();
+        let checker = TxSignatureChecker::new(&tx, 0);
+        let trace = trace_script(
+            &tx.inputs[0].script_sig,
+            &script_pubkey,
+            &[],
+            VERIFY_STRICTENC,
+            &checker,
+        );
+        assert_eq!(trace.result, Ok(()));
+        assert_eq!(trace.scripts.len(), 2);
+        assert_eq!(trace.scripts[0].steps.len(), 2);
+        assert_eq!(trace.scripts[1].steps.len(), 5);
+        assert!(trace.failed_step().is_none());
+
+        let dup = &trace.scripts[1].steps[0];
+        assert_eq!(dup.command, Command::Op(OP_DUP));
+        assert_eq!(dup.stack_after.len(), 3);
+        assert_eq!(dup.stack_after[1], dup.stack_after[2]);
+
+        let checksig = &trace.scripts[1].steps[4];
+        assert_eq!(checksig.stack_after, [vec![1]]);
+        assert_eq!(
+            checksig.sighashes,
+            [SighashCheck {
+                sighash_type: 1,
+                sighash: Some(hex::decode(BOOK_SIGHASH).unwrap().try_into().unwrap()),
+            }]
+        );
+        let operands = checksig.signature_operands().unwrap();
+        assert_eq!(operands.signatures, [&checksig.stack_before[0][..]]);
+        assert_eq!(operands.keys, [&checksig.stack_before[1][..]]);
+
+        let text = trace.to_string();
+        assert!(text.contains(&format!("sighash type 0x01: {}", BOOK_SIGHASH)));
+        assert!(text.contains("[ALL]"));
+        assert!(text.ends_with("ok"));
+    }
+
+    #[test]
+    fn bad_signature() {
+        let tx = Tx::from_hex(BOOK_TX).unwrap();
+        let script_pubkey = hex::decode(BOOK_PREV_SCRIPT_PUBKEY).unwrap();
+        let wrong_digest = |_: &[u8], _: u32| [1; 32];
+        let trace = trace_script(
+            &tx.inputs[0].script_sig,
+            &script_pubkey,
+            &[],
+            VERIFY_STRICTENC,
+            &wrong_digest,
+        );
+        assert_eq!(trace.result, Err(ScriptError::EvalFalse));
+        // OP_CHECKSIG pushes false rather than failing.
+        let checksig = trace.steps().last().unwrap();
+        assert_eq!(checksig.command, Command::Op(OP_CHECKSIG));
+        assert_eq!(checksig.stack_after, [Vec::<u8>::new()]);
+        assert_eq!(checksig.sighashes[0].sighash, Some([1; 32]));
+        assert!(trace
+            .to_string()
+            .contains(&format!("sighash type 0x01: {}", "01".repeat(32))));
+
+        // As OP_CHECKSIGVERIFY the step itself fails.
+        let mut script = Script::parse(&script_pubkey).unwrap();
+        script.commands.pop();
+        script.commands.push(Command::Op(OP_CHECKSIGVERIFY));
+        script.commands.push(Command::Op(OP_1));
+        let trace = trace_script(
+            &tx.inputs[0].script_sig,
+            &script.serialize(),
+            &[],
+            VERIFY_STRICTENC,
+            &wrong_digest,
+        );
+        assert_eq!(trace.result, Err(ScriptError::CheckSigVerify));
+        let failed = trace.failed_step().unwrap();
+        assert_eq!(failed.command, Command::Op(OP_CHECKSIGVERIFY));
+        assert_eq!(failed.sighashes.len(), 1);
+        assert_eq!(trace.steps().count(), 7);
+    }
+
+    #[test]
+    fn branches_and_alt_stack() {
+        let trace = trace_asm("0 OP_IF 2 OP_ELSE 3 OP_TOALTSTACK 1 OP_ENDIF");
+        assert_eq!(trace.result, Ok(()));
+        let executing: Vec<bool> = trace.steps().map(|step| step.executing).collect();
+        assert_eq!(
+            executing,
+            [true, true, false, false, true, true, true, true]
+        );
+        let toaltstack = trace.steps().nth(5).unwrap();
+        assert_eq!(toaltstack.command, Command::Op(OP_TOALTSTACK));
+        assert!(toaltstack.alt_stack_before.is_empty());
+        assert_eq!(toaltstack.alt_stack_after, [vec![3]]);
+        let next = trace.steps().nth(6).unwrap();
+        assert_eq!(next.alt_stack_before, [vec![3]]);
+        let text = trace.to_string();
+        assert!(text.contains("   2 2 (not executed)"));
+        assert!(text.contains("alt [] -> [03]"));
+        assert!(text.contains("alt [03] -> [03]"));
+    }
+
+    #[test]
+    fn failures() {
+        let trace = trace_asm("1 2 OP_ADD 4 OP_EQUALVERIFY");
+        assert_eq!(trace.result, Err(ScriptError::EqualVerify));
+        let failed = trace.failed_step().unwrap();
+        assert_eq!(failed.position, 4);
+        assert_eq!(failed.stack_before, [vec![3], vec![4]]);
+        assert!(trace.to_string().contains("error: "));
+
+        // Failing after the last step: nothing to blame on a step.
+        let trace = trace_asm("1 OP_IF 1");
+        assert_eq!(trace.result, Err(ScriptError::UnbalancedConditional));
+        assert!(trace.failed_step().is_none());
+        assert_eq!(trace.steps().count(), 3);
+    }
+}
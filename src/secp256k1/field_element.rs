@@ -36,22 +36,27 @@ impl FieldElement {
 
     pub fn to_the_power_of(&self, exponent: BigUint) -> Self {
         let exp = exponent % (&self.prime - BigUint::from_u64(1u64).unwrap());
-        let new_num = Self::mod_pow(self.num.clone(), exp.into(), &self.prime);
+        let new_num = Self::mod_pow(self.num.clone(), exp, &self.prime);
         FieldElement::new(new_num)
     }
 
+    // The secp256k1 prime is 3 mod 4, so a square root (if one exists) is num^((p + 1) / 4).
+    pub fn sqrt(&self) -> Self {
+        self.to_the_power_of((&self.prime + BigUint::one()) / BigUint::from(4u64))
+    }
+
     // credit to https://rob.co.bb/posts/2019-02-10-modular-exponentiation-in-rust/
     fn mod_pow(mut base: BigUint, mut exp: BigUint, modulus: &BigUint) -> BigUint {
         if *modulus == BigUint::one() {
             return BigUint::zero();
         }
         let mut result = BigUint::one();
-        base = base % modulus;
+        base %= modulus;
         while exp > BigUint::zero() {
             if &exp % BigUint::from_u64(2u64).unwrap() == BigUint::one() {
                 result = result * &base % modulus;
             }
-            exp = exp >> 1;
+            exp >>= 1;
             base = base.clone() * base % modulus
         }
         result
@@ -60,7 +65,7 @@ impl FieldElement {
 
 impl PartialEq for FieldElement {
     fn eq(&self, other: &Self) -> bool {
-        return self.num == other.num && self.prime == other.prime;
+        self.num == other.num && self.prime == other.prime
     }
 }
 
@@ -191,6 +196,7 @@ mod field_element_tests {
     }
 
     #[test]
+    #[allow(clippy::assertions_on_constants, clippy::identity_op)]
     fn mul_works() {
         let a = FieldElement::new(3u64.into());
         let b = FieldElement::new(12u64.into());
@@ -216,6 +222,16 @@ mod field_element_tests {
         );
     }
 
+    #[test]
+    fn sqrt_works() {
+        let a = FieldElement::new(49u64.into());
+        let root = a.sqrt();
+        assert!(
+            root == FieldElement::new(7u64.into())
+                || root + FieldElement::new(7u64.into()) == FieldElement::zero()
+        );
+    }
+
     #[test]
     fn div_works() {
         let a = FieldElement::new(2u64.into());
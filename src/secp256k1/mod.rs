@@ -1,3 +1,5 @@
 pub mod field_element;
 pub mod point;
+pub mod private_key;
+pub mod schnorr;
 pub mod signature;
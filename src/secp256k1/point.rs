@@ -1,12 +1,17 @@
 use std::fmt;
-use std::ops::{Add, AddAssign, BitAnd, Shr};
+use std::ops::{Add, AddAssign, BitAnd};
 
 use num::{BigUint, Num, One, Zero};
 
 use super::field_element::FieldElement;
 use super::signature::Signature;
+use crate::address::Address;
+use crate::error::Error;
+use crate::hash::hash160;
+use crate::helper::int_to_32_bytes;
+use crate::network::Network;
 
-fn n() -> BigUint {
+pub fn n() -> BigUint {
     BigUint::from_str_radix(
         "fffffffffffffffffffffffffffffffebaaedce6af48a03bbfd25e8cd0364141",
         16,
@@ -14,7 +19,7 @@ fn n() -> BigUint {
     .unwrap()
 }
 
-fn generator_point() -> Secp256k1Point {
+pub fn generator_point() -> Secp256k1Point {
     let generator_x = BigUint::from_str_radix(
         "79be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798",
         16,
@@ -32,7 +37,7 @@ fn generator_point() -> Secp256k1Point {
 }
 
 #[derive(Clone, Debug)]
-struct Secp256k1Point {
+pub struct Secp256k1Point {
     x: Option<FieldElement>,
     y: Option<FieldElement>,
     a: FieldElement,
@@ -48,9 +53,7 @@ impl Secp256k1Point {
         }
         let x = x.unwrap();
         let y = y.unwrap();
-        if (y.to_the_power_of(2u64.into()))
-            != x.to_the_power_of(3u64.into()) + a.clone() * x.clone() + b.clone()
-        {
+        if !Self::is_on_curve(&x, &y) {
             panic!("{:?}, {:?} is not on the curve.", x, y);
         }
         Self {
@@ -67,25 +70,138 @@ impl Secp256k1Point {
         let mut result = Self::infinity_point();
         while *coefficient != BigUint::zero() {
             if coefficient.clone().bitand(BigUint::one()) == BigUint::one() {
-                result = result + current.clone();
+                result += current.clone();
             }
             current = current.clone() + current;
-            *coefficient = coefficient.clone().shr(1u16);
+            *coefficient >>= 1u16;
         }
         result
     }
 
-    fn infinity_point() -> Secp256k1Point {
+    pub fn infinity_point() -> Secp256k1Point {
         Secp256k1Point::new(None, None)
     }
 
     pub fn verify(self, z: BigUint, signature: Signature) -> bool {
+        // r and s must be in [1, n); anything else would make s_inv meaningless.
+        let in_range = |value: &BigUint| !value.is_zero() && *value < n();
+        if !in_range(signature.r()) || !in_range(signature.s()) || self.is_infinity() {
+            return false;
+        }
         let order_minus_two = n() - BigUint::from(2u64);
         let s_inv = signature.s().modpow(&order_minus_two, &n());
         let mut u = (z * &s_inv) % n();
         let mut v = (signature.r() * s_inv) % n();
         let total = generator_point().multiply_by(&mut u) + self.multiply_by(&mut v);
-        total.x.unwrap().get_number() == *signature.r()
+        match total.x {
+            Some(x) => x.get_number() % n() == *signature.r(),
+            None => false,
+        }
+    }
+
+    pub fn x(&self) -> Option<&FieldElement> {
+        self.x.as_ref()
+    }
+
+    pub fn y(&self) -> Option<&FieldElement> {
+        self.y.as_ref()
+    }
+
+    pub fn is_infinity(&self) -> bool {
+        self.x.is_none() || self.y.is_none()
+    }
+
+    // SEC1 encoding: 0x04 || x || y uncompressed, 0x02/0x03 (even/odd y) || x compressed.
+    pub fn sec(&self, compressed: bool) -> Vec<u8> {
+        if self.is_infinity() {
+            panic!("The point at infinity has no SEC encoding.");
+        }
+        let x = int_to_32_bytes(&self.x.clone().unwrap().get_number());
+        let y = self.y.clone().unwrap().get_number();
+        let mut sec = Vec::with_capacity(65);
+        if compressed {
+            sec.push(if y.bit(0) { 0x03 } else { 0x02 });
+            sec.extend_from_slice(&x);
+        } else {
+            sec.push(0x04);
+            sec.extend_from_slice(&x);
+            sec.extend_from_slice(&int_to_32_bytes(&y));
+        }
+        sec
+    }
+
+    pub fn parse_sec(sec: &[u8]) -> Result<Self, Error> {
+        let prefix = *sec.first().ok_or(Error::InvalidLength(0))?;
+        let expected_length = match prefix {
+            0x02 | 0x03 => 33,
+            0x04 => 65,
+            _ => return Err(Error::InvalidPrefix(prefix)),
+        };
+        if sec.len() != expected_length {
+            return Err(Error::InvalidLength(sec.len()));
+        }
+        let x = Self::parse_coordinate(&sec[1..33])?;
+        let y = if prefix == 0x04 {
+            Self::parse_coordinate(&sec[33..65])?
+        } else {
+            let alpha = x.to_the_power_of(3u64.into()) + FieldElement::new(BigUint::from(7u64));
+            let beta = alpha.sqrt();
+            let beta_is_odd = beta.clone().get_number().bit(0);
+            if beta_is_odd == (prefix == 0x03) {
+                beta
+            } else {
+                FieldElement::zero() - beta
+            }
+        };
+        if !Self::is_on_curve(&x, &y) {
+            return Err(Error::PointNotOnCurve);
+        }
+        Ok(Secp256k1Point::new(Some(x), Some(y)))
+    }
+
+    fn parse_coordinate(bytes: &[u8]) -> Result<FieldElement, Error> {
+        let num = BigUint::from_bytes_be(bytes);
+        if &num >= FieldElement::zero().get_prime() {
+            return Err(Error::PointNotOnCurve);
+        }
+        Ok(FieldElement::new(num))
+    }
+
+    fn is_on_curve(x: &FieldElement, y: &FieldElement) -> bool {
+        y.to_the_power_of(2u64.into())
+            == x.to_the_power_of(3u64.into()) + FieldElement::new(BigUint::from(7u64))
+    }
+
+    pub fn has_even_y(&self) -> bool {
+        !self.y.clone().unwrap().get_number().bit(0)
+    }
+
+    pub fn negate(&self) -> Self {
+        if self.is_infinity() {
+            return self.clone();
+        }
+        let y = FieldElement::zero() - self.y.clone().unwrap();
+        Secp256k1Point::new(self.x.clone(), Some(y))
+    }
+
+    // BIP340 x-only public key: just the x coordinate, the even y is implied.
+    pub fn x_only(&self) -> [u8; 32] {
+        int_to_32_bytes(&self.x.clone().unwrap().get_number())
+    }
+
+    pub fn lift_x(x_only: &[u8]) -> Result<Self, Error> {
+        if x_only.len() != 32 {
+            return Err(Error::InvalidLength(x_only.len()));
+        }
+        Self::parse_sec(&[&[0x02], x_only].concat())
+    }
+
+    pub fn hash160(&self, compressed: bool) -> [u8; 20] {
+        hash160(&self.sec(compressed))
+    }
+
+    pub fn address(&self, compressed: bool, network: Network) -> String {
+        Address::P2pkh(self.hash160(compressed)).encode(network)
     }
 }
 
@@ -127,16 +243,13 @@ impl Add for Secp256k1Point {
             return Secp256k1Point::infinity_point();
         }
 
-        let slope: FieldElement;
-
-        if self == other {
+        let slope = if self == other {
             let x_to_the_second = self_x.to_the_power_of(2u64.into());
-            slope = ((x_to_the_second.clone() + x_to_the_second.clone() + x_to_the_second)
-                + self.a)
-                / (self_y.clone() + self_y.clone());
+            ((x_to_the_second.clone() + x_to_the_second.clone() + x_to_the_second) + self.a)
+                / (self_y.clone() + self_y.clone())
         } else {
-            slope = (other_y - self_y.clone()) / (other_x.clone() - self_x.clone());
-        }
+            (other_y - self_y.clone()) / (other_x.clone() - self_x.clone())
+        };
 
         let binding = slope.to_the_power_of(2u64.into());
         let x = &binding - self_x - other_x;
@@ -145,7 +258,7 @@ impl Add for Secp256k1Point {
         let x = Some(x.clone());
         let y = Some(y.clone());
 
-        return Secp256k1Point::new(x, y);
+        Secp256k1Point::new(x, y)
     }
 }
 
@@ -157,7 +270,7 @@ impl AddAssign for Secp256k1Point {
 
 impl PartialEq for Secp256k1Point {
     fn eq(&self, other: &Self) -> bool {
-        return self.a == other.a && self.b == other.b && self.x == other.x && self.y == other.y;
+        self.a == other.a && self.b == other.b && self.x == other.x && self.y == other.y
     }
 }
 
@@ -265,11 +378,8 @@ mod point_tests {
         )
         .unwrap();
         assert_eq!(
-            BigUint::zero(),
-            ((y.clone().mul(y))
-                - (x.clone().mul(x.clone()).mul(x))
-                - BigUint::from_u32(7).unwrap())
-                % prime
+            y.clone().mul(y) % &prime,
+            ((x.clone().mul(x.clone()).mul(x)) + BigUint::from_u32(7).unwrap()) % prime
         )
     }
 
@@ -351,6 +461,80 @@ mod point_tests {
         );
     }
 
+    #[test]
+    fn chapter_4_sec() {
+        let cases = [
+            (
+                BigUint::from(5000u64),
+                false,
+                "04ffe558e388852f0120e46af2d1b370f85854a8eb0841811ece0e3e03d282d57c315dc72890a4f10a1481c031b03b351b0dc79901ca18a00cf009dbdb157a1d10",
+            ),
+            (
+                BigUint::from(2019u64).pow(5),
+                true,
+                "02933ec2d2b111b92737ec12f1c5d20f3233a0ad21cd8b36d0bca7a0cfa5cb8701",
+            ),
+            (
+                BigUint::from_str_radix("deadbeef54321", 16).unwrap(),
+                true,
+                "0296be5b1292f6c856b3c5654e886fc13511462059089cdf9c479623bfcbe77690",
+            ),
+        ];
+        for (mut secret, compressed, expected) in cases {
+            let point = generator_point().multiply_by(&mut secret);
+            let sec = point.sec(compressed);
+            assert_eq!(hex::encode(&sec), expected);
+            assert_eq!(Secp256k1Point::parse_sec(&sec).unwrap(), point);
+        }
+    }
+
+    #[test]
+    fn parse_sec_rejects_bad_input() {
+        let mut not_on_curve = vec![0x04];
+        not_on_curve.extend_from_slice(&[0u8; 63]);
+        not_on_curve.push(1);
+        assert_eq!(
+            Secp256k1Point::parse_sec(&not_on_curve),
+            Err(Error::PointNotOnCurve)
+        );
+        assert_eq!(
+            Secp256k1Point::parse_sec(&[0x05; 33]),
+            Err(Error::InvalidPrefix(0x05))
+        );
+        assert_eq!(
+            Secp256k1Point::parse_sec(&[0x02; 20]),
+            Err(Error::InvalidLength(20))
+        );
+    }
+
+    #[test]
+    fn chapter_4_exercise_5() {
+        let cases = [
+            (
+                BigUint::from(5002u64),
+                false,
+                Network::Testnet,
+                "mmTPbXQFxboEtNRkwfh6K51jvdtHLxGeMA",
+            ),
+            (
+                BigUint::from(2020u64).pow(5),
+                true,
+                Network::Testnet,
+                "mopVkxp8UhXqRYbCYJsbeE1h1fiF64jcoH",
+            ),
+            (
+                BigUint::from_str_radix("12345deadbeef", 16).unwrap(),
+                true,
+                Network::Mainnet,
+                "1F1Pn2y6pDb68E5nYJJeba4TLg2U7B6KF1",
+            ),
+        ];
+        for (mut secret, compressed, network, expected) in cases {
+            let point = generator_point().multiply_by(&mut secret);
+            assert_eq!(point.address(compressed, network), expected);
+        }
+    }
+
     // Assuming the previous tests pass, our code functions as expected
     // so the following tests are excluded.
 
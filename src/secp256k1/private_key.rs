@@ -0,0 +1,215 @@
+use num::{BigUint, Zero};
+
+use super::point::{generator_point, n, Secp256k1Point};
+use super::signature::Signature;
+use crate::base58::{decode_base58_checksum, encode_base58_checksum};
+use crate::error::Error;
+use crate::hash::hmac_sha256;
+use crate::helper::int_to_32_bytes;
+use crate::network::Network;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PrivateKey {
+    secret: BigUint,
+    point: Secp256k1Point,
+}
+
+impl PrivateKey {
+    pub fn new(secret: BigUint) -> Self {
+        if secret.is_zero() || secret >= n() {
+            panic!("Secret {} is not a valid private key.", secret);
+        }
+        let point = generator_point().multiply_by(&mut secret.clone());
+        Self { secret, point }
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        if bytes.len() != 32 {
+            return Err(Error::InvalidLength(bytes.len()));
+        }
+        let secret = BigUint::from_bytes_be(bytes);
+        if secret.is_zero() || secret >= n() {
+            return Err(Error::SecretOutOfRange);
+        }
+        Ok(PrivateKey::new(secret))
+    }
+
+    pub fn secret(&self) -> &BigUint {
+        &self.secret
+    }
+
+    pub fn point(&self) -> &Secp256k1Point {
+        &self.point
+    }
+
+    pub fn to_bytes(&self) -> [u8; 32] {
+        int_to_32_bytes(&self.secret)
+    }
+
+    // ECDSA over the message hash z, with the nonce from RFC6979 and s made low
+    // (at most n/2) as standardness requires.
+    pub fn sign(&self, z: &BigUint) -> Signature {
+        let k = self.deterministic_k(z);
+        let r = generator_point()
+            .multiply_by(&mut k.clone())
+            .x()
+            .unwrap()
+            .clone()
+            .get_number()
+            % n();
+        let k_inv = k.modpow(&(n() - BigUint::from(2u64)), &n());
+        let mut s = (z + &r * &self.secret) * k_inv % n();
+        if s > n() / BigUint::from(2u64) {
+            s = n() - s;
+        }
+        Signature::new(r, s)
+    }
+
+    // RFC6979 section 3.2 with HMAC-SHA256.
+    fn deterministic_k(&self, z: &BigUint) -> BigUint {
+        let z = if z >= &n() { z - n() } else { z.clone() };
+        let secret_and_z = [self.to_bytes(), int_to_32_bytes(&z)].concat();
+        let mut k = [0u8; 32];
+        let mut v = [1u8; 32];
+        k = hmac_sha256(&k, &[&v[..], &[0x00], &secret_and_z].concat());
+        v = hmac_sha256(&k, &v);
+        k = hmac_sha256(&k, &[&v[..], &[0x01], &secret_and_z].concat());
+        v = hmac_sha256(&k, &v);
+        loop {
+            v = hmac_sha256(&k, &v);
+            let candidate = BigUint::from_bytes_be(&v);
+            if !candidate.is_zero() && candidate < n() {
+                return candidate;
+            }
+            k = hmac_sha256(&k, &[&v[..], &[0x00]].concat());
+            v = hmac_sha256(&k, &v);
+        }
+    }
+
+    // Wallet Import Format: prefix || secret || 0x01 if the public key is compressed.
+    pub fn wif(&self, compressed: bool, network: Network) -> String {
+        let mut payload = vec![network.params().wif_prefix];
+        payload.extend_from_slice(&self.to_bytes());
+        if compressed {
+            payload.push(0x01);
+        }
+        encode_base58_checksum(&payload)
+    }
+
+    // Returns the key and whether it was marked as compressed.
+    pub fn from_wif(wif: &str, network: Network) -> Result<(Self, bool), Error> {
+        let payload = decode_base58_checksum(wif)?;
+        let compressed = match payload.len() {
+            33 => false,
+            34 if payload[33] == 0x01 => true,
+            length => return Err(Error::InvalidLength(length)),
+        };
+        if payload[0] != network.params().wif_prefix {
+            return Err(Error::WrongNetwork);
+        }
+        Ok((PrivateKey::from_bytes(&payload[1..33])?, compressed))
+    }
+}
+
+#[cfg(test)]
+mod private_key_tests {
+    use num::Num;
+
+    use super::*;
+    use crate::hash::sha256;
+
+    // Widely used secp256k1 RFC6979 vectors, cross-checked against an
+    // independent implementation.
+    #[test]
+    fn rfc6979_signatures() {
+        for (secret, message, k, signature) in [
+            (
+                BigUint::from(1u64),
+                "Satoshi Nakamoto",
+                "8f8a276c19f4149656b280621e358cce24f5f52542772691ee69063b74f15d15",
+                "934b1ea10a4b3c1757e2b0c017d0b6143ce3c9a7e6a4a49860d7a6ab210ee3d82442ce9d2b916064108014783e923ec36b49743e2ffa1c4496f01a512aafd9e5",
+            ),
+            (
+                BigUint::from(1u64),
+                "All those moments will be lost in time, like tears in rain. Time to die...",
+                "38aa22d72376b4dbc472e06c3ba403ee0a394da63fc58d88686c611aba98d6b3",
+                "8600dbd41e348fe5c9465ab92d23e3db8b98b873beecd930736488696438cb6b547fe64427496db33bf66019dacbf0039c04199abb0122918601db38a72cfc21",
+            ),
+            (
+                n() - BigUint::from(1u64),
+                "Satoshi Nakamoto",
+                "33a19b60e25fb6f4435af53a3d42d493644827367e6453928554f43e49aa6f90",
+                "fd567d121db66e382991534ada77a6bd3106f0a1098c231e47993447cd6af2d06b39cd0eb1bc8603e159ef5c20a5c8ad685a45b06ce9bebed3f153d10d93bed5",
+            ),
+            (
+                BigUint::from_str_radix(
+                    "69ec59eaa1f4f2e36b639716b7c30ca86d9a5375c7b38d8918bd9c0ebc80ba64",
+                    16,
+                )
+                .unwrap(),
+                "Computer science is no more about computers than astronomy is about telescopes.",
+                "6bb4a594ad57c1aa22dbe991a9d8501daf4688bf50a4892ef21bd7c711afda97",
+                "7186363571d65e084e7f02b0b77c3ec44fb1b257dee26274c38c928986fea45d0de0b38e06807e46bda1f1e293f4f6323e854c86d58abdd00c46c16441085df6",
+            ),
+        ] {
+            let key = PrivateKey::new(secret);
+            let z = BigUint::from_bytes_be(&sha256(message.as_bytes()));
+            assert_eq!(hex::encode(int_to_32_bytes(&key.deterministic_k(&z))), k);
+            let signed = key.sign(&z);
+            assert_eq!(
+                hex::encode([int_to_32_bytes(signed.r()), int_to_32_bytes(signed.s())].concat()),
+                signature
+            );
+            assert!(key.point().clone().verify(z, signed));
+        }
+    }
+
+    #[test]
+    #[should_panic]
+    fn zero_is_not_a_private_key() {
+        PrivateKey::new(BigUint::zero());
+    }
+
+    #[test]
+    fn chapter_4_exercise_6() {
+        let cases = [
+            (
+                BigUint::from(5003u64),
+                true,
+                Network::Testnet,
+                "cMahea7zqjxrtgAbB7LSGbcQUr1uX1ojuat9jZodMN8rFTv2sfUK",
+            ),
+            (
+                BigUint::from(2021u64).pow(5),
+                false,
+                Network::Testnet,
+                "91avARGdfge8E4tZfYLoxeJ5sGBdNJQH4kvjpWAxgzczjbCwxic",
+            ),
+            (
+                BigUint::from_str_radix("54321deadbeef", 16).unwrap(),
+                true,
+                Network::Mainnet,
+                "KwDiBf89QgGbjEhKnhXJuH7LrciVrZi3qYjgiuQJv1h8Ytr2S53a",
+            ),
+        ];
+        for (secret, compressed, network, expected) in cases {
+            let key = PrivateKey::new(secret.clone());
+            assert_eq!(key.wif(compressed, network), expected);
+            let (parsed, parsed_compressed) = PrivateKey::from_wif(expected, network).unwrap();
+            assert_eq!(parsed.secret(), &secret);
+            assert_eq!(parsed_compressed, compressed);
+        }
+    }
+
+    #[test]
+    fn wif_checks_network() {
+        assert_eq!(
+            PrivateKey::from_wif(
+                "KwDiBf89QgGbjEhKnhXJuH7LrciVrZi3qYjgiuQJv1h8Ytr2S53a",
+                Network::Regtest
+            )
+            .unwrap_err(),
+            Error::WrongNetwork
+        );
+    }
+}
@@ -0,0 +1,261 @@
+use num::{BigUint, Zero};
+
+use super::field_element::FieldElement;
+use super::point::{generator_point, n, Secp256k1Point};
+use super::private_key::PrivateKey;
+use crate::error::Error;
+use crate::hash::tagged_hash;
+use crate::helper::int_to_32_bytes;
+
+// BIP340 signature: the x coordinate of the nonce point R and the scalar s.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SchnorrSignature {
+    r: [u8; 32],
+    s: BigUint,
+}
+
+impl SchnorrSignature {
+    pub fn new(r: [u8; 32], s: BigUint) -> Self {
+        SchnorrSignature { r, s }
+    }
+
+    pub fn r(&self) -> &[u8; 32] {
+        &self.r
+    }
+
+    pub fn s(&self) -> &BigUint {
+        &self.s
+    }
+
+    pub fn serialize(&self) -> [u8; 64] {
+        let mut result = [0u8; 64];
+        result[..32].copy_from_slice(&self.r);
+        result[32..].copy_from_slice(&int_to_32_bytes(&self.s));
+        result
+    }
+
+    // Any 64 bytes parse; out of range values simply fail verification.
+    pub fn parse(bytes: &[u8]) -> Result<Self, Error> {
+        if bytes.len() != 64 {
+            return Err(Error::InvalidLength(bytes.len()));
+        }
+        Ok(SchnorrSignature {
+            r: bytes[..32].try_into().unwrap(),
+            s: BigUint::from_bytes_be(&bytes[32..]),
+        })
+    }
+}
+
+// e = hash_BIP0340/challenge(r || x(P) || m) mod n
+pub(crate) fn challenge(r: &[u8; 32], x_only: &[u8; 32], message: &[u8]) -> BigUint {
+    let data = [&r[..], &x_only[..], message].concat();
+    BigUint::from_bytes_be(&tagged_hash("BIP0340/challenge", &data)) % n()
+}
+
+impl Secp256k1Point {
+    // BIP340 verification against this point's x-only key, i.e. with its y taken
+    // as even whatever it actually is.
+    pub fn verify_schnorr(&self, message: &[u8], signature: &SchnorrSignature) -> bool {
+        if self.is_infinity() {
+            return false;
+        }
+        let r = BigUint::from_bytes_be(&signature.r);
+        if &r >= FieldElement::zero().get_prime() || signature.s >= n() {
+            return false;
+        }
+        let x_only = self.x_only();
+        let key = Secp256k1Point::lift_x(&x_only).unwrap();
+        let e = challenge(&signature.r, &x_only, message);
+
+        // R = sG - eP, with -e taken mod n.
+        let mut minus_e = (n() - e) % n();
+        let mut s = signature.s.clone();
+        let point = generator_point().multiply_by(&mut s) + key.multiply_by(&mut minus_e);
+        if point.is_infinity() || !point.has_even_y() {
+            return false;
+        }
+        point.x().unwrap().clone().get_number() == r
+    }
+}
+
+impl PrivateKey {
+    // BIP340 signing. `aux_rand` should be fresh randomness; the nonce is still
+    // derived deterministically from the key and message, so zeros are safe too.
+    pub fn sign_schnorr(&self, message: &[u8], aux_rand: &[u8; 32]) -> SchnorrSignature {
+        // The x-only public key stands for the even y point, so sign with the
+        // secret for that point.
+        let secret = if self.point().has_even_y() {
+            self.secret().clone()
+        } else {
+            n() - self.secret()
+        };
+        let x_only = self.point().x_only();
+
+        let mask = tagged_hash("BIP0340/aux", aux_rand);
+        let mut t = int_to_32_bytes(&secret);
+        t.iter_mut()
+            .zip(mask)
+            .for_each(|(byte, mask)| *byte ^= mask);
+        let nonce_data = [&t[..], &x_only[..], message].concat();
+        let k = BigUint::from_bytes_be(&tagged_hash("BIP0340/nonce", &nonce_data)) % n();
+        if k.is_zero() {
+            panic!("BIP340 nonce is zero.");
+        }
+        let nonce_point = generator_point().multiply_by(&mut k.clone());
+        let k = if nonce_point.has_even_y() { k } else { n() - k };
+
+        let r = nonce_point.x_only();
+        let e = challenge(&r, &x_only, message);
+        SchnorrSignature::new(r, (k + e * secret) % n())
+    }
+}
+
+#[cfg(test)]
+mod schnorr_tests {
+    use super::*;
+
+    fn verify(public_key: &str, message: &str, signature: &str) -> bool {
+        let key = Secp256k1Point::lift_x(&hex::decode(public_key).unwrap()).unwrap();
+        let signature = SchnorrSignature::parse(&hex::decode(signature).unwrap()).unwrap();
+        key.verify_schnorr(&hex::decode(message).unwrap(), &signature)
+    }
+
+    // BIP340 test vectors 0-3: secret key, aux_rand, message, signature.
+    #[test]
+    fn bip340_signing() {
+        for (secret, aux_rand, message, signature) in [
+            (
+                "0000000000000000000000000000000000000000000000000000000000000003",
+                "0000000000000000000000000000000000000000000000000000000000000000",
+                "0000000000000000000000000000000000000000000000000000000000000000",
+                "e907831f80848d1069a5371b402410364bdf1c5f8307b0084c55f1ce2dca821525f66a4a85ea8b71e482a74f382d2ce5ebeee8fdb2172f477df4900d310536c0",
+            ),
+            (
+                "b7e151628aed2a6abf7158809cf4f3c762e7160f38b4da56a784d9045190cfef",
+                "0000000000000000000000000000000000000000000000000000000000000001",
+                "243f6a8885a308d313198a2e03707344a4093822299f31d0082efa98ec4e6c89",
+                "6896bd60eeae296db48a229ff71dfe071bde413e6d43f917dc8dcf8c78de33418906d11ac976abccb20b091292bff4ea897efcb639ea871cfa95f6de339e4b0a",
+            ),
+            (
+                "c90fdaa22168c234c4c6628b80dc1cd129024e088a67cc74020bbea63b14e5c9",
+                "c87aa53824b4d7ae2eb035a2b5bbbccc080e76cdc6d1692c4b0b62d798e6d906",
+                "7e2d58d8b3bcdf1abadec7829054f90dda9805aab56c77333024b9d0a508b75c",
+                "5831aaeed7b44bb74e5eab94ba9d4294c49bcf2a60728d8b4c200f50dd313c1bab745879a5ad954a72c45a91c3a51d3c7adea98d82f8481e0e1e03674a6f3fb7",
+            ),
+            (
+                "0b432b2677937381aef05bb02a66ecd012773062cf3fa2549e44f58ed2401710",
+                "ffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffff",
+                "ffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffff",
+                "7eb0509757e246f19449885651611cb965ecc1a187dd51b64fda1edc9637d5ec97582b9cb13db3933705b32ba982af5af25fd78881ebb32771fc5922efc66ea3",
+            ),
+        ] {
+            let key = PrivateKey::from_bytes(&hex::decode(secret).unwrap()).unwrap();
+            let aux_rand: [u8; 32] = hex::decode(aux_rand).unwrap().try_into().unwrap();
+            let message = hex::decode(message).unwrap();
+            let signed = key.sign_schnorr(&message, &aux_rand);
+            assert_eq!(hex::encode(signed.serialize()), signature);
+            assert!(key.point().verify_schnorr(&message, &signed));
+        }
+    }
+
+    // BIP340 test vectors 15-18 sign messages that are not 32 bytes long.
+    #[test]
+    fn bip340_variable_length_messages() {
+        let key = PrivateKey::from_bytes(
+            &hex::decode("0340034003400340034003400340034003400340034003400340034003400340")
+                .unwrap(),
+        )
+        .unwrap();
+        for (message, signature) in [
+            (vec![], "71535db165ecd9fbbc046e5ffaea61186bb6ad436732fccc25291a55895464cf6069ce26bf03466228f19a3a62db8a649f2d560fac652827d1af0574e427ab63"),
+            (vec![0x11], "08a20a0afef64124649232e0693c583ab1b9934ae63b4c3511f3ae1134c6a303ea3173bfea6683bd101fa5aa5dbc1996fe7cacfc5a577d33ec14564cec2bacbf"),
+            ((1..=0x11).collect(), "5130f39a4059b43bc7cac09a19ece52b5d8699d1a71e3c52da9afdb6b50ac370c4a482b77bf960f8681540e25b6771ece1e5a37fd80e5a51897c5566a97ea5a5"),
+            (vec![0x99; 100], "403b12b0d8555a344175ea7ec746566303321e5dbfa8be6f091635163eca79a8585ed3e3170807e7c03b720fc54c7b23897fcba0e9d0b4a06894cfd249f22367"),
+        ] {
+            assert_eq!(hex::encode(key.sign_schnorr(&message, &[0; 32]).serialize()), signature);
+        }
+    }
+
+    // BIP340 test vectors 0-4.
+    #[test]
+    fn bip340_valid_signatures() {
+        for (public_key, message, signature) in [
+            (
+                "f9308a019258c31049344f85f89d5229b531c845836f99b08601f113bce036f9",
+                "0000000000000000000000000000000000000000000000000000000000000000",
+                "e907831f80848d1069a5371b402410364bdf1c5f8307b0084c55f1ce2dca821525f66a4a85ea8b71e482a74f382d2ce5ebeee8fdb2172f477df4900d310536c0",
+            ),
+            (
+                "dff1d77f2a671c5f36183726db2341be58feae1da2deced843240f7b502ba659",
+                "243f6a8885a308d313198a2e03707344a4093822299f31d0082efa98ec4e6c89",
+                "6896bd60eeae296db48a229ff71dfe071bde413e6d43f917dc8dcf8c78de33418906d11ac976abccb20b091292bff4ea897efcb639ea871cfa95f6de339e4b0a",
+            ),
+            (
+                "dd308afec5777e13121fa72b9cc1b7cc0139715309b086c960e18fd969774eb8",
+                "7e2d58d8b3bcdf1abadec7829054f90dda9805aab56c77333024b9d0a508b75c",
+                "5831aaeed7b44bb74e5eab94ba9d4294c49bcf2a60728d8b4c200f50dd313c1bab745879a5ad954a72c45a91c3a51d3c7adea98d82f8481e0e1e03674a6f3fb7",
+            ),
+            (
+                "25d1dff95105f5253c4022f628a996ad3a0d95fbf21d468a1b33f8c160d8f517",
+                "ffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffff",
+                "7eb0509757e246f19449885651611cb965ecc1a187dd51b64fda1edc9637d5ec97582b9cb13db3933705b32ba982af5af25fd78881ebb32771fc5922efc66ea3",
+            ),
+            (
+                "d69c3509bb99e412e68b0fe8544e72837dfa30746d8be2aa65975f29d22dc7b9",
+                "4df3c3f68fcc83b27e9d42c90431a72499f17875c81a599b566c9889b9696703",
+                "00000000000000000000003b78ce563f89a0ed9414f5aa28ad0d96d6795f9c6376afb1548af603b3eb45c9f8207dee1060cb71c04e80f593060b07d28308d7f4",
+            ),
+        ] {
+            assert!(verify(public_key, message, signature), "{}", signature);
+        }
+    }
+
+    // BIP340 test vectors 6-13, all against the same key and message.
+    #[test]
+    fn bip340_invalid_signatures() {
+        let public_key = "dff1d77f2a671c5f36183726db2341be58feae1da2deced843240f7b502ba659";
+        let message = "243f6a8885a308d313198a2e03707344a4093822299f31d0082efa98ec4e6c89";
+        for signature in [
+            // R has an odd y.
+            "fff97bd5755eeea420453a14355235d382f6472f8568a18b2f057a14602975563cc27944640ac607cd107ae10923d9ef7a73c643e166be5ebeafa34b1ac553e2",
+            // Negated message.
+            "1fa62e331edbc21c394792d2ab1100a7b432b013df3f6ff4f99fcb33e0e1515f28890b3edb6e7189b630448b515ce4f8622a954cfe545735aaea5134fccdb2bd",
+            // Negated s.
+            "6cff5c3ba86c69ea4b7376f31a9bcb4f74c1976089b2d9963da2e5543e177769961764b3aa9b2ffcb6ef947b6887a226e8d7c93e00c5ed0c1834ff0d0c2e6da6",
+            // sG - eP is infinity, with r = 0 and r = 1.
+            "0000000000000000000000000000000000000000000000000000000000000000123dda8328af9c23a94c1feecfd123ba4fb73476f0d594dcb65c6425bd186051",
+            "00000000000000000000000000000000000000000000000000000000000000017615fbaf5ae28864013c099742deadb4dba87f11ac6754f93780d5a1837cf197",
+            // r is not an x coordinate on the curve.
+            "4a298dacae57395a15d0795ddbfd1dcb564da82b0f269bc70a74f8220429ba1d69e89b4c5564d00349106b8497785dd7d1d713a8ae82b32fa79d5f7fc407d39b",
+            // r is the field size.
+            "fffffffffffffffffffffffffffffffffffffffffffffffffffffffefffffc2f69e89b4c5564d00349106b8497785dd7d1d713a8ae82b32fa79d5f7fc407d39b",
+            // s is the curve order.
+            "6cff5c3ba86c69ea4b7376f31a9bcb4f74c1976089b2d9963da2e5543e177769fffffffffffffffffffffffffffffffebaaedce6af48a03bbfd25e8cd0364141",
+        ] {
+            assert!(!verify(public_key, message, signature), "{}", signature);
+        }
+    }
+
+    #[test]
+    fn invalid_keys_are_rejected() {
+        for x in [
+            // Not on the curve.
+            "eefdea4cdb677750a420fee807eacf21eb9898ae79b9768766e4faa04a2d4a34",
+            // Exceeds the field size.
+            "fffffffffffffffffffffffffffffffffffffffffffffffffffffffefffffc30",
+        ] {
+            assert!(Secp256k1Point::lift_x(&hex::decode(x).unwrap()).is_err());
+        }
+    }
+
+    #[test]
+    fn serialize_round_trip() {
+        let bytes = hex::decode("6896bd60eeae296db48a229ff71dfe071bde413e6d43f917dc8dcf8c78de33418906d11ac976abccb20b091292bff4ea897efcb639ea871cfa95f6de339e4b0a").unwrap();
+        let signature = SchnorrSignature::parse(&bytes).unwrap();
+        assert_eq!(signature.serialize().to_vec(), bytes);
+        assert_eq!(
+            SchnorrSignature::parse(&bytes[1..]).unwrap_err(),
+            Error::InvalidLength(63)
+        );
+    }
+}
@@ -1,6 +1,8 @@
 use num::BigUint;
 
-#[derive(Debug)]
+use crate::error::Error;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Signature {
     // Random point x value
     r: BigUint,
@@ -19,4 +21,208 @@ impl Signature {
     pub fn s(&self) -> &BigUint {
         &self.s
     }
+
+    // 0x30 len 0x02 len(r) r 0x02 len(s) s, each integer minimal and positive.
+    pub fn der(&self) -> Vec<u8> {
+        let mut body = der_integer(&self.r);
+        body.extend(der_integer(&self.s));
+        let mut result = vec![0x30, body.len() as u8];
+        result.extend(body);
+        result
+    }
+
+    // Strict DER as required by BIP66, without the trailing sighash byte.
+    pub fn parse_der(der: &[u8]) -> Result<Self, Error> {
+        let invalid = |reason: &str| Error::InvalidSignature(reason.to_string());
+        if der.len() < 8 || der.len() > 72 {
+            return Err(Error::InvalidLength(der.len()));
+        }
+        if der[0] != 0x30 {
+            return Err(invalid("missing sequence marker"));
+        }
+        if der[1] as usize != der.len() - 2 {
+            return Err(invalid("sequence length mismatch"));
+        }
+        let (r, rest) = parse_der_integer(&der[2..])?;
+        let (s, rest) = parse_der_integer(rest)?;
+        if !rest.is_empty() {
+            return Err(invalid("trailing bytes"));
+        }
+        Ok(Signature { r, s })
+    }
+
+    // Core's ecdsa_signature_parse_der_lax, for signatures from before BIP66:
+    // lengths may take several bytes, integers may be padded or negative, and
+    // anything may follow s. An integer too long for 32 bytes leaves r and s
+    // zero, which no key verifies.
+    pub fn parse_der_lax(der: &[u8]) -> Result<Self, Error> {
+        let invalid = |reason: &str| Error::InvalidSignature(reason.to_string());
+        let mut input = der;
+        if input.first() != Some(&0x30) {
+            return Err(invalid("missing sequence marker"));
+        }
+        input = &input[1..];
+        // The sequence length is skipped.
+        let (_, rest) = read_der_length(input, false)?;
+        input = rest;
+        let (r, rest) = read_der_integer_lax(input)?;
+        let (s, _) = read_der_integer_lax(rest)?;
+        let trimmed = |bytes: &[u8]| {
+            let start = bytes
+                .iter()
+                .position(|byte| *byte != 0)
+                .unwrap_or(bytes.len());
+            bytes.len() - start
+        };
+        if trimmed(r) > 32 || trimmed(s) > 32 {
+            return Ok(Signature::new(BigUint::from(0u8), BigUint::from(0u8)));
+        }
+        Ok(Signature::new(
+            BigUint::from_bytes_be(r),
+            BigUint::from_bytes_be(s),
+        ))
+    }
+}
+
+fn der_integer(value: &BigUint) -> Vec<u8> {
+    let mut bytes = value.to_bytes_be();
+    // A set high bit would make the integer negative.
+    if bytes[0] & 0x80 != 0 {
+        bytes.insert(0, 0x00);
+    }
+    let mut result = vec![0x02, bytes.len() as u8];
+    result.extend(bytes);
+    result
+}
+
+fn parse_der_integer(der: &[u8]) -> Result<(BigUint, &[u8]), Error> {
+    let invalid = |reason: &str| Error::InvalidSignature(reason.to_string());
+    if der.len() < 2 || der[0] != 0x02 {
+        return Err(invalid("missing integer marker"));
+    }
+    let len = der[1] as usize;
+    if len == 0 || der.len() < 2 + len {
+        return Err(invalid("bad integer length"));
+    }
+    let bytes = &der[2..2 + len];
+    if bytes[0] & 0x80 != 0 {
+        return Err(invalid("negative integer"));
+    }
+    if len > 1 && bytes[0] == 0x00 && bytes[1] & 0x80 == 0 {
+        return Err(invalid("integer is not minimally encoded"));
+    }
+    Ok((BigUint::from_bytes_be(bytes), &der[2 + len..]))
+}
+
+// A length byte, or 0x80 plus the count of big endian length bytes after
+// it. Only the length of an integer is decoded; `decode` false skips them.
+fn read_der_length(der: &[u8], decode: bool) -> Result<(usize, &[u8]), Error> {
+    let invalid = || Error::InvalidSignature("bad length".to_string());
+    let (&first, mut rest) = der.split_first().ok_or_else(invalid)?;
+    if first & 0x80 == 0 {
+        return Ok((first as usize, rest));
+    }
+    let mut count = (first - 0x80) as usize;
+    if count > rest.len() {
+        return Err(invalid());
+    }
+    if !decode {
+        return Ok((0, &rest[count..]));
+    }
+    while count > 0 && rest[0] == 0 {
+        rest = &rest[1..];
+        count -= 1;
+    }
+    if count >= 4 {
+        return Err(invalid());
+    }
+    let len = rest[..count]
+        .iter()
+        .fold(0usize, |len, byte| len << 8 | *byte as usize);
+    Ok((len, &rest[count..]))
+}
+
+fn read_der_integer_lax(der: &[u8]) -> Result<(&[u8], &[u8]), Error> {
+    if der.first() != Some(&0x02) {
+        return Err(Error::InvalidSignature(
+            "missing integer marker".to_string(),
+        ));
+    }
+    let (len, rest) = read_der_length(&der[1..], true)?;
+    if len > rest.len() {
+        return Err(Error::InvalidSignature("bad integer length".to_string()));
+    }
+    Ok(rest.split_at(len))
+}
+
+#[cfg(test)]
+mod signature_tests {
+    use num::Num;
+
+    use super::*;
+
+    // Programming Bitcoin, chapter 4 exercise 7.
+    #[test]
+    fn chapter_4_der() {
+        let r = BigUint::from_str_radix(
+            "37206a0610995c58074999cb9767b87af4c4978db68c06e8e6e81d282047a7c6",
+            16,
+        )
+        .unwrap();
+        let s = BigUint::from_str_radix(
+            "8ca63759c1157ebeaec0d03cecca119fc9a75bf8e6d0fa65c841c8e2738cdaec",
+            16,
+        )
+        .unwrap();
+        let signature = Signature::new(r, s);
+        let der = signature.der();
+        assert_eq!(hex::encode(&der), "3045022037206a0610995c58074999cb9767b87af4c4978db68c06e8e6e81d282047a7c60221008ca63759c1157ebeaec0d03cecca119fc9a75bf8e6d0fa65c841c8e2738cdaec");
+        assert_eq!(Signature::parse_der(&der).unwrap(), signature);
+    }
+
+    #[test]
+    fn rejects_non_strict_der() {
+        let valid = "3045022037206a0610995c58074999cb9767b87af4c4978db68c06e8e6e81d282047a7c60221008ca63759c1157ebeaec0d03cecca119fc9a75bf8e6d0fa65c841c8e2738cdaec";
+        assert!(Signature::parse_der(&hex::decode(valid).unwrap()).is_ok());
+        for der in [
+            // Wrong sequence length.
+            "3046022037206a0610995c58074999cb9767b87af4c4978db68c06e8e6e81d282047a7c60221008ca63759c1157ebeaec0d03cecca119fc9a75bf8e6d0fa65c841c8e2738cdaec",
+            // s is negative.
+            "3044022037206a0610995c58074999cb9767b87af4c4978db68c06e8e6e81d282047a7c602208ca63759c1157ebeaec0d03cecca119fc9a75bf8e6d0fa65c841c8e2738cdaec",
+            // r has a superfluous leading zero.
+            "304602210037206a0610995c58074999cb9767b87af4c4978db68c06e8e6e81d282047a7c60221008ca63759c1157ebeaec0d03cecca119fc9a75bf8e6d0fa65c841c8e2738cdaec",
+            // Trailing garbage inside the sequence.
+            "3047022037206a0610995c58074999cb9767b87af4c4978db68c06e8e6e81d282047a7c60221008ca63759c1157ebeaec0d03cecca119fc9a75bf8e6d0fa65c841c8e2738cdaec0000",
+        ] {
+            assert!(Signature::parse_der(&hex::decode(der).unwrap()).is_err(), "{}", der);
+        }
+    }
+
+    #[test]
+    fn lax_der() {
+        let valid = "3045022037206a0610995c58074999cb9767b87af4c4978db68c06e8e6e81d282047a7c60221008ca63759c1157ebeaec0d03cecca119fc9a75bf8e6d0fa65c841c8e2738cdaec";
+        let signature = Signature::parse_der(&hex::decode(valid).unwrap()).unwrap();
+        for der in [
+            valid,
+            // r padded, s unpadded and so negative, with a wrong sequence
+            // length and trailing bytes.
+            "307f0222000037206a0610995c58074999cb9767b87af4c4978db68c06e8e6e81d282047a7c602208ca63759c1157ebeaec0d03cecca119fc9a75bf8e6d0fa65c841c8e2738cdaec0000",
+            // Lengths in long form.
+            "30814a0282002037206a0610995c58074999cb9767b87af4c4978db68c06e8e6e81d282047a7c602820021008ca63759c1157ebeaec0d03cecca119fc9a75bf8e6d0fa65c841c8e2738cdaec",
+        ] {
+            assert_eq!(
+                Signature::parse_der_lax(&hex::decode(der).unwrap()),
+                Ok(signature.clone()),
+                "{}",
+                der
+            );
+        }
+        // Too long an r parses, as a signature nothing verifies.
+        let long_r = format!("30460221{}0220{}", "01".repeat(33), "01".repeat(32));
+        let signature = Signature::parse_der_lax(&hex::decode(long_r).unwrap()).unwrap();
+        assert_eq!(signature.r(), &BigUint::from(0u8));
+        for der in ["", "3100", "3006020101", "30060201010202"] {
+            assert!(Signature::parse_der_lax(&hex::decode(der).unwrap()).is_err());
+        }
+    }
 }
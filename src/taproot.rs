@@ -0,0 +1,139 @@
+use num::BigUint;
+
+use crate::hash::tagged_hash;
+use crate::helper::encode_var_bytes;
+use crate::secp256k1::point::{generator_point, n, Secp256k1Point};
+use crate::secp256k1::private_key::PrivateKey;
+
+// BIP341 tweak t = hash_TapTweak(x(P) || merkle_root); key path only outputs commit to no root.
+pub fn tap_tweak(internal_key: &Secp256k1Point, merkle_root: Option<&[u8; 32]>) -> BigUint {
+    let mut data = internal_key.x_only().to_vec();
+    if let Some(merkle_root) = merkle_root {
+        data.extend_from_slice(merkle_root);
+    }
+    let tweak = BigUint::from_bytes_be(&tagged_hash("TapTweak", &data));
+    if tweak >= n() {
+        panic!("Taproot tweak is not a valid scalar.");
+    }
+    tweak
+}
+
+// Q = P + tG, where P is the internal key with its y made even.
+pub fn output_key(internal_key: &Secp256k1Point, merkle_root: Option<&[u8; 32]>) -> Secp256k1Point {
+    let internal_key = if internal_key.has_even_y() {
+        internal_key.clone()
+    } else {
+        internal_key.negate()
+    };
+    let mut tweak = tap_tweak(&internal_key, merkle_root);
+    internal_key + generator_point().multiply_by(&mut tweak)
+}
+
+// The secret for output_key: the internal secret, negated if its point has an
+// odd y, plus the tweak.
+pub fn tweak_private_key(internal_key: &PrivateKey, merkle_root: Option<&[u8; 32]>) -> PrivateKey {
+    let secret = if internal_key.point().has_even_y() {
+        internal_key.secret().clone()
+    } else {
+        n() - internal_key.secret()
+    };
+    let tweak = tap_tweak(internal_key.point(), merkle_root);
+    PrivateKey::new((secret + tweak) % n())
+}
+
+// The version tapscript leaves are committed with.
+pub const TAPSCRIPT_LEAF_VERSION: u8 = 0xc0;
+
+// BIP341 leaf hash, what script path signatures commit to.
+pub fn tap_leaf_hash(script: &[u8], leaf_version: u8) -> [u8; 32] {
+    let mut data = vec![leaf_version];
+    data.extend(encode_var_bytes(script));
+    tagged_hash("TapLeaf", &data)
+}
+
+#[cfg(test)]
+mod taproot_tests {
+    use super::*;
+
+    #[test]
+    fn bip86_output_key() {
+        let internal_key = Secp256k1Point::lift_x(
+            &hex::decode("cc8a4bc64d897bddc5fbc2f670f7a8ba0b386779106cf1223c6fc5d7cd6fc115")
+                .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(
+            hex::encode(output_key(&internal_key, None).x_only()),
+            "a60869f0dbcf1dc659c9cecbaf8050135ea9e8cdc487053f1dc6880949dc684c"
+        );
+    }
+
+    #[test]
+    fn odd_internal_key_is_negated_first() {
+        let mut secret = BigUint::from(3u64);
+        let point = generator_point().multiply_by(&mut secret);
+        let negated = point.negate();
+        assert_ne!(point.has_even_y(), negated.has_even_y());
+        assert_eq!(output_key(&point, None), output_key(&negated, None));
+    }
+
+    // BIP341 key path test vectors, inputs 0 (no script tree) and 1.
+    #[test]
+    fn bip341_tweaked_private_keys() {
+        for (internal, merkle_root, tweaked) in [
+            (
+                "6b973d88838f27366ed61c9ad6367663045cb456e28335c109e30717ae0c6baa",
+                None,
+                "2405b971772ad26915c8dcdf10f238753a9b837e5f8e6a86fd7c0cce5b7296d9",
+            ),
+            (
+                "1e4da49f6aaf4e5cd175fe08a32bb5cb4863d963921255f33d3bc31e1343907f",
+                Some("5b75adecf53548f3ec6ad7d78383bf84cc57b55a3127c72b9a2481752dd88b21"),
+                "ea260c3b10e60f6de018455cd0278f2f5b7e454be1999572789e6a9565d26080",
+            ),
+        ] {
+            let internal = PrivateKey::from_bytes(&hex::decode(internal).unwrap()).unwrap();
+            let merkle_root: Option<[u8; 32]> =
+                merkle_root.map(|root| hex::decode(root).unwrap().try_into().unwrap());
+            let key = tweak_private_key(&internal, merkle_root.as_ref());
+            assert_eq!(hex::encode(key.to_bytes()), tweaked);
+            assert_eq!(
+                key.point().x_only(),
+                output_key(internal.point(), merkle_root.as_ref()).x_only()
+            );
+        }
+    }
+
+    fn leaves(tree: &serde_json::Value, found: &mut Vec<(usize, Vec<u8>, u8)>) {
+        match tree {
+            serde_json::Value::Array(branches) => {
+                branches.iter().for_each(|branch| leaves(branch, found))
+            }
+            serde_json::Value::Null => {}
+            leaf => found.push((
+                leaf["id"].as_u64().unwrap() as usize,
+                hex::decode(leaf["script"].as_str().unwrap()).unwrap(),
+                leaf["leafVersion"].as_u64().unwrap() as u8,
+            )),
+        }
+    }
+
+    #[test]
+    fn bip341_leaf_hashes() {
+        let tests: serde_json::Value =
+            serde_json::from_str(include_str!("../test_data/bip341_tests.json")).unwrap();
+        let mut checked = 0;
+        for test in tests["scriptPubKey"].as_array().unwrap() {
+            let mut found = Vec::new();
+            leaves(&test["given"]["scriptTree"], &mut found);
+            for (id, script, leaf_version) in found {
+                assert_eq!(
+                    hex::encode(tap_leaf_hash(&script, leaf_version)),
+                    test["intermediary"]["leafHashes"][id].as_str().unwrap()
+                );
+                checked += 1;
+            }
+        }
+        assert!(checked > 5);
+    }
+}
@@ -0,0 +1,382 @@
+use super::size::InputType;
+use super::{OutPoint, Tx, TxIn, TxOut};
+use crate::address::Address;
+use crate::error::Error;
+use crate::script::interpreter::witness_program;
+use crate::secp256k1::private_key::PrivateKey;
+
+// Opts in to replace-by-fee, as Bitcoin Core's wallet does, and keeps the
+// locktime enforced.
+const SEQUENCE_RBF: u32 = 0xffff_fffd;
+
+// Bitcoin Core's default dust relay fee, in sat/vB.
+const DUST_RELAY_FEE: u64 = 3;
+
+// Change smaller than this is left to the miner instead; it would cost more to
+// spend than it is worth. As in Bitcoin Core, that is the output and an input
+// spending it at the dust relay fee, the input being P2PKH, or P2WPKH for any
+// witness program: 546 satoshis for P2PKH, 294 for P2WPKH and 330 for P2TR.
+pub fn dust_limit(script_pubkey: &[u8]) -> u64 {
+    let output_size = TxOut::new(0, script_pubkey.to_vec()).serialize().len();
+    let spend_size = match witness_program(script_pubkey) {
+        // Core leaves the witness item count out and rounds the vsize down.
+        Some(_) => (InputType::P2wpkh.input_weight() - 1) / 4,
+        None => InputType::P2pkh.input_weight() / 4,
+    };
+    (output_size + spend_size) as u64 * DUST_RELAY_FEE
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Utxo {
+    pub outpoint: OutPoint,
+    pub amount: u64,
+    pub script_pubkey: Vec<u8>,
+}
+
+impl Utxo {
+    pub fn new(outpoint: OutPoint, amount: u64, script_pubkey: Vec<u8>) -> Self {
+        Utxo {
+            outpoint,
+            amount,
+            script_pubkey,
+        }
+    }
+
+    pub fn tx_out(&self) -> TxOut {
+        TxOut::new(self.amount, self.script_pubkey.clone())
+    }
+}
+
+// Spends all the given UTXOs to the outputs, paying `fee_rate` sat/vB and sending
+// what is left over to the change address.
+#[derive(Debug, Clone)]
+pub struct TxBuilder {
+    utxos: Vec<Utxo>,
+    outputs: Vec<TxOut>,
+    fee_rate: f64,
+    change_address: Option<Address>,
+    locktime: u32,
+}
+
+impl Default for TxBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TxBuilder {
+    pub fn new() -> Self {
+        TxBuilder {
+            utxos: Vec::new(),
+            outputs: Vec::new(),
+            fee_rate: 1.0,
+            change_address: None,
+            locktime: 0,
+        }
+    }
+
+    pub fn add_utxo(mut self, utxo: Utxo) -> Self {
+        self.utxos.push(utxo);
+        self
+    }
+
+    pub fn add_output(mut self, address: &Address, amount: u64) -> Self {
+        self.outputs
+            .push(TxOut::new(amount, address.script_pubkey()));
+        self
+    }
+
+    // Checked when building, which fails on a negative or non-finite rate.
+    pub fn fee_rate(mut self, sat_per_vbyte: f64) -> Self {
+        self.fee_rate = sat_per_vbyte;
+        self
+    }
+
+    // Without a change address anything above the outputs goes to the fee.
+    pub fn change_address(mut self, address: Address) -> Self {
+        self.change_address = Some(address);
+        self
+    }
+
+    pub fn locktime(mut self, locktime: u32) -> Self {
+        self.locktime = locktime;
+        self
+    }
+
+    // The outputs spent by the transaction's inputs, in order, as signing needs.
+    pub fn prevouts(&self) -> Vec<TxOut> {
+        self.utxos.iter().map(Utxo::tx_out).collect()
+    }
+
+    // The unsigned transaction, with change added if it is worth keeping.
+    pub fn build(&self) -> Result<Tx, Error> {
+        if !self.fee_rate.is_finite() || self.fee_rate < 0.0 {
+            return Err(Error::InvalidFeeRate(self.fee_rate.to_string()));
+        }
+        let inputs = self
+            .utxos
+            .iter()
+            .map(|utxo| TxIn {
+                sequence: SEQUENCE_RBF,
+                ..TxIn::new(utxo.outpoint)
+            })
+            .collect();
+        let mut tx = Tx::new(2, inputs, self.outputs.clone(), self.locktime);
+
+        let available = sum_amounts(self.utxos.iter().map(|utxo| utxo.amount))?;
+        let spent = sum_amounts(self.outputs.iter().map(|tx_out| tx_out.value))?;
+        let needed = sum_amounts([spent, self.fee(&tx)?])?;
+        if available < needed {
+            return Err(Error::InsufficientFunds(needed - available));
+        }
+
+        if let Some(change_address) = &self.change_address {
+            tx.outputs
+                .push(TxOut::new(0, change_address.script_pubkey()));
+            let change = available.checked_sub(sum_amounts([spent, self.fee(&tx)?])?);
+            match change {
+                Some(change) if change >= dust_limit(&change_address.script_pubkey()) => {
+                    tx.outputs.last_mut().unwrap().value = change
+                }
+                _ => {
+                    tx.outputs.pop();
+                }
+            }
+        }
+        if tx.outputs.is_empty() {
+            return Err(Error::InvalidTransaction("no outputs".to_string()));
+        }
+        Ok(tx)
+    }
+
+    pub fn build_and_sign(&self, keys: &[PrivateKey]) -> Result<Tx, Error> {
+        let mut tx = self.build()?;
+        tx.sign_inputs(&self.prevouts(), keys)?;
+        Ok(tx)
+    }
+
+    // The fee for `tx` once signed.
+    fn fee(&self, tx: &Tx) -> Result<u64, Error> {
+        let input_types = self
+            .utxos
+            .iter()
+            .map(|utxo| {
+                InputType::from_script_pubkey(&utxo.script_pubkey).ok_or_else(|| {
+                    Error::UnsupportedScript(
+                        "only P2PKH, P2WPKH and P2TR outputs can be spent".to_string(),
+                    )
+                })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let vsize = tx.estimated_vsize(&input_types)?;
+        let fee = (vsize as f64 * self.fee_rate).ceil();
+        // Casting would saturate rather than fail.
+        if fee >= u64::MAX as f64 {
+            return Err(Error::InvalidFeeRate(self.fee_rate.to_string()));
+        }
+        Ok(fee as u64)
+    }
+}
+
+fn sum_amounts(amounts: impl IntoIterator<Item = u64>) -> Result<u64, Error> {
+    amounts.into_iter().try_fold(0u64, |sum, amount| {
+        sum.checked_add(amount)
+            .ok_or_else(|| Error::InvalidTransaction("amounts overflow".to_string()))
+    })
+}
+
+#[cfg(test)]
+mod builder_tests {
+    use num::BigUint;
+
+    use super::*;
+    use crate::taproot;
+
+    fn key(secret: u64) -> PrivateKey {
+        PrivateKey::new(BigUint::from(secret))
+    }
+
+    fn p2pkh(key: &PrivateKey) -> Address {
+        Address::P2pkh(key.point().hash160(true))
+    }
+
+    fn p2wpkh(key: &PrivateKey) -> Address {
+        Address::Witness {
+            version: 0,
+            program: key.point().hash160(true).to_vec(),
+        }
+    }
+
+    fn p2tr(key: &PrivateKey) -> Address {
+        Address::Witness {
+            version: 1,
+            program: taproot::output_key(key.point(), None).x_only().to_vec(),
+        }
+    }
+
+    fn utxo(vout: u32, amount: u64, address: &Address) -> Utxo {
+        Utxo::new(
+            OutPoint::new([0x11; 32], vout),
+            amount,
+            address.script_pubkey(),
+        )
+    }
+
+    #[test]
+    fn signs_every_input_type() {
+        let keys = [key(1001), key(1002), key(1003)];
+        let builder = TxBuilder::new()
+            .add_utxo(utxo(0, 40_000, &p2pkh(&keys[0])))
+            .add_utxo(utxo(1, 50_000, &p2wpkh(&keys[1])))
+            .add_utxo(utxo(2, 60_000, &p2tr(&keys[2])))
+            .add_output(&p2wpkh(&key(2001)), 100_000)
+            .fee_rate(2.5)
+            .change_address(p2tr(&key(2002)));
+        let tx = builder.build_and_sign(&keys).unwrap();
+        let prevouts = builder.prevouts();
+
+        assert!(tx.verify_input(0, &prevouts[0].script_pubkey).unwrap());
+        assert!(tx.verify_segwit_v0_input(1, &prevouts[1]).unwrap());
+        assert!(tx.verify_taproot_key_path(2, &prevouts).unwrap());
+
+        assert_eq!(tx.outputs.len(), 2);
+        assert_eq!(
+            tx.outputs[1].script_pubkey,
+            p2tr(&key(2002)).script_pubkey()
+        );
+        let fee = tx.fee(&prevouts).unwrap();
+        // Signatures can come out a byte or two shorter than budgeted for.
+        let actual_vsize = tx.vsize();
+        assert!(fee as f64 >= actual_vsize as f64 * 2.5, "fee {}", fee);
+        assert!(fee as f64 <= (actual_vsize + 1) as f64 * 2.5, "fee {}", fee);
+
+        // The unsigned transaction is the same apart from the signatures.
+        let unsigned = builder.build().unwrap();
+        assert_eq!(unsigned.outputs, tx.outputs);
+        assert!(unsigned
+            .inputs
+            .iter()
+            .all(|tx_in| tx_in.script_sig.is_empty()));
+    }
+
+    #[test]
+    fn dust_change_goes_to_the_fee() {
+        let owner = key(7);
+        // With a P2WPKH change output the transaction is 141 vbytes, leaving 259
+        // satoshis of change.
+        let builder = TxBuilder::new()
+            .add_utxo(utxo(0, 50_000, &p2wpkh(&owner)))
+            .add_output(&p2wpkh(&key(8)), 49_600)
+            .fee_rate(1.0)
+            .change_address(p2wpkh(&owner));
+        let tx = builder.build().unwrap();
+        assert_eq!(tx.outputs.len(), 1);
+
+        let tx = builder.clone().fee_rate(0.0).build().unwrap();
+        assert_eq!(tx.outputs.len(), 2);
+        assert_eq!(tx.outputs[1].value, 400);
+    }
+
+    #[test]
+    fn dust_limit_depends_on_the_change_script() {
+        let owner = key(7);
+        assert_eq!(dust_limit(&p2pkh(&owner).script_pubkey()), 546);
+        assert_eq!(dust_limit(&Address::P2sh([0; 20]).script_pubkey()), 540);
+        assert_eq!(dust_limit(&p2wpkh(&owner).script_pubkey()), 294);
+        let p2wsh = Address::Witness {
+            version: 0,
+            program: vec![0; 32],
+        };
+        assert_eq!(dust_limit(&p2wsh.script_pubkey()), 330);
+        assert_eq!(dust_limit(&p2tr(&owner).script_pubkey()), 330);
+
+        // 400 satoshis of change is kept as P2WPKH but is dust as P2PKH.
+        let builder = TxBuilder::new()
+            .add_utxo(utxo(0, 50_000, &p2wpkh(&owner)))
+            .add_output(&p2wpkh(&key(8)), 49_600)
+            .fee_rate(0.0);
+        let tx = builder
+            .clone()
+            .change_address(p2wpkh(&owner))
+            .build()
+            .unwrap();
+        assert_eq!(tx.outputs.len(), 2);
+        let tx = builder.change_address(p2pkh(&owner)).build().unwrap();
+        assert_eq!(tx.outputs.len(), 1);
+    }
+
+    #[test]
+    fn insufficient_funds() {
+        let owner = key(7);
+        let builder = TxBuilder::new()
+            .add_utxo(utxo(0, 50_000, &p2wpkh(&owner)))
+            .add_output(&p2wpkh(&key(8)), 50_000)
+            .fee_rate(1.0);
+        assert_eq!(builder.build().unwrap_err(), Error::InsufficientFunds(110));
+    }
+
+    #[test]
+    fn rejects_invalid_fee_rates() {
+        let owner = key(7);
+        let builder = TxBuilder::new()
+            .add_utxo(utxo(0, 50_000, &p2wpkh(&owner)))
+            .add_output(&p2wpkh(&key(8)), 40_000);
+        for rate in [f64::NAN, f64::INFINITY, f64::NEG_INFINITY, -1.0, 1e300] {
+            assert!(
+                matches!(
+                    builder.clone().fee_rate(rate).build().unwrap_err(),
+                    Error::InvalidFeeRate(_)
+                ),
+                "{}",
+                rate
+            );
+        }
+    }
+
+    #[test]
+    fn amounts_that_overflow_are_errors() {
+        let owner = key(7);
+        let builder = TxBuilder::new()
+            .add_utxo(utxo(0, u64::MAX, &p2wpkh(&owner)))
+            .add_utxo(utxo(1, 1, &p2wpkh(&owner)))
+            .add_output(&p2wpkh(&key(8)), 40_000);
+        assert!(matches!(
+            builder.build().unwrap_err(),
+            Error::InvalidTransaction(_)
+        ));
+
+        let builder = TxBuilder::new()
+            .add_utxo(utxo(0, 50_000, &p2wpkh(&owner)))
+            .add_output(&p2wpkh(&key(8)), u64::MAX)
+            .add_output(&p2wpkh(&key(9)), 1);
+        assert!(matches!(
+            builder.build().unwrap_err(),
+            Error::InvalidTransaction(_)
+        ));
+
+        // Spending everything leaves no room for the fee.
+        let builder = TxBuilder::new()
+            .add_utxo(utxo(0, u64::MAX, &p2wpkh(&owner)))
+            .add_output(&p2wpkh(&key(8)), u64::MAX);
+        assert!(builder.build().is_err());
+    }
+
+    #[test]
+    fn signing_needs_every_key() {
+        let builder = TxBuilder::new()
+            .add_utxo(utxo(0, 50_000, &p2wpkh(&key(7))))
+            .add_utxo(utxo(1, 50_000, &p2tr(&key(8))))
+            .add_output(&p2wpkh(&key(9)), 90_000);
+        assert_eq!(
+            builder.build_and_sign(&[key(7)]).unwrap_err(),
+            Error::MissingKey(1)
+        );
+
+        let p2sh = Address::P2sh([0; 20]);
+        let builder = builder.add_utxo(utxo(2, 50_000, &p2sh));
+        assert!(matches!(
+            builder.build().unwrap_err(),
+            Error::UnsupportedScript(_)
+        ));
+    }
+}
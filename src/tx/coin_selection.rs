@@ -0,0 +1,602 @@
+use std::cmp::Reverse;
+
+use rand::seq::SliceRandom;
+use rand::Rng;
+
+use super::builder::Utxo;
+use super::size::InputType;
+use crate::error::Error;
+
+// Bitcoin Core's search limits.
+const BNB_TOTAL_TRIES: usize = 100_000;
+const KNAPSACK_ITERATIONS: usize = 1000;
+
+// Core's CHANGE_LOWER: Knapsack and SRD aim to leave at least this much change,
+// so they do not produce change outputs too small to be useful.
+pub const CHANGE_LOWER: u64 = 50_000;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Candidate {
+    pub utxo: Utxo,
+    pub input_type: InputType,
+}
+
+impl Candidate {
+    pub fn new(utxo: Utxo, input_type: InputType) -> Self {
+        Candidate { utxo, input_type }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CoinSelectionParams {
+    // sat/vB for this transaction.
+    pub fee_rate: f64,
+    // sat/vB we expect to pay to spend an output later. Spending inputs at a
+    // higher rate than this is waste, at a lower rate consolidation.
+    pub long_term_fee_rate: f64,
+    pub change_type: InputType,
+    // Smaller change goes to the fee instead.
+    pub min_change: u64,
+}
+
+impl CoinSelectionParams {
+    fn fee(fee_rate: f64, weight: usize) -> i64 {
+        (fee_rate * weight.div_ceil(4) as f64).ceil() as i64
+    }
+
+    // What a change output costs in this transaction.
+    pub fn change_fee(&self) -> u64 {
+        Self::fee(self.fee_rate, self.change_type.output_size() * 4) as u64
+    }
+
+    // Creating the change output now and spending it later.
+    pub fn cost_of_change(&self) -> u64 {
+        self.change_fee()
+            + Self::fee(self.long_term_fee_rate, self.change_type.input_weight()) as u64
+    }
+
+    // An input's value less what it costs to spend it at the current fee rate.
+    pub fn effective_value(&self, candidate: &Candidate) -> i64 {
+        candidate.utxo.amount as i64 - Self::fee(self.fee_rate, candidate.input_type.input_weight())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Algorithm {
+    BranchAndBound,
+    Knapsack,
+    SingleRandomDraw,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Selection {
+    pub algorithm: Algorithm,
+    pub selected: Vec<Candidate>,
+    // What is left for a change output once it is paid for, 0 when below min_change.
+    pub change: u64,
+    pub waste: i64,
+}
+
+// A spendable candidate in the terms the algorithms work in.
+#[derive(Debug, Clone, Copy)]
+struct Group {
+    index: usize,
+    value: i64,
+    fee: i64,
+    long_term_fee: i64,
+}
+
+// Candidates worth spending at all, i.e. with a positive effective value.
+fn groups(candidates: &[Candidate], params: &CoinSelectionParams) -> Vec<Group> {
+    candidates
+        .iter()
+        .enumerate()
+        .map(|(index, candidate)| {
+            let weight = candidate.input_type.input_weight();
+            Group {
+                index,
+                value: params.effective_value(candidate),
+                fee: CoinSelectionParams::fee(params.fee_rate, weight),
+                long_term_fee: CoinSelectionParams::fee(params.long_term_fee_rate, weight),
+            }
+        })
+        .filter(|group| group.value > 0)
+        .collect()
+}
+
+// Core's waste metric: the inputs' fees now versus at the long term rate, plus
+// either the cost of the change output or the excess given up to the fee.
+fn selection(
+    algorithm: Algorithm,
+    candidates: &[Candidate],
+    selected: &[Group],
+    target: u64,
+    params: &CoinSelectionParams,
+) -> Selection {
+    let value: i64 = selected.iter().map(|group| group.value).sum();
+    let excess = value - target as i64;
+    let change = match excess - params.change_fee() as i64 {
+        change if change >= params.min_change as i64 => change as u64,
+        _ => 0,
+    };
+    let mut waste: i64 = selected
+        .iter()
+        .map(|group| group.fee - group.long_term_fee)
+        .sum();
+    waste += if change > 0 {
+        params.cost_of_change() as i64
+    } else {
+        excess
+    };
+    Selection {
+        algorithm,
+        selected: selected
+            .iter()
+            .map(|group| candidates[group.index].clone())
+            .collect(),
+        change,
+        waste,
+    }
+}
+
+// Depth first search for an input set whose effective value lands within
+// cost_of_change above `target`, so no change output is needed, minimising
+// waste. `target` is the payments plus the fee for everything but the inputs.
+pub fn branch_and_bound(
+    candidates: &[Candidate],
+    target: u64,
+    params: &CoinSelectionParams,
+) -> Option<Selection> {
+    let mut pool = groups(candidates, params);
+    pool.sort_by_key(|group| Reverse(group.value));
+    let target = target as i64;
+    let cost_of_change = params.cost_of_change() as i64;
+    // With fees above the long term rate, adding inputs only adds waste.
+    let more_inputs_add_waste = pool
+        .first()
+        .is_some_and(|group| group.fee > group.long_term_fee);
+
+    let mut available: i64 = pool.iter().map(|group| group.value).sum();
+    let mut value = 0;
+    let mut waste = 0;
+    let mut current: Vec<usize> = Vec::new();
+    let mut best: Option<(Vec<usize>, i64)> = None;
+    let mut index = 0;
+    for _ in 0..BNB_TOTAL_TRIES {
+        let best_waste = best.as_ref().map_or(i64::MAX, |(_, waste)| *waste);
+        let mut backtrack = false;
+        if value + available < target
+            || value > target + cost_of_change
+            || (waste > best_waste && more_inputs_add_waste)
+        {
+            backtrack = true;
+        } else if value >= target {
+            // The excess is waste too, since it goes to the fee.
+            let total_waste = waste + value - target;
+            if total_waste <= best_waste {
+                best = Some((current.clone(), total_waste));
+            }
+            backtrack = true;
+        }
+
+        if backtrack {
+            let Some(&last) = current.last() else {
+                break;
+            };
+            // Put everything after the last included input back in reach, then
+            // explore the branch that leaves it out.
+            index -= 1;
+            while index > last {
+                available += pool[index].value;
+                index -= 1;
+            }
+            value -= pool[last].value;
+            waste -= pool[last].fee - pool[last].long_term_fee;
+            current.pop();
+        } else {
+            let group = pool[index];
+            available -= group.value;
+            // Leaving an input out and then including an identical one next
+            // would only revisit the same sets.
+            let is_duplicate_of_omitted = index > 0
+                && current.last() != Some(&(index - 1))
+                && group.value == pool[index - 1].value
+                && group.fee == pool[index - 1].fee;
+            if current.is_empty() || !is_duplicate_of_omitted {
+                current.push(index);
+                value += group.value;
+                waste += group.fee - group.long_term_fee;
+            }
+        }
+        index += 1;
+    }
+
+    let (indices, _) = best?;
+    let selected: Vec<Group> = indices.iter().map(|&index| pool[index]).collect();
+    Some(selection(
+        Algorithm::BranchAndBound,
+        candidates,
+        &selected,
+        target as u64,
+        params,
+    ))
+}
+
+// Core's original algorithm: an exact match if there is one, otherwise the best
+// of many random subsets of the smaller inputs, falling back to the smallest
+// input that covers everything on its own. Aims for CHANGE_LOWER of change.
+pub fn knapsack<R: Rng>(
+    candidates: &[Candidate],
+    target: u64,
+    params: &CoinSelectionParams,
+    rng: &mut R,
+) -> Option<Selection> {
+    let mut pool = groups(candidates, params);
+    pool.shuffle(rng);
+    let target_value = target as i64;
+    let change_target = (CHANGE_LOWER + params.change_fee()) as i64;
+    let done = |selected: &[Group]| {
+        Some(selection(
+            Algorithm::Knapsack,
+            candidates,
+            selected,
+            target,
+            params,
+        ))
+    };
+
+    let mut lowest_larger: Option<Group> = None;
+    let mut applicable = Vec::new();
+    let mut total_lower = 0;
+    for group in pool {
+        if group.value == target_value {
+            return done(&[group]);
+        } else if group.value < target_value + change_target {
+            applicable.push(group);
+            total_lower += group.value;
+        } else if lowest_larger.is_none_or(|lowest| group.value < lowest.value) {
+            lowest_larger = Some(group);
+        }
+    }
+    if total_lower == target_value {
+        return done(&applicable);
+    }
+    if total_lower < target_value {
+        return done(&[lowest_larger?]);
+    }
+
+    applicable.sort_by_key(|group| Reverse(group.value));
+    let (mut best, mut best_value) =
+        approximate_best_subset(&applicable, total_lower, target_value, rng);
+    if best_value != target_value && total_lower >= target_value + change_target {
+        (best, best_value) =
+            approximate_best_subset(&applicable, total_lower, target_value + change_target, rng);
+    }
+    if let Some(lowest_larger) = lowest_larger {
+        if (best_value != target_value && best_value < target_value + change_target)
+            || lowest_larger.value <= best_value
+        {
+            return done(&[lowest_larger]);
+        }
+    }
+    let selected: Vec<Group> = applicable
+        .iter()
+        .zip(best)
+        .filter(|(_, included)| *included)
+        .map(|(group, _)| *group)
+        .collect();
+    done(&selected)
+}
+
+// Random subsets, each grown past the target then trimmed of its last input
+// whenever that keeps it above; returns the smallest total found.
+fn approximate_best_subset<R: Rng>(
+    groups: &[Group],
+    total_lower: i64,
+    target: i64,
+    rng: &mut R,
+) -> (Vec<bool>, i64) {
+    let mut best = vec![true; groups.len()];
+    let mut best_value = total_lower;
+    for _ in 0..KNAPSACK_ITERATIONS {
+        if best_value == target {
+            break;
+        }
+        let mut included = vec![false; groups.len()];
+        let mut total = 0;
+        let mut reached_target = false;
+        for pass in 0..2 {
+            if reached_target {
+                break;
+            }
+            for (i, group) in groups.iter().enumerate() {
+                // First pass picks at random, the second adds whatever is left.
+                let pick = if pass == 0 {
+                    rng.gen_bool(0.5)
+                } else {
+                    !included[i]
+                };
+                if !pick {
+                    continue;
+                }
+                total += group.value;
+                included[i] = true;
+                if total >= target {
+                    reached_target = true;
+                    if total < best_value {
+                        best_value = total;
+                        best = included.clone();
+                    }
+                    total -= group.value;
+                    included[i] = false;
+                }
+            }
+        }
+    }
+    (best, best_value)
+}
+
+// Single random draw: inputs in random order until the target plus
+// CHANGE_LOWER and the change output are covered.
+pub fn single_random_draw<R: Rng>(
+    candidates: &[Candidate],
+    target: u64,
+    params: &CoinSelectionParams,
+    rng: &mut R,
+) -> Option<Selection> {
+    let mut pool = groups(candidates, params);
+    pool.shuffle(rng);
+    let needed = (target + CHANGE_LOWER + params.change_fee()) as i64;
+    let mut value = 0;
+    let mut selected = Vec::new();
+    for group in pool {
+        value += group.value;
+        selected.push(group);
+        if value >= needed {
+            return Some(selection(
+                Algorithm::SingleRandomDraw,
+                candidates,
+                &selected,
+                target,
+                params,
+            ));
+        }
+    }
+    None
+}
+
+// Runs every algorithm and keeps the least wasteful result, preferring more
+// inputs on a tie as Core does.
+pub fn select_coins<R: Rng>(
+    candidates: &[Candidate],
+    target: u64,
+    params: &CoinSelectionParams,
+    rng: &mut R,
+) -> Result<Selection, Error> {
+    let results = [
+        branch_and_bound(candidates, target, params),
+        knapsack(candidates, target, params, rng),
+        single_random_draw(candidates, target, params, rng),
+    ];
+    results
+        .into_iter()
+        .flatten()
+        .min_by(|a, b| {
+            a.waste
+                .cmp(&b.waste)
+                .then(b.selected.len().cmp(&a.selected.len()))
+        })
+        .ok_or_else(|| {
+            let available: i64 = groups(candidates, params)
+                .iter()
+                .map(|group| group.value)
+                .sum();
+            Error::InsufficientFunds(target.saturating_sub(available as u64))
+        })
+}
+
+#[cfg(test)]
+mod coin_selection_tests {
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    use super::*;
+    use crate::tx::OutPoint;
+
+    // At a zero fee rate effective values are just the amounts.
+    const FREE: CoinSelectionParams = CoinSelectionParams {
+        fee_rate: 0.0,
+        long_term_fee_rate: 0.0,
+        change_type: InputType::P2wpkh,
+        min_change: 1000,
+    };
+
+    fn candidates(amounts: &[u64], input_type: InputType) -> Vec<Candidate> {
+        amounts
+            .iter()
+            .enumerate()
+            .map(|(vout, amount)| {
+                Candidate::new(
+                    Utxo::new(OutPoint::new([0x33; 32], vout as u32), *amount, Vec::new()),
+                    input_type,
+                )
+            })
+            .collect()
+    }
+
+    fn amounts(selection: &Selection) -> Vec<u64> {
+        let mut amounts: Vec<u64> = selection
+            .selected
+            .iter()
+            .map(|candidate| candidate.utxo.amount)
+            .collect();
+        amounts.sort();
+        amounts
+    }
+
+    #[test]
+    fn effective_values_and_change_costs() {
+        let params = CoinSelectionParams {
+            fee_rate: 10.0,
+            long_term_fee_rate: 5.0,
+            change_type: InputType::P2wpkh,
+            min_change: 1000,
+        };
+        let effective_value =
+            |input_type| params.effective_value(&candidates(&[100_000], input_type)[0]);
+        assert_eq!(effective_value(InputType::P2pkh), 98_520);
+        assert_eq!(effective_value(InputType::P2wpkh), 99_320);
+        assert_eq!(effective_value(InputType::P2tr), 99_420);
+        // 31 vbytes now, 68 vbytes later at the long term rate.
+        assert_eq!(params.change_fee(), 310);
+        assert_eq!(params.cost_of_change(), 310 + 340);
+    }
+
+    #[test]
+    fn branch_and_bound_finds_changeless_solution() {
+        let pool = candidates(
+            &[100_000, 200_000, 300_000, 400_000, 500_000],
+            InputType::P2wpkh,
+        );
+        let selection = branch_and_bound(&pool, 1_000_000, &FREE).unwrap();
+        assert_eq!(selection.algorithm, Algorithm::BranchAndBound);
+        assert_eq!(
+            selection
+                .selected
+                .iter()
+                .map(|c| c.utxo.amount)
+                .sum::<u64>(),
+            1_000_000
+        );
+        assert_eq!(selection.change, 0);
+        assert_eq!(selection.waste, 0);
+
+        // Nothing lands within cost_of_change (0 here) of an odd target.
+        assert!(branch_and_bound(&pool, 1_000_001, &FREE).is_none());
+        assert!(branch_and_bound(&pool, 1_500_001, &FREE).is_none());
+    }
+
+    #[test]
+    fn branch_and_bound_minimises_waste() {
+        // Inputs cost more now than later, so fewer inputs waste less.
+        let params = CoinSelectionParams {
+            fee_rate: 10.0,
+            long_term_fee_rate: 1.0,
+            ..FREE
+        };
+        let pool = candidates(&[100_680, 100_680, 200_680], InputType::P2wpkh);
+        // Effective values are 100_000 and 200_000.
+        let selection = branch_and_bound(&pool, 200_000, &params).unwrap();
+        assert_eq!(amounts(&selection), vec![200_680]);
+        assert_eq!(selection.waste, 680 - 68);
+
+        // At a fee rate below the long term one, consolidating is preferred.
+        let params = CoinSelectionParams {
+            fee_rate: 1.0,
+            long_term_fee_rate: 10.0,
+            ..FREE
+        };
+        let pool = candidates(&[100_068, 100_068, 200_068], InputType::P2wpkh);
+        let selection = branch_and_bound(&pool, 200_000, &params).unwrap();
+        assert_eq!(amounts(&selection), vec![100_068, 100_068]);
+        assert_eq!(selection.waste, 2 * (68 - 680));
+    }
+
+    #[test]
+    fn uneconomical_inputs_are_skipped() {
+        let params = CoinSelectionParams {
+            fee_rate: 50.0,
+            ..FREE
+        };
+        // Spending a P2PKH input costs 7400 at this rate.
+        let pool = candidates(&[7_000, 7_400, 200_000], InputType::P2pkh);
+        let mut rng = StdRng::seed_from_u64(1);
+        let selection = knapsack(&pool, 100_000, &params, &mut rng).unwrap();
+        assert_eq!(amounts(&selection), vec![200_000]);
+        assert_eq!(
+            select_coins(&pool[..2], 1, &params, &mut rng).unwrap_err(),
+            Error::InsufficientFunds(1)
+        );
+    }
+
+    #[test]
+    fn knapsack_prefers_exact_matches() {
+        let mut rng = StdRng::seed_from_u64(7);
+        let pool = candidates(
+            &[10_000, 30_000, 60_000, 90_000, 1_000_000],
+            InputType::P2wpkh,
+        );
+        let selection = knapsack(&pool, 90_000, &FREE, &mut rng).unwrap();
+        assert_eq!(amounts(&selection), vec![90_000]);
+        // 10k + 30k + 60k is the only exact combination.
+        let selection = knapsack(&pool, 100_000, &FREE, &mut rng).unwrap();
+        assert_eq!(amounts(&selection), vec![10_000, 30_000, 60_000]);
+        assert_eq!(selection.change, 0);
+    }
+
+    #[test]
+    fn knapsack_falls_back_to_lowest_larger() {
+        let mut rng = StdRng::seed_from_u64(7);
+        let pool = candidates(&[10_000, 20_000, 500_000, 2_000_000], InputType::P2wpkh);
+        let selection = knapsack(&pool, 100_000, &FREE, &mut rng).unwrap();
+        assert_eq!(amounts(&selection), vec![500_000]);
+        assert_eq!(selection.change, 400_000);
+        assert!(knapsack(&pool, 3_000_000, &FREE, &mut rng).is_none());
+    }
+
+    #[test]
+    fn single_random_draw_is_seeded() {
+        let pool = candidates(
+            &[40_000, 50_000, 60_000, 70_000, 80_000, 90_000, 100_000],
+            InputType::P2tr,
+        );
+        let params = CoinSelectionParams {
+            fee_rate: 2.0,
+            long_term_fee_rate: 2.0,
+            ..FREE
+        };
+        let draw = |seed| {
+            single_random_draw(&pool, 100_000, &params, &mut StdRng::seed_from_u64(seed)).unwrap()
+        };
+        let selection = draw(3);
+        assert_eq!(selection, draw(3));
+        assert_eq!(selection.algorithm, Algorithm::SingleRandomDraw);
+        let value: i64 = selection
+            .selected
+            .iter()
+            .map(|candidate| params.effective_value(candidate))
+            .sum();
+        assert!(value >= (100_000 + CHANGE_LOWER + params.change_fee()) as i64);
+        assert_eq!(
+            selection.change as i64,
+            value - 100_000 - params.change_fee() as i64
+        );
+        assert_eq!(selection.waste, params.cost_of_change() as i64);
+    }
+
+    #[test]
+    fn select_coins_keeps_least_waste() {
+        let params = CoinSelectionParams {
+            fee_rate: 5.0,
+            long_term_fee_rate: 5.0,
+            ..FREE
+        };
+        // 100_340 is exactly 100_000 effective, so BnB wins with zero waste.
+        let pool = candidates(&[30_000, 100_340, 250_000, 400_000], InputType::P2wpkh);
+        let mut rng = StdRng::seed_from_u64(42);
+        let selection = select_coins(&pool, 100_000, &params, &mut rng).unwrap();
+        assert_eq!(selection.algorithm, Algorithm::BranchAndBound);
+        assert_eq!(amounts(&selection), vec![100_340]);
+        assert_eq!(selection.waste, 0);
+
+        // Without an exact match, some result with change is the best.
+        let selection = select_coins(&pool[2..], 100_000, &params, &mut rng).unwrap();
+        assert!(selection.change > 0);
+        assert_eq!(selection.waste, params.cost_of_change() as i64);
+
+        assert_eq!(
+            select_coins(&pool, 1_000_000, &params, &mut rng).unwrap_err(),
+            Error::InsufficientFunds(1_000_000 - (780_340 - 4 * 340))
+        );
+    }
+}
@@ -0,0 +1,256 @@
+use super::{Tx, TxIn, SEQUENCE_FINAL};
+use crate::error::Error;
+
+// Locktimes below this are block heights, from it on unix times.
+pub const LOCKTIME_THRESHOLD: u32 = 500_000_000;
+
+// BIP68: set, the sequence is no relative locktime.
+pub const SEQUENCE_LOCKTIME_DISABLE_FLAG: u32 = 1 << 31;
+// Set, the locktime counts units of 512 seconds rather than blocks.
+pub const SEQUENCE_LOCKTIME_TYPE_FLAG: u32 = 1 << 22;
+pub const SEQUENCE_LOCKTIME_MASK: u32 = 0x0000ffff;
+pub const SEQUENCE_LOCKTIME_GRANULARITY: u32 = 9;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LockTime {
+    Height(u32),
+    Time(u32),
+}
+
+impl LockTime {
+    pub fn from_consensus(locktime: u32) -> Self {
+        if locktime < LOCKTIME_THRESHOLD {
+            LockTime::Height(locktime)
+        } else {
+            LockTime::Time(locktime)
+        }
+    }
+
+    // Whether a block at `height` with `median_time_past` is past it.
+    pub fn is_satisfied_by(&self, height: u32, median_time_past: u32) -> bool {
+        match *self {
+            LockTime::Height(locktime) => locktime < height,
+            LockTime::Time(locktime) => locktime < median_time_past,
+        }
+    }
+}
+
+// BIP68: how long after the output it spends was confirmed an input may be
+// mined.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RelativeLockTime {
+    Blocks(u16),
+    // In units of 512 seconds.
+    Time(u16),
+}
+
+impl RelativeLockTime {
+    pub fn from_sequence(sequence: u32) -> Option<Self> {
+        if sequence & SEQUENCE_LOCKTIME_DISABLE_FLAG != 0 {
+            return None;
+        }
+        let value = (sequence & SEQUENCE_LOCKTIME_MASK) as u16;
+        if sequence & SEQUENCE_LOCKTIME_TYPE_FLAG != 0 {
+            Some(RelativeLockTime::Time(value))
+        } else {
+            Some(RelativeLockTime::Blocks(value))
+        }
+    }
+
+    pub fn to_sequence(&self) -> u32 {
+        match *self {
+            RelativeLockTime::Blocks(blocks) => blocks as u32,
+            RelativeLockTime::Time(units) => SEQUENCE_LOCKTIME_TYPE_FLAG | units as u32,
+        }
+    }
+
+    // A time lock rounded down to whole units.
+    pub fn from_seconds(seconds: u32) -> Self {
+        RelativeLockTime::Time((seconds >> SEQUENCE_LOCKTIME_GRANULARITY).min(0xffff) as u16)
+    }
+
+    pub fn seconds(&self) -> Option<u32> {
+        match *self {
+            RelativeLockTime::Blocks(_) => None,
+            RelativeLockTime::Time(units) => Some((units as u32) << SEQUENCE_LOCKTIME_GRANULARITY),
+        }
+    }
+}
+
+impl TxIn {
+    pub fn is_final(&self) -> bool {
+        self.sequence == SEQUENCE_FINAL
+    }
+
+    // The BIP68 locktime the sequence encodes, ignoring the transaction
+    // version, which Tx::relative_locktime also checks.
+    pub fn relative_locktime(&self) -> Option<RelativeLockTime> {
+        RelativeLockTime::from_sequence(self.sequence)
+    }
+}
+
+impl Tx {
+    // None when the locktime has no effect: zero, or every input final.
+    pub fn absolute_locktime(&self) -> Option<LockTime> {
+        if self.locktime == 0 || self.inputs.iter().all(TxIn::is_final) {
+            return None;
+        }
+        Some(LockTime::from_consensus(self.locktime))
+    }
+
+    // Relative locktimes only apply from version 2 on.
+    pub fn relative_locktime(&self, input_index: usize) -> Option<RelativeLockTime> {
+        if self.version < 2 {
+            return None;
+        }
+        self.inputs.get(input_index)?.relative_locktime()
+    }
+
+    // Core's IsFinalTx: whether the transaction may go in a block at `height`
+    // whose previous blocks have `median_time_past`, as far as its locktime goes.
+    pub fn is_final(&self, height: u32, median_time_past: u32) -> bool {
+        self.absolute_locktime()
+            .is_none_or(|locktime| locktime.is_satisfied_by(height, median_time_past))
+    }
+
+    // BIP68 for a block at `height` whose previous blocks have
+    // `median_time_past`. `spent` has, for every input in order, the height of
+    // the block that confirmed the output it spends and the median time past
+    // of the block before that one.
+    pub fn check_sequence_locks(
+        &self,
+        spent: &[(u32, u32)],
+        height: u32,
+        median_time_past: u32,
+    ) -> Result<bool, Error> {
+        if spent.len() != self.inputs.len() {
+            return Err(Error::InvalidTransaction(format!(
+                "{} spent outputs for {} inputs",
+                spent.len(),
+                self.inputs.len()
+            )));
+        }
+        // The last height and time at which the transaction is still locked;
+        // -1 for no lock.
+        let mut min_height = -1i64;
+        let mut min_time = -1i64;
+        for (index, (coin_height, coin_time)) in spent.iter().enumerate() {
+            match self.relative_locktime(index) {
+                Some(RelativeLockTime::Blocks(blocks)) => {
+                    min_height = min_height.max(*coin_height as i64 + blocks as i64 - 1);
+                }
+                Some(lock @ RelativeLockTime::Time(_)) => {
+                    let seconds = lock.seconds().unwrap() as i64;
+                    min_time = min_time.max(*coin_time as i64 + seconds - 1);
+                }
+                None => {}
+            }
+        }
+        Ok(min_height < height as i64 && min_time < median_time_past as i64)
+    }
+}
+
+#[cfg(test)]
+mod locktime_tests {
+    use super::*;
+    use crate::tx::{OutPoint, TxOut};
+
+    fn tx(version: u32, sequences: &[u32], locktime: u32) -> Tx {
+        let inputs = sequences
+            .iter()
+            .map(|sequence| {
+                let mut tx_in = TxIn::new(OutPoint::null());
+                tx_in.sequence = *sequence;
+                tx_in
+            })
+            .collect();
+        Tx::new(
+            version,
+            inputs,
+            vec![TxOut::new(1000, vec![0x51])],
+            locktime,
+        )
+    }
+
+    #[test]
+    fn absolute() {
+        assert_eq!(
+            LockTime::from_consensus(499_999_999),
+            LockTime::Height(499_999_999)
+        );
+        assert_eq!(
+            LockTime::from_consensus(LOCKTIME_THRESHOLD),
+            LockTime::Time(LOCKTIME_THRESHOLD)
+        );
+
+        let locked = tx(1, &[0xfffffffe], 100);
+        assert_eq!(locked.absolute_locktime(), Some(LockTime::Height(100)));
+        // Mined in the block after the locktime, at the earliest.
+        assert!(!locked.is_final(100, 0));
+        assert!(locked.is_final(101, 0));
+        // Final inputs turn the locktime off.
+        assert_eq!(tx(1, &[SEQUENCE_FINAL], 100).absolute_locktime(), None);
+        assert!(tx(1, &[SEQUENCE_FINAL], 100).is_final(0, 0));
+        assert!(tx(1, &[0], 0).is_final(0, 0));
+
+        let locked = tx(1, &[0, SEQUENCE_FINAL], 1_600_000_000);
+        assert!(!locked.is_final(1_000_000, 1_600_000_000));
+        assert!(locked.is_final(0, 1_600_000_001));
+    }
+
+    #[test]
+    fn relative() {
+        assert_eq!(
+            RelativeLockTime::from_sequence(144),
+            Some(RelativeLockTime::Blocks(144))
+        );
+        assert_eq!(
+            RelativeLockTime::from_sequence(0x00400010),
+            Some(RelativeLockTime::Time(16))
+        );
+        // Bits outside the type flag and the low 16 mean nothing.
+        assert_eq!(
+            RelativeLockTime::from_sequence(0x7fbf0010),
+            Some(RelativeLockTime::Blocks(16))
+        );
+        assert_eq!(RelativeLockTime::from_sequence(SEQUENCE_FINAL), None);
+        assert_eq!(RelativeLockTime::from_sequence(0x80000010), None);
+
+        let day = RelativeLockTime::from_seconds(86400);
+        assert_eq!(day, RelativeLockTime::Time(168));
+        assert_eq!(day.seconds(), Some(86016));
+        assert_eq!(day.to_sequence(), 0x004000a8);
+        assert_eq!(RelativeLockTime::Blocks(6).seconds(), None);
+
+        assert_eq!(tx(1, &[144], 0).relative_locktime(0), None);
+        assert_eq!(
+            tx(2, &[144], 0).relative_locktime(0),
+            Some(RelativeLockTime::Blocks(144))
+        );
+    }
+
+    #[test]
+    fn sequence_locks() {
+        // Six blocks after an output confirmed at height 100, and 1024 seconds
+        // after the median time past of the block before it.
+        let sequences = [6, SEQUENCE_LOCKTIME_TYPE_FLAG | 2, SEQUENCE_FINAL];
+        let spent = [(100, 0), (100, 1_600_000_000), (0, 0)];
+        let locked = tx(2, &sequences, 0);
+        assert_eq!(
+            locked.check_sequence_locks(&spent, 105, 1_600_001_024),
+            Ok(false)
+        );
+        assert_eq!(
+            locked.check_sequence_locks(&spent, 106, 1_600_001_023),
+            Ok(false)
+        );
+        assert_eq!(
+            locked.check_sequence_locks(&spent, 106, 1_600_001_024),
+            Ok(true)
+        );
+        // Before version 2 the sequences lock nothing.
+        let unlocked = tx(1, &sequences, 0);
+        assert_eq!(unlocked.check_sequence_locks(&spent, 0, 0), Ok(true));
+        assert!(locked.check_sequence_locks(&spent[..2], 106, 0).is_err());
+    }
+}
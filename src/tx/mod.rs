@@ -0,0 +1,434 @@
+use std::fmt;
+
+use crate::error::Error;
+use crate::hash::hash256;
+use crate::helper::{
+    decode_hex, encode_var_bytes, encode_varint, hash_to_hex, read_array, read_bytes, read_u16_le,
+    read_u32_le, read_u64_le, read_u8, read_var_bytes, read_varint,
+};
+
+pub mod builder;
+pub mod coin_selection;
+pub mod locktime;
+pub mod sighash;
+pub mod sign;
+pub mod size;
+pub mod verify;
+
+// An input with this sequence opts out of the transaction's locktime.
+pub const SEQUENCE_FINAL: u32 = 0xffff_ffff;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct OutPoint {
+    // Internal byte order, i.e. hash256 output as is.
+    pub txid: [u8; 32],
+    pub vout: u32,
+}
+
+impl OutPoint {
+    pub fn new(txid: [u8; 32], vout: u32) -> Self {
+        OutPoint { txid, vout }
+    }
+
+    // What a coinbase input spends.
+    pub fn null() -> Self {
+        OutPoint {
+            txid: [0; 32],
+            vout: 0xffff_ffff,
+        }
+    }
+
+    pub fn is_null(&self) -> bool {
+        *self == OutPoint::null()
+    }
+
+    pub fn read(input: &mut &[u8]) -> Result<Self, Error> {
+        Ok(OutPoint {
+            txid: read_array(input)?,
+            vout: read_u32_le(input)?,
+        })
+    }
+
+    pub fn serialize(&self) -> Vec<u8> {
+        [&self.txid[..], &self.vout.to_le_bytes()].concat()
+    }
+}
+
+impl fmt::Display for OutPoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", hash_to_hex(&self.txid), self.vout)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TxIn {
+    pub previous_output: OutPoint,
+    pub script_sig: Vec<u8>,
+    pub sequence: u32,
+    // Serialized after the outputs, and only in the BIP144 format.
+    pub witness: Vec<Vec<u8>>,
+}
+
+impl TxIn {
+    pub fn new(previous_output: OutPoint) -> Self {
+        TxIn {
+            previous_output,
+            script_sig: Vec::new(),
+            sequence: SEQUENCE_FINAL,
+            witness: Vec::new(),
+        }
+    }
+
+    pub fn read(input: &mut &[u8]) -> Result<Self, Error> {
+        Ok(TxIn {
+            previous_output: OutPoint::read(input)?,
+            script_sig: read_var_bytes(input)?,
+            sequence: read_u32_le(input)?,
+            witness: Vec::new(),
+        })
+    }
+
+    // Everything but the witness.
+    pub fn serialize(&self) -> Vec<u8> {
+        let mut result = self.previous_output.serialize();
+        result.extend(encode_var_bytes(&self.script_sig));
+        result.extend(self.sequence.to_le_bytes());
+        result
+    }
+
+    fn read_witness(&mut self, input: &mut &[u8]) -> Result<(), Error> {
+        let count = read_varint(input)?;
+        self.witness = (0..count)
+            .map(|_| read_var_bytes(input))
+            .collect::<Result<_, _>>()?;
+        Ok(())
+    }
+
+    fn serialize_witness(&self) -> Vec<u8> {
+        let mut result = encode_varint(self.witness.len() as u64);
+        for item in &self.witness {
+            result.extend(encode_var_bytes(item));
+        }
+        result
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TxOut {
+    // In satoshis.
+    pub value: u64,
+    pub script_pubkey: Vec<u8>,
+}
+
+impl TxOut {
+    pub fn new(value: u64, script_pubkey: Vec<u8>) -> Self {
+        TxOut {
+            value,
+            script_pubkey,
+        }
+    }
+
+    pub fn read(input: &mut &[u8]) -> Result<Self, Error> {
+        Ok(TxOut {
+            value: read_u64_le(input)?,
+            script_pubkey: read_var_bytes(input)?,
+        })
+    }
+
+    pub fn serialize(&self) -> Vec<u8> {
+        let mut result = self.value.to_le_bytes().to_vec();
+        result.extend(encode_var_bytes(&self.script_pubkey));
+        result
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Tx {
+    pub version: u32,
+    pub inputs: Vec<TxIn>,
+    pub outputs: Vec<TxOut>,
+    pub locktime: u32,
+}
+
+impl Tx {
+    pub fn new(version: u32, inputs: Vec<TxIn>, outputs: Vec<TxOut>, locktime: u32) -> Self {
+        Tx {
+            version,
+            inputs,
+            outputs,
+            locktime,
+        }
+    }
+
+    // Parses a whole transaction; anything left over is an error.
+    pub fn parse(bytes: &[u8]) -> Result<Self, Error> {
+        let mut input = bytes;
+        let tx = Tx::read(&mut input)?;
+        if !input.is_empty() {
+            return Err(Error::TrailingBytes(input.len()));
+        }
+        Ok(tx)
+    }
+
+    pub fn from_hex(s: &str) -> Result<Self, Error> {
+        Tx::parse(&decode_hex(s)?)
+    }
+
+    // Reads either format. BIP144 puts a zero marker and a 0x01 flag where the
+    // legacy format has its input count, and the witnesses before the locktime.
+    pub fn read(input: &mut &[u8]) -> Result<Self, Error> {
+        let version = read_u32_le(input)?;
+        let segwit = input.first() == Some(&0x00);
+        if segwit {
+            let [_, flag] = read_array::<2>(input)?;
+            if flag != 0x01 {
+                return Err(Error::InvalidTransaction(format!(
+                    "unknown segwit flag {:#04x}",
+                    flag
+                )));
+            }
+        }
+        let (mut inputs, outputs) = read_inputs_and_outputs(input)?;
+        if segwit {
+            for tx_in in inputs.iter_mut() {
+                tx_in.read_witness(input)?;
+            }
+            if inputs.iter().all(|tx_in| tx_in.witness.is_empty()) {
+                return Err(Error::InvalidTransaction(
+                    "witness flag set but no witnesses present".to_string(),
+                ));
+            }
+        }
+        let locktime = read_u32_le(input)?;
+        Ok(Tx {
+            version,
+            inputs,
+            outputs,
+            locktime,
+        })
+    }
+
+    // Only the legacy format, as in PSBTs, where a transaction without inputs
+    // would otherwise be mistaken for a segwit marker.
+    pub(crate) fn parse_legacy(bytes: &[u8]) -> Result<Self, Error> {
+        let mut input = bytes;
+        let version = read_u32_le(&mut input)?;
+        let (inputs, outputs) = read_inputs_and_outputs(&mut input)?;
+        let locktime = read_u32_le(&mut input)?;
+        if !input.is_empty() {
+            return Err(Error::TrailingBytes(input.len()));
+        }
+        Ok(Tx {
+            version,
+            inputs,
+            outputs,
+            locktime,
+        })
+    }
+
+    pub fn has_witness(&self) -> bool {
+        self.inputs.iter().any(|tx_in| !tx_in.witness.is_empty())
+    }
+
+    // BIP144 format when any input carries a witness, legacy otherwise.
+    pub fn serialize(&self) -> Vec<u8> {
+        if !self.has_witness() {
+            return self.serialize_legacy();
+        }
+        let mut result = self.version.to_le_bytes().to_vec();
+        result.extend([0x00, 0x01]);
+        result.extend(self.serialize_inputs_and_outputs());
+        for tx_in in &self.inputs {
+            result.extend(tx_in.serialize_witness());
+        }
+        result.extend(self.locktime.to_le_bytes());
+        result
+    }
+
+    // The pre-segwit serialization the txid commits to.
+    pub fn serialize_legacy(&self) -> Vec<u8> {
+        let mut result = self.version.to_le_bytes().to_vec();
+        result.extend(self.serialize_inputs_and_outputs());
+        result.extend(self.locktime.to_le_bytes());
+        result
+    }
+
+    fn serialize_inputs_and_outputs(&self) -> Vec<u8> {
+        let mut result = encode_varint(self.inputs.len() as u64);
+        for tx_in in &self.inputs {
+            result.extend(tx_in.serialize());
+        }
+        result.extend(encode_varint(self.outputs.len() as u64));
+        for tx_out in &self.outputs {
+            result.extend(tx_out.serialize());
+        }
+        result
+    }
+
+    pub fn to_hex(&self) -> String {
+        hex::encode(self.serialize())
+    }
+
+    pub fn txid(&self) -> [u8; 32] {
+        hash256(&self.serialize_legacy())
+    }
+
+    // Equal to the txid when there is no witness.
+    pub fn wtxid(&self) -> [u8; 32] {
+        hash256(&self.serialize())
+    }
+
+    pub fn is_coinbase(&self) -> bool {
+        self.inputs.len() == 1 && self.inputs[0].previous_output.is_null()
+    }
+}
+
+fn read_inputs_and_outputs(input: &mut &[u8]) -> Result<(Vec<TxIn>, Vec<TxOut>), Error> {
+    let input_count = read_varint(input)?;
+    let inputs = (0..input_count)
+        .map(|_| TxIn::read(input))
+        .collect::<Result<Vec<_>, _>>()?;
+    let output_count = read_varint(input)?;
+    let outputs = (0..output_count)
+        .map(|_| TxOut::read(input))
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok((inputs, outputs))
+}
+
+// Splits the next opcode, and its data if it is a push, off the front of a script.
+pub(crate) fn read_op<'a>(script: &mut &'a [u8]) -> Result<(u8, &'a [u8]), Error> {
+    let opcode = read_u8(script)?;
+    let len = match opcode {
+        0x01..=0x4b => opcode as usize,
+        0x4c => read_u8(script)? as usize,
+        0x4d => read_u16_le(script)? as usize,
+        0x4e => read_u32_le(script)? as usize,
+        _ => 0,
+    };
+    Ok((opcode, read_bytes(script, len)?))
+}
+
+// Smallest push of `data`: the direct length opcodes up to 75 bytes, then PUSHDATA1/2/4.
+pub(crate) fn encode_push(data: &[u8]) -> Vec<u8> {
+    let mut result = match data.len() {
+        len @ 0..=0x4b => vec![len as u8],
+        len @ 0x4c..=0xff => vec![0x4c, len as u8],
+        len @ 0x100..=0xffff => [&[0x4d][..], &(len as u16).to_le_bytes()].concat(),
+        len => [&[0x4e][..], &(len as u32).to_le_bytes()].concat(),
+    };
+    result.extend_from_slice(data);
+    result
+}
+
+#[cfg(test)]
+mod tx_tests {
+    use super::*;
+    use crate::fixtures::BOOK_TX;
+
+    // Mainnet transactions with known txids and wtxids, as used by rust-bitcoin.
+    const LEGACY_TX: &str = "0100000001a15d57094aa7a21a28cb20b59aab8fc7d1149a3bdbcddba9c622e4f5f6a99ece010000006c493046022100f93bb0e7d8db7bd46e40132d1f8242026e045f03a0efe71bbb8e3f475e970d790221009337cd7f1f929f00cc6ff01f03729b069a7c21b59b1736ddfee5db5946c5da8c0121033b9b137ee87d5a812d6f506efdd37f0affa7ffc310711c06c7f3e097c9447c52ffffffff0100e1f505000000001976a9140389035a9225b3839e2bbf32d826a1e222031fd888ac00000000";
+    const SEGWIT_TX: &str = "02000000000101595895ea20179de87052b4046dfe6fd515860505d6511a9004cf12a1f93cac7c0100000000ffffffff01deb807000000000017a9140f3444e271620c736808aa7b33e370bd87cb5a078702483045022100fb60dad8df4af2841adc0346638c16d0b8035f5e3f3753b88db122e70c79f9370220756e6633b17fd2710e626347d28d60b0a2d6cbb41de51740644b9fb3ba7751040121028fa937ca8cba2197a37c007176ed8941055d3bcb8627d085e94553e62f057dcc00000000";
+
+    #[test]
+    fn chapter_5_parse() {
+        let tx = Tx::from_hex(BOOK_TX).unwrap();
+        assert_eq!(tx.version, 1);
+        assert_eq!(tx.inputs.len(), 1);
+        assert_eq!(
+            tx.inputs[0].previous_output.to_string(),
+            "d1c789a9c60383bf715f3f6ad9d14b91fe55f3deb369fe5d9280cb1a01793f81:0"
+        );
+        assert_eq!(hex::encode(&tx.inputs[0].script_sig), "483045022100ed81ff192e75a3fd2304004dcadb746fa5e24c5031ccfcf21320b0277457c98f02207a986d955c6e0cb35d446a89d3f56100f4d7f67801c31967743a9c8e10615bed01210349fc4e631e3624a545de3f89f5d8684c7b8138bd94bdd531d2e213bf016b278a");
+        assert_eq!(tx.inputs[0].sequence, 0xfffffffe);
+        assert_eq!(tx.outputs.len(), 2);
+        assert_eq!(tx.outputs[0].value, 32454049);
+        assert_eq!(
+            hex::encode(&tx.outputs[0].script_pubkey),
+            "76a914bc3b654dca7e56b04dca18f2566cdaf02e8d9ada88ac"
+        );
+        assert_eq!(tx.outputs[1].value, 10011545);
+        assert_eq!(tx.locktime, 410393);
+        assert_eq!(tx.to_hex(), BOOK_TX);
+        assert_eq!(
+            hash_to_hex(&tx.txid()),
+            "452c629d67e41baec3ac6f04fe744b4b9617f8f859c63b3002f8684e7a4fee03"
+        );
+        assert!(!tx.is_coinbase());
+    }
+
+    #[test]
+    fn legacy_txid_equals_wtxid() {
+        let tx = Tx::from_hex(LEGACY_TX).unwrap();
+        assert!(!tx.has_witness());
+        assert_eq!(tx.to_hex(), LEGACY_TX);
+        let txid = "a6eab3c14ab5272a58a5ba91505ba1a4b6d7a3a9fcbd187b6cd99a7b6d548cb7";
+        assert_eq!(hash_to_hex(&tx.txid()), txid);
+        assert_eq!(hash_to_hex(&tx.wtxid()), txid);
+    }
+
+    #[test]
+    fn segwit_round_trip_and_ids() {
+        let tx = Tx::from_hex(SEGWIT_TX).unwrap();
+        assert_eq!(tx.version, 2);
+        assert_eq!(
+            tx.inputs[0].previous_output.to_string(),
+            "7cac3cf9a112cf04901a51d605058615d56ffe6d04b45270e89d1720ea955859:1"
+        );
+        assert!(tx.inputs[0].script_sig.is_empty());
+        assert_eq!(tx.inputs[0].witness.len(), 2);
+        assert_eq!(tx.to_hex(), SEGWIT_TX);
+        assert_eq!(
+            hash_to_hex(&tx.txid()),
+            "f5864806e3565c34d1b41e716f72609d00b55ea5eac5b924c9719a842ef42206"
+        );
+        assert_eq!(
+            hash_to_hex(&tx.wtxid()),
+            "80b7d8a82d5d5bf92905b06f2014dd699e03837ca172e3a59d51426ebbe3e7f5"
+        );
+
+        // Dropping the witness gives the legacy serialization the txid commits to.
+        let mut stripped = tx.clone();
+        stripped.inputs[0].witness.clear();
+        assert_eq!(stripped.serialize(), tx.serialize_legacy());
+        assert_eq!(stripped.wtxid(), tx.txid());
+    }
+
+    #[test]
+    fn rejects_malformed_transactions() {
+        let bytes = hex::decode(SEGWIT_TX).unwrap();
+        for len in 0..bytes.len() {
+            assert!(Tx::parse(&bytes[..len]).is_err(), "prefix of {} bytes", len);
+        }
+        let mut trailing = bytes.clone();
+        trailing.push(0);
+        assert_eq!(Tx::parse(&trailing).unwrap_err(), Error::TrailingBytes(1));
+
+        let mut bad_flag = bytes.clone();
+        bad_flag[5] = 0x02;
+        assert!(matches!(
+            Tx::parse(&bad_flag).unwrap_err(),
+            Error::InvalidTransaction(_)
+        ));
+
+        // From rust-bitcoin: segwit marker and flag with every witness empty.
+        let superfluous = "0000fd000001021921212121212121212121f8b372b0239cc1dff600000000004f4f4f4f4f4f4f4f000000000000000000000000000000333732343133380d000000000000000000000000000000ff000000000009000dff000000000000000800000000000000000d";
+        assert_eq!(
+            Tx::from_hex(superfluous).unwrap_err(),
+            Error::InvalidTransaction("witness flag set but no witnesses present".to_string())
+        );
+    }
+
+    #[test]
+    fn coinbase_detection() {
+        let mut tx_in = TxIn::new(OutPoint::null());
+        tx_in.script_sig = vec![0x03, 0x01, 0x02, 0x03];
+        let tx = Tx::new(
+            1,
+            vec![tx_in],
+            vec![TxOut::new(50_0000_0000, vec![0x51])],
+            0,
+        );
+        assert!(tx.is_coinbase());
+        assert_eq!(Tx::parse(&tx.serialize()).unwrap(), tx);
+    }
+}
@@ -0,0 +1,761 @@
+use std::cell::OnceCell;
+
+use super::{read_op, Tx, TxIn, TxOut};
+use crate::error::Error;
+use crate::hash::{hash256, sha256, tagged_hash};
+use crate::helper::encode_var_bytes;
+
+// Taproot only: commits to everything like ALL, and lets the signature omit the type byte.
+pub const SIGHASH_DEFAULT: u32 = 0x00;
+pub const SIGHASH_ALL: u32 = 0x01;
+pub const SIGHASH_NONE: u32 = 0x02;
+pub const SIGHASH_SINGLE: u32 = 0x03;
+pub const SIGHASH_ANYONECANPAY: u32 = 0x80;
+
+const OP_CODESEPARATOR: u8 = 0xab;
+
+// Core's uint256 one, returned instead of a real hash when SIGHASH_SINGLE has
+// no matching output (or the input does not exist). Signatures over it can be
+// reused for any such input.
+const SIGHASH_SINGLE_BUG: [u8; 32] = {
+    let mut one = [0u8; 32];
+    one[0] = 1;
+    one
+};
+
+impl Tx {
+    // The pre-segwit signature hash of one input. Any type byte is accepted: the
+    // low five bits pick ALL/NONE/SINGLE (anything unknown acts like ALL), 0x80 is
+    // ANYONECANPAY, and the full value is committed to.
+    pub fn legacy_sighash(
+        &self,
+        input_index: usize,
+        script_code: &[u8],
+        sighash_type: u32,
+    ) -> [u8; 32] {
+        let base_type = sighash_type & 0x1f;
+        let anyone_can_pay = sighash_type & SIGHASH_ANYONECANPAY != 0;
+        if input_index >= self.inputs.len()
+            || (base_type == SIGHASH_SINGLE && input_index >= self.outputs.len())
+        {
+            return SIGHASH_SINGLE_BUG;
+        }
+        let script_code = remove_codeseparators(script_code);
+
+        // Only the input being signed keeps a script; with NONE and SINGLE the
+        // others' sequence numbers are zeroed so they can be updated freely.
+        let inputs = self
+            .inputs
+            .iter()
+            .enumerate()
+            .filter(|(index, _)| !anyone_can_pay || *index == input_index)
+            .map(|(index, tx_in)| {
+                let mut tx_in = TxIn {
+                    witness: Vec::new(),
+                    ..tx_in.clone()
+                };
+                if index == input_index {
+                    tx_in.script_sig = script_code.clone();
+                } else {
+                    tx_in.script_sig.clear();
+                    if base_type == SIGHASH_NONE || base_type == SIGHASH_SINGLE {
+                        tx_in.sequence = 0;
+                    }
+                }
+                tx_in
+            })
+            .collect();
+
+        // SINGLE keeps the outputs up to the signed one, blanking all but that.
+        let outputs = match base_type {
+            SIGHASH_NONE => Vec::new(),
+            SIGHASH_SINGLE => self.outputs[..=input_index]
+                .iter()
+                .enumerate()
+                .map(|(index, tx_out)| {
+                    if index == input_index {
+                        tx_out.clone()
+                    } else {
+                        TxOut::new(u64::MAX, Vec::new())
+                    }
+                })
+                .collect(),
+            _ => self.outputs.clone(),
+        };
+
+        let mut preimage = Tx::new(self.version, inputs, outputs, self.locktime).serialize_legacy();
+        preimage.extend(sighash_type.to_le_bytes());
+        hash256(&preimage)
+    }
+}
+
+// The script code BIP143 prescribes for P2WPKH: the equivalent P2PKH script.
+pub fn p2wpkh_script_code(pubkey_hash: &[u8; 20]) -> Vec<u8> {
+    [&[0x76, 0xa9, 0x14][..], pubkey_hash, &[0x88, 0xac]].concat()
+}
+
+// BIP143 hashes over the whole transaction, shared by every input's digest.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct SegwitV0Hashes {
+    prevouts: [u8; 32],
+    sequences: [u8; 32],
+    outputs: [u8; 32],
+}
+
+// BIP341 uses single SHA256 and also commits to every spent output. The
+// outputs hashed are kept, so later digests can be checked to use the same.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct TaprootHashes {
+    spent_outputs: Vec<TxOut>,
+    prevouts: [u8; 32],
+    amounts: [u8; 32],
+    script_pubkeys: [u8; 32],
+    sequences: [u8; 32],
+    outputs: [u8; 32],
+}
+
+// Computes signature hashes for the inputs of one transaction, hashing the
+// parts they have in common only once instead of once per input as the
+// legacy algorithm does.
+pub struct SighashCache<'a> {
+    tx: &'a Tx,
+    segwit_v0: OnceCell<SegwitV0Hashes>,
+    taproot: OnceCell<TaprootHashes>,
+}
+
+impl<'a> SighashCache<'a> {
+    pub fn new(tx: &'a Tx) -> Self {
+        SighashCache {
+            tx,
+            segwit_v0: OnceCell::new(),
+            taproot: OnceCell::new(),
+        }
+    }
+
+    fn segwit_v0_hashes(&self) -> &SegwitV0Hashes {
+        self.segwit_v0.get_or_init(|| {
+            let inputs = &self.tx.inputs;
+            SegwitV0Hashes {
+                prevouts: hash256(
+                    &inputs
+                        .iter()
+                        .flat_map(|tx_in| tx_in.previous_output.serialize())
+                        .collect::<Vec<u8>>(),
+                ),
+                sequences: hash256(
+                    &inputs
+                        .iter()
+                        .flat_map(|tx_in| tx_in.sequence.to_le_bytes())
+                        .collect::<Vec<u8>>(),
+                ),
+                outputs: hash256(
+                    &self
+                        .tx
+                        .outputs
+                        .iter()
+                        .flat_map(TxOut::serialize)
+                        .collect::<Vec<u8>>(),
+                ),
+            }
+        })
+    }
+
+    // BIP143 digest for a segwit v0 input spending `value` satoshis. The script
+    // code is the P2PKH equivalent for P2WPKH and the witness script for P2WSH.
+    pub fn segwit_v0(
+        &self,
+        input_index: usize,
+        script_code: &[u8],
+        value: u64,
+        sighash_type: u32,
+    ) -> Result<[u8; 32], Error> {
+        let tx_in = self
+            .tx
+            .inputs
+            .get(input_index)
+            .ok_or(Error::InvalidInputIndex(input_index))?;
+        let base_type = sighash_type & 0x1f;
+        let anyone_can_pay = sighash_type & SIGHASH_ANYONECANPAY != 0;
+        let single_or_none = base_type == SIGHASH_SINGLE || base_type == SIGHASH_NONE;
+
+        let hash_prevouts = if anyone_can_pay {
+            [0; 32]
+        } else {
+            self.segwit_v0_hashes().prevouts
+        };
+        let hash_sequence = if anyone_can_pay || single_or_none {
+            [0; 32]
+        } else {
+            self.segwit_v0_hashes().sequences
+        };
+        let hash_outputs = if !single_or_none {
+            self.segwit_v0_hashes().outputs
+        } else if base_type == SIGHASH_SINGLE && input_index < self.tx.outputs.len() {
+            hash256(&self.tx.outputs[input_index].serialize())
+        } else {
+            [0; 32]
+        };
+
+        let mut preimage = self.tx.version.to_le_bytes().to_vec();
+        preimage.extend(hash_prevouts);
+        preimage.extend(hash_sequence);
+        preimage.extend(tx_in.previous_output.serialize());
+        preimage.extend(encode_var_bytes(script_code));
+        preimage.extend(value.to_le_bytes());
+        preimage.extend(tx_in.sequence.to_le_bytes());
+        preimage.extend(hash_outputs);
+        preimage.extend(self.tx.locktime.to_le_bytes());
+        preimage.extend(sighash_type.to_le_bytes());
+        Ok(hash256(&preimage))
+    }
+
+    fn taproot_hashes(&self, prevouts: &[TxOut]) -> Result<&TaprootHashes, Error> {
+        let hashes = self.taproot.get_or_init(|| {
+            let inputs = &self.tx.inputs;
+            TaprootHashes {
+                spent_outputs: prevouts.to_vec(),
+                prevouts: sha256(
+                    &inputs
+                        .iter()
+                        .flat_map(|tx_in| tx_in.previous_output.serialize())
+                        .collect::<Vec<u8>>(),
+                ),
+                amounts: sha256(
+                    &prevouts
+                        .iter()
+                        .flat_map(|tx_out| tx_out.value.to_le_bytes())
+                        .collect::<Vec<u8>>(),
+                ),
+                script_pubkeys: sha256(
+                    &prevouts
+                        .iter()
+                        .flat_map(|tx_out| encode_var_bytes(&tx_out.script_pubkey))
+                        .collect::<Vec<u8>>(),
+                ),
+                sequences: sha256(
+                    &inputs
+                        .iter()
+                        .flat_map(|tx_in| tx_in.sequence.to_le_bytes())
+                        .collect::<Vec<u8>>(),
+                ),
+                outputs: sha256(
+                    &self
+                        .tx
+                        .outputs
+                        .iter()
+                        .flat_map(TxOut::serialize)
+                        .collect::<Vec<u8>>(),
+                ),
+            }
+        });
+        if hashes.spent_outputs != prevouts {
+            return Err(Error::InvalidTransaction(
+                "spent outputs differ from those already hashed".to_string(),
+            ));
+        }
+        Ok(hashes)
+    }
+
+    // BIP341 digest for a taproot input. `prevouts` are the outputs spent by every
+    // input, in order. `annex` is the last witness item when it starts with 0x50,
+    // and `leaf` the tapleaf hash and last executed OP_CODESEPARATOR position
+    // (0xffffffff for none) of a script path spend; key path spends pass None.
+    pub fn taproot(
+        &self,
+        input_index: usize,
+        prevouts: &[TxOut],
+        annex: Option<&[u8]>,
+        leaf: Option<(&[u8; 32], u32)>,
+        sighash_type: u32,
+    ) -> Result<[u8; 32], Error> {
+        let message = self.taproot_message(input_index, prevouts, annex, leaf, sighash_type)?;
+        Ok(tagged_hash("TapSighash", &message))
+    }
+
+    // SigMsg, prefixed with the 0x00 epoch byte.
+    fn taproot_message(
+        &self,
+        input_index: usize,
+        prevouts: &[TxOut],
+        annex: Option<&[u8]>,
+        leaf: Option<(&[u8; 32], u32)>,
+        sighash_type: u32,
+    ) -> Result<Vec<u8>, Error> {
+        let tx_in = self
+            .tx
+            .inputs
+            .get(input_index)
+            .ok_or(Error::InvalidInputIndex(input_index))?;
+        if prevouts.len() != self.tx.inputs.len() {
+            return Err(Error::InvalidTransaction(format!(
+                "{} spent outputs for {} inputs",
+                prevouts.len(),
+                self.tx.inputs.len()
+            )));
+        }
+        if !matches!(sighash_type, 0x00..=0x03 | 0x81..=0x83) {
+            return Err(Error::InvalidSighashType(sighash_type));
+        }
+        let base_type = sighash_type & 0x03;
+        let anyone_can_pay = sighash_type & SIGHASH_ANYONECANPAY != 0;
+        if base_type == SIGHASH_SINGLE && input_index >= self.tx.outputs.len() {
+            return Err(Error::InvalidSighashType(sighash_type));
+        }
+
+        let mut message = vec![0x00, sighash_type as u8];
+        message.extend(self.tx.version.to_le_bytes());
+        message.extend(self.tx.locktime.to_le_bytes());
+        if !anyone_can_pay {
+            let hashes = self.taproot_hashes(prevouts)?;
+            message.extend(hashes.prevouts);
+            message.extend(hashes.amounts);
+            message.extend(hashes.script_pubkeys);
+            message.extend(hashes.sequences);
+        }
+        if base_type != SIGHASH_NONE && base_type != SIGHASH_SINGLE {
+            message.extend(self.taproot_hashes(prevouts)?.outputs);
+        }
+
+        let ext_flag = if leaf.is_some() { 1 } else { 0 };
+        message.push(ext_flag * 2 + annex.is_some() as u8);
+        if anyone_can_pay {
+            let prevout = &prevouts[input_index];
+            message.extend(tx_in.previous_output.serialize());
+            message.extend(prevout.value.to_le_bytes());
+            message.extend(encode_var_bytes(&prevout.script_pubkey));
+            message.extend(tx_in.sequence.to_le_bytes());
+        } else {
+            message.extend((input_index as u32).to_le_bytes());
+        }
+        if let Some(annex) = annex {
+            message.extend(sha256(&encode_var_bytes(annex)));
+        }
+        if base_type == SIGHASH_SINGLE {
+            message.extend(sha256(&self.tx.outputs[input_index].serialize()));
+        }
+        if let Some((leaf_hash, codeseparator_position)) = leaf {
+            message.extend(leaf_hash);
+            // Key version 0, the only one defined by BIP342.
+            message.push(0x00);
+            message.extend(codeseparator_position.to_le_bytes());
+        }
+        Ok(message)
+    }
+}
+
+impl Tx {
+    // One-off BIP143 digest; use a SighashCache when signing several inputs.
+    pub fn segwit_v0_sighash(
+        &self,
+        input_index: usize,
+        script_code: &[u8],
+        value: u64,
+        sighash_type: u32,
+    ) -> Result<[u8; 32], Error> {
+        SighashCache::new(self).segwit_v0(input_index, script_code, value, sighash_type)
+    }
+
+    // One-off BIP341 key path digest; use a SighashCache when signing several inputs.
+    pub fn taproot_key_path_sighash(
+        &self,
+        input_index: usize,
+        prevouts: &[TxOut],
+        sighash_type: u32,
+    ) -> Result<[u8; 32], Error> {
+        SighashCache::new(self).taproot(input_index, prevouts, None, None, sighash_type)
+    }
+}
+
+// Core strips every OP_CODESEPARATOR from the script code. A push that runs off
+// the end stops the scan and the remainder is kept as is.
+fn remove_codeseparators(script: &[u8]) -> Vec<u8> {
+    let mut result = Vec::with_capacity(script.len());
+    let mut rest = script;
+    while !rest.is_empty() {
+        let before = rest;
+        match read_op(&mut rest) {
+            Ok((OP_CODESEPARATOR, _)) => {}
+            Ok(_) => result.extend_from_slice(&before[..before.len() - rest.len()]),
+            Err(_) => {
+                result.extend_from_slice(before);
+                break;
+            }
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod sighash_tests {
+    use super::*;
+    use crate::helper::hash_to_hex;
+    use crate::tx::OutPoint;
+
+    // Three inputs, two outputs, so SINGLE on the last input hits the bug.
+    fn three_input_tx() -> Tx {
+        let inputs = (1..=3u8)
+            .map(|i| {
+                let mut tx_in = TxIn::new(OutPoint::new([i; 32], i as u32));
+                tx_in.sequence = 0xffff_fffe - i as u32;
+                tx_in
+            })
+            .collect();
+        let outputs = (0..2u8)
+            .map(|i| {
+                TxOut::new(
+                    50_000 * (i as u64 + 1),
+                    [&[0x00, 0x14], &[i; 20][..]].concat(),
+                )
+            })
+            .collect();
+        Tx::new(1, inputs, outputs, 500_000)
+    }
+
+    // Computed with an independent Python port of Core's SignatureHash. The script
+    // code is a P2PKH script wrapped in OP_CODESEPARATORs, which must be removed.
+    #[test]
+    fn every_sighash_type() {
+        let tx = three_input_tx();
+        let script_code =
+            hex::decode("ab76a914ab3665129a0bed7a82797a417a9897d05859c77b88acab").unwrap();
+        let one = "0100000000000000000000000000000000000000000000000000000000000000";
+        let cases = [
+            (
+                SIGHASH_ALL,
+                [
+                    "75014beb87b5426ecb43ba3567ec1592a1c6a0d5b73dae6e50377e6907a5e797",
+                    "669c02bcf30dba8a1488946ccd9ea520e195e5485473e4850014b19087316600",
+                    "30a0eaf286fecb09fe78f860c85d1c696053d7ec147ea9ebbea5d0cd03d04da7",
+                ],
+            ),
+            (
+                SIGHASH_NONE,
+                [
+                    "c6114f4a5c0e58725a08c40096b68ca783b73920ab1b828183ac6136d0b39587",
+                    "faf340f98f7dbb0773d3bb0b7c834616e641320d0fcb78ecf92b3911b79de0ab",
+                    "07b70698c7beddf0104c5464cd2ef113694e14781b445196719a18bbcf4aef75",
+                ],
+            ),
+            (
+                SIGHASH_SINGLE,
+                [
+                    "6aaa7bea0ddcf0180f705625b8e4b0d93da0c701b37ffa135df925c176fe932e",
+                    "e87b920a14b7fcac244b8ea50716e1b7eec556ae445457fd84e83f3b3c011016",
+                    one,
+                ],
+            ),
+            (
+                SIGHASH_ALL | SIGHASH_ANYONECANPAY,
+                [
+                    "91e50c6a1da33f6d1ab6a84666b87f70e650513b545eb182f67ffe789bc65281",
+                    "bc3b162c30f42bfedc3beffde1d330b6de57bc57158f28360daadc8559f3a136",
+                    "4078b4c39919b1c8a3f34b40853b84317e74c0a09b86a917d19ef3e304eb1c63",
+                ],
+            ),
+            (
+                SIGHASH_NONE | SIGHASH_ANYONECANPAY,
+                [
+                    "18181cddfe8430e78361f05308b80af34a2283c33000e5f2f1145d16819c94b0",
+                    "b600a90e7626655aacce038f696584ad68a5214f1a07061c0df29205015b2e28",
+                    "029363f28f4650c4cea52c0d3f30d3f75852bc5ce39fa94596c054a17bc663eb",
+                ],
+            ),
+            (
+                SIGHASH_SINGLE | SIGHASH_ANYONECANPAY,
+                [
+                    "772e00fef37c36486c8eed49528850196e06b6023ecb18f772f9fcb75d953c4b",
+                    "7218ac793d457362250eec3c4b174d7082ed2e80df18fee1f0c967e243f31b3c",
+                    one,
+                ],
+            ),
+            // Not a defined type: signs like ALL but commits to the 0x04.
+            (
+                0x04,
+                [
+                    "3fd16d23b42206b078698b538dbe3b01d698cf372b143b2445822c6044263d5f",
+                    "c3a018d89aece68874d870134fa597090dcc678f54fc2bb7d0bf0e7e67d5dee9",
+                    "5076f3556656851eb73f6b951e6557f63d666130b84e176d387e10c2c67d665d",
+                ],
+            ),
+        ];
+        for (sighash_type, expected) in cases {
+            for (index, expected) in expected.iter().enumerate() {
+                assert_eq!(
+                    hex::encode(tx.legacy_sighash(index, &script_code, sighash_type)),
+                    *expected,
+                    "type {:#x} input {}",
+                    sighash_type,
+                    index
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn out_of_range_input_hashes_to_one() {
+        assert_eq!(
+            three_input_tx().legacy_sighash(3, &[], SIGHASH_ALL),
+            SIGHASH_SINGLE_BUG
+        );
+    }
+
+    #[test]
+    fn codeseparator_inside_push_is_kept() {
+        assert_eq!(
+            remove_codeseparators(&[0x01, 0xab, 0xab, 0x51]),
+            [0x01, 0xab, 0x51]
+        );
+        // A truncated push is copied through untouched.
+        assert_eq!(
+            remove_codeseparators(&[0xab, 0x4c, 0x05, 0xab]),
+            [0x4c, 0x05, 0xab]
+        );
+    }
+
+    // The first, second and fourth examples of BIP143 (native P2WPKH, P2SH-P2WPKH
+    // and a 6-of-6 P2SH-P2WSH), checking the cached hashes as well.
+    #[test]
+    fn bip143_examples() {
+        let cases = [
+            (
+                "0100000002fff7f7881a8099afa6940d42d1e7f6362bec38171ea3edf433541db4e4ad969f0000000000eeffffffef51e1b804cc89d182d279655c3aa89e815b1b309fe287d9b2b55d57b90ec68a0100000000ffffffff02202cb206000000001976a9148280b37df378db99f66f85c95a783a76ac7a6d5988ac9093510d000000001976a9143bde42dbee7e4dbe6a21b2d50ce2f0167faa815988ac11000000",
+                1,
+                p2wpkh_script_code(&hex::decode("1d0f172a0ecb48aee1be1f2687d2963ae33f71a1").unwrap().try_into().unwrap()),
+                600_000_000,
+                [
+                    "96b827c8483d4e9b96712b6713a7b68d6e8003a781feba36c31143470b4efd37",
+                    "52b0a642eea2fb7ae638c36f6252b6750293dbe574a806984b8e4d8548339a3b",
+                    "863ef3e1a92afbfdb97f31ad0fc7683ee943e9abcf2501590ff8f6551f47e5e5",
+                ],
+                "c37af31116d1b27caf68aae9e3ac82f1477929014d5b917657d0eb49478cb670",
+            ),
+            (
+                "0100000001db6b1b20aa0fd7b23880be2ecbd4a98130974cf4748fb66092ac4d3ceb1a54770100000000feffffff02b8b4eb0b000000001976a914a457b684d7f0d539a46a45bbc043f35b59d0d96388ac0008af2f000000001976a914fd270b1ee6abcaea97fea7ad0402e8bd8ad6d77c88ac92040000",
+                0,
+                p2wpkh_script_code(&hex::decode("79091972186c449eb1ded22b78e40d009bdf0089").unwrap().try_into().unwrap()),
+                1_000_000_000,
+                [
+                    "b0287b4a252ac05af83d2dcef00ba313af78a3e9c329afa216eb3aa2a7b4613a",
+                    "18606b350cd8bf565266bc352f0caddcf01e8fa789dd8a15386327cf8cabe198",
+                    "de984f44532e2173ca0d64314fcefe6d30da6f8cf27bafa706da61df8a226c83",
+                ],
+                "64f3b0f4dd2bb3aa1ce8566d220cc74dda9df97d8490cc81d89d735c92e59fb6",
+            ),
+            (
+                "010000000136641869ca081e70f394c6948e8af409e18b619df2ed74aa106c1ca29787b96e0100000000ffffffff0200e9a435000000001976a914389ffce9cd9ae88dcc0631e88a821ffdbe9bfe2688acc0832f05000000001976a9147480a33f950689af511e6e84c138dbbd3c3ee41588ac00000000",
+                0,
+                hex::decode("56210307b8ae49ac90a048e9b53357a2354b3334e9c8bee813ecb98e99a7e07e8c3ba32103b28f0c28bfab54554ae8c658ac5c3e0ce6e79ad336331f78c428dd43eea8449b21034b8113d703413d57761b8b9781957b8c0ac1dfe69f492580ca4195f50376ba4a21033400f6afecb833092a9a21cfdf1ed1376e58c5d1f47de74683123987e967a8f42103a6d48b1131e94ba04d9737d61acdaa1322008af9602b3b14862c07a1789aac162102d8b661b0b3302ee2f162b09e07a55ad5dfbe673a9f01d9f0c19617681024306b56ae").unwrap(),
+                987_654_321,
+                [
+                    "74afdc312af5183c4198a40ca3c1a275b485496dd3929bca388c4b5e31f7aaa0",
+                    "3bb13029ce7b1f559ef5e747fcac439f1455a2ec7c5f09b72290795e70665044",
+                    "bc4d309071414bed932f98832b27b4d76dad7e6c1346f487a8fdbb8eb90307cc",
+                ],
+                "185c0be5263dce5b4bb50a047973c1b6272bfbd0103a89444597dc40b248ee7c",
+            ),
+        ];
+        for (tx, input_index, script_code, value, [prevouts, sequences, outputs], sighash) in cases
+        {
+            let tx = Tx::from_hex(tx).unwrap();
+            let cache = SighashCache::new(&tx);
+            let digest = cache
+                .segwit_v0(input_index, &script_code, value, SIGHASH_ALL)
+                .unwrap();
+            assert_eq!(hex::encode(digest), sighash);
+            let hashes = cache.segwit_v0_hashes();
+            assert_eq!(hex::encode(hashes.prevouts), prevouts);
+            assert_eq!(hex::encode(hashes.sequences), sequences);
+            assert_eq!(hex::encode(hashes.outputs), outputs);
+        }
+    }
+
+    // Generated by Bitcoin Core's signrawtransactionwithwallet, as collected by
+    // rust-bitcoin. Core printed these byte reversed, like a txid.
+    #[test]
+    fn bip143_sighash_types() {
+        let tx = Tx::from_hex("0200000001cf309ee0839b8aaa3fbc84f8bd32e9c6357e99b49bf6a3af90308c68e762f1d70100000000feffffff0288528c61000000001600146e8d9e07c543a309dcdeba8b50a14a991a658c5be0aebb0000000000160014698d8419804a5d5994704d47947889ff7620c004db000000").unwrap();
+        let script_code =
+            hex::decode("76a91462744660c6b5133ddeaacbc57d2dc2d7b14d0b0688ac").unwrap();
+        let cache = SighashCache::new(&tx);
+        for (sighash_type, expected) in [
+            (
+                SIGHASH_ALL,
+                "0a1bc2758dbb5b3a56646f8cafbf63f410cc62b77a482f8b87552683300a7711",
+            ),
+            (
+                SIGHASH_NONE,
+                "3e275ac8b084f79f756dcd535bffb615cc94a685eefa244d9031eaf22e4cec12",
+            ),
+            (
+                SIGHASH_SINGLE,
+                "191a08165ffacc3ea55753b225f323c35fd00d9cc0268081a4a501921fc6ec14",
+            ),
+            (
+                SIGHASH_ALL | SIGHASH_ANYONECANPAY,
+                "4b6b612530f94470bbbdef18f57f2990d56b239f41b8728b9a49dc8121de4559",
+            ),
+            (
+                SIGHASH_NONE | SIGHASH_ANYONECANPAY,
+                "a7e916d3acd4bb97a21e6793828279aeab02162adf8099ea4f309af81f3d5adb",
+            ),
+            (
+                SIGHASH_SINGLE | SIGHASH_ANYONECANPAY,
+                "d9276e2a48648ddb53a4aaa58314fc2b8067c13013e1913ffb67e0988ce82c78",
+            ),
+        ] {
+            assert_eq!(
+                hash_to_hex(
+                    &cache
+                        .segwit_v0(0, &script_code, 1648888940, sighash_type)
+                        .unwrap()
+                ),
+                expected,
+                "type {:#x}",
+                sighash_type
+            );
+        }
+        assert_eq!(
+            cache
+                .segwit_v0(1, &script_code, 0, SIGHASH_ALL)
+                .unwrap_err(),
+            Error::InvalidInputIndex(1)
+        );
+    }
+
+    fn bip341_key_path_vectors() -> (Tx, Vec<TxOut>, serde_json::Value) {
+        let vectors: serde_json::Value =
+            serde_json::from_str(include_str!("../../test_data/bip341_tests.json")).unwrap();
+        let vectors = vectors["keyPathSpending"][0].clone();
+        let tx = Tx::from_hex(vectors["given"]["rawUnsignedTx"].as_str().unwrap()).unwrap();
+        let prevouts = vectors["given"]["utxosSpent"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|utxo| {
+                TxOut::new(
+                    utxo["amountSats"].as_u64().unwrap(),
+                    hex::decode(utxo["scriptPubKey"].as_str().unwrap()).unwrap(),
+                )
+            })
+            .collect();
+        (tx, prevouts, vectors)
+    }
+
+    // BIP341's own key path test vectors, from test_data/bip341_tests.json.
+    #[test]
+    fn bip341_key_path_sighashes() {
+        let (tx, prevouts, vectors) = bip341_key_path_vectors();
+        let cache = SighashCache::new(&tx);
+        for input in vectors["inputSpending"].as_array().unwrap() {
+            let index = input["given"]["txinIndex"].as_u64().unwrap() as usize;
+            let sighash_type = input["given"]["hashType"].as_u64().unwrap() as u32;
+            let intermediary = &input["intermediary"];
+            assert_eq!(
+                hex::encode(
+                    cache
+                        .taproot_message(index, &prevouts, None, None, sighash_type)
+                        .unwrap()
+                ),
+                intermediary["sigMsg"].as_str().unwrap(),
+                "input {}",
+                index
+            );
+            assert_eq!(
+                hex::encode(
+                    cache
+                        .taproot(index, &prevouts, None, None, sighash_type)
+                        .unwrap()
+                ),
+                intermediary["sigHash"].as_str().unwrap(),
+                "input {}",
+                index
+            );
+        }
+
+        let expected = &vectors["intermediary"];
+        let hashes = cache.taproot_hashes(&prevouts).unwrap();
+        for (hash, name) in [
+            (hashes.prevouts, "hashPrevouts"),
+            (hashes.amounts, "hashAmounts"),
+            (hashes.script_pubkeys, "hashScriptPubkeys"),
+            (hashes.sequences, "hashSequences"),
+            (hashes.outputs, "hashOutputs"),
+        ] {
+            assert_eq!(
+                hex::encode(hash),
+                expected[name].as_str().unwrap(),
+                "{}",
+                name
+            );
+        }
+    }
+
+    #[test]
+    fn taproot_rejects_bad_sighash_types() {
+        let (tx, prevouts, _) = bip341_key_path_vectors();
+        let cache = SighashCache::new(&tx);
+        for sighash_type in [0x04, 0x80, 0x84, 0x101] {
+            assert_eq!(
+                cache
+                    .taproot(0, &prevouts, None, None, sighash_type)
+                    .unwrap_err(),
+                Error::InvalidSighashType(sighash_type)
+            );
+        }
+        // The transaction has two outputs, so input 3 has nothing to sign with SINGLE.
+        assert_eq!(
+            cache
+                .taproot(3, &prevouts, None, None, SIGHASH_SINGLE)
+                .unwrap_err(),
+            Error::InvalidSighashType(SIGHASH_SINGLE)
+        );
+        assert!(cache
+            .taproot(0, &prevouts[1..], None, None, SIGHASH_DEFAULT)
+            .is_err());
+
+        // Once hashed, the spent outputs cannot change under the cache.
+        let mut other = prevouts.clone();
+        other[1].value += 1;
+        assert!(cache
+            .taproot(0, &prevouts, None, None, SIGHASH_DEFAULT)
+            .is_ok());
+        assert!(matches!(
+            cache.taproot(0, &other, None, None, SIGHASH_DEFAULT),
+            Err(Error::InvalidTransaction(_))
+        ));
+        assert!(SighashCache::new(&tx)
+            .taproot(0, &other, None, None, SIGHASH_DEFAULT)
+            .is_ok());
+    }
+
+    #[test]
+    fn taproot_commits_to_annex_and_leaf() {
+        let (tx, prevouts, _) = bip341_key_path_vectors();
+        let cache = SighashCache::new(&tx);
+        let key_path = cache
+            .taproot_message(0, &prevouts, None, None, SIGHASH_DEFAULT)
+            .unwrap();
+
+        let annex = [0x50, 0x01];
+        let with_annex = cache
+            .taproot_message(0, &prevouts, Some(&annex), None, SIGHASH_DEFAULT)
+            .unwrap();
+        let spend_type_at = key_path.len() - 5;
+        assert_eq!(with_annex[spend_type_at], 0x01);
+        assert_eq!(with_annex[..spend_type_at], key_path[..spend_type_at]);
+        assert_eq!(
+            with_annex[with_annex.len() - 32..],
+            sha256(&[0x02, 0x50, 0x01])
+        );
+
+        let leaf_hash = [0xab; 32];
+        let script_path = cache
+            .taproot_message(0, &prevouts, None, Some((&leaf_hash, 7)), SIGHASH_DEFAULT)
+            .unwrap();
+        assert_eq!(script_path[spend_type_at], 0x02);
+        assert_eq!(
+            script_path[key_path.len()..],
+            [&leaf_hash[..], &[0x00, 7, 0, 0, 0]].concat()
+        );
+    }
+}
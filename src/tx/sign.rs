@@ -0,0 +1,86 @@
+use num::BigUint;
+use rand::RngCore;
+
+use super::sighash::{p2wpkh_script_code, SighashCache, SIGHASH_ALL, SIGHASH_DEFAULT};
+use super::{encode_push, Tx, TxOut};
+use crate::error::Error;
+use crate::secp256k1::private_key::PrivateKey;
+use crate::taproot;
+
+impl Tx {
+    // Signs every input, looking up the key for each among `keys` by the output it
+    // spends. P2PKH and P2WPKH use SIGHASH_ALL with the compressed public key; P2TR
+    // is a key path spend with SIGHASH_DEFAULT, for a BIP86 output (no script tree).
+    // `prevouts` are the outputs spent by every input, in order.
+    pub fn sign_inputs(&mut self, prevouts: &[TxOut], keys: &[PrivateKey]) -> Result<(), Error> {
+        if prevouts.len() != self.inputs.len() {
+            return Err(Error::InvalidTransaction(format!(
+                "{} spent outputs for {} inputs",
+                prevouts.len(),
+                self.inputs.len()
+            )));
+        }
+        // None of the digests cover other inputs' script_sigs or witnesses, so all
+        // of them can be computed before any input is filled in.
+        let cache = SighashCache::new(self);
+        let mut signed = Vec::with_capacity(prevouts.len());
+        for (index, prevout) in prevouts.iter().enumerate() {
+            let missing_key = || Error::MissingKey(index);
+            match prevout.script_pubkey.as_slice() {
+                [0x76, 0xa9, 0x14, hash @ .., 0x88, 0xac] if hash.len() == 20 => {
+                    let key = keys
+                        .iter()
+                        .find(|key| key.point().hash160(true) == hash)
+                        .ok_or_else(missing_key)?;
+                    let sighash = self.legacy_sighash(index, &prevout.script_pubkey, SIGHASH_ALL);
+                    let mut script_sig = encode_push(&ecdsa_signature(key, &sighash, SIGHASH_ALL));
+                    script_sig.extend(encode_push(&key.point().sec(true)));
+                    signed.push((script_sig, Vec::new()));
+                }
+                [0x00, 0x14, hash @ ..] if hash.len() == 20 => {
+                    let key = keys
+                        .iter()
+                        .find(|key| key.point().hash160(true) == hash)
+                        .ok_or_else(missing_key)?;
+                    let script_code = p2wpkh_script_code(&key.point().hash160(true));
+                    let sighash =
+                        cache.segwit_v0(index, &script_code, prevout.value, SIGHASH_ALL)?;
+                    let witness = vec![
+                        ecdsa_signature(key, &sighash, SIGHASH_ALL),
+                        key.point().sec(true),
+                    ];
+                    signed.push((Vec::new(), witness));
+                }
+                [0x51, 0x20, output_key @ ..] if output_key.len() == 32 => {
+                    let key = keys
+                        .iter()
+                        .map(|key| taproot::tweak_private_key(key, None))
+                        .find(|key| key.point().x_only() == output_key)
+                        .ok_or_else(missing_key)?;
+                    let sighash = cache.taproot(index, prevouts, None, None, SIGHASH_DEFAULT)?;
+                    let mut aux_rand = [0u8; 32];
+                    rand::thread_rng().fill_bytes(&mut aux_rand);
+                    let signature = key.sign_schnorr(&sighash, &aux_rand);
+                    signed.push((Vec::new(), vec![signature.serialize().to_vec()]));
+                }
+                _ => {
+                    return Err(Error::UnsupportedScript(
+                        "only P2PKH, P2WPKH and P2TR outputs can be signed".to_string(),
+                    ))
+                }
+            }
+        }
+        for (tx_in, (script_sig, witness)) in self.inputs.iter_mut().zip(signed) {
+            tx_in.script_sig = script_sig;
+            tx_in.witness = witness;
+        }
+        Ok(())
+    }
+}
+
+// DER with the sighash type byte appended.
+pub(crate) fn ecdsa_signature(key: &PrivateKey, sighash: &[u8; 32], sighash_type: u32) -> Vec<u8> {
+    let mut signature = key.sign(&BigUint::from_bytes_be(sighash)).der();
+    signature.push(sighash_type as u8);
+    signature
+}
@@ -0,0 +1,259 @@
+use super::{Tx, TxOut};
+use crate::error::Error;
+
+const WITNESS_SCALE_FACTOR: usize = 4;
+
+impl Tx {
+    // Size without witness data, which is all pre-segwit nodes see.
+    pub fn base_size(&self) -> usize {
+        self.serialize_legacy().len()
+    }
+
+    // Full BIP144 size, witness included.
+    pub fn total_size(&self) -> usize {
+        self.serialize().len()
+    }
+
+    // BIP141: base bytes count four times, witness bytes once.
+    pub fn weight(&self) -> usize {
+        self.base_size() * (WITNESS_SCALE_FACTOR - 1) + self.total_size()
+    }
+
+    pub fn vsize(&self) -> usize {
+        self.weight().div_ceil(WITNESS_SCALE_FACTOR)
+    }
+
+    // `prev_outputs` are the outputs spent by every input, in order.
+    pub fn fee(&self, prev_outputs: &[TxOut]) -> Result<u64, Error> {
+        if prev_outputs.len() != self.inputs.len() {
+            return Err(Error::InvalidTransaction(format!(
+                "{} spent outputs for {} inputs",
+                prev_outputs.len(),
+                self.inputs.len()
+            )));
+        }
+        let input_value: u64 = prev_outputs.iter().map(|tx_out| tx_out.value).sum();
+        let output_value: u64 = self.outputs.iter().map(|tx_out| tx_out.value).sum();
+        input_value
+            .checked_sub(output_value)
+            .ok_or_else(|| Error::InvalidTransaction("outputs exceed inputs".to_string()))
+    }
+
+    // In sat/vB.
+    pub fn fee_rate(&self, prev_outputs: &[TxOut]) -> Result<f64, Error> {
+        Ok(self.fee(prev_outputs)? as f64 / self.vsize() as f64)
+    }
+
+    // Predicts the weight once every input is signed, spending outputs of the given
+    // types, so a fee can be settled on before signing. Existing script_sigs and
+    // witnesses are ignored.
+    pub fn estimated_weight(&self, input_types: &[InputType]) -> Result<usize, Error> {
+        if input_types.len() != self.inputs.len() {
+            return Err(Error::InvalidTransaction(format!(
+                "{} input types for {} inputs",
+                input_types.len(),
+                self.inputs.len()
+            )));
+        }
+        let mut unsigned = self.clone();
+        for tx_in in unsigned.inputs.iter_mut() {
+            tx_in.script_sig.clear();
+            tx_in.witness.clear();
+        }
+        let script_sig_size: usize = input_types.iter().map(|t| t.script_sig_size()).sum();
+        let base_size = unsigned.base_size() + script_sig_size;
+
+        let mut witness_size = 0;
+        if input_types.iter().any(|t| t.witness_size() > 0) {
+            // Marker and flag, then every input's witness, empty ones being a zero count.
+            witness_size = 2 + input_types
+                .iter()
+                .map(|t| t.witness_size().max(1))
+                .sum::<usize>();
+        }
+        Ok(base_size * WITNESS_SCALE_FACTOR + witness_size)
+    }
+
+    pub fn estimated_vsize(&self, input_types: &[InputType]) -> Result<usize, Error> {
+        Ok(self
+            .estimated_weight(input_types)?
+            .div_ceil(WITNESS_SCALE_FACTOR))
+    }
+}
+
+// What an input spends, which decides how large it becomes once signed. Sizes
+// assume 72 byte DER signatures (sighash byte included) and 33 byte SEC keys.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InputType {
+    P2pkh,
+    P2shP2wpkh,
+    P2wpkh,
+    // Key path spend with SIGHASH_DEFAULT.
+    P2tr,
+}
+
+impl InputType {
+    // Nested P2WPKH can't be told from any other P2SH output, so None for P2SH.
+    pub fn from_script_pubkey(script_pubkey: &[u8]) -> Option<Self> {
+        match script_pubkey {
+            [0x76, 0xa9, 0x14, hash @ .., 0x88, 0xac] if hash.len() == 20 => Some(InputType::P2pkh),
+            [0x00, 0x14, hash @ ..] if hash.len() == 20 => Some(InputType::P2wpkh),
+            [0x51, 0x20, key @ ..] if key.len() == 32 => Some(InputType::P2tr),
+            _ => None,
+        }
+    }
+
+    // Weight one signed input of this type adds, not counting the segwit marker
+    // and flag.
+    pub fn input_weight(self) -> usize {
+        // Outpoint, script_sig length, sequence.
+        let base_size = 32 + 4 + 1 + self.script_sig_size() + 4;
+        base_size * WITNESS_SCALE_FACTOR + self.witness_size()
+    }
+
+    // Size of an output paying to this type, e.g. change.
+    pub fn output_size(self) -> usize {
+        let script_pubkey_size = match self {
+            InputType::P2pkh => 25,
+            InputType::P2shP2wpkh => 23,
+            InputType::P2wpkh => 22,
+            InputType::P2tr => 34,
+        };
+        8 + 1 + script_pubkey_size
+    }
+
+    // Bytes the script_sig adds over an empty one; all stay below the 253 byte
+    // varint boundary, so the length prefix does not grow.
+    fn script_sig_size(self) -> usize {
+        match self {
+            // <signature> <pubkey>
+            InputType::P2pkh => 1 + 72 + 1 + 33,
+            // <0 <20 byte hash>>
+            InputType::P2shP2wpkh => 1 + 22,
+            InputType::P2wpkh | InputType::P2tr => 0,
+        }
+    }
+
+    // Serialized witness including its item count, 0 if there is none.
+    fn witness_size(self) -> usize {
+        match self {
+            InputType::P2pkh => 0,
+            InputType::P2shP2wpkh | InputType::P2wpkh => 1 + (1 + 72) + (1 + 33),
+            InputType::P2tr => 1 + (1 + 64),
+        }
+    }
+}
+
+#[cfg(test)]
+mod size_tests {
+    use super::*;
+    use crate::tx::{OutPoint, TxIn};
+
+    // From rust-bitcoin's transaction tests.
+    #[test]
+    fn weights_of_known_transactions() {
+        let segwit = Tx::from_hex("02000000000101595895ea20179de87052b4046dfe6fd515860505d6511a9004cf12a1f93cac7c0100000000ffffffff01deb807000000000017a9140f3444e271620c736808aa7b33e370bd87cb5a078702483045022100fb60dad8df4af2841adc0346638c16d0b8035f5e3f3753b88db122e70c79f9370220756e6633b17fd2710e626347d28d60b0a2d6cbb41de51740644b9fb3ba7751040121028fa937ca8cba2197a37c007176ed8941055d3bcb8627d085e94553e62f057dcc00000000").unwrap();
+        assert_eq!(segwit.weight(), 442);
+        assert_eq!(segwit.vsize(), 111);
+        assert_eq!(segwit.total_size(), 193);
+        assert_eq!(segwit.base_size(), (442 - 193) / 3);
+
+        let coinbase = Tx::from_hex("010000000001010000000000000000000000000000000000000000000000000000000000000000ffffffff3603da1b0e00045503bd5704c7dd8a0d0ced13bb5785010800000000000a636b706f6f6c122f4e696e6a61506f6f6c2f5345475749542fffffffff02b4e5a212000000001976a914876fbb82ec05caa6af7a3b5e5a983aae6c6cc6d688ac0000000000000000266a24aa21a9edf91c46b49eb8a29089980f02ee6b57e7d63d33b18b4fddac2bcd7db2a39837040120000000000000000000000000000000000000000000000000000000000000000000000000").unwrap();
+        assert_eq!(coinbase.weight(), 780);
+
+        let legacy = Tx::from_hex("0100000001a15d57094aa7a21a28cb20b59aab8fc7d1149a3bdbcddba9c622e4f5f6a99ece010000006c493046022100f93bb0e7d8db7bd46e40132d1f8242026e045f03a0efe71bbb8e3f475e970d790221009337cd7f1f929f00cc6ff01f03729b069a7c21b59b1736ddfee5db5946c5da8c0121033b9b137ee87d5a812d6f506efdd37f0affa7ffc310711c06c7f3e097c9447c52ffffffff0100e1f505000000001976a9140389035a9225b3839e2bbf32d826a1e222031fd888ac00000000").unwrap();
+        assert_eq!(legacy.weight(), legacy.total_size() * 4);
+        assert_eq!(legacy.vsize(), legacy.base_size());
+    }
+
+    #[test]
+    fn fee_and_fee_rate() {
+        let tx = Tx::from_hex("02000000000101595895ea20179de87052b4046dfe6fd515860505d6511a9004cf12a1f93cac7c0100000000ffffffff01deb807000000000017a9140f3444e271620c736808aa7b33e370bd87cb5a078702483045022100fb60dad8df4af2841adc0346638c16d0b8035f5e3f3753b88db122e70c79f9370220756e6633b17fd2710e626347d28d60b0a2d6cbb41de51740644b9fb3ba7751040121028fa937ca8cba2197a37c007176ed8941055d3bcb8627d085e94553e62f057dcc00000000").unwrap();
+        let value = tx.outputs[0].value;
+        let prev_outputs = [TxOut::new(value + 1110, Vec::new())];
+        assert_eq!(tx.fee(&prev_outputs).unwrap(), 1110);
+        assert_eq!(tx.fee_rate(&prev_outputs).unwrap(), 10.0);
+        assert!(tx.fee(&[TxOut::new(value - 1, Vec::new())]).is_err());
+        assert!(tx.fee(&[]).is_err());
+    }
+
+    fn unsigned(inputs: usize, output_scripts: &[&str]) -> Tx {
+        let inputs = (0..inputs)
+            .map(|vout| TxIn::new(OutPoint::new([0x22; 32], vout as u32)))
+            .collect();
+        let outputs = output_scripts
+            .iter()
+            .map(|script| TxOut::new(1000, hex::decode(script).unwrap()))
+            .collect();
+        Tx::new(2, inputs, outputs, 0)
+    }
+
+    // The well known sizes for single key spends.
+    #[test]
+    fn estimates_for_common_shapes() {
+        let p2pkh = "76a914000000000000000000000000000000000000000088ac";
+        let p2wpkh = "00140000000000000000000000000000000000000000";
+        let p2tr = "51200000000000000000000000000000000000000000000000000000000000000000";
+        for (input_types, outputs, vsize) in [
+            (vec![InputType::P2pkh], vec![p2pkh, p2pkh], 226),
+            (vec![InputType::P2wpkh], vec![p2wpkh], 110),
+            (
+                vec![InputType::P2wpkh, InputType::P2wpkh],
+                vec![p2wpkh, p2wpkh],
+                209,
+            ),
+            (vec![InputType::P2shP2wpkh], vec![p2wpkh], 133),
+            (vec![InputType::P2tr], vec![p2tr], 111),
+            // A legacy input next to a segwit one still needs its empty witness.
+            (vec![InputType::P2pkh, InputType::P2tr], vec![p2tr], 260),
+        ] {
+            let tx = unsigned(input_types.len(), &outputs);
+            assert_eq!(
+                tx.estimated_vsize(&input_types).unwrap(),
+                vsize,
+                "{:?}",
+                input_types
+            );
+        }
+        assert!(unsigned(2, &[p2tr])
+            .estimated_weight(&[InputType::P2tr])
+            .is_err());
+    }
+
+    #[test]
+    fn input_and_output_sizes() {
+        for (input_type, input_weight, output_size) in [
+            (InputType::P2pkh, 592, 34),
+            (InputType::P2shP2wpkh, 364, 32),
+            (InputType::P2wpkh, 272, 31),
+            (InputType::P2tr, 230, 43),
+        ] {
+            assert_eq!(input_type.input_weight(), input_weight);
+            assert_eq!(input_type.output_size(), output_size);
+        }
+    }
+
+    #[test]
+    fn input_types_from_script_pubkeys() {
+        for (script_pubkey, input_type) in [
+            (
+                "76a91462e907b15cbf27d5425399ebf6f0fb50ebb88f1888ac",
+                Some(InputType::P2pkh),
+            ),
+            (
+                "0014751e76e8199196d454941c45d1b3a323f1433bd6",
+                Some(InputType::P2wpkh),
+            ),
+            (
+                "5120a60869f0dbcf1dc659c9cecbaf8050135ea9e8cdc487053f1dc6880949dc684c",
+                Some(InputType::P2tr),
+            ),
+            ("a91474d691da1574e6b3c192ecfb52cc8984ee7b6c5687", None),
+        ] {
+            assert_eq!(
+                InputType::from_script_pubkey(&hex::decode(script_pubkey).unwrap()),
+                input_type
+            );
+        }
+    }
+}
@@ -0,0 +1,434 @@
+use num::BigUint;
+
+use super::sighash::{p2wpkh_script_code, SighashCache, SIGHASH_DEFAULT};
+use super::{read_op, Tx, TxOut};
+use crate::error::Error;
+use crate::hash::{hash160, sha256};
+use crate::secp256k1::point::Secp256k1Point;
+use crate::secp256k1::schnorr::SchnorrSignature;
+use crate::secp256k1::signature::Signature;
+
+impl Tx {
+    // Checks a legacy P2PKH or P2PK spend: pulls the DER signature (with its sighash
+    // byte) and SEC pubkey out of the script_sig and verifies them against the
+    // legacy sighash. Ok(false) means the input is well formed but does not verify.
+    pub fn verify_input(
+        &self,
+        input_index: usize,
+        prev_script_pubkey: &[u8],
+    ) -> Result<bool, Error> {
+        let tx_in = self
+            .inputs
+            .get(input_index)
+            .ok_or(Error::InvalidInputIndex(input_index))?;
+        let pushes = pushes(&tx_in.script_sig)?;
+        let (signature, sec) = match (prev_script_pubkey, pushes.as_slice()) {
+            ([0x76, 0xa9, 0x14, hash @ .., 0x88, 0xac], [signature, sec]) if hash.len() == 20 => {
+                if hash160(sec) != hash {
+                    return Ok(false);
+                }
+                (*signature, *sec)
+            }
+            ([len, sec @ .., 0xac], [signature])
+                if (*len == 33 || *len == 65) && sec.len() == *len as usize =>
+            {
+                (*signature, sec)
+            }
+            _ => {
+                return Err(Error::UnsupportedScript(
+                    "only P2PKH and P2PK spends can be verified".to_string(),
+                ))
+            }
+        };
+        check_signature(signature, sec, |sighash_type| {
+            Ok(self.legacy_sighash(input_index, prev_script_pubkey, sighash_type))
+        })
+    }
+
+    // Checks a segwit v0 spend of `prev_output`, native or nested in P2SH: P2WPKH,
+    // or P2WSH whose witness script is a single <pubkey> OP_CHECKSIG.
+    pub fn verify_segwit_v0_input(
+        &self,
+        input_index: usize,
+        prev_output: &TxOut,
+    ) -> Result<bool, Error> {
+        let tx_in = self
+            .inputs
+            .get(input_index)
+            .ok_or(Error::InvalidInputIndex(input_index))?;
+        let pushes = pushes(&tx_in.script_sig)?;
+        // P2SH-wrapped programs reveal the program as the only script_sig push.
+        let program = match (prev_output.script_pubkey.as_slice(), pushes.as_slice()) {
+            ([0xa9, 0x14, hash @ .., 0x87], [redeem_script]) if hash.len() == 20 => {
+                if hash160(redeem_script) != hash {
+                    return Ok(false);
+                }
+                *redeem_script
+            }
+            (script_pubkey, []) => script_pubkey,
+            _ => return Ok(false),
+        };
+
+        let witness = &tx_in.witness;
+        let (signature, sec, script_code) = match (program, witness.as_slice()) {
+            ([0x00, 0x14, hash @ ..], [signature, sec]) if hash.len() == 20 => {
+                if hash160(sec) != hash {
+                    return Ok(false);
+                }
+                (
+                    signature,
+                    sec.as_slice(),
+                    p2wpkh_script_code(&hash.try_into().unwrap()),
+                )
+            }
+            ([0x00, 0x20, hash @ ..], [signature, witness_script]) if hash.len() == 32 => {
+                if sha256(witness_script) != hash {
+                    return Ok(false);
+                }
+                match witness_script.as_slice() {
+                    [len, sec @ .., 0xac]
+                        if (*len == 33 || *len == 65) && sec.len() == *len as usize =>
+                    {
+                        (signature, sec, witness_script.clone())
+                    }
+                    _ => {
+                        return Err(Error::UnsupportedScript(
+                            "only single key witness scripts can be verified".to_string(),
+                        ))
+                    }
+                }
+            }
+            _ => {
+                return Err(Error::UnsupportedScript(
+                    "only P2WPKH and P2WSH spends can be verified".to_string(),
+                ))
+            }
+        };
+        let cache = SighashCache::new(self);
+        check_signature(signature, sec, |sighash_type| {
+            cache.segwit_v0(input_index, &script_code, prev_output.value, sighash_type)
+        })
+    }
+
+    // Checks a taproot key path spend. BIP341 signs every spent output, so
+    // `prevouts` must hold the outputs spent by all inputs, in order.
+    pub fn verify_taproot_key_path(
+        &self,
+        input_index: usize,
+        prevouts: &[TxOut],
+    ) -> Result<bool, Error> {
+        let tx_in = self
+            .inputs
+            .get(input_index)
+            .ok_or(Error::InvalidInputIndex(input_index))?;
+        let prevout = prevouts
+            .get(input_index)
+            .ok_or(Error::InvalidInputIndex(input_index))?;
+        let output_key = match prevout.script_pubkey.as_slice() {
+            [0x51, 0x20, key @ ..] if key.len() == 32 => Secp256k1Point::lift_x(key)?,
+            _ => {
+                return Err(Error::UnsupportedScript(
+                    "output is not a taproot output".to_string(),
+                ))
+            }
+        };
+
+        let mut witness = tx_in.witness.as_slice();
+        let annex = match witness {
+            [rest @ .., last] if !rest.is_empty() && last.first() == Some(&0x50) => {
+                witness = rest;
+                Some(last.as_slice())
+            }
+            _ => None,
+        };
+        let signature = match witness {
+            [signature] => signature,
+            _ => {
+                return Err(Error::UnsupportedScript(
+                    "only key path spends can be verified".to_string(),
+                ))
+            }
+        };
+        // A 65th byte carries the sighash type; it may not restate the default.
+        let (signature, sighash_type) = match signature.len() {
+            64 => (signature.as_slice(), SIGHASH_DEFAULT),
+            65 if signature[64] != SIGHASH_DEFAULT as u8 => {
+                (&signature[..64], signature[64] as u32)
+            }
+            65 => {
+                return Err(Error::InvalidSignature(
+                    "explicit SIGHASH_DEFAULT type byte".to_string(),
+                ))
+            }
+            len => return Err(Error::InvalidLength(len)),
+        };
+        let signature = SchnorrSignature::parse(signature)?;
+        let sighash =
+            SighashCache::new(self).taproot(input_index, prevouts, annex, None, sighash_type)?;
+        Ok(output_key.verify_schnorr(&sighash, &signature))
+    }
+}
+
+// `signature` is DER with the sighash type byte appended.
+fn check_signature(
+    signature: &[u8],
+    sec: &[u8],
+    sighash: impl FnOnce(u32) -> Result<[u8; 32], Error>,
+) -> Result<bool, Error> {
+    let (sighash_type, der) = signature
+        .split_last()
+        .ok_or_else(|| Error::InvalidSignature("empty signature".to_string()))?;
+    let signature = Signature::parse_der(der)?;
+    let point = Secp256k1Point::parse_sec(sec)?;
+    let z = sighash(*sighash_type as u32)?;
+    Ok(point.verify(BigUint::from_bytes_be(&z), signature))
+}
+
+fn pushes(script_sig: &[u8]) -> Result<Vec<&[u8]>, Error> {
+    let mut rest = script_sig;
+    let mut pushes = Vec::new();
+    while !rest.is_empty() {
+        match read_op(&mut rest)? {
+            (opcode, data) if opcode <= 0x4e => pushes.push(data),
+            _ => {
+                return Err(Error::UnsupportedScript(
+                    "script_sig is not push only".to_string(),
+                ))
+            }
+        }
+    }
+    Ok(pushes)
+}
+
+#[cfg(test)]
+mod verify_tests {
+    use super::*;
+    use crate::fixtures::{BOOK_PREV_SCRIPT_PUBKEY, BOOK_TX};
+    use crate::helper::int_to_32_bytes;
+    use crate::secp256k1::private_key::PrivateKey;
+    use crate::taproot;
+    use crate::tx::sighash::SIGHASH_ALL;
+
+    // Input 1 is signed with SIGHASH_NONE | SIGHASH_ANYONECANPAY.
+    const NONE_ANYONECANPAY_TX: &str = "010000000301010101010101010101010101010101010101010101010101010101010101010100000000fdffffff0202020202020202020202020202020202020202020202020202020202020202020000006b483045022100d47644539acec3da5e3ecf5fe8863c628a9c97e8b71e9ea9167a6f4f83c03c3202204697c1a62f6872ae3d75ccc94064adeff54db016f3e793460ffb48e69c1116dd822103d90cd625ee87dd38656dd95cf79f65f60f7273b67d3096e68bd81e4f5342691ffcffffff03030303030303030303030303030303030303030303030303030303030303030300000000fbffffff0250c30000000000001600140000000000000000000000000000000000000000a086010000000000160014010101010101010101010101010101010101010120a10700";
+    const NONE_ANYONECANPAY_PREV_SCRIPT_PUBKEY: &str =
+        "76a914ab3665129a0bed7a82797a417a9897d05859c77b88ac";
+
+    #[test]
+    fn chapter_7_sighash_and_verify() {
+        let tx = Tx::from_hex(BOOK_TX).unwrap();
+        let prev_script_pubkey = hex::decode(BOOK_PREV_SCRIPT_PUBKEY).unwrap();
+        assert_eq!(
+            hex::encode(tx.legacy_sighash(0, &prev_script_pubkey, SIGHASH_ALL)),
+            "27e0c5994dec7824e56dec6b2fcb342eb7cdb0d0957c2fce9882f715e85d81a6"
+        );
+        assert!(tx.verify_input(0, &prev_script_pubkey).unwrap());
+
+        let mut changed = tx.clone();
+        changed.outputs[0].value += 1;
+        assert!(!changed.verify_input(0, &prev_script_pubkey).unwrap());
+    }
+
+    #[test]
+    fn none_anyonecanpay_ignores_outputs_and_other_inputs() {
+        let tx = Tx::from_hex(NONE_ANYONECANPAY_TX).unwrap();
+        let prev_script_pubkey = hex::decode(NONE_ANYONECANPAY_PREV_SCRIPT_PUBKEY).unwrap();
+        assert!(tx.verify_input(1, &prev_script_pubkey).unwrap());
+
+        let mut changed = tx.clone();
+        changed.outputs.pop();
+        changed.inputs.remove(0);
+        changed.inputs[1].sequence = 0;
+        assert!(changed.verify_input(0, &prev_script_pubkey).unwrap());
+
+        // The signed input itself is still committed to.
+        let mut changed = tx.clone();
+        changed.inputs[1].sequence = 0;
+        assert!(!changed.verify_input(1, &prev_script_pubkey).unwrap());
+    }
+
+    #[test]
+    fn rejects_what_it_cannot_check() {
+        let tx = Tx::from_hex(BOOK_TX).unwrap();
+        let prev_script_pubkey = hex::decode(BOOK_PREV_SCRIPT_PUBKEY).unwrap();
+        assert_eq!(
+            tx.verify_input(1, &prev_script_pubkey).unwrap_err(),
+            Error::InvalidInputIndex(1)
+        );
+        // A P2PKH output for some other key.
+        let mut other_key = prev_script_pubkey.clone();
+        other_key[3] ^= 1;
+        assert!(!tx.verify_input(0, &other_key).unwrap());
+        // P2SH is left to the script interpreter.
+        let p2sh = hex::decode("a914a802fc56c704ce87c42d7c92eb75e7896bdc41ae87").unwrap();
+        assert!(matches!(
+            tx.verify_input(0, &p2sh).unwrap_err(),
+            Error::UnsupportedScript(_)
+        ));
+    }
+
+    // BIP143's native P2WPKH example: input 0 is a legacy P2PK spend, input 1 P2WPKH.
+    const BIP143_P2WPKH_TX: &str = "01000000000102fff7f7881a8099afa6940d42d1e7f6362bec38171ea3edf433541db4e4ad969f00000000494830450221008b9d1dc26ba6a9cb62127b02742fa9d754cd3bebf337f7a55d114c8e5cdd30be022040529b194ba3f9281a99f2b1c0a19c0489bc22ede944ccf4ecbab4cc618ef3ed01eeffffffef51e1b804cc89d182d279655c3aa89e815b1b309fe287d9b2b55d57b90ec68a0100000000ffffffff02202cb206000000001976a9148280b37df378db99f66f85c95a783a76ac7a6d5988ac9093510d000000001976a9143bde42dbee7e4dbe6a21b2d50ce2f0167faa815988ac000247304402203609e17b84f6a7d30c80bfa610b5b4542f32a8a0d5447a12fb1366d7f01cc44a0220573a954c4518331561406f90300e8f3358f51928d43c212a8caed02de67eebee0121025476c2e83188368da1ff3e292e7acafcdb3566bb0ad253f62fc70f07aeee635711000000";
+
+    // BIP143's P2SH-P2WPKH example.
+    const BIP143_P2SH_P2WPKH_TX: &str = "01000000000101db6b1b20aa0fd7b23880be2ecbd4a98130974cf4748fb66092ac4d3ceb1a5477010000001716001479091972186c449eb1ded22b78e40d009bdf0089feffffff02b8b4eb0b000000001976a914a457b684d7f0d539a46a45bbc043f35b59d0d96388ac0008af2f000000001976a914fd270b1ee6abcaea97fea7ad0402e8bd8ad6d77c88ac02473044022047ac8e878352d3ebbde1c94ce3a10d057c24175747116f8288e5d794d12d482f0220217f36a485cae903c713331d877c1f64677e3622ad4010726870540656fe9dcb012103ad1d8e89212f0b92c74d23bb710c00662ad1470198ac48c43f7d6f93a2a2687392040000";
+
+    fn prev_output(value: u64, script_pubkey: &str) -> TxOut {
+        TxOut::new(value, hex::decode(script_pubkey).unwrap())
+    }
+
+    #[test]
+    fn bip143_native_p2wpkh() {
+        let tx = Tx::from_hex(BIP143_P2WPKH_TX).unwrap();
+        let p2pk =
+            hex::decode("2103c9f4836b9a4f77fc0d81f7bcb01b7f1b35916864b9476c241ce9fc198bd25432ac")
+                .unwrap();
+        assert!(tx.verify_input(0, &p2pk).unwrap());
+
+        let p2wpkh = "00141d0f172a0ecb48aee1be1f2687d2963ae33f71a1";
+        assert!(tx
+            .verify_segwit_v0_input(1, &prev_output(600_000_000, p2wpkh))
+            .unwrap());
+        // The amount is committed to.
+        assert!(!tx
+            .verify_segwit_v0_input(1, &prev_output(600_000_001, p2wpkh))
+            .unwrap());
+    }
+
+    #[test]
+    fn bip143_p2sh_p2wpkh() {
+        let tx = Tx::from_hex(BIP143_P2SH_P2WPKH_TX).unwrap();
+        let p2sh = "a9144733f37cf4db86fbc2efed2500b4f4e49f31202387";
+        assert!(tx
+            .verify_segwit_v0_input(0, &prev_output(1_000_000_000, p2sh))
+            .unwrap());
+        // The same witness does not satisfy the bare program, which wants an empty script_sig.
+        assert!(!tx
+            .verify_segwit_v0_input(
+                0,
+                &prev_output(
+                    1_000_000_000,
+                    "001479091972186c449eb1ded22b78e40d009bdf0089"
+                )
+            )
+            .unwrap());
+    }
+
+    #[test]
+    fn single_key_p2wsh() {
+        let tx = Tx::from_hex("0200000000010107070707070707070707070707070707070707070707070707070707070707070100000000fdffffff01204bbc00000000001600140909090909090909090909090909090909090909024730440220688b6d4fcfdc97512ca3668934bbdabdbe33530898c65a801ecfe130386d06ce02201a0816d9768b817f0c029095d54d6e54ef44f5dea7910c0bfec59f5fe6cab0b601232103cf7cb83f5278ddeac7fcc170f75691bf0a7210632dd888cfa7ae45bade2ee0d6ac00000000").unwrap();
+        let p2wsh = "00206718b21e47e2846b6384cb20591dc54119c16b3a67db2d4479f63328f4b3cfef";
+        assert!(tx
+            .verify_segwit_v0_input(0, &prev_output(12_345_678, p2wsh))
+            .unwrap());
+        let mut changed = tx.clone();
+        changed.outputs[0].value -= 1;
+        assert!(!changed
+            .verify_segwit_v0_input(0, &prev_output(12_345_678, p2wsh))
+            .unwrap());
+    }
+
+    // The fully signed transaction from BIP341's key path test vectors.
+    fn bip341_signed_tx() -> (Tx, Vec<TxOut>, serde_json::Value) {
+        let vectors: serde_json::Value =
+            serde_json::from_str(include_str!("../../test_data/bip341_tests.json")).unwrap();
+        let vectors = vectors["keyPathSpending"][0].clone();
+        let tx = Tx::from_hex(vectors["auxiliary"]["fullySignedTx"].as_str().unwrap()).unwrap();
+        let prevouts = vectors["given"]["utxosSpent"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|utxo| {
+                prev_output(
+                    utxo["amountSats"].as_u64().unwrap(),
+                    utxo["scriptPubKey"].as_str().unwrap(),
+                )
+            })
+            .collect();
+        (tx, prevouts, vectors)
+    }
+
+    #[test]
+    fn bip341_key_path_spends() {
+        let (tx, prevouts, vectors) = bip341_signed_tx();
+        for input in vectors["inputSpending"].as_array().unwrap() {
+            let index = input["given"]["txinIndex"].as_u64().unwrap() as usize;
+            assert!(
+                tx.verify_taproot_key_path(index, &prevouts).unwrap(),
+                "input {}",
+                index
+            );
+            assert_eq!(
+                hex::encode(&tx.inputs[index].witness[0]),
+                input["expected"]["witness"][0].as_str().unwrap()
+            );
+
+            // The output key is the internal key tweaked with the script tree root.
+            let secret = hex::decode(input["given"]["internalPrivkey"].as_str().unwrap()).unwrap();
+            let internal_key = PrivateKey::from_bytes(&secret).unwrap().point().clone();
+            let merkle_root = input["given"]["merkleRoot"]
+                .as_str()
+                .map(|root| hex::decode(root).unwrap().try_into().unwrap());
+            let intermediary = &input["intermediary"];
+            assert_eq!(
+                hex::encode(internal_key.x_only()),
+                intermediary["internalPubkey"].as_str().unwrap()
+            );
+            assert_eq!(
+                hex::encode(int_to_32_bytes(&taproot::tap_tweak(
+                    &internal_key,
+                    merkle_root.as_ref()
+                ))),
+                intermediary["tweak"].as_str().unwrap()
+            );
+            let output_key = taproot::output_key(&internal_key, merkle_root.as_ref());
+            assert_eq!(prevouts[index].script_pubkey[2..], output_key.x_only());
+        }
+
+        // Unless signed with ANYONECANPAY, an input commits to every spent amount.
+        // Input 0 signs with SINGLE, input 1 with SINGLE|ANYONECANPAY.
+        let mut changed_prevouts = prevouts.clone();
+        changed_prevouts[2].value += 1;
+        assert!(!tx.verify_taproot_key_path(0, &changed_prevouts).unwrap());
+        assert!(tx.verify_taproot_key_path(1, &changed_prevouts).unwrap());
+    }
+
+    #[test]
+    fn taproot_annex_is_committed_to() {
+        let (tx, prevouts, _) = bip341_signed_tx();
+        let mut changed = tx.clone();
+        changed.inputs[0].witness.push(vec![0x50]);
+        assert!(!changed.verify_taproot_key_path(0, &prevouts).unwrap());
+
+        // Without the 0x50 prefix a second item makes it a script path spend.
+        changed.inputs[0].witness[1] = vec![0x51];
+        assert!(changed.verify_taproot_key_path(0, &prevouts).is_err());
+    }
+
+    #[test]
+    fn taproot_rejects_what_it_cannot_check() {
+        let (tx, prevouts, _) = bip341_signed_tx();
+        // Input 2 spends a P2PKH output.
+        assert!(matches!(
+            tx.verify_taproot_key_path(2, &prevouts).unwrap_err(),
+            Error::UnsupportedScript(_)
+        ));
+        assert_eq!(
+            tx.verify_taproot_key_path(9, &prevouts).unwrap_err(),
+            Error::InvalidInputIndex(9)
+        );
+
+        // Input 4 signs with SIGHASH_DEFAULT, which may not be spelled out.
+        let mut changed = tx.clone();
+        changed.inputs[4].witness[0].push(0x00);
+        assert!(matches!(
+            changed.verify_taproot_key_path(4, &prevouts).unwrap_err(),
+            Error::InvalidSignature(_)
+        ));
+        changed.inputs[4].witness[0].truncate(63);
+        assert_eq!(
+            changed.verify_taproot_key_path(4, &prevouts).unwrap_err(),
+            Error::InvalidLength(63)
+        );
+    }
+}
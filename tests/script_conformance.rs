@@ -0,0 +1,502 @@
+// Runs the script interpreter over Bitcoin Core's script_tests.json,
+// tx_valid.json and tx_invalid.json, copied unchanged from Core's
+// src/test/data, reading them the way its script_tests.cpp and
+// transaction_tests.cpp do. Run with --nocapture for a line per case.
+
+use std::collections::{HashMap, HashSet};
+
+use programming_bitcoin_in_rust::helper::hex_to_hash;
+use programming_bitcoin_in_rust::script::interpreter::*;
+use programming_bitcoin_in_rust::script::opcode::{Opcode, OP_NOP, OP_RESERVED};
+use programming_bitcoin_in_rust::script::Command;
+use programming_bitcoin_in_rust::secp256k1::point::{generator_point, Secp256k1Point};
+use programming_bitcoin_in_rust::taproot::{output_key, tap_leaf_hash, TAPSCRIPT_LEAF_VERSION};
+use programming_bitcoin_in_rust::tx::{OutPoint, Tx, TxIn, TxOut};
+use serde_json::Value;
+
+const MAX_MONEY: u64 = 21_000_000 * 100_000_000;
+
+// Every flag Core's tests name.
+const FLAG_NAMES: &[(&str, u32)] = &[
+    ("P2SH", VERIFY_P2SH),
+    ("STRICTENC", VERIFY_STRICTENC),
+    ("DERSIG", VERIFY_DERSIG),
+    ("LOW_S", VERIFY_LOW_S),
+    ("SIGPUSHONLY", VERIFY_SIGPUSHONLY),
+    ("MINIMALDATA", VERIFY_MINIMALDATA),
+    ("NULLDUMMY", VERIFY_NULLDUMMY),
+    (
+        "DISCOURAGE_UPGRADABLE_NOPS",
+        VERIFY_DISCOURAGE_UPGRADABLE_NOPS,
+    ),
+    ("CLEANSTACK", VERIFY_CLEANSTACK),
+    ("MINIMALIF", VERIFY_MINIMALIF),
+    ("NULLFAIL", VERIFY_NULLFAIL),
+    ("CHECKLOCKTIMEVERIFY", VERIFY_CHECKLOCKTIMEVERIFY),
+    ("CHECKSEQUENCEVERIFY", VERIFY_CHECKSEQUENCEVERIFY),
+    ("WITNESS", VERIFY_WITNESS),
+    (
+        "DISCOURAGE_UPGRADABLE_WITNESS_PROGRAM",
+        VERIFY_DISCOURAGE_UPGRADABLE_WITNESS_PROGRAM,
+    ),
+    ("WITNESS_PUBKEYTYPE", VERIFY_WITNESS_PUBKEYTYPE),
+    ("CONST_SCRIPTCODE", VERIFY_CONST_SCRIPTCODE),
+    ("TAPROOT", VERIFY_TAPROOT),
+    (
+        "DISCOURAGE_UPGRADABLE_PUBKEYTYPE",
+        VERIFY_DISCOURAGE_UPGRADABLE_PUBKEYTYPE,
+    ),
+    ("DISCOURAGE_OP_SUCCESS", VERIFY_DISCOURAGE_OP_SUCCESS),
+    (
+        "DISCOURAGE_UPGRADABLE_TAPROOT_VERSION",
+        VERIFY_DISCOURAGE_UPGRADABLE_TAPROOT_VERSION,
+    ),
+];
+
+fn parse_flags(names: &str) -> u32 {
+    if names.is_empty() || names == "NONE" {
+        return VERIFY_NONE;
+    }
+    names.split(',').fold(VERIFY_NONE, |flags, name| {
+        let (_, flag) = FLAG_NAMES
+            .iter()
+            .find(|(known, _)| *known == name)
+            .unwrap_or_else(|| panic!("unknown flag {}", name));
+        flags | flag
+    })
+}
+
+fn all_flags() -> u32 {
+    FLAG_NAMES
+        .iter()
+        .fold(VERIFY_NONE, |all, (_, flag)| all | flag)
+}
+
+// CLEANSTACK is only defined with WITNESS, and WITNESS only with P2SH.
+fn fixup_flags(mut flags: u32) -> u32 {
+    if flags & VERIFY_CLEANSTACK != 0 {
+        flags |= VERIFY_WITNESS;
+    }
+    if flags & VERIFY_WITNESS != 0 {
+        flags |= VERIFY_P2SH;
+    }
+    flags
+}
+
+// The other way round, for flags left after some are excluded.
+fn trim_flags(mut flags: u32) -> u32 {
+    if flags & VERIFY_P2SH == 0 {
+        flags &= !VERIFY_WITNESS;
+    }
+    if flags & VERIFY_WITNESS == 0 {
+        flags &= !VERIFY_CLEANSTACK;
+    }
+    flags
+}
+
+// Core's ParseScript: numbers push as script numbers, 0x words are raw bytes,
+// quoted strings are pushed, and anything else is an opcode name with or
+// without its OP_ prefix.
+fn parse_script(source: &str) -> Vec<u8> {
+    let mut script = Vec::new();
+    for word in source.split_whitespace() {
+        let digits = word.strip_prefix('-').unwrap_or(word);
+        if !digits.is_empty() && digits.bytes().all(|b| b.is_ascii_digit()) {
+            let n: i64 = word.parse().unwrap();
+            match Opcode::from_small_int(n) {
+                Some(opcode) => script.push(opcode.0),
+                None => script.extend(Command::push(&encode_num(n)).serialize()),
+            }
+        } else if let Some(bytes) = word.strip_prefix("0x") {
+            script.extend(hex::decode(bytes).unwrap());
+        } else if word.len() >= 2 && word.starts_with('\'') && word.ends_with('\'') {
+            script.extend(Command::push(&word.as_bytes()[1..word.len() - 1]).serialize());
+        } else {
+            let name = if word.starts_with("OP_") {
+                word.to_string()
+            } else {
+                format!("OP_{}", word)
+            };
+            match Opcode::from_name(&name) {
+                Some(opcode) if opcode >= OP_NOP || opcode == OP_RESERVED => script.push(opcode.0),
+                _ => panic!("bad script word {:?}", word),
+            }
+        }
+    }
+    script
+}
+
+fn error_name(result: Result<(), ScriptError>) -> &'static str {
+    let error = match result {
+        Ok(()) => return "OK",
+        Err(error) => error,
+    };
+    match error {
+        ScriptError::EvalFalse => "EVAL_FALSE",
+        ScriptError::OpReturn => "OP_RETURN",
+        ScriptError::ScriptSize => "SCRIPT_SIZE",
+        ScriptError::PushSize => "PUSH_SIZE",
+        ScriptError::OpCount => "OP_COUNT",
+        ScriptError::StackSize => "STACK_SIZE",
+        ScriptError::Verify => "VERIFY",
+        ScriptError::EqualVerify => "EQUALVERIFY",
+        ScriptError::CheckSigVerify => "CHECKSIGVERIFY",
+        ScriptError::CheckMultisigVerify => "CHECKMULTISIGVERIFY",
+        ScriptError::NumEqualVerify => "NUMEQUALVERIFY",
+        ScriptError::BadOpcode => "BAD_OPCODE",
+        ScriptError::DisabledOpcode => "DISABLED_OPCODE",
+        ScriptError::PubkeyCount => "PUBKEY_COUNT",
+        ScriptError::SigCount => "SIG_COUNT",
+        ScriptError::InvalidStackOperation => "INVALID_STACK_OPERATION",
+        ScriptError::InvalidAltstackOperation => "INVALID_ALTSTACK_OPERATION",
+        ScriptError::UnbalancedConditional => "UNBALANCED_CONDITIONAL",
+        ScriptError::NegativeLocktime => "NEGATIVE_LOCKTIME",
+        ScriptError::UnsatisfiedLocktime => "UNSATISFIED_LOCKTIME",
+        ScriptError::NumberOverflow | ScriptError::NonMinimalNumber => "SCRIPTNUM",
+        ScriptError::MinimalData => "MINIMALDATA",
+        ScriptError::SigHashType => "SIG_HASHTYPE",
+        ScriptError::SigDer => "SIG_DER",
+        ScriptError::SigHighS => "SIG_HIGH_S",
+        ScriptError::PubkeyType => "PUBKEYTYPE",
+        ScriptError::SigNullFail => "NULLFAIL",
+        ScriptError::SigNullDummy => "SIG_NULLDUMMY",
+        ScriptError::SigPushOnly => "SIG_PUSHONLY",
+        ScriptError::SigFindAndDelete => "SIG_FINDANDDELETE",
+        ScriptError::OpCodeSeparator => "OP_CODESEPARATOR",
+        ScriptError::CleanStack => "CLEANSTACK",
+        ScriptError::MinimalIf => "MINIMALIF",
+        ScriptError::WitnessPubkeyType => "WITNESS_PUBKEYTYPE",
+        ScriptError::WitnessProgramWrongLength => "WITNESS_PROGRAM_WRONG_LENGTH",
+        ScriptError::WitnessProgramWitnessEmpty => "WITNESS_PROGRAM_WITNESS_EMPTY",
+        ScriptError::WitnessProgramMismatch => "WITNESS_PROGRAM_MISMATCH",
+        ScriptError::WitnessMalleated => "WITNESS_MALLEATED",
+        ScriptError::WitnessMalleatedP2sh => "WITNESS_MALLEATED_P2SH",
+        ScriptError::WitnessUnexpected => "WITNESS_UNEXPECTED",
+        ScriptError::SchnorrSigSize => "SCHNORR_SIG_SIZE",
+        ScriptError::SchnorrSigHashType => "SCHNORR_SIG_HASHTYPE",
+        ScriptError::SchnorrSig => "SCHNORR_SIG",
+        ScriptError::TaprootWrongControlSize => "TAPROOT_WRONG_CONTROL_SIZE",
+        ScriptError::TapscriptValidationWeight => "TAPSCRIPT_VALIDATION_WEIGHT",
+        ScriptError::TapscriptCheckMultisig => "TAPSCRIPT_CHECKMULTISIG",
+        ScriptError::TapscriptMinimalIf => "TAPSCRIPT_MINIMALIF",
+        ScriptError::TapscriptEmptyPubkey => "TAPSCRIPT_EMPTY_PUBKEY",
+        ScriptError::DiscourageUpgradableNops => "DISCOURAGE_UPGRADABLE_NOPS",
+        ScriptError::DiscourageUpgradableWitnessProgram => "DISCOURAGE_UPGRADABLE_WITNESS_PROGRAM",
+        ScriptError::DiscourageUpgradableTaprootVersion => "DISCOURAGE_UPGRADABLE_TAPROOT_VERSION",
+        ScriptError::DiscourageOpSuccess => "DISCOURAGE_OP_SUCCESS",
+        ScriptError::DiscourageUpgradablePubkeyType => "DISCOURAGE_UPGRADABLE_PUBKEYTYPE",
+    }
+}
+
+// Amounts are in coins, as JSON numbers.
+fn satoshis(amount: &Value) -> u64 {
+    (amount.as_f64().unwrap() * 100_000_000.0).round() as u64
+}
+
+// Core's BuildCreditingTransaction and BuildSpendingTransaction: a coinbase-like
+// transaction paying to the script_pubkey, and one spending that output to an
+// empty script with the script_sig and witness.
+fn spending_tx(script_sig: Vec<u8>, witness: Vec<Vec<u8>>, credit: TxOut) -> Tx {
+    let mut coinbase = TxIn::new(OutPoint::null());
+    coinbase.script_sig = vec![0x00, 0x00];
+    let value = credit.value;
+    let crediting = Tx::new(1, vec![coinbase], vec![credit], 0);
+    let mut input = TxIn::new(OutPoint::new(crediting.txid(), 0));
+    input.script_sig = script_sig;
+    input.witness = witness;
+    Tx::new(1, vec![input], vec![TxOut::new(value, Vec::new())], 0)
+}
+
+// Cases known to differ from Core, by their position in the file, each with
+// the reason. They are run and reported, but do not fail the suite. None are
+// known at present.
+const SCRIPT_TESTS_SKIPPED: &[(usize, &str)] = &[];
+const TX_VALID_SKIPPED: &[(usize, &str)] = &[];
+const TX_INVALID_SKIPPED: &[(usize, &str)] = &[];
+
+// Prints a line for every case and fails on the unexpected results of cases
+// outside the skip list.
+struct Report {
+    suite: &'static str,
+    skipped: &'static [(usize, &'static str)],
+    passed: usize,
+    failures: Vec<String>,
+    skip_failures: usize,
+}
+
+impl Report {
+    fn new(suite: &'static str, skipped: &'static [(usize, &'static str)]) -> Self {
+        Report {
+            suite,
+            skipped,
+            passed: 0,
+            failures: Vec::new(),
+            skip_failures: 0,
+        }
+    }
+
+    fn case(&mut self, index: usize, case: &Value, result: Result<(), String>) {
+        let skip = self.skipped.iter().find(|(skipped, _)| *skipped == index);
+        match (result, skip) {
+            (Ok(()), _) => {
+                self.passed += 1;
+                println!("{} #{}: pass", self.suite, index);
+            }
+            (Err(error), Some((_, reason))) => {
+                self.skip_failures += 1;
+                println!(
+                    "{} #{}: FAIL (skipped, {}): {}",
+                    self.suite, index, reason, error
+                );
+            }
+            (Err(error), None) => {
+                println!("{} #{}: FAIL: {}", self.suite, index, error);
+                self.failures
+                    .push(format!("#{} {}: {}", index, case, error));
+            }
+        }
+    }
+
+    fn finish(self) {
+        println!(
+            "{}: {} passed, {} failed, {} known failures skipped",
+            self.suite,
+            self.passed,
+            self.failures.len(),
+            self.skip_failures
+        );
+        assert!(self.failures.is_empty(), "\n{}", self.failures.join("\n"));
+    }
+}
+
+fn read_cases(json: &str) -> Vec<(usize, Vec<Value>)> {
+    let cases: Vec<Vec<Value>> = serde_json::from_str(json).unwrap();
+    // Lone strings are comments.
+    cases
+        .into_iter()
+        .enumerate()
+        .filter(|(_, case)| case.len() > 1)
+        .collect()
+}
+
+// The generator, whose secret is 1, as key0 in Core's script_tests.cpp; the
+// internal key of the outputs the taproot cases build.
+fn key0() -> Secp256k1Point {
+    generator_point()
+}
+
+#[test]
+fn script_tests() {
+    let mut report = Report::new("script_tests.json", SCRIPT_TESTS_SKIPPED);
+    for (index, case) in read_cases(include_str!("../test_data/script_tests.json")) {
+        // An optional leading array holds the witness items and the amount.
+        // #SCRIPT# items are scripts to parse, and #CONTROLBLOCK# is the
+        // control block for the item before it as the only leaf of a tree.
+        let mut witness: Vec<Vec<u8>> = Vec::new();
+        let mut leaf_hash = None;
+        let (amount, fields) = match &case[0] {
+            Value::Array(items) => {
+                let (amount, items) = items.split_last().unwrap();
+                for item in items {
+                    let item = item.as_str().unwrap();
+                    if let Some(script) = item.strip_prefix("#SCRIPT#") {
+                        witness.push(parse_script(script));
+                    } else if item == "#CONTROLBLOCK#" {
+                        let leaf = tap_leaf_hash(witness.last().unwrap(), TAPSCRIPT_LEAF_VERSION);
+                        let output = output_key(&key0(), Some(&leaf));
+                        let parity = if output.has_even_y() { 0 } else { 1 };
+                        let mut control = vec![TAPSCRIPT_LEAF_VERSION | parity];
+                        control.extend(key0().x_only());
+                        witness.push(control);
+                        leaf_hash = Some(leaf);
+                    } else {
+                        witness.push(hex::decode(item).unwrap());
+                    }
+                }
+                (satoshis(amount), &case[1..])
+            }
+            _ => (0, &case[..]),
+        };
+        // Anything after the expected error is a comment.
+        if fields.len() < 4 {
+            report.case(index, &Value::from(case), Err("bad test".to_string()));
+            continue;
+        }
+        let text = |i: usize| fields[i].as_str().unwrap();
+        let mut flags = parse_flags(text(2));
+        if flags & VERIFY_CLEANSTACK != 0 {
+            flags |= VERIFY_P2SH | VERIFY_WITNESS;
+        }
+        let script_sig = parse_script(text(0));
+        let script_pubkey = if text(1) == "0x51 0x20 #TAPROOTOUTPUT#" {
+            let output = output_key(&key0(), leaf_hash.as_ref());
+            [&[0x51, 0x20][..], &output.x_only()].concat()
+        } else {
+            parse_script(text(1))
+        };
+
+        let credit = TxOut::new(amount, script_pubkey.clone());
+        let tx = spending_tx(script_sig.clone(), witness.clone(), credit.clone());
+        let spent_outputs = [credit];
+        let checker = TxSignatureChecker::with_spent_outputs(&tx, 0, &spent_outputs);
+        let result = verify_script(&script_sig, &script_pubkey, &witness, flags, &checker);
+        let outcome = if error_name(result) == text(3) {
+            Ok(())
+        } else {
+            Err(format!("expected {}, got {:?}", text(3), result))
+        };
+        report.case(index, &Value::from(case.clone()), outcome);
+    }
+    report.finish();
+}
+
+// What the inputs of a tx_valid.json or tx_invalid.json case spend.
+fn prevouts(case: &[Value]) -> HashMap<OutPoint, TxOut> {
+    case[0]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|prevout| {
+            let prevout = prevout.as_array().unwrap();
+            let txid = hex_to_hash(prevout[0].as_str().unwrap()).unwrap();
+            let vout = prevout[1].as_i64().unwrap() as u32;
+            let script_pubkey = parse_script(prevout[2].as_str().unwrap());
+            let value = prevout.get(3).map_or(0, |amount| amount.as_u64().unwrap());
+            (OutPoint::new(txid, vout), TxOut::new(value, script_pubkey))
+        })
+        .collect()
+}
+
+// The context-free checks of Core's CheckTransaction.
+fn check_transaction(tx: &Tx) -> bool {
+    if tx.inputs.is_empty() || tx.outputs.is_empty() {
+        return false;
+    }
+    let mut total = 0;
+    for output in &tx.outputs {
+        total += output.value;
+        if output.value > MAX_MONEY || total > MAX_MONEY {
+            return false;
+        }
+    }
+    let mut spent = HashSet::new();
+    if !tx
+        .inputs
+        .iter()
+        .all(|input| spent.insert(input.previous_output))
+    {
+        return false;
+    }
+    if tx.is_coinbase() {
+        (2..=100).contains(&tx.inputs[0].script_sig.len())
+    } else {
+        tx.inputs
+            .iter()
+            .all(|input| !input.previous_output.is_null())
+    }
+}
+
+// Every input verified against the output it spends.
+fn check_inputs(tx: &Tx, prevouts: &HashMap<OutPoint, TxOut>, flags: u32) -> Result<(), String> {
+    let spent_outputs: Vec<TxOut> = tx
+        .inputs
+        .iter()
+        .map(|input| prevouts[&input.previous_output].clone())
+        .collect();
+    for (index, input) in tx.inputs.iter().enumerate() {
+        let checker = TxSignatureChecker::with_spent_outputs(tx, index, &spent_outputs);
+        verify_script(
+            &input.script_sig,
+            &spent_outputs[index].script_pubkey,
+            &input.witness,
+            flags,
+            &checker,
+        )
+        .map_err(|error| format!("input {}: {:?}", index, error))?;
+    }
+    Ok(())
+}
+
+// Core's ExcludeIndividualFlags: `flags` with each flag it has taken out in
+// turn, trimmed, without repeats.
+fn exclude_individual_flags(flags: u32) -> Vec<u32> {
+    let mut combinations = Vec::new();
+    for (_, flag) in FLAG_NAMES {
+        let excluding_one = trim_flags(flags & !flag);
+        if excluding_one != flags && !combinations.contains(&excluding_one) {
+            combinations.push(excluding_one);
+        }
+    }
+    combinations
+}
+
+fn parse_case(case: &[Value]) -> Result<(HashMap<OutPoint, TxOut>, Tx), String> {
+    let tx = Tx::from_hex(case[1].as_str().unwrap()).map_err(|error| format!("{:?}", error))?;
+    Ok((prevouts(case), tx))
+}
+
+#[test]
+fn tx_valid() {
+    let mut report = Report::new("tx_valid.json", TX_VALID_SKIPPED);
+    for (index, case) in read_cases(include_str!("../test_data/tx_valid.json")) {
+        let outcome = parse_case(&case).and_then(|(prevouts, tx)| {
+            if !check_transaction(&tx) {
+                return Err("fails CheckTransaction".to_string());
+            }
+            // The flags listed are the ones the transaction does not pass with.
+            let excluded = parse_flags(case[2].as_str().unwrap());
+            check_inputs(&tx, &prevouts, all_flags() & !excluded)?;
+            // Taking more flags away keeps it valid.
+            for (name, flag) in FLAG_NAMES {
+                let flags = trim_flags(all_flags() & !(excluded | flag));
+                check_inputs(&tx, &prevouts, flags)
+                    .map_err(|error| format!("without {}: {}", name, error))?;
+            }
+            // And none of the listed flags could be left out of the list.
+            for excluding_one in exclude_individual_flags(excluded) {
+                if check_inputs(&tx, &prevouts, all_flags() & !excluding_one).is_ok() {
+                    return Err(format!("passes excluding only {:#x}", excluding_one));
+                }
+            }
+            Ok(())
+        });
+        report.case(index, &Value::from(case.clone()), outcome);
+    }
+    report.finish();
+}
+
+#[test]
+fn tx_invalid() {
+    let mut report = Report::new("tx_invalid.json", TX_INVALID_SKIPPED);
+    for (index, case) in read_cases(include_str!("../test_data/tx_invalid.json")) {
+        let outcome = parse_case(&case).and_then(|(prevouts, tx)| {
+            let flags = case[2].as_str().unwrap();
+            // BADTX cases fail CheckTransaction, and no others do.
+            match (check_transaction(&tx), flags == "BADTX") {
+                (false, true) => return Ok(()),
+                (true, true) => return Err("passes CheckTransaction".to_string()),
+                (false, false) => return Err("fails CheckTransaction".to_string()),
+                (true, false) => {}
+            }
+            let flags = parse_flags(flags);
+            if check_inputs(&tx, &prevouts, flags).is_ok() {
+                return Err("verifies".to_string());
+            }
+            // Adding flags keeps it invalid.
+            for (name, flag) in FLAG_NAMES {
+                if check_inputs(&tx, &prevouts, fixup_flags(flags | flag)).is_ok() {
+                    return Err(format!("verifies with {}", name));
+                }
+            }
+            // But every flag listed is needed to reject it.
+            for excluding_one in exclude_individual_flags(flags) {
+                if let Err(error) = check_inputs(&tx, &prevouts, excluding_one) {
+                    return Err(format!("fails without all its flags: {}", error));
+                }
+            }
+            Ok(())
+        });
+        report.case(index, &Value::from(case.clone()), outcome);
+    }
+    report.finish();
+}